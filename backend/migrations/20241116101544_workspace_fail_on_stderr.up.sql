@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE workspace_settings ADD COLUMN fail_on_stderr BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP TABLE dependency_checksum;
+ALTER TABLE workspace_settings DROP COLUMN verify_dependency_checksums;
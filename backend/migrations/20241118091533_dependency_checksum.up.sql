@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE workspace_settings ADD COLUMN verify_dependency_checksums BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TABLE dependency_checksum (
+    workspace_id VARCHAR(50) NOT NULL,
+    lock_hash VARCHAR(255) NOT NULL,
+    tree_hash VARCHAR(64) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now(),
+    PRIMARY KEY (workspace_id, lock_hash)
+);
@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE completed_job DROP COLUMN metrics;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE completed_job ADD COLUMN metrics JSONB;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS workspace_pull_turn (
+    workspace_id VARCHAR(50) PRIMARY KEY,
+    last_pulled_at TIMESTAMP WITH TIME ZONE NOT NULL
+);
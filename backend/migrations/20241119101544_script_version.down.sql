@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE completed_job DROP COLUMN script_version;
+ALTER TABLE script DROP COLUMN version;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE script ADD COLUMN version VARCHAR(255);
+ALTER TABLE completed_job ADD COLUMN script_version VARCHAR(255);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE worker_ping DROP COLUMN paused;
+DROP TABLE IF EXISTS worker_pause;
@@ -0,0 +1,8 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS worker_pause (
+    worker VARCHAR(255) PRIMARY KEY,
+    paused_by VARCHAR(255) NOT NULL,
+    paused_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
+
+ALTER TABLE worker_ping ADD COLUMN paused BOOLEAN NOT NULL DEFAULT false;
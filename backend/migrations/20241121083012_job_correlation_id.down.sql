@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP INDEX IF EXISTS ix_completed_job_correlation_id;
+DROP INDEX IF EXISTS ix_queue_correlation_id;
+ALTER TABLE completed_job DROP COLUMN correlation_id;
+ALTER TABLE queue DROP COLUMN correlation_id;
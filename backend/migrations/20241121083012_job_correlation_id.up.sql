@@ -0,0 +1,5 @@
+-- Add up migration script here
+ALTER TABLE queue ADD COLUMN correlation_id VARCHAR(255);
+ALTER TABLE completed_job ADD COLUMN correlation_id VARCHAR(255);
+CREATE INDEX ix_queue_correlation_id ON queue (workspace_id, correlation_id) WHERE correlation_id IS NOT NULL;
+CREATE INDEX ix_completed_job_correlation_id ON completed_job (workspace_id, correlation_id) WHERE correlation_id IS NOT NULL;
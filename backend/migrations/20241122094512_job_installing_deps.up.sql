@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS installing_deps BOOLEAN NOT NULL DEFAULT false;
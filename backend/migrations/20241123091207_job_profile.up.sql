@@ -0,0 +1,7 @@
+-- Add up migration script here
+CREATE TABLE IF NOT EXISTS job_profile (
+    job_id UUID PRIMARY KEY,
+    workspace_id VARCHAR(50) NOT NULL,
+    profile BYTEA NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
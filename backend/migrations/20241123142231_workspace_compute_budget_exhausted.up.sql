@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE workspace_settings ADD COLUMN compute_budget_exhausted BOOLEAN NOT NULL DEFAULT false;
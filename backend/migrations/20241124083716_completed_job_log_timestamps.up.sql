@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE completed_job ADD COLUMN first_log_at TIMESTAMPTZ, ADD COLUMN last_log_at TIMESTAMPTZ;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE workspace_settings ADD COLUMN pip_require_hashes BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+ALTER TABLE script DROP COLUMN lock_error_kind;
+ALTER TABLE script DROP COLUMN lock_pinned_packages;
+ALTER TABLE script DROP COLUMN lock_duration_ms;
+ALTER TABLE script DROP COLUMN lock_exit_code;
+ALTER TABLE script DROP COLUMN lock_success;
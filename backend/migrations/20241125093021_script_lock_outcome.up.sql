@@ -0,0 +1,6 @@
+-- Add up migration script here
+ALTER TABLE script ADD COLUMN lock_success BOOLEAN;
+ALTER TABLE script ADD COLUMN lock_exit_code INTEGER;
+ALTER TABLE script ADD COLUMN lock_duration_ms BIGINT;
+ALTER TABLE script ADD COLUMN lock_pinned_packages INTEGER;
+ALTER TABLE script ADD COLUMN lock_error_kind VARCHAR(50);
@@ -0,0 +1,5 @@
+-- Add down migration script here
+DROP TABLE IF EXISTS hedge_group_winner;
+DROP INDEX IF EXISTS queue_hedge_group_idx;
+ALTER TABLE completed_job DROP COLUMN IF EXISTS hedge_group;
+ALTER TABLE queue DROP COLUMN IF EXISTS hedge_group;
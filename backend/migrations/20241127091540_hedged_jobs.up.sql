@@ -0,0 +1,10 @@
+-- Add up migration script here
+ALTER TABLE queue ADD COLUMN IF NOT EXISTS hedge_group UUID;
+ALTER TABLE completed_job ADD COLUMN IF NOT EXISTS hedge_group UUID;
+CREATE INDEX IF NOT EXISTS queue_hedge_group_idx ON queue (hedge_group) WHERE hedge_group IS NOT NULL;
+
+CREATE TABLE IF NOT EXISTS hedge_group_winner (
+    group_id UUID PRIMARY KEY,
+    job_id UUID NOT NULL,
+    completed_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT now()
+);
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE script ADD COLUMN IF NOT EXISTS content_hash VARCHAR(64);
@@ -17,6 +17,7 @@ use sqlx::{postgres::PgListener, Pool, Postgres};
 use std::{
     collections::HashMap,
     net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
     time::Duration,
 };
 use tokio::{
@@ -25,6 +26,7 @@ use tokio::{
 };
 use uuid::Uuid;
 use windmill_api::HTTP_CLIENT;
+use windmill_queue::{JobQueue, JobRunRecords, PostgresJobQueue};
 
 #[cfg(feature = "enterprise")]
 use windmill_common::ee::{maybe_renew_license_key_on_start, LICENSE_KEY_ID, LICENSE_KEY_VALID};
@@ -43,7 +45,7 @@ use windmill_common::{
     scripts::ScriptLang,
     stats_ee::schedule_stats,
     utils::{hostname, rd_string, Mode},
-    worker::{reload_custom_tags_setting, HUB_CACHE_DIR, TMP_DIR, WORKER_GROUP},
+    worker::{claim_worker_name, reload_custom_tags_setting, HUB_CACHE_DIR, TMP_DIR, WORKER_GROUP},
     DB, METRICS_ENABLED,
 };
 
@@ -71,12 +73,13 @@ use windmill_worker::{
 };
 
 use crate::monitor::{
-    initial_load, load_keep_job_dir, load_metrics_debug_enabled, load_require_preexisting_user,
-    load_tag_per_workspace_enabled, load_tag_per_workspace_workspaces, monitor_db, monitor_pool,
-    reload_base_url_setting, reload_bunfig_install_scopes_setting,
-    reload_critical_error_channels_setting, reload_extra_pip_index_url_setting,
-    reload_hub_base_url_setting, reload_job_default_timeout_setting, reload_jwt_secret_setting,
-    reload_license_key, reload_npm_config_registry_setting, reload_pip_index_url_setting,
+    cleanup_completed_jobs_periodically, initial_load, load_keep_job_dir,
+    load_metrics_debug_enabled, load_require_preexisting_user, load_tag_per_workspace_enabled,
+    load_tag_per_workspace_workspaces, monitor_db, monitor_pool, reload_base_url_setting,
+    reload_bunfig_install_scopes_setting, reload_critical_error_channels_setting,
+    reload_extra_pip_index_url_setting, reload_hub_base_url_setting,
+    reload_job_default_timeout_setting, reload_jwt_secret_setting, reload_license_key,
+    reload_npm_config_registry_setting, reload_pip_index_url_setting,
     reload_retention_period_setting, reload_scim_token_setting, reload_smtp_config,
     reload_worker_config,
 };
@@ -152,8 +155,10 @@ async fn cache_hub_scripts(file_path: Option<String>) -> anyhow::Result<()> {
                 &res.content,
                 &mut 0,
                 &mut None,
+                &mut JobRunRecords::default(),
                 &job_dir,
                 None,
+                None,
                 "global",
                 "global",
                 "",
@@ -171,9 +176,11 @@ async fn cache_hub_scripts(file_path: Option<String>) -> anyhow::Result<()> {
                 let _ = windmill_worker::install_bun_lockfile(
                     &mut 0,
                     &mut None,
+                    &mut JobRunRecords::default(),
                     &job_id,
                     "admins",
                     None,
+                    None,
                     &job_dir,
                     "cache_init",
                     envs.clone(),
@@ -191,6 +198,7 @@ async fn cache_hub_scripts(file_path: Option<String>) -> anyhow::Result<()> {
                     &job_id,
                     "admins",
                     None,
+                    None,
                     &job_dir,
                     "",
                     "cache_init",
@@ -621,8 +629,10 @@ Windmill Community Edition {GIT_VERSION}
             if !killpill_rx.try_recv().is_ok() {
                 let base_internal_url = base_internal_rx.await?;
                 if worker_mode {
+                    let bookkeeping_db = windmill_common::connect_bookkeeping_db().await?;
                     run_workers(
                         db.clone(),
+                        bookkeeping_db,
                         rx,
                         killpill_tx.clone(),
                         num_workers,
@@ -950,6 +960,7 @@ fn display_config(envs: &[&str]) {
 
 pub async fn run_workers<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 'static>(
     db: Pool<Postgres>,
+    bookkeeping_db: Option<Pool<Postgres>>,
     mut rx: tokio::sync::broadcast::Receiver<()>,
     tx: tokio::sync::broadcast::Sender<()>,
     num_workers: i32,
@@ -1013,6 +1024,29 @@ pub async fn run_workers<R: rsmq_async::RsmqConnection + Send + Sync + Clone + '
             .expect("could not create initial worker dir");
     }
 
+    #[cfg(feature = "parquet")]
+    if !agent_mode {
+        windmill_worker::restore_cache_snapshots().await;
+        tokio::spawn(windmill_worker::snapshot_caches_periodically(
+            db.clone(),
+            rx.resubscribe(),
+        ));
+    }
+
+    tokio::spawn(windmill_worker::evict_caches_periodically(rx.resubscribe()));
+    windmill_worker::start_analytics_export(
+        db.clone(),
+        base_internal_url.clone(),
+        rx.resubscribe(),
+    );
+
+    if !agent_mode {
+        tokio::spawn(cleanup_completed_jobs_periodically(
+            db.clone(),
+            rx.resubscribe(),
+        ));
+    }
+
     tracing::info!(
         "Starting {num_workers} workers and SLEEP_QUEUE={}ms",
         *windmill_worker::SLEEP_QUEUE
@@ -1026,9 +1060,21 @@ pub async fn run_workers<R: rsmq_async::RsmqConnection + Send + Sync + Clone + '
         let tx = tx.clone();
         let base_internal_url = base_internal_url.clone();
         let rsmq2 = rsmq.clone();
+        let job_queue: Arc<dyn JobQueue> = Arc::new(
+            PostgresJobQueue::new(db.clone(), rsmq.clone())
+                .with_bookkeeping_db(bookkeeping_db.clone()),
+        );
         let hostname = hostname.clone();
 
         handles.push(tokio::spawn(async move {
+            let worker_name = match claim_worker_name(&db1, worker_name).await {
+                Ok(worker_name) => worker_name,
+                Err(e) => {
+                    tracing::error!("could not start worker {i}: {e:#}");
+                    let _ = tx.send(());
+                    return;
+                }
+            };
             if num_workers > 1 {
                 tracing::info!(worker = %worker_name, "starting worker {i}");
             }
@@ -1044,6 +1090,7 @@ pub async fn run_workers<R: rsmq_async::RsmqConnection + Send + Sync + Clone + '
                 tx,
                 &base_internal_url,
                 rsmq2,
+                job_queue,
                 agent_mode,
             );
 
@@ -18,6 +18,7 @@ use tokio::{
     join,
     sync::{mpsc, RwLock},
 };
+use uuid::Uuid;
 
 #[cfg(feature = "embedding")]
 use windmill_api::embeddings::update_embeddings_db;
@@ -57,11 +58,11 @@ use windmill_common::{
     BASE_URL, CRITICAL_ERROR_CHANNELS, DB, DEFAULT_HUB_BASE_URL, HUB_BASE_URL, JOB_RETENTION_SECS,
     METRICS_DEBUG_ENABLED, METRICS_ENABLED,
 };
-use windmill_queue::cancel_job;
+use windmill_queue::{add_completed_job_error, append_logs, cancel_job, CanceledBy, JobRunRecords};
 use windmill_worker::{
     create_token_for_owner, handle_job_error, AuthedClient, SameWorkerPayload, SameWorkerSender,
     SendResult, BUNFIG_INSTALL_SCOPES, JOB_DEFAULT_TIMEOUT, KEEP_JOB_DIR, NPM_CONFIG_REGISTRY,
-    PIP_EXTRA_INDEX_URL, PIP_INDEX_URL, SCRIPT_TOKEN_EXPIRY,
+    NUM_SECS_PING, PIP_EXTRA_INDEX_URL, PIP_INDEX_URL, SCRIPT_TOKEN_EXPIRY,
 };
 
 #[cfg(feature = "parquet")]
@@ -84,12 +85,39 @@ lazy_static::lazy_static! {
     .and_then(|x| x.parse::<String>().ok())
     .unwrap_or_else(|| "30".to_string());
 
+    /// ZOMBIE_JOB_TIMEOUT of the jobs installing their dependencies, heavy installs being able to
+    /// delay the pings of healthy jobs
+    static ref ZOMBIE_JOB_INSTALL_TIMEOUT: String = std::env::var("ZOMBIE_JOB_INSTALL_TIMEOUT")
+    .ok()
+    .filter(|x| x.parse::<u64>().is_ok())
+    .unwrap_or_else(|| ZOMBIE_JOB_TIMEOUT.clone());
+
     static ref FLOW_ZOMBIE_TRANSITION_TIMEOUT: String = std::env::var("FLOW_ZOMBIE_TRANSITION_TIMEOUT")
     .ok()
     .and_then(|x| x.parse::<String>().ok())
     .unwrap_or_else(|| "30".to_string());
 
 
+    /// when set, the running jobs of a worker that has not pinged for this many seconds are restarted
+    /// right away, without waiting for their own last_ping to go stale. Must span more than 2
+    /// pings of the workers so that a single late ping does not restart their jobs
+    static ref WORKER_ZOMBIE_TIMEOUT: Option<String> = std::env::var("WORKER_ZOMBIE_TIMEOUT")
+    .ok()
+    .filter(|x| match x.parse::<u64>() {
+        Ok(secs) if secs > 2 * NUM_SECS_PING => true,
+        Ok(_) => {
+            tracing::error!(
+                "WORKER_ZOMBIE_TIMEOUT ({x}s) must be longer than 2 worker pings ({}s), ignoring it",
+                2 * NUM_SECS_PING
+            );
+            false
+        }
+        Err(e) => {
+            tracing::error!("Invalid WORKER_ZOMBIE_TIMEOUT {x}, ignoring it: {e}");
+            false
+        }
+    });
+
     pub static ref RESTART_ZOMBIE_JOBS: bool = std::env::var("RESTART_ZOMBIE_JOBS")
     .ok()
     .and_then(|x| x.parse::<bool>().ok())
@@ -106,6 +134,19 @@ lazy_static::lazy_static! {
     )
     .unwrap();
 
+    static ref QUEUE_WAIT_EXCEEDED_COUNT: prometheus::IntCounter = prometheus::register_int_counter!(
+        "queue_wait_exceeded_count",
+        "Total number of jobs failed because they were not started within the max queue wait."
+    )
+    .unwrap();
+
+    /// seconds a job may wait in the queue past its scheduled time before it is failed instead of
+    /// being run late, overridden by the max queue wait of its workspace. Disabled if not set
+    static ref MAX_QUEUE_WAIT_SECS: Option<i32> = std::env::var("MAX_QUEUE_WAIT_SECS")
+    .ok()
+    .and_then(|x| x.parse::<i32>().ok())
+    .filter(|x| *x > 0);
+
     static ref QUEUE_COUNT: prometheus::IntGaugeVec = prometheus::register_int_gauge_vec!(
         "queue_count",
         "Number of jobs in the queue",
@@ -114,6 +155,27 @@ lazy_static::lazy_static! {
 
     static ref QUEUE_COUNT_TAGS: Arc<RwLock<Vec<String>>> = Arc::new(RwLock::new(Vec::new()));
 
+    /// how often, in seconds, the completed jobs past the retention of their workspace are
+    /// cleaned up. 0 disables the cleanup
+    static ref COMPLETED_JOB_CLEANUP_INTERVAL_SECS: u64 = std::env::var("COMPLETED_JOB_CLEANUP_INTERVAL_SECS")
+    .ok()
+    .and_then(|x| x.parse::<u64>().ok())
+    .unwrap_or(3600);
+
+    /// number of completed jobs deleted per transaction by the cleanup
+    static ref COMPLETED_JOB_CLEANUP_BATCH_SIZE: i64 = std::env::var("COMPLETED_JOB_CLEANUP_BATCH_SIZE")
+    .ok()
+    .and_then(|x| x.parse::<i64>().ok())
+    .filter(|x| *x > 0)
+    .unwrap_or(1000);
+
+    /// when set, the results and logs of the cleaned up jobs are first archived to the instance
+    /// object storage, if one is configured
+    static ref COMPLETED_JOB_CLEANUP_ARCHIVE: bool = std::env::var("COMPLETED_JOB_CLEANUP_ARCHIVE")
+    .ok()
+    .and_then(|x| x.parse::<bool>().ok())
+    .unwrap_or(false);
+
 }
 
 pub async fn initial_load(
@@ -141,6 +203,8 @@ pub async fn initial_load(
 
     if server_mode {
         load_require_preexisting_user(db).await;
+        // reports an invalid WORKER_ZOMBIE_TIMEOUT at startup rather than at the first zombie check
+        lazy_static::initialize(&WORKER_ZOMBIE_TIMEOUT);
     }
 
     if worker_mode {
@@ -607,16 +671,29 @@ pub async fn delete_expired_items(db: &DB) -> () {
         Err(e) => tracing::error!("Error deleting cache resource {}", e.to_string()),
     }
 
+    // the winner of a hedged job is only needed while replicas of the job can still complete
+    let deleted_hedge_winners: std::result::Result<Vec<Uuid>, _> = sqlx::query_scalar(
+        "DELETE FROM hedge_group_winner w WHERE completed_at <= now() - interval '1 hour'
+        AND NOT EXISTS (SELECT 1 FROM queue WHERE hedge_group = w.group_id) RETURNING group_id",
+    )
+    .fetch_all(db)
+    .await;
+
+    match deleted_hedge_winners {
+        Ok(res) => {
+            if res.len() > 0 {
+                tracing::info!("deleted {} settled hedged jobs: {:?}", res.len(), res)
+            }
+        }
+        Err(e) => tracing::error!("Error deleting settled hedged jobs: {}", e.to_string()),
+    }
+
     let job_retention_secs = *JOB_RETENTION_SECS.read().await;
     if job_retention_secs > 0 {
         match db.begin().await {
             Ok(mut tx) => {
-                let deleted_jobs = sqlx::query_scalar!(
-                            "DELETE FROM completed_job WHERE created_at <= now() - ($1::bigint::text || ' s')::interval  AND started_at + ((duration_ms/1000 + $1::bigint) || ' s')::interval <= now() RETURNING id",
-                            job_retention_secs
-                        )
-                        .fetch_all(&mut *tx)
-                        .await;
+                let deleted_jobs =
+                    delete_expired_jobs(&mut tx, None, job_retention_secs, None).await;
 
                 match deleted_jobs {
                     Ok(deleted_jobs) => {
@@ -627,24 +704,6 @@ pub async fn delete_expired_items(db: &DB) -> () {
                                 job_retention_secs,
                                 deleted_jobs,
                             );
-                            if let Err(e) = sqlx::query!(
-                                "DELETE FROM job_stats WHERE job_id = ANY($1)",
-                                &deleted_jobs
-                            )
-                            .execute(&mut *tx)
-                            .await
-                            {
-                                tracing::error!("Error deleting job stats: {:?}", e);
-                            }
-                            if let Err(e) = sqlx::query!(
-                                "DELETE FROM job_logs WHERE job_id = ANY($1)",
-                                &deleted_jobs
-                            )
-                            .execute(&mut *tx)
-                            .await
-                            {
-                                tracing::error!("Error deleting job stats: {:?}", e);
-                            }
                             if let Err(e) = sqlx::query!(
                                 "DELETE FROM concurrency_key WHERE  ended_at <= now() - ($1::bigint::text || ' s')::interval ",
                                 job_retention_secs
@@ -682,6 +741,227 @@ pub async fn delete_expired_items(db: &DB) -> () {
     }
 }
 
+/// Delete the jobs, of `w_id` or of every workspace, that completed more than `retention_secs` ago
+/// along with their stats, logs and profiles, and return their ids. The `created_at` bound lets
+/// postgres use the created_at indexes before checking the completion time of each job
+async fn delete_expired_jobs(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    w_id: Option<&str>,
+    retention_secs: i64,
+    limit: Option<i64>,
+) -> sqlx::Result<Vec<Uuid>> {
+    let ids = sqlx::query_scalar::<_, Uuid>(
+        "DELETE FROM completed_job WHERE id IN (SELECT id FROM completed_job
+            WHERE created_at <= now() - ($1::bigint::text || ' s')::interval
+            AND started_at + ((duration_ms/1000 + $1::bigint) || ' s')::interval <= now()
+            AND ($2::varchar IS NULL OR workspace_id = $2)
+            ORDER BY created_at LIMIT $3)
+        RETURNING id",
+    )
+    .bind(retention_secs)
+    .bind(w_id)
+    .bind(limit)
+    .fetch_all(&mut **tx)
+    .await?;
+    if ids.is_empty() {
+        return Ok(ids);
+    }
+
+    sqlx::query("DELETE FROM job_stats WHERE job_id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM job_logs WHERE job_id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query("DELETE FROM job_profile WHERE job_id = ANY($1)")
+        .bind(&ids)
+        .execute(&mut **tx)
+        .await?;
+    Ok(ids)
+}
+
+/// advisory lock held by the worker running the completed job cleanup, so that a single worker of
+/// the instance runs it at a time
+const COMPLETED_JOB_CLEANUP_LOCK_ID: i64 = 4243;
+
+/// Periodically delete the completed jobs that are older than the `completed_job_retention_secs`
+/// of their workspace. Started on every worker process but only one of them runs each cleanup
+pub async fn cleanup_completed_jobs_periodically(
+    db: DB,
+    mut killpill_rx: tokio::sync::broadcast::Receiver<()>,
+) {
+    if *COMPLETED_JOB_CLEANUP_INTERVAL_SECS == 0 {
+        return;
+    }
+    loop {
+        tokio::select! {
+            _ = killpill_rx.recv() => return,
+            _ = tokio::time::sleep(Duration::from_secs(*COMPLETED_JOB_CLEANUP_INTERVAL_SECS)) => (),
+        }
+        if let Err(e) = cleanup_completed_jobs(&db, &mut killpill_rx).await {
+            tracing::error!("Error cleaning up completed jobs: {e:#}");
+        }
+    }
+}
+
+async fn cleanup_completed_jobs(
+    db: &DB,
+    killpill_rx: &mut tokio::sync::broadcast::Receiver<()>,
+) -> error::Result<()> {
+    // advisory locks are held by the session so the lock and unlock must use the same connection
+    let mut conn = db.acquire().await?;
+    let locked = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)")
+        .bind(COMPLETED_JOB_CLEANUP_LOCK_ID)
+        .fetch_one(&mut *conn)
+        .await?;
+    if !locked {
+        tracing::debug!("completed job cleanup already running on another worker");
+        return Ok(());
+    }
+
+    let r = cleanup_completed_jobs_locked(db, killpill_rx).await;
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(COMPLETED_JOB_CLEANUP_LOCK_ID)
+        .execute(&mut *conn)
+        .await?;
+    r
+}
+
+async fn cleanup_completed_jobs_locked(
+    db: &DB,
+    killpill_rx: &mut tokio::sync::broadcast::Receiver<()>,
+) -> error::Result<()> {
+    let retentions = sqlx::query_as::<_, (String, i32)>(
+        "SELECT workspace_id, completed_job_retention_secs FROM workspace_settings
+        WHERE completed_job_retention_secs > 0",
+    )
+    .fetch_all(db)
+    .await?;
+
+    for (w_id, retention_secs) in retentions {
+        let mut deleted = 0;
+        loop {
+            if killpill_rx.try_recv().is_ok() {
+                tracing::info!("received killpill, stopping the completed job cleanup");
+                return Ok(());
+            }
+
+            let mut tx = db.begin().await?;
+            let ids = delete_expired_jobs(
+                &mut tx,
+                Some(&w_id),
+                retention_secs as i64,
+                Some(*COMPLETED_JOB_CLEANUP_BATCH_SIZE),
+            )
+            .await?;
+            if ids.is_empty() {
+                break;
+            }
+
+            // the deletion is not committed yet so the archive still reads the jobs
+            if *COMPLETED_JOB_CLEANUP_ARCHIVE {
+                if let Err(e) = archive_completed_jobs(db, &w_id, &ids).await {
+                    // keep the jobs rather than losing their results and logs
+                    tracing::error!(
+                        "Error archiving completed jobs of workspace {w_id}, skipping their cleanup: {e:#}"
+                    );
+                    tx.rollback().await?;
+                    break;
+                }
+            }
+            tx.commit().await?;
+
+            deleted += ids.len();
+            if (ids.len() as i64) < *COMPLETED_JOB_CLEANUP_BATCH_SIZE {
+                break;
+            }
+        }
+        if deleted > 0 {
+            tracing::info!(
+                "deleted {deleted} jobs of workspace {w_id} completed more than {retention_secs}s ago"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Put the results and logs of the jobs to `completed_jobs_archive/<workspace>/` in the instance
+/// object storage
+#[cfg(feature = "parquet")]
+async fn archive_completed_jobs(db: &DB, w_id: &str, ids: &[Uuid]) -> error::Result<()> {
+    let Some(os) = OBJECT_STORE_CACHE_SETTINGS.read().await.clone() else {
+        return Err(error::Error::BadConfig(
+            "COMPLETED_JOB_CLEANUP_ARCHIVE is set but no object storage is configured".to_string(),
+        ));
+    };
+    let mut archive = sqlx::query_scalar::<_, Option<serde_json::Value>>(
+        "SELECT json_agg(json_build_object('id', c.id, 'created_at', c.created_at,
+            'started_at', c.started_at, 'duration_ms', c.duration_ms, 'success', c.success,
+            'script_path', c.script_path, 'result', c.result, 'logs', l.logs))
+        FROM completed_job c LEFT JOIN job_logs l ON l.job_id = c.id
+        WHERE c.id = ANY($1)",
+    )
+    .bind(ids)
+    .fetch_one(db)
+    .await?
+    .unwrap_or_else(|| serde_json::json!([]));
+    let compressed_results = sqlx::query_as::<_, (Uuid, Vec<u8>)>(
+        "SELECT id, result_compressed FROM completed_job
+        WHERE id = ANY($1) AND result_compressed IS NOT NULL",
+    )
+    .bind(ids)
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .collect::<HashMap<_, _>>();
+    // archived as plain text, the compressed chunks of the logs and compressed results being only
+    // readable by windmill
+    if let Some(jobs) = archive.as_array_mut() {
+        for job in jobs.iter_mut() {
+            if let Some(serde_json::Value::String(logs)) = job.get_mut("logs") {
+                *logs = windmill_common::log_compression::decompress_log_chunks(logs).into_owned();
+            }
+            let compressed_result = job
+                .get("id")
+                .and_then(|id| id.as_str())
+                .and_then(|id| Uuid::parse_str(id).ok())
+                .and_then(|id| compressed_results.get(&id));
+            if let Some(compressed_result) = compressed_result {
+                job["result"] =
+                    windmill_common::result_compression::decompress_result(compressed_result)
+                        .and_then(|x| serde_json::from_str(x.get()).ok())
+                        .unwrap_or_default();
+            }
+        }
+    }
+
+    let path = format!(
+        "completed_jobs_archive/{w_id}/{}_{}.json",
+        Utc::now().format("%Y%m%dT%H%M%S"),
+        ids[0]
+    );
+    os.put(
+        &object_store::path::Path::from(path.clone()),
+        serde_json::to_vec(&archive)
+            .map_err(|e| error::Error::InternalErr(format!("serializing archive: {e}")))?
+            .into(),
+    )
+    .await
+    .map_err(|e| error::Error::InternalErr(format!("putting {path} to object storage: {e}")))?;
+    Ok(())
+}
+
+#[cfg(not(feature = "parquet"))]
+async fn archive_completed_jobs(_db: &DB, _w_id: &str, _ids: &[Uuid]) -> error::Result<()> {
+    Err(error::Error::BadConfig(
+        "COMPLETED_JOB_CLEANUP_ARCHIVE requires the parquet feature to archive to object storage"
+            .to_string(),
+    ))
+}
+
 pub async fn reload_scim_token_setting(db: &DB) {
     reload_option_setting_with_tracing(db, SCIM_TOKEN_SETTING, "SCIM_TOKEN", SCIM_TOKEN.clone())
         .await;
@@ -1022,6 +1302,18 @@ pub async fn monitor_db(
         }
     };
 
+    let compute_budget_f = async {
+        if server_mode && !initial_load {
+            cancel_jobs_of_exhausted_compute_budgets(db, rsmq.clone()).await;
+        }
+    };
+
+    let queue_wait_f = async {
+        if server_mode && !initial_load {
+            fail_jobs_exceeding_queue_wait(db, rsmq.clone()).await;
+        }
+    };
+
     let verify_license_key_f = async {
         #[cfg(feature = "enterprise")]
         if !initial_load {
@@ -1068,6 +1360,8 @@ pub async fn monitor_db(
     join!(
         expired_items_f,
         zombie_jobs_f,
+        compute_budget_f,
+        queue_wait_f,
         expose_queue_metrics_f,
         verify_license_key_f,
         worker_groups_alerts_f,
@@ -1271,12 +1565,17 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
     worker_name: &str,
 ) {
     if *RESTART_ZOMBIE_JOBS {
-        let restarted = sqlx::query!(
-                "UPDATE queue SET running = false, started_at = null
-                WHERE last_ping < now() - ($1 || ' seconds')::interval
+        if let Some(worker_zombie_timeout) = WORKER_ZOMBIE_TIMEOUT.as_ref() {
+            restart_jobs_of_dead_workers(db, worker_zombie_timeout).await;
+        }
+
+        let restarted = sqlx::query_as::<_, (Uuid, String, Option<chrono::DateTime<Utc>>)>(
+                "UPDATE queue SET running = false, started_at = null, installing_deps = false
+                WHERE last_ping < now() - ((CASE WHEN installing_deps THEN $2 ELSE $1 END) || ' seconds')::interval
                  AND running = true AND job_kind NOT IN ('flow', 'flowpreview', 'singlescriptflow') AND same_worker = false RETURNING id, workspace_id, last_ping",
-                *ZOMBIE_JOB_TIMEOUT,
             )
+            .bind(ZOMBIE_JOB_TIMEOUT.as_str())
+            .bind(ZOMBIE_JOB_INSTALL_TIMEOUT.as_str())
             .fetch_all(db)
             .await
             .ok()
@@ -1286,21 +1585,21 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
             QUEUE_ZOMBIE_RESTART_COUNT.inc_by(restarted.len() as _);
         }
         let base_url = BASE_URL.read().await.clone();
-        for r in restarted {
-            let last_ping = if let Some(x) = r.last_ping {
+        for (id, workspace_id, last_ping) in restarted {
+            let last_ping = if let Some(x) = last_ping {
                 format!("last ping at {x}")
             } else {
                 "no last ping".to_string()
             };
-            let url = format!("{}/run/{}?workspace={}", base_url, r.id, r.workspace_id,);
+            let url = format!("{}/run/{}?workspace={}", base_url, id, workspace_id,);
             let error_message = format!(
                 "Zombie job {} on {} ({}) detected, restarting it, {}",
-                r.id, r.workspace_id, url, last_ping
+                id, workspace_id, url, last_ping
             );
 
             let _ = sqlx::query!("
                 INSERT INTO job_logs (job_id, logs) VALUES ($1,'Restarted job after not receiving job''s ping for too long the ' || now() || '\n\n') 
-                ON CONFLICT (job_id) DO UPDATE SET logs = job_logs.logs || '\nRestarted job after not receiving job''s ping for too long the ' || now() || '\n\n' WHERE job_logs.job_id = $1", r.id)
+                ON CONFLICT (job_id) DO UPDATE SET logs = job_logs.logs || '\nRestarted job after not receiving job''s ping for too long the ' || now() || '\n\n' WHERE job_logs.job_id = $1", id)
                 .execute(db).await;
             tracing::error!(error_message);
             report_critical_error(error_message, db.clone()).await;
@@ -1308,7 +1607,7 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
     }
 
     let mut timeout_query =
-        "SELECT * FROM queue WHERE last_ping < now() - ($1 || ' seconds')::interval 
+        "SELECT * FROM queue WHERE last_ping < now() - ((CASE WHEN installing_deps THEN $2 ELSE $1 END) || ' seconds')::interval
     AND running = true  AND job_kind NOT IN ('flow', 'flowpreview', 'singlescriptflow')"
             .to_string();
     if *RESTART_ZOMBIE_JOBS {
@@ -1316,6 +1615,7 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
     };
     let timeouts = sqlx::query_as::<_, QueuedJob>(&timeout_query)
         .bind(ZOMBIE_JOB_TIMEOUT.as_str())
+        .bind(ZOMBIE_JOB_INSTALL_TIMEOUT.as_str())
         .fetch_all(db)
         .await
         .ok()
@@ -1369,11 +1669,12 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
             0,
             None,
             error::Error::ExecutionErr(format!(
-                "Job timed out after no ping from job since {} (ZOMBIE_JOB_TIMEOUT: {})",
+                "Job timed out after no ping from job since {} (ZOMBIE_JOB_TIMEOUT: {}, ZOMBIE_JOB_INSTALL_TIMEOUT: {})",
                 last_ping
                     .map(|x| x.to_string())
                     .unwrap_or_else(|| "no ping".to_string()),
-                *ZOMBIE_JOB_TIMEOUT
+                *ZOMBIE_JOB_TIMEOUT,
+                *ZOMBIE_JOB_INSTALL_TIMEOUT
             )),
             true,
             same_worker_tx_never_used,
@@ -1388,6 +1689,47 @@ async fn handle_zombie_jobs<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
     }
 }
 
+/// Restart the jobs claimed by workers whose own ping is stale (e.g. after an OOM kill or SIGKILL).
+/// The job -> worker link is the `current_job_id` of the worker ping, which is cleared so that the
+/// job is not restarted again once picked up by another worker. That link can be stale (the job was
+/// requeued before the worker died and is now running elsewhere), so the job itself must also have
+/// stopped pinging.
+async fn restart_jobs_of_dead_workers(db: &Pool<Postgres>, worker_zombie_timeout: &str) {
+    let restarted = sqlx::query_as::<_, (Uuid, String, String)>(
+        "WITH dead AS (
+            SELECT worker, current_job_id FROM worker_ping
+            WHERE ping_at < now() - ($1 || ' seconds')::interval AND current_job_id IS NOT NULL
+            FOR UPDATE
+        ), cleared AS (
+            UPDATE worker_ping SET current_job_id = NULL FROM dead WHERE worker_ping.worker = dead.worker
+        )
+        UPDATE queue SET running = false, started_at = null, installing_deps = false FROM dead
+        WHERE queue.id = dead.current_job_id AND queue.running = true
+            AND queue.last_ping < now() - ($1 || ' seconds')::interval
+            AND queue.job_kind NOT IN ('flow', 'flowpreview', 'singlescriptflow') AND queue.same_worker = false
+        RETURNING queue.id, queue.workspace_id, dead.worker",
+    )
+    .bind(worker_zombie_timeout)
+    .fetch_all(db)
+    .await
+    .unwrap_or_else(|e| {
+        tracing::error!("Error restarting the jobs of dead workers: {e:#}");
+        vec![]
+    });
+
+    if METRICS_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        QUEUE_ZOMBIE_RESTART_COUNT.inc_by(restarted.len() as _);
+    }
+    for (job_id, w_id, worker) in restarted {
+        let error_message = format!(
+            "Job {job_id} on {w_id} was running on worker {worker} which has not pinged for more than {worker_zombie_timeout}s (WORKER_ZOMBIE_TIMEOUT), restarting it"
+        );
+        append_logs(&job_id, &w_id, format!("\n{error_message}\n\n"), db).await;
+        tracing::error!(error_message);
+        report_critical_error(error_message, db.clone()).await;
+    }
+}
+
 async fn handle_zombie_flows(
     db: &DB,
     rsmq: Option<rsmq_async::MultiplexedRsmq>,
@@ -1478,6 +1820,126 @@ async fn handle_zombie_flows(
     Ok(())
 }
 
+/// Cancel the running jobs of the workspaces whose compute budget is exhausted, the flag being set
+/// by the external accounting. Their queued jobs are left as is, the workers not pulling them
+/// until the budget resets
+async fn cancel_jobs_of_exhausted_compute_budgets(
+    db: &Pool<Postgres>,
+    rsmq: Option<MultiplexedRsmq>,
+) {
+    let jobs = sqlx::query_as::<_, (Uuid, String)>(
+        "SELECT id, workspace_id FROM queue WHERE running = true AND canceled = false AND parent_job IS NULL
+            AND workspace_id IN (SELECT workspace_id FROM workspace_settings WHERE compute_budget_exhausted)",
+    )
+    .fetch_all(db)
+    .await;
+    let jobs = match jobs {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            tracing::error!("Error fetching the jobs of exhausted compute budgets: {e:#}");
+            return;
+        }
+    };
+    for (id, w_id) in jobs {
+        tracing::info!("compute budget of workspace {w_id} exhausted, canceling job {id}");
+        let r = async {
+            let tx = db.begin().await?;
+            let (tx, _) = cancel_job(
+                "monitor",
+                Some("workspace compute budget exhausted".to_string()),
+                id,
+                &w_id,
+                tx,
+                db,
+                rsmq.clone(),
+                false,
+                false,
+            )
+            .await?;
+            tx.commit().await?;
+            Ok::<_, error::Error>(())
+        }
+        .await;
+        if let Err(e) = r {
+            tracing::error!("Error canceling job {id} of exhausted compute budget: {e:#}");
+        }
+    }
+}
+
+const QUEUE_WAIT_EXCEEDED_REASON: &str = "queue wait exceeded";
+
+/// Fail the jobs not started within the max queue wait (of their workspace or MAX_QUEUE_WAIT_SECS)
+/// past their scheduled time, rather than running them long after they were relevant. Only the
+/// top-level jobs are failed, the flow steps being bounded by their flow
+async fn fail_jobs_exceeding_queue_wait(db: &Pool<Postgres>, rsmq: Option<MultiplexedRsmq>) {
+    // canceled first so that a worker pulling one of the jobs concurrently fails it right away
+    let jobs = sqlx::query_as::<_, QueuedJob>(
+        "UPDATE queue SET canceled = true, canceled_by = 'monitor', canceled_reason = $2
+        FROM (
+            SELECT q.id FROM queue q
+            LEFT JOIN workspace_settings ws ON ws.workspace_id = q.workspace_id
+            WHERE q.running = false AND q.canceled = false AND q.parent_job IS NULL
+                AND q.scheduled_for < now() - COALESCE(ws.max_queue_wait_secs, $1) * interval '1 second'
+            LIMIT 100
+            FOR UPDATE OF q SKIP LOCKED
+        ) overdue
+        WHERE queue.id = overdue.id
+        RETURNING queue.*",
+    )
+    .bind(*MAX_QUEUE_WAIT_SECS)
+    .bind(QUEUE_WAIT_EXCEEDED_REASON)
+    .fetch_all(db)
+    .await;
+    let jobs = match jobs {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            tracing::error!("Error fetching the jobs exceeding their max queue wait: {e:#}");
+            return;
+        }
+    };
+
+    if METRICS_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        QUEUE_WAIT_EXCEEDED_COUNT.inc_by(jobs.len() as _);
+    }
+    for job in jobs {
+        let message = format!(
+            "Job was not started within the max queue wait after its scheduled time ({}), it was failed instead of running late",
+            job.scheduled_for
+        );
+        tracing::warn!("job {} in {}: {message}", job.id, job.workspace_id);
+        append_logs(&job.id, &job.workspace_id, format!("\n{message}\n"), db).await;
+        let e = serde_json::json!({
+            "message": message,
+            "name": "QueueWaitExceeded",
+            "reason": QUEUE_WAIT_EXCEEDED_REASON,
+            "canceler": "monitor",
+        });
+        if let Err(e) = add_completed_job_error(
+            db,
+            &job,
+            0,
+            Some(CanceledBy {
+                username: Some("monitor".to_string()),
+                reason: Some(QUEUE_WAIT_EXCEEDED_REASON.to_string()),
+            }),
+            JobRunRecords::default(),
+            e,
+            rsmq.clone(),
+            "monitor",
+            false,
+            #[cfg(feature = "benchmark")]
+            &mut windmill_common::bench::BenchmarkIter::new(),
+        )
+        .await
+        {
+            tracing::error!(
+                "Error failing job {} exceeding its max queue wait: {e:#}",
+                job.id
+            );
+        }
+    }
+}
+
 async fn cancel_zombie_flow_job(
     db: &Pool<Postgres>,
     flow: QueuedJob,
@@ -1603,3 +2065,72 @@ pub async fn reload_jwt_secret_setting(db: &DB) -> error::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_running_job(db: &DB, last_ping_secs_ago: i64) -> Uuid {
+        let job_id = Uuid::new_v4();
+        sqlx::query(
+            "INSERT INTO queue (id, workspace_id, created_by, scheduled_for, running, started_at, last_ping)
+             VALUES ($1, 'test-workspace', 'test-user', now(), true, now(), now() - ($2 || ' seconds')::interval)",
+        )
+        .bind(job_id)
+        .bind(last_ping_secs_ago.to_string())
+        .execute(db)
+        .await
+        .unwrap();
+        job_id
+    }
+
+    async fn insert_worker_ping(db: &DB, worker: &str, ping_secs_ago: i64, job_id: Uuid) {
+        sqlx::query(
+            "INSERT INTO worker_ping (worker, worker_instance, ping_at, current_job_id)
+             VALUES ($1, 'test-instance', now() - ($2 || ' seconds')::interval, $3)",
+        )
+        .bind(worker)
+        .bind(ping_secs_ago.to_string())
+        .bind(job_id)
+        .execute(db)
+        .await
+        .unwrap();
+    }
+
+    async fn is_running(db: &DB, job_id: Uuid) -> bool {
+        sqlx::query_scalar::<_, bool>("SELECT running FROM queue WHERE id = $1")
+            .bind(job_id)
+            .fetch_one(db)
+            .await
+            .unwrap()
+    }
+
+    #[sqlx::test(fixtures(path = "../tests/fixtures", scripts("base")))]
+    async fn test_restart_jobs_of_dead_workers(db: DB) {
+        let job_id = insert_running_job(&db, 300).await;
+        insert_worker_ping(&db, "dead-worker", 300, job_id).await;
+
+        restart_jobs_of_dead_workers(&db, "60").await;
+
+        assert!(!is_running(&db, job_id).await);
+    }
+
+    #[sqlx::test(fixtures(path = "../tests/fixtures", scripts("base")))]
+    async fn test_restart_jobs_of_dead_workers_requeued_job(db: DB) {
+        // requeued on termination notice by worker a before it died, then claimed by worker b
+        let job_id = insert_running_job(&db, 0).await;
+        insert_worker_ping(&db, "worker-a", 300, job_id).await;
+        insert_worker_ping(&db, "worker-b", 0, job_id).await;
+
+        restart_jobs_of_dead_workers(&db, "60").await;
+
+        assert!(is_running(&db, job_id).await);
+        let current_job_id = sqlx::query_scalar::<_, Option<Uuid>>(
+            "SELECT current_job_id FROM worker_ping WHERE worker = 'worker-a'",
+        )
+        .fetch_one(&db)
+        .await
+        .unwrap();
+        assert_eq!(current_job_id, None);
+    }
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{str::FromStr, sync::Arc};
 use windmill_api_client::types::{NewScript, NewScriptLanguage};
 
 #[cfg(feature = "enterprise")]
@@ -29,7 +29,7 @@ use windmill_common::{
     jobs::{JobKind, JobPayload, RawCode},
     scripts::{ScriptHash, ScriptLang},
 };
-use windmill_queue::PushIsolationLevel;
+use windmill_queue::{JobQueue, PostgresJobQueue, PushIsolationLevel};
 
 #[derive(Debug, sqlx::FromRow, Serialize)]
 pub struct CompletedJob {
@@ -1028,6 +1028,10 @@ fn spawn_test_worker(
             tx2,
             &base_internal_url,
             None,
+            Arc::new(PostgresJobQueue::new(
+                db.clone(),
+                None::<rsmq_async::MultiplexedRsmq>,
+            )),
             false,
         )
         .await
@@ -1823,6 +1827,193 @@ echo "hello $msg"
     assert_eq!(job.json_result(), Some(json!("hello world")));
 }
 
+#[sqlx::test(fixtures("base"))]
+async fn test_bash_job_inline(db: Pool<Postgres>) {
+    initialize_tracing().await;
+
+    let job = windmill_common::jobs::QueuedJob {
+        id: Uuid::new_v4(),
+        workspace_id: "test-workspace".to_string(),
+        job_kind: JobKind::Preview,
+        language: Some(ScriptLang::Bash),
+        raw_code: Some(
+            r#"
+msg="$1"
+echo "hello $msg"
+"#
+            .to_string(),
+        ),
+        args: Some(sqlx::types::Json(std::collections::HashMap::from([(
+            "msg".to_string(),
+            serde_json::value::to_raw_value(&json!("world")).unwrap(),
+        )]))),
+        ..Default::default()
+    };
+    let config = windmill_worker::InlineJobConfig {
+        worker_name: next_worker_name(),
+        worker_dir: std::env::temp_dir()
+            .join("windmill-inline")
+            .to_string_lossy()
+            .to_string(),
+        base_internal_url: "http://localhost".to_string(),
+        token: "".to_string(),
+    };
+    let job_queue: Arc<dyn JobQueue> = Arc::new(PostgresJobQueue::new(
+        db.clone(),
+        None::<rsmq_async::MultiplexedRsmq>,
+    ));
+    let r = windmill_worker::execute_job_inline(&job, &db, &job_queue, &config)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(r.result.unwrap().get()).unwrap(),
+        json!("hello world")
+    );
+    assert!(r.logs.contains("hello world"));
+    let persisted_logs =
+        sqlx::query_scalar::<_, i64>("SELECT count(*) FROM job_logs WHERE job_id = $1")
+            .bind(job.id)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+    assert_eq!(persisted_logs, 0);
+}
+
+#[sqlx::test(fixtures("base"))]
+async fn test_add_completed_job_is_complete(db: Pool<Postgres>) {
+    initialize_tracing().await;
+
+    let push_job = || {
+        RunJob::from(JobPayload::Code(RawCode {
+            hash: None,
+            content: "echo hello".to_string(),
+            path: None,
+            language: ScriptLang::Bash,
+            lock: None,
+            custom_concurrency_key: None,
+            concurrent_limit: None,
+            concurrency_time_window_s: None,
+            cache_ttl: None,
+            dedicated_worker: None,
+        }))
+        .push(&db)
+    };
+
+    // a job that left the queue without being completed is not reported as complete
+    let removed = push_job().await;
+    sqlx::query("DELETE FROM queue WHERE id = $1")
+        .bind(removed)
+        .execute(&db)
+        .await
+        .unwrap();
+    assert!(
+        !windmill_queue::job_is_complete(&db, removed, "test-workspace")
+            .await
+            .unwrap()
+    );
+
+    let completed = push_job().await;
+    let job = windmill_queue::get_queued_job(&completed, "test-workspace", &db)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(
+        !windmill_queue::job_is_complete(&db, completed, "test-workspace")
+            .await
+            .unwrap()
+    );
+    windmill_queue::add_completed_job(
+        &db,
+        &job,
+        true,
+        false,
+        sqlx::types::Json(&json!("hello")),
+        0,
+        None,
+        windmill_queue::JobRunRecords::default(),
+        None::<rsmq_async::MultiplexedRsmq>,
+        false,
+    )
+    .await
+    .unwrap();
+    assert!(
+        windmill_queue::job_is_complete(&db, completed, "test-workspace")
+            .await
+            .unwrap()
+    );
+    assert_eq!(
+        completed_job(completed, &db).await.json_result(),
+        Some(json!("hello"))
+    );
+}
+
+#[sqlx::test(fixtures("base"))]
+async fn test_fair_workspace_scheduling(db: Pool<Postgres>) {
+    initialize_tracing().await;
+
+    sqlx::query(
+        "INSERT INTO workspace (id, name, owner) VALUES ('test-workspace-2', 'test-workspace-2', 'test-user')",
+    )
+    .execute(&db)
+    .await
+    .unwrap();
+
+    let bash_job = || {
+        RunJob::from(JobPayload::Code(RawCode {
+            hash: None,
+            content: "echo hello".to_string(),
+            path: None,
+            lock: None,
+            language: ScriptLang::Bash,
+            custom_concurrency_key: None,
+            concurrent_limit: None,
+            concurrency_time_window_s: None,
+            cache_ttl: None,
+            dedicated_worker: None,
+        }))
+    };
+
+    // a burst of 3 jobs in the first workspace, then 3 jobs in the second one
+    let mut burst = vec![];
+    for _ in 0..3 {
+        burst.push(bash_job().push(&db).await);
+    }
+    let mut others = vec![];
+    for _ in 0..3 {
+        others.push(bash_job().push(&db).await);
+    }
+    sqlx::query("UPDATE queue SET workspace_id = 'test-workspace-2' WHERE id = ANY($1)")
+        .bind(&others)
+        .execute(&db)
+        .await
+        .unwrap();
+
+    let query = windmill_common::worker::make_pull_query_for_tags(
+        &windmill_common::worker::DEFAULT_TAGS,
+        true,
+    );
+    let mut pulled = vec![];
+    for _ in 0..6 {
+        let job = sqlx::query_as::<_, windmill_common::jobs::QueuedJob>(&query)
+            .fetch_one(&db)
+            .await
+            .unwrap();
+        pulled.push(job.workspace_id);
+    }
+    assert_eq!(
+        pulled,
+        [
+            "test-workspace",
+            "test-workspace-2",
+            "test-workspace",
+            "test-workspace-2",
+            "test-workspace",
+            "test-workspace-2"
+        ]
+    );
+}
+
 #[sqlx::test(fixtures("base"))]
 async fn test_python_job(db: Pool<Postgres>) {
     initialize_tracing().await;
@@ -1856,6 +2047,43 @@ def main():
     assert_eq!(result, serde_json::json!("hello world"));
 }
 
+#[sqlx::test(fixtures("base"))]
+async fn test_python_job_large_integer(db: Pool<Postgres>) {
+    initialize_tracing().await;
+    let server = ApiServer::start(db.clone()).await;
+    let port = server.addr.port();
+
+    let content = r#"
+def main():
+    return {"id": 2**70 + 1, "twitter_id": 1460323737035677698}
+        "#
+    .to_owned();
+
+    let job = JobPayload::Code(RawCode {
+        hash: None,
+        content,
+        path: None,
+        language: ScriptLang::Python3,
+        lock: None,
+        custom_concurrency_key: None,
+        concurrent_limit: None,
+        concurrency_time_window_s: None,
+        cache_ttl: None,
+        dedicated_worker: None,
+    });
+
+    let result = run_job_in_new_worker_until_complete(&db, job, port)
+        .await
+        .result
+        .expect("job should have a result");
+
+    // the result is kept as raw json all the way to the database so integers beyond 2^53
+    // (and beyond u64) must not go through a f64 conversion
+    let raw = result.get().replace(' ', "");
+    assert!(raw.contains(r#""id":1180591620717411303425"#), "{raw}");
+    assert!(raw.contains(r#""twitter_id":1460323737035677698"#), "{raw}");
+}
+
 #[sqlx::test(fixtures("base"))]
 async fn test_python_job_heavy_dep(db: Pool<Postgres>) {
     initialize_tracing().await;
@@ -1892,6 +2120,48 @@ def main():
     assert_eq!(result, serde_json::json!(3));
 }
 
+#[sqlx::test(fixtures("base"))]
+async fn test_python_job_dependency_timeout(db: Pool<Postgres>) {
+    initialize_tracing().await;
+    let server = ApiServer::start(db.clone()).await;
+    let port = server.addr.port();
+
+    let content = r#"
+import numpy as np
+
+def main():
+    return 1
+        "#
+    .to_owned();
+
+    let job = JobPayload::Code(RawCode {
+        hash: None,
+        content,
+        path: None,
+        language: ScriptLang::Python3,
+        lock: None,
+        custom_concurrency_key: None,
+        concurrent_limit: None,
+        concurrency_time_window_s: None,
+        cache_ttl: None,
+        dedicated_worker: None,
+    });
+
+    let completed = RunJob::from(job)
+        .arg("_DEPENDENCY_TIMEOUT", json!(0))
+        .run_until_complete(&db, port)
+        .await;
+
+    assert!(!completed.success);
+    let result = completed.json_result().unwrap();
+    let message = result["error"]["message"].as_str().unwrap();
+    assert!(
+        message.contains("timeout after exceeding job-specific dependency install duration limit"),
+        "{message}"
+    );
+    assert!(message.contains("no user code was run"), "{message}");
+}
+
 #[sqlx::test(fixtures("base"))]
 async fn test_python_job_with_imports(db: Pool<Postgres>) {
     initialize_tracing().await;
@@ -1961,6 +2231,49 @@ export async function main(a: Date) {
     assert_eq!(result, serde_json::json!("object"));
 }
 
+#[sqlx::test(fixtures("base"))]
+async fn test_deno_job_out_of_memory_classification(db: Pool<Postgres>) {
+    initialize_tracing().await;
+    let server = ApiServer::start(db.clone()).await;
+    let port = server.addr.port();
+
+    // the heap limit is not actually reached: the script prints what V8 prints before aborting
+    // and exits like it, which tests how such a failure is reported, not DENO_MAX_HEAP_SIZE_MB
+    let content = r#"
+export async function main() {
+    console.error("FATAL ERROR: Reached heap limit Allocation failed - JavaScript heap out of memory");
+    Deno.exit(134);
+}
+        "#
+    .to_owned();
+
+    let job = RunJob::from(JobPayload::Code(RawCode {
+        hash: None,
+        content,
+        path: None,
+        lock: None,
+        language: ScriptLang::Deno,
+        custom_concurrency_key: None,
+        concurrent_limit: None,
+        concurrency_time_window_s: None,
+        cache_ttl: None,
+        dedicated_worker: None,
+    }))
+    .run_until_complete(&db, port)
+    .await;
+
+    assert!(!job.success);
+    let result = job.json_result().unwrap();
+    assert_eq!(result["error"]["name"], json!("OutOfMemoryError"));
+    assert!(
+        result["error"]["message"]
+            .as_str()
+            .unwrap()
+            .contains("heap memory limit of the deno runtime"),
+        "{result}"
+    );
+}
+
 #[sqlx::test(fixtures("base"))]
 async fn test_deno_job_datetime(db: Pool<Postgres>) {
     initialize_tracing().await;
@@ -1995,6 +2308,42 @@ export async function main(a: Date) {
     assert_eq!(result, serde_json::json!("object"));
 }
 
+#[sqlx::test(fixtures("base"))]
+async fn test_python_job_result_token_scrubbed(db: Pool<Postgres>) {
+    initialize_tracing().await;
+    let server = ApiServer::start(db.clone()).await;
+    let port = server.addr.port();
+
+    let content = r#"
+import os
+def main():
+    return {"nested": {"token": os.environ["WM_TOKEN"], "msg": "token=" + os.environ["WM_TOKEN"]}}
+        "#
+    .to_owned();
+
+    let result = RunJob::from(JobPayload::Code(RawCode {
+        hash: None,
+        content,
+        path: None,
+        lock: None,
+        language: ScriptLang::Python3,
+        custom_concurrency_key: None,
+        concurrent_limit: None,
+        concurrency_time_window_s: None,
+        cache_ttl: None,
+        dedicated_worker: None,
+    }))
+    .run_until_complete(&db, port)
+    .await
+    .json_result()
+    .unwrap();
+
+    assert_eq!(
+        result,
+        serde_json::json!({"nested": {"token": "***", "msg": "token=***"}})
+    );
+}
+
 #[sqlx::test(fixtures("base"))]
 async fn test_python_job_datetime_and_bytes(db: Pool<Postgres>) {
     initialize_tracing().await;
@@ -3112,6 +3461,7 @@ async fn test_script_schedule_handlers(db: Pool<Postgres>) {
         summary: None,
         tag: None,
         paused_until: None,
+        overlap_policy: None,
     };
 
     let _ = client.create_schedule("test-workspace", &schedule).await;
@@ -3179,6 +3529,7 @@ async fn test_script_schedule_handlers(db: Pool<Postgres>) {
                 no_flow_overlap: None,
                 tag: None,
                 paused_until: None,
+                overlap_policy: None,
             },
         )
         .await
@@ -3261,6 +3612,7 @@ async fn test_flow_schedule_handlers(db: Pool<Postgres>) {
         summary: None,
         tag: None,
         paused_until: None,
+        overlap_policy: None,
     };
 
     let _ = client.create_schedule("test-workspace", &schedule).await;
@@ -3329,6 +3681,7 @@ async fn test_flow_schedule_handlers(db: Pool<Postgres>) {
                 no_flow_overlap: None,
                 tag: None,
                 paused_until: None,
+                overlap_policy: None,
             },
         )
         .await
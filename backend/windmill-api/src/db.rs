@@ -199,6 +199,13 @@ pub async fn migrate(db: &DB) -> Result<(), Error> {
         }
     });
 
+    let db2 = db.clone();
+    let _ = tokio::task::spawn(async move {
+        if let Err(err) = backfill_script_content_hash(&db2).await {
+            tracing::error!("Could not backfill the content hash of the scripts: {err:#}");
+        }
+    });
+
     Ok(())
 }
 
@@ -492,6 +499,29 @@ async fn fix_job_completed_index(db: &DB) -> Result<(), Error> {
     Ok(())
 }
 
+/// Hash the content of the scripts deployed before the workers started checking it, in batches
+/// to not lock the whole script table
+async fn backfill_script_content_hash(db: &DB) -> Result<(), Error> {
+    run_windmill_migration!("backfill_script_content_hash", &db, {
+        loop {
+            let updated = sqlx::query(
+                "UPDATE script SET content_hash = encode(sha256(convert_to(content, 'UTF8')), 'hex') \
+                 WHERE (workspace_id, hash) IN (SELECT workspace_id, hash FROM script \
+                 WHERE content_hash IS NULL AND deleted = false LIMIT 1000)",
+            )
+            .execute(db)
+            .await?
+            .rows_affected();
+            if updated == 0 {
+                break;
+            }
+            tracing::info!("backfilled the content hash of {updated} scripts");
+        }
+    });
+
+    Ok(())
+}
+
 #[derive(Clone, Debug)]
 pub struct ApiAuthed {
     pub email: String,
@@ -1418,7 +1418,8 @@ mod tests {
                     multiplier: 1,
                     seconds: 123,
                     random_factor: None
-                }
+                },
+                retryable_errors: None,
             },
             serde_json::from_str(
                 r#"
@@ -1442,6 +1443,7 @@ mod tests {
                 seconds: 3,
                 random_factor: None,
             },
+            retryable_errors: None,
         };
         assert_eq!(
             vec![
@@ -1468,6 +1470,7 @@ mod tests {
                 seconds: 3,
                 random_factor: None,
             },
+            retryable_errors: None,
         };
         assert_eq!(
             vec![
@@ -1484,4 +1487,26 @@ mod tests {
 
         assert_eq!(Some(81 * SECOND), retry.max_interval());
     }
+
+    #[test]
+    fn retry_retryable_errors() {
+        let retry = Retry::default();
+        assert!(retry.is_retryable(None));
+        assert!(retry.is_retryable(Some(&serde_json::json!({"name": "Exception"}))));
+        assert!(!retry.is_retryable(Some(
+            &serde_json::json!({"name": "Exception", "idempotent": false})
+        )));
+        assert!(!retry.is_retryable(Some(
+            &serde_json::json!({"name": "Exception", "extra": {"idempotent": false}})
+        )));
+
+        let retry =
+            Retry { retryable_errors: Some(vec!["TimeoutError".to_string()]), ..Retry::default() };
+        assert!(retry.is_retryable(Some(&serde_json::json!({"name": "TimeoutError"}))));
+        assert!(!retry.is_retryable(Some(&serde_json::json!({"name": "ValueError"}))));
+        assert!(!retry.is_retryable(None));
+        assert!(!retry.is_retryable(Some(
+            &serde_json::json!({"name": "TimeoutError", "idempotent": false})
+        )));
+    }
 }
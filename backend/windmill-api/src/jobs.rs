@@ -22,7 +22,7 @@ use windmill_common::error::JsonResult;
 use windmill_common::flow_status::{JobResult, RestartedFrom};
 use windmill_common::jobs::{
     format_completed_job_result, format_result, CompletedJobWithFormattedResult, FormattedResult,
-    ENTRYPOINT_OVERRIDE,
+    ENTRYPOINT_OVERRIDE, STDIN_STREAM_ARG,
 };
 use windmill_common::worker::{CLOUD_HOSTED, TMP_DIR};
 
@@ -67,7 +67,9 @@ use windmill_common::{
     flow_status::{Approval, FlowStatus, FlowStatusModule},
     flows::FlowValue,
     jobs::{script_path_to_payload, CompletedJob, JobKind, JobPayload, QueuedJob, RawCode},
+    log_compression::decompress_log_chunks,
     oauth2::HmacSha256,
+    result_compression::{completed_job_result, decompress_result_at_path},
     scripts::{ScriptHash, ScriptLang},
     users::username_to_permissioned_as,
     utils::{
@@ -204,6 +206,15 @@ pub fn workspaced_service() -> Router {
         .route("/queue/count", get(count_queue_jobs))
         .route("/queue/list_filtered_uuids", get(list_filtered_uuids))
         .route("/queue/cancel_selection", post(cancel_selection))
+        .route(
+            "/queue/cancel_by_correlation_id/:correlation_id",
+            post(cancel_by_correlation_id),
+        )
+        .route(
+            "/list_by_correlation_id/:correlation_id",
+            get(list_jobs_by_correlation_id),
+        )
+        .route("/queue/send_stdin/:id", post(send_job_stdin))
         .route("/completed/count", get(count_completed_jobs))
         .route(
             "/completed/list",
@@ -221,6 +232,30 @@ pub fn workspaced_service() -> Router {
             "/completed/get_result_maybe/:id",
             get(get_completed_job_result_maybe).layer(cors.clone()),
         )
+        .route(
+            "/completed/get_result_preview/:id",
+            get(get_completed_job_result_preview).layer(cors.clone()),
+        )
+        .route(
+            "/completed/get_metrics/:id",
+            get(get_completed_job_metrics).layer(cors.clone()),
+        )
+        .route(
+            "/completed/get_warnings/:id",
+            get(get_completed_job_warnings).layer(cors.clone()),
+        )
+        .route(
+            "/completed/get_installed_packages/:id",
+            get(get_completed_job_installed_packages).layer(cors.clone()),
+        )
+        .route(
+            "/completed/get_profile/:id",
+            get(get_completed_job_profile).layer(cors.clone()),
+        )
+        .route(
+            "/completed/get_log_timestamps/:id",
+            get(get_completed_job_log_timestamps).layer(cors.clone()),
+        )
         .route(
             "/completed/delete/:id",
             post(delete_completed_job).layer(cors.clone()),
@@ -704,7 +739,9 @@ fn generate_get_job_query(no_logs: bool, table: &str) -> String {
         deleted,    
         is_skipped,
         result->'wm_labels' as labels,
-        CASE WHEN result is null or pg_column_size(result) < 90000 THEN result ELSE '\"WINDMILL_TOO_BIG\"'::jsonb END as result"
+        script_version,
+        CASE WHEN octet_length(result_compressed) >= 90000 THEN null ELSE result_compressed END as result_compressed,
+        CASE WHEN octet_length(result_compressed) >= 90000 THEN '\"WINDMILL_TOO_BIG\"'::jsonb WHEN result is null or pg_column_size(result) < 90000 THEN result ELSE '\"WINDMILL_TOO_BIG\"'::jsonb END as result"
     } else {
         "scheduled_for,  
         running,       
@@ -751,7 +788,10 @@ pub async fn get_job_internal(
     .bind(workspace_id)
     .fetch_optional(db)
     .await?
-    .map(Job::CompletedJob);
+    .map(|mut cjob| {
+        cjob.logs = cjob.logs.map(|x| decompress_log_chunks(&x).into_owned());
+        Job::CompletedJob(cjob)
+    });
 
     if let Some(cjob) = cjob_maybe {
         Ok(match cjob {
@@ -776,7 +816,10 @@ pub async fn get_job_internal(
         .bind(workspace_id)
         .fetch_optional(db)
         .await?
-        .map(Job::QueuedJob);
+        .map(|mut job| {
+            job.logs = job.logs.map(|x| decompress_log_chunks(&x).into_owned());
+            Job::QueuedJob(job)
+        });
         let job: Job = not_found_if_none(job_o, "Job", job_id.to_string())?;
         if opt_authed.is_some_and(|x| x.is_none()) && job.created_by() != "anonymous" {
             return Err(Error::BadRequest(
@@ -897,7 +940,7 @@ async fn get_job_logs(
                 "As a non logged in user, you can only see jobs ran by anonymous users".to_string(),
             ));
         }
-        let logs = record.logs.unwrap_or_default();
+        let logs = decompress_log_chunks(&record.logs.unwrap_or_default()).into_owned();
 
         log_job_view(&db, opt_authed.as_ref(), &w_id, &id).await?;
 
@@ -933,7 +976,7 @@ async fn get_job_logs(
                 "As a non logged in user, you can only see jobs ran by anonymous users".to_string(),
             ));
         }
-        let logs = text.logs.unwrap_or_default();
+        let logs = decompress_log_chunks(&text.logs.unwrap_or_default()).into_owned();
 
         log_job_view(&db, opt_authed.as_ref(), &w_id, &id).await?;
 
@@ -1505,6 +1548,181 @@ async fn cancel_selection(
     .await
 }
 
+async fn cancel_by_correlation_id(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Extension(user_db): Extension<UserDB>,
+    Extension(rsmq): Extension<Option<rsmq_async::MultiplexedRsmq>>,
+    Path((w_id, correlation_id)): Path<(String, String)>,
+    Json(CancelJob { reason }): Json<CancelJob>,
+) -> error::JsonResult<Vec<Uuid>> {
+    // jobs sharing a correlation ID are typically retries of the same external request so they
+    // are all canceled, running or not, except the runs of schedules
+    let mut tx = user_db.begin(&authed).await?;
+    let cancelled_job_ids = sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM queue WHERE workspace_id = $1 AND correlation_id = $2 AND canceled = false AND schedule_path IS NULL",
+    )
+    .bind(&w_id)
+    .bind(&correlation_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    let mut tx = db.begin().await?;
+    for job_id in cancelled_job_ids.iter() {
+        (tx, _) = cancel_job(
+            &authed.username,
+            reason.clone(),
+            *job_id,
+            &w_id,
+            tx,
+            &db,
+            rsmq.clone(),
+            false,
+            false,
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    audit_log(
+        &db,
+        &authed,
+        "jobs.cancel_by_correlation_id",
+        ActionKind::Delete,
+        &w_id,
+        Some(&correlation_id),
+        Some(
+            [(
+                "job_ids",
+                cancelled_job_ids
+                    .iter()
+                    .map(|uuid| uuid.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",")
+                    .as_str(),
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    Ok(Json(cancelled_job_ids))
+}
+
+#[derive(Deserialize)]
+struct SendStdin {
+    #[serde(default)]
+    lines: Vec<String>,
+    /// close the stdin of the job after the lines
+    #[serde(default)]
+    eof: bool,
+}
+
+#[derive(Serialize)]
+struct SendStdinResponse {
+    /// false if the process of the job has not started yet, the lines are written once it starts
+    running: bool,
+}
+
+/// Send lines to the stdin of a job run with `_STDIN_STREAM`, see `windmill_worker::stdin_stream`
+async fn send_job_stdin(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Extension(user_db): Extension<UserDB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+    Json(SendStdin { lines, eof }): Json<SendStdin>,
+) -> error::JsonResult<SendStdinResponse> {
+    if lines.iter().any(|line| line.contains('\n')) {
+        return Err(Error::BadRequest(
+            "stdin lines cannot contain newlines, send them as separate lines".to_string(),
+        ));
+    }
+
+    let mut tx = user_db.begin(&authed).await?;
+    let running = sqlx::query_scalar::<_, bool>(
+        "SELECT running FROM queue WHERE id = $1 AND workspace_id = $2 AND args->$3 = 'true'::jsonb",
+    )
+    .bind(id)
+    .bind(&w_id)
+    .bind(STDIN_STREAM_ARG)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| {
+        Error::NotFound(format!(
+            "no queued job {id} streaming its stdin (`{STDIN_STREAM_ARG}`)"
+        ))
+    })?;
+    tx.commit().await?;
+
+    let mut tx = db.begin().await?;
+    let closed = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM job_stdin WHERE job_id = $1 AND eof)",
+    )
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await?;
+    if closed {
+        return Err(Error::BadRequest(format!(
+            "the stdin of job {id} is already closed"
+        )));
+    }
+    sqlx::query(
+        "INSERT INTO job_stdin (workspace_id, job_id, line, eof)
+        SELECT $1, $2, line, false FROM UNNEST($3::text[]) WITH ORDINALITY AS t(line, n) ORDER BY n",
+    )
+    .bind(&w_id)
+    .bind(id)
+    .bind(&lines)
+    .execute(&mut *tx)
+    .await?;
+    if eof {
+        sqlx::query(
+            "INSERT INTO job_stdin (workspace_id, job_id, line, eof) VALUES ($1, $2, '', true)",
+        )
+        .bind(&w_id)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Json(SendStdinResponse { running }))
+}
+
+#[derive(Serialize, FromRow)]
+struct CorrelatedJob {
+    id: Uuid,
+    #[serde(rename = "type")]
+    typ: String,
+    running: bool,
+    canceled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    success: Option<bool>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+async fn list_jobs_by_correlation_id(
+    authed: ApiAuthed,
+    Extension(user_db): Extension<UserDB>,
+    Path((w_id, correlation_id)): Path<(String, String)>,
+) -> error::JsonResult<Vec<CorrelatedJob>> {
+    let mut tx = user_db.begin(&authed).await?;
+    let jobs = sqlx::query_as::<_, CorrelatedJob>(
+        "SELECT id, 'QueuedJob' AS typ, running, canceled, NULL::bool AS success, created_at
+            FROM queue WHERE workspace_id = $1 AND correlation_id = $2
+        UNION ALL
+        SELECT id, 'CompletedJob' AS typ, false AS running, canceled, success, created_at
+            FROM completed_job WHERE workspace_id = $1 AND correlation_id = $2
+        ORDER BY created_at DESC",
+    )
+    .bind(&w_id)
+    .bind(&correlation_id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Json(jobs))
+}
+
 async fn list_filtered_uuids(
     authed: ApiAuthed,
     Extension(db): Extension<DB>,
@@ -2590,6 +2808,10 @@ impl<'a> From<UnifiedJob> for Job {
                 tag: uj.tag,
                 priority: uj.priority,
                 labels: uj.labels,
+                script_version: None,
+                correlation_id: None,
+                hedge_group: None,
+                result_compressed: None,
                 self_wait_time_ms: uj.self_wait_time_ms,
                 aggregate_wait_time_ms: uj.aggregate_wait_time_ms,
             }),
@@ -2634,6 +2856,8 @@ impl<'a> From<UnifiedJob> for Job {
                 flow_step_id: None,
                 cache_ttl: None,
                 priority: uj.priority,
+                correlation_id: None,
+                hedge_group: None,
                 self_wait_time_ms: uj.self_wait_time_ms,
                 aggregate_wait_time_ms: uj.aggregate_wait_time_ms,
             }),
@@ -3300,7 +3524,7 @@ async fn run_wait_result(
 
         if result.is_none() {
             let row = sqlx::query_as::<_, RawResult>(
-                "SELECT null as created_by, result, language, flow_status FROM completed_job WHERE id = $1 AND workspace_id = $2",
+                "SELECT null as created_by, result, language, flow_status, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
             )
             .bind(uuid)
             .bind(&w_id)
@@ -3310,7 +3534,7 @@ async fn run_wait_result(
                 result = match format_result(
                     raw_result.language.as_ref(),
                     raw_result.flow_status.map(|x| x.0),
-                    raw_result.result.map(|x| x.0),
+                    completed_job_result(raw_result.result, raw_result.result_compressed),
                 ) {
                     FormattedResult::RawValue(rv) => rv,
                     FormattedResult::Vec(v) => Some(to_raw_value(&v)),
@@ -3394,7 +3618,7 @@ async fn run_wait_result(
 async fn delete_job_metadata_after_use(db: &DB, job_uuid: Uuid) -> Result<(), Error> {
     sqlx::query!(
         "UPDATE completed_job
-        SET logs = '##DELETED##', args = '{}'::jsonb, result = '{}'::jsonb
+        SET logs = '##DELETED##', args = '{}'::jsonb, result = '{}'::jsonb, result_compressed = null
         WHERE id = $1",
         job_uuid,
     )
@@ -4749,7 +4973,7 @@ async fn get_job_update(
             },
             log_offset: record.log_offset,
             completed: None,
-            new_logs: record.logs,
+            new_logs: record.logs.map(|x| decompress_log_chunks(&x).into_owned()),
             mem_peak: record.mem_peak,
             progress,
             flow_status: record
@@ -4782,7 +5006,7 @@ async fn get_job_update(
                 running: Some(false),
                 completed: Some(true),
                 log_offset: record.log_offset,
-                new_logs: record.logs,
+                new_logs: record.logs.map(|x| decompress_log_chunks(&x).into_owned()),
                 mem_peak: record.mem_peak,
                 progress,
                 flow_status: record
@@ -5026,7 +5250,7 @@ async fn get_completed_job<'a>(
     let job_o = sqlx::query_as::<_, CompletedJob>("SELECT id, workspace_id, parent_job, created_by, created_at, duration_ms, success, script_hash, script_path, 
     CASE WHEN args is null or pg_column_size(args) < 90000 THEN args ELSE '\"WINDMILL_TOO_BIG\"'::jsonb END as args, CASE WHEN result is null or pg_column_size(result) < 90000 THEN result ELSE '\"WINDMILL_TOO_BIG\"'::jsonb END as result, logs, deleted, raw_code, canceled, canceled_by, canceled_reason, job_kind,
     schedule_path, permissioned_as, flow_status, raw_flow, is_flow_step, language, started_at, is_skipped,
-    raw_lock, email, visible_to_owner, mem_peak, tag, priority, result->'wm_labels' as labels FROM completed_job WHERE id = $1 AND workspace_id = $2")
+    raw_lock, email, visible_to_owner, mem_peak, tag, priority, result->'wm_labels' as labels, script_version, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2")
         .bind(id)
         .bind(&w_id)
         .fetch_optional(&db)
@@ -5062,6 +5286,8 @@ pub struct RawResult {
     pub flow_status: Option<sqlx::types::Json<Box<RawValue>>>,
     pub language: Option<ScriptLang>,
     pub created_by: Option<String>,
+    #[sqlx(default)]
+    pub result_compressed: Option<Vec<u8>>,
 }
 
 #[derive(FromRow)]
@@ -5071,6 +5297,8 @@ pub struct RawResultWithSuccess {
     pub language: Option<ScriptLang>,
     pub success: bool,
     pub created_by: String,
+    #[sqlx(default)]
+    pub result_compressed: Option<Vec<u8>>,
 }
 
 async fn get_completed_job_result(
@@ -5079,22 +5307,20 @@ async fn get_completed_job_result(
     Path((w_id, id)): Path<(String, Uuid)>,
     Query(JsonPath { json_path, suspended_job, approver, resume_id, secret }): Query<JsonPath>,
 ) -> error::Result<Response> {
-    let result_o = if let Some(json_path) = json_path {
+    let json_path = json_path
+        .map(|x| x.split(".").map(|x| x.to_string()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let result_o = if !json_path.is_empty() {
         sqlx::query_as::<_, RawResult>(
-            "SELECT result #> $3 as result, flow_status, language, created_by FROM completed_job WHERE id = $1 AND workspace_id = $2",
+            "SELECT result #> $3 as result, flow_status, language, created_by, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
         )
         .bind(id)
         .bind(&w_id)
-        .bind(
-            json_path
-                .split(".")
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>(),
-        )
+        .bind(&json_path)
         .fetch_optional(&db)
         .await?
     } else {
-        sqlx::query_as::<_, RawResult>("SELECT result, flow_status, language, created_by FROM completed_job WHERE id = $1 AND workspace_id = $2")
+        sqlx::query_as::<_, RawResult>("SELECT result, flow_status, language, created_by, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2")
             .bind(id)
             .bind(&w_id)
             .fetch_optional(&db)
@@ -5141,10 +5367,15 @@ async fn get_completed_job_result(
         }
     }
 
+    let result = raw_result.result.map(|x| x.0).or_else(|| {
+        raw_result
+            .result_compressed
+            .and_then(|x| decompress_result_at_path(&x, &json_path))
+    });
     let result = format_result(
         raw_result.language.as_ref(),
         raw_result.flow_status.map(|x| x.0),
-        raw_result.result.map(|x| x.0),
+        result,
     );
 
     log_job_view(&db, opt_authed.as_ref(), &w_id, &id).await?;
@@ -5210,7 +5441,7 @@ async fn get_completed_job_result_maybe(
     Query(GetCompletedJobQuery { get_started }): Query<GetCompletedJobQuery>,
 ) -> error::Result<Response> {
     let result_o = sqlx::query_as::<_, RawResultWithSuccess>(
-        "SELECT result, success, language, flow_status, created_by FROM completed_job WHERE id = $1 AND workspace_id = $2",
+        "SELECT result, success, language, flow_status, created_by, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
     )
     .bind(id)
     .bind(&w_id)
@@ -5221,7 +5452,7 @@ async fn get_completed_job_result_maybe(
         let result = format_result(
             res.language.as_ref(),
             res.flow_status.map(|x| x.0),
-            res.result.map(|x| x.0),
+            completed_job_result(res.result, res.result_compressed),
         );
         if opt_authed.is_none() && res.created_by != "anonymous" {
             return Err(Error::BadRequest(
@@ -5265,6 +5496,151 @@ async fn get_completed_job_result_maybe(
     }
 }
 
+/// Completed job data is only readable anonymously for jobs ran by anonymous users, every read
+/// being audited like the other completed job getters
+async fn check_completed_job_view(
+    db: &DB,
+    opt_authed: Option<&ApiAuthed>,
+    w_id: &str,
+    id: &Uuid,
+    created_by: &str,
+) -> error::Result<()> {
+    if opt_authed.is_none() && created_by != "anonymous" {
+        return Err(Error::BadRequest(
+            "As a non logged in user, you can only see jobs ran by anonymous users".to_string(),
+        ));
+    }
+
+    log_job_view(db, opt_authed, w_id, id).await
+}
+
+/// Fetch a json column of a completed job, see [`check_completed_job_view`]
+async fn fetch_completed_job_column(
+    db: &DB,
+    opt_authed: Option<&ApiAuthed>,
+    w_id: &str,
+    id: Uuid,
+    column: &'static str,
+) -> error::Result<Option<sqlx::types::Json<Box<RawValue>>>> {
+    let row = sqlx::query_as::<_, (Option<sqlx::types::Json<Box<RawValue>>>, String)>(&format!(
+        "SELECT {column}, created_by FROM completed_job WHERE id = $1 AND workspace_id = $2"
+    ))
+    .bind(id)
+    .bind(w_id)
+    .fetch_optional(db)
+    .await?;
+
+    let (value, created_by) = not_found_if_none(row, "Completed Job", id.to_string())?;
+    check_completed_job_view(db, opt_authed, w_id, &id, &created_by).await?;
+
+    Ok(value)
+}
+
+async fn get_completed_job_result_preview(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> error::Result<Json<Option<sqlx::types::Json<Box<RawValue>>>>> {
+    fetch_completed_job_column(&db, opt_authed.as_ref(), &w_id, id, "result_preview")
+        .await
+        .map(Json)
+}
+
+async fn get_completed_job_metrics(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> error::Result<Json<Option<sqlx::types::Json<Box<RawValue>>>>> {
+    fetch_completed_job_column(&db, opt_authed.as_ref(), &w_id, id, "metrics")
+        .await
+        .map(Json)
+}
+
+async fn get_completed_job_warnings(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> error::Result<Json<Option<sqlx::types::Json<Box<RawValue>>>>> {
+    fetch_completed_job_column(&db, opt_authed.as_ref(), &w_id, id, "warnings")
+        .await
+        .map(Json)
+}
+
+async fn get_completed_job_installed_packages(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> error::Result<Json<Option<sqlx::types::Json<Box<RawValue>>>>> {
+    fetch_completed_job_column(&db, opt_authed.as_ref(), &w_id, id, "installed_packages")
+        .await
+        .map(Json)
+}
+
+#[derive(Serialize)]
+struct LogTimestamps {
+    first_log_at: Option<chrono::DateTime<chrono::Utc>>,
+    last_log_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+async fn get_completed_job_log_timestamps(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> JsonResult<LogTimestamps> {
+    let row = sqlx::query_as::<
+        _,
+        (
+            Option<chrono::DateTime<chrono::Utc>>,
+            Option<chrono::DateTime<chrono::Utc>>,
+            String,
+        ),
+    >(
+        "SELECT first_log_at, last_log_at, created_by FROM completed_job WHERE id = $1 AND workspace_id = $2",
+    )
+    .bind(id)
+    .bind(&w_id)
+    .fetch_optional(&db)
+    .await?;
+
+    let (first_log_at, last_log_at, created_by) =
+        not_found_if_none(row, "Completed Job", id.to_string())?;
+    check_completed_job_view(&db, opt_authed.as_ref(), &w_id, &id, &created_by).await?;
+
+    Ok(Json(LogTimestamps { first_log_at, last_log_at }))
+}
+
+async fn get_completed_job_profile(
+    OptAuthed(opt_authed): OptAuthed,
+    Extension(db): Extension<DB>,
+    Path((w_id, id)): Path<(String, Uuid)>,
+) -> error::Result<Response> {
+    let row = sqlx::query_as::<_, (Option<Vec<u8>>, String)>(
+        "SELECT p.profile, cj.created_by FROM completed_job cj
+        LEFT JOIN job_profile p ON p.job_id = cj.id
+        WHERE cj.id = $1 AND cj.workspace_id = $2",
+    )
+    .bind(id)
+    .bind(&w_id)
+    .fetch_optional(&db)
+    .await?;
+
+    let (profile, created_by) = not_found_if_none(row, "Completed Job", id.to_string())?;
+    check_completed_job_view(&db, opt_authed.as_ref(), &w_id, &id, &created_by).await?;
+
+    let profile = not_found_if_none(profile, "Profile of job", id.to_string())?;
+    let headers = [
+        (
+            http::header::CONTENT_TYPE,
+            "application/octet-stream".to_string(),
+        ),
+        (
+            http::header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{id}.prof\""),
+        ),
+    ];
+    Ok((headers, profile).into_response())
+}
+
 async fn delete_completed_job<'a>(
     authed: ApiAuthed,
     Extension(user_db): Extension<UserDB>,
@@ -5276,7 +5652,7 @@ async fn delete_completed_job<'a>(
 
     require_admin(authed.is_admin, &authed.username)?;
     let job_o = sqlx::query_as::<_, CompletedJob>(
-        "UPDATE completed_job SET args = null, logs = '', result = null, deleted = true WHERE id = $1 AND workspace_id = $2 \
+        "UPDATE completed_job SET args = null, logs = '', result = null, result_compressed = null, result_preview = null, metrics = null, warnings = null, deleted = true WHERE id = $1 AND workspace_id = $2 \
          RETURNING *, null as labels",
     )
     .bind(id)
@@ -5288,6 +5664,11 @@ async fn delete_completed_job<'a>(
         .execute(&mut *tx)
         .await?;
 
+    sqlx::query("DELETE FROM job_profile WHERE job_id = $1")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
     let cj = not_found_if_none(job_o, "Completed Job", id.to_string())?;
 
     audit_log(
@@ -27,7 +27,7 @@ use windmill_audit::ActionKind;
 use windmill_common::{
     db::UserDB,
     error::{Error, JsonResult, Result},
-    schedule::Schedule,
+    schedule::{Schedule, ScheduleOverlapPolicy},
     utils::{not_found_if_none, paginate, Pagination, StripPath},
 };
 use windmill_git_sync::{handle_deployment_metadata, DeployedObject};
@@ -58,6 +58,7 @@ pub struct NewSchedule {
     pub timezone: String,
     pub summary: Option<String>,
     pub no_flow_overlap: Option<bool>,
+    pub overlap_policy: Option<ScheduleOverlapPolicy>,
     pub script_path: String,
     pub is_flow: bool,
     pub args: Option<serde_json::Value>,
@@ -169,9 +170,9 @@ async fn create_schedule(
             is_flow, args, enabled, email, on_failure, on_failure_times, on_failure_exact, \
             on_failure_extra_args, on_recovery, on_recovery_times, on_recovery_extra_args, \
             on_success, on_success_extra_args, \
-            ws_error_handler_muted, retry, summary, no_flow_overlap, tag, paused_until \
+            ws_error_handler_muted, retry, summary, no_flow_overlap, tag, paused_until, overlap_policy \
         ) VALUES ( \
-            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25 \
+            $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26 \
         ) RETURNING *")
         .bind(&w_id)
         .bind(&ns.path)
@@ -198,6 +199,7 @@ async fn create_schedule(
         .bind(&ns.no_flow_overlap.unwrap_or(false))
         .bind(&ns.tag)
         .bind(&ns.paused_until)
+        .bind(ns.overlap_policy.as_ref().map(ScheduleOverlapPolicy::as_str))
     .fetch_one(&mut tx)
     .await
     .map_err(|e| Error::InternalErr(format!("inserting schedule in {w_id}: {e:#}")))?;
@@ -263,8 +265,8 @@ async fn edit_schedule(
             on_failure_exact = $6, on_failure_extra_args = $7, on_recovery = $8, on_recovery_times = $9, \
             on_recovery_extra_args = $10, on_success = $11, on_success_extra_args = $12, \
             ws_error_handler_muted = $13, retry = $14, summary = $15, \
-            no_flow_overlap = $16, tag = $17, paused_until = $18
-        WHERE path = $19 AND workspace_id = $20 RETURNING *")
+            no_flow_overlap = $16, tag = $17, paused_until = $18, overlap_policy = $19
+        WHERE path = $20 AND workspace_id = $21 RETURNING *")
         .bind(&es.schedule)
         .bind(&es.timezone)
         .bind(&es.args)
@@ -283,6 +285,7 @@ async fn edit_schedule(
         .bind(&es.no_flow_overlap.unwrap_or(false))
         .bind(&es.tag)
         .bind(&es.paused_until)
+        .bind(es.overlap_policy.as_ref().map(ScheduleOverlapPolicy::as_str))
         .bind(&path)
         .bind(&w_id)
     .fetch_one(&mut tx)
@@ -401,6 +404,7 @@ pub struct ScheduleWJobs {
     pub no_flow_overlap: bool,
     pub tag: Option<String>,
     pub paused_until: Option<DateTime<Utc>>,
+    pub overlap_policy: Option<String>,
 }
 
 async fn list_schedule_with_jobs(
@@ -844,6 +848,7 @@ pub struct EditSchedule {
     pub ws_error_handler_muted: Option<bool>,
     pub retry: Option<serde_json::Value>,
     pub no_flow_overlap: Option<bool>,
+    pub overlap_policy: Option<ScheduleOverlapPolicy>,
     pub tag: Option<String>,
     pub paused_until: Option<DateTime<Utc>>,
 }
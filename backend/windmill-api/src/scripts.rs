@@ -54,7 +54,8 @@ use windmill_common::{
     },
     users::username_to_permissioned_as,
     utils::{
-        not_found_if_none, paginate, query_elems_from_hub, require_admin, Pagination, StripPath,
+        calculate_hash, not_found_if_none, paginate, query_elems_from_hub, require_admin,
+        Pagination, StripPath,
     },
     worker::to_raw_value,
     HUB_BASE_URL,
@@ -621,8 +622,9 @@ async fn create_script_internal<'c>(
          content, created_by, schema, is_template, extra_perms, lock, language, kind, tag, \
          draft_only, envs, concurrent_limit, concurrency_time_window_s, cache_ttl, \
          dedicated_worker, ws_error_handler_muted, priority, restart_unless_cancelled, \
-         delete_after_use, timeout, concurrency_key, visible_to_runner_only, no_main_func, codebase, has_preprocessor) \
-         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::json, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31)",
+         delete_after_use, timeout, concurrency_key, visible_to_runner_only, no_main_func, codebase, has_preprocessor, \
+         content_hash) \
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::json, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32)",
         &w_id,
         &hash.0,
         ns.path,
@@ -654,9 +656,19 @@ async fn create_script_internal<'c>(
         ns.no_main_func,
         codebase,
         ns.has_preprocessor,
+        // checked by the workers before running the script
+        calculate_hash(&ns.content),
     )
     .execute(&mut tx)
     .await?;
+    if let Some(version) = ns.version.as_ref() {
+        sqlx::query("UPDATE script SET version = $1 WHERE hash = $2 AND workspace_id = $3")
+            .bind(version)
+            .bind(&hash.0)
+            .bind(&w_id)
+            .execute(&mut tx)
+            .await?;
+    }
     let p_path_opt = parent_hashes_and_perms.as_ref().map(|x| x.p_path.clone());
     if let Some(ref p_path) = p_path_opt {
         sqlx::query!(
@@ -1255,18 +1267,23 @@ async fn raw_script_by_hash(
 struct DeploymentStatus {
     lock: Option<String>,
     lock_error_logs: Option<String>,
+    lock_success: Option<bool>,
+    lock_exit_code: Option<i32>,
+    lock_duration_ms: Option<i64>,
+    lock_pinned_packages: Option<i32>,
+    lock_error_kind: Option<String>,
 }
 async fn get_deployment_status(
     Extension(db): Extension<DB>,
     Path((w_id, hash)): Path<(String, ScriptHash)>,
 ) -> JsonResult<DeploymentStatus> {
     let mut tx = db.begin().await?;
-    let status_o: Option<DeploymentStatus> = sqlx::query_as!(
-        DeploymentStatus,
-        "SELECT lock, lock_error_logs FROM script WHERE hash = $1 AND workspace_id = $2",
-        hash.0,
-        w_id,
+    let status_o: Option<DeploymentStatus> = sqlx::query_as(
+        "SELECT lock, lock_error_logs, lock_success, lock_exit_code, lock_duration_ms, \
+         lock_pinned_packages, lock_error_kind FROM script WHERE hash = $1 AND workspace_id = $2",
     )
+    .bind(hash.0)
+    .bind(&w_id)
     .fetch_optional(&mut *tx)
     .await?;
 
@@ -1394,7 +1411,7 @@ async fn delete_script_by_hash(
 
     require_admin(authed.is_admin, &authed.username)?;
     let script = sqlx::query_as::<_, Script>(
-        "UPDATE script SET content = '', archived = true, deleted = true, lock = '', schema = null WHERE hash = $1 AND \
+        "UPDATE script SET content = '', content_hash = null, archived = true, deleted = true, lock = '', schema = null WHERE hash = $1 AND \
          workspace_id = $2 RETURNING *",
     )
     .bind(&hash.0)
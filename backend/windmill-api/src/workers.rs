@@ -7,8 +7,8 @@
  */
 
 use axum::{
-    extract::{Extension, Query},
-    routing::get,
+    extract::{Extension, Path, Query},
+    routing::{get, post},
     Json, Router,
 };
 
@@ -17,7 +17,7 @@ use sqlx::FromRow;
 use uuid::Uuid;
 use windmill_common::{
     db::UserDB,
-    error::JsonResult,
+    error::{JsonResult, Result},
     utils::{paginate, Pagination},
     worker::{ALL_TAGS, DEFAULT_TAGS, DEFAULT_TAGS_PER_WORKSPACE},
     DB,
@@ -36,6 +36,8 @@ pub fn global_service() -> Router {
         )
         .route("/get_default_tags", get(get_default_tags))
         .route("/queue_metrics", get(get_queue_metrics))
+        .route("/pause/:worker", post(pause_worker))
+        .route("/resume/:worker", post(resume_worker))
 }
 
 #[derive(FromRow, Serialize, Deserialize)]
@@ -67,6 +69,7 @@ struct WorkerPing {
     memory_usage: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     wm_memory_usage: Option<i64>,
+    paused: bool,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -96,7 +99,7 @@ async fn list_worker_pings(
         WorkerPing,
         "SELECT worker, worker_instance,  EXTRACT(EPOCH FROM (now() - ping_at))::integer as last_ping, started_at, ip, jobs_executed,
         CASE WHEN $4 IS TRUE THEN current_job_id ELSE NULL END as last_job_id, CASE WHEN $4 IS TRUE THEN current_job_workspace_id ELSE NULL END as last_job_workspace_id, 
-        custom_tags, worker_group, wm_version, occupancy_rate, occupancy_rate_15s, occupancy_rate_5m, occupancy_rate_30m, memory, vcpus, memory_usage, wm_memory_usage
+        custom_tags, worker_group, wm_version, occupancy_rate, occupancy_rate_15s, occupancy_rate_5m, occupancy_rate_30m, memory, vcpus, memory_usage, wm_memory_usage, paused
         FROM worker_ping
         WHERE ($1::integer IS NULL AND ping_at > now() - interval '5 minute') OR (ping_at > now() - ($1 || ' seconds')::interval)
         ORDER BY ping_at desc LIMIT $2 OFFSET $3",
@@ -176,3 +179,38 @@ async fn get_queue_metrics(
 
     Ok(Json(queue_metrics))
 }
+
+/// Stop the worker from pulling new jobs, without killing it: the jobs it is running complete
+/// and it keeps pinging while paused
+async fn pause_worker(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(worker): Path<String>,
+) -> Result<String> {
+    require_super_admin(&db, &authed.email).await?;
+
+    sqlx::query(
+        "INSERT INTO worker_pause (worker, paused_by) VALUES ($1, $2) ON CONFLICT (worker) DO NOTHING",
+    )
+    .bind(&worker)
+    .bind(&authed.email)
+    .execute(&db)
+    .await?;
+
+    Ok(format!("Paused worker {worker}"))
+}
+
+async fn resume_worker(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(worker): Path<String>,
+) -> Result<String> {
+    require_super_admin(&db, &authed.email).await?;
+
+    sqlx::query("DELETE FROM worker_pause WHERE worker = $1")
+        .bind(&worker)
+        .execute(&db)
+        .await?;
+
+    Ok(format!("Resumed worker {worker}"))
+}
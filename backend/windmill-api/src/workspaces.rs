@@ -89,6 +89,21 @@ pub fn workspaced_service() -> Router {
             post(run_slack_message_test_job),
         )
         .route("/edit_webhook", post(edit_webhook))
+        .route("/edit_fail_on_stderr", post(edit_fail_on_stderr))
+        .route(
+            "/edit_verify_dependency_checksums",
+            post(edit_verify_dependency_checksums),
+        )
+        .route("/edit_pip_require_hashes", post(edit_pip_require_hashes))
+        .route(
+            "/edit_completed_job_retention",
+            post(edit_completed_job_retention),
+        )
+        .route("/edit_max_queue_wait", post(edit_max_queue_wait))
+        .route(
+            "/set_compute_budget_exhausted",
+            post(set_compute_budget_exhausted),
+        )
         .route("/edit_auto_invite", post(edit_auto_invite))
         .route("/edit_deploy_to", post(edit_deploy_to))
         .route("/tarball", get(tarball_workspace))
@@ -179,6 +194,12 @@ pub struct WorkspaceSettings {
     pub default_app: Option<String>,
     pub automatic_billing: bool,
     pub default_scripts: Option<serde_json::Value>,
+    pub fail_on_stderr: bool,
+    pub verify_dependency_checksums: bool,
+    pub completed_job_retention_secs: Option<i32>,
+    pub max_queue_wait_secs: Option<i32>,
+    pub compute_budget_exhausted: bool,
+    pub pip_require_hashes: bool,
 }
 
 #[derive(FromRow, Serialize, Debug)]
@@ -232,6 +253,36 @@ struct EditWebhook {
     webhook: Option<String>,
 }
 
+#[derive(Deserialize)]
+struct EditFailOnStderr {
+    fail_on_stderr: bool,
+}
+
+#[derive(Deserialize)]
+struct EditPipRequireHashes {
+    pip_require_hashes: bool,
+}
+
+#[derive(Deserialize)]
+struct SetComputeBudgetExhausted {
+    compute_budget_exhausted: bool,
+}
+
+#[derive(Deserialize)]
+struct EditVerifyDependencyChecksums {
+    verify_dependency_checksums: bool,
+}
+
+#[derive(Deserialize)]
+struct EditCompletedJobRetention {
+    completed_job_retention_secs: Option<i32>,
+}
+
+#[derive(Deserialize)]
+struct EditMaxQueueWait {
+    max_queue_wait_secs: Option<i32>,
+}
+
 #[derive(Deserialize)]
 struct EditCopilotConfig {
     openai_resource_path: Option<String>,
@@ -634,6 +685,252 @@ async fn edit_webhook(
     Ok(format!("Edit webhook for workspace {}", &w_id))
 }
 
+async fn edit_fail_on_stderr(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    ApiAuthed { is_admin, username, .. }: ApiAuthed,
+    Json(ef): Json<EditFailOnStderr>,
+) -> Result<String> {
+    require_admin(is_admin, &username)?;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query("UPDATE workspace_settings SET fail_on_stderr = $1 WHERE workspace_id = $2")
+        .bind(ef.fail_on_stderr)
+        .bind(&w_id)
+        .execute(&mut *tx)
+        .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.edit_fail_on_stderr",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some([("fail_on_stderr", &format!("{:?}", ef.fail_on_stderr)[..])].into()),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!("Edit fail on stderr for workspace {}", &w_id))
+}
+
+async fn edit_pip_require_hashes(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    ApiAuthed { is_admin, username, .. }: ApiAuthed,
+    Json(ep): Json<EditPipRequireHashes>,
+) -> Result<String> {
+    require_admin(is_admin, &username)?;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query("UPDATE workspace_settings SET pip_require_hashes = $1 WHERE workspace_id = $2")
+        .bind(ep.pip_require_hashes)
+        .bind(&w_id)
+        .execute(&mut *tx)
+        .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.edit_pip_require_hashes",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some(
+            [(
+                "pip_require_hashes",
+                &format!("{:?}", ep.pip_require_hashes)[..],
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!("Edit pip require hashes for workspace {}", &w_id))
+}
+
+/// Jobs not started within this many seconds past their scheduled time are failed by the monitor
+/// instead of running late, overrides MAX_QUEUE_WAIT_SECS
+async fn edit_max_queue_wait(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    ApiAuthed { is_admin, username, .. }: ApiAuthed,
+    Json(em): Json<EditMaxQueueWait>,
+) -> Result<String> {
+    require_admin(is_admin, &username)?;
+
+    if em.max_queue_wait_secs.is_some_and(|x| x <= 0) {
+        return Err(Error::BadRequest(
+            "max queue wait must be a positive number of seconds".to_string(),
+        ));
+    }
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query("UPDATE workspace_settings SET max_queue_wait_secs = $1 WHERE workspace_id = $2")
+        .bind(em.max_queue_wait_secs)
+        .bind(&w_id)
+        .execute(&mut *tx)
+        .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.edit_max_queue_wait",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some(
+            [(
+                "max_queue_wait_secs",
+                &format!("{:?}", em.max_queue_wait_secs)[..],
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!("Edit max queue wait for workspace {}", &w_id))
+}
+
+/// Set by the external accounting of the compute budgets. While set, the jobs of the workspace
+/// are not pulled anymore and the running ones are canceled by the monitor
+async fn set_compute_budget_exhausted(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    Json(sc): Json<SetComputeBudgetExhausted>,
+) -> Result<String> {
+    require_super_admin(&db, &authed.email).await?;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        "UPDATE workspace_settings SET compute_budget_exhausted = $1 WHERE workspace_id = $2",
+    )
+    .bind(sc.compute_budget_exhausted)
+    .bind(&w_id)
+    .execute(&mut *tx)
+    .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.set_compute_budget_exhausted",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some(
+            [(
+                "compute_budget_exhausted",
+                &format!("{:?}", sc.compute_budget_exhausted)[..],
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!(
+        "Set compute budget exhausted to {} for workspace {}",
+        sc.compute_budget_exhausted, &w_id
+    ))
+}
+
+async fn edit_verify_dependency_checksums(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    ApiAuthed { is_admin, username, .. }: ApiAuthed,
+    Json(ev): Json<EditVerifyDependencyChecksums>,
+) -> Result<String> {
+    require_admin(is_admin, &username)?;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        "UPDATE workspace_settings SET verify_dependency_checksums = $1 WHERE workspace_id = $2",
+    )
+    .bind(ev.verify_dependency_checksums)
+    .bind(&w_id)
+    .execute(&mut *tx)
+    .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.edit_verify_dependency_checksums",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some(
+            [(
+                "verify_dependency_checksums",
+                &format!("{:?}", ev.verify_dependency_checksums)[..],
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!(
+        "Edit verify dependency checksums for workspace {}",
+        &w_id
+    ))
+}
+
+async fn edit_completed_job_retention(
+    authed: ApiAuthed,
+    Extension(db): Extension<DB>,
+    Path(w_id): Path<String>,
+    ApiAuthed { is_admin, username, .. }: ApiAuthed,
+    Json(er): Json<EditCompletedJobRetention>,
+) -> Result<String> {
+    require_admin(is_admin, &username)?;
+
+    if er.completed_job_retention_secs.is_some_and(|x| x <= 0) {
+        return Err(Error::BadRequest(
+            "completed job retention must be a positive number of seconds".to_string(),
+        ));
+    }
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query(
+        "UPDATE workspace_settings SET completed_job_retention_secs = $1 WHERE workspace_id = $2",
+    )
+    .bind(er.completed_job_retention_secs)
+    .bind(&w_id)
+    .execute(&mut *tx)
+    .await?;
+    audit_log(
+        &mut *tx,
+        &authed,
+        "workspaces.edit_completed_job_retention",
+        ActionKind::Update,
+        &w_id,
+        Some(&authed.email),
+        Some(
+            [(
+                "completed_job_retention_secs",
+                &format!("{:?}", er.completed_job_retention_secs)[..],
+            )]
+            .into(),
+        ),
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(format!(
+        "Edit completed job retention for workspace {}",
+        &w_id
+    ))
+}
+
 async fn edit_copilot_config(
     authed: ApiAuthed,
     Extension(db): Extension<DB>,
@@ -1702,6 +1999,11 @@ async fn delete_workspace(
         .execute(&mut *tx)
         .await?;
 
+    sqlx::query("DELETE FROM job_profile WHERE workspace_id = $1")
+        .bind(&w_id)
+        .execute(&mut *tx)
+        .await?;
+
     sqlx::query!(
         "DELETE FROM deployment_metadata WHERE workspace_id = $1",
         &w_id
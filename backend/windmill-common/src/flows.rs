@@ -136,6 +136,9 @@ pub struct StopAfterIf {
 pub struct Retry {
     pub constant: ConstantDelay,
     pub exponential: ExponentialDelay,
+    /// names of the errors (e.g. `TimeoutError`) worth retrying, any error is retried if unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retryable_errors: Option<Vec<String>>,
 }
 
 impl Retry {
@@ -143,7 +146,7 @@ impl Retry {
     ///
     /// May return [`Duration::ZERO`] to retry immediately.
     pub fn interval(&self, previous_attempts: u16, silent: bool) -> Option<Duration> {
-        let Self { constant, exponential } = self;
+        let Self { constant, exponential, .. } = self;
 
         if previous_attempts < constant.attempts {
             Some(Duration::from_secs(constant.seconds as u64))
@@ -169,6 +172,24 @@ impl Retry {
         }
     }
 
+    /// Whether a step that failed with `error` may be retried. Errors marked `idempotent = false`
+    /// by the step (e.g. an attribute of the raised exception, reported in the `extra` fields of the
+    /// error), because it already performed side effects that must not be repeated, never are
+    pub fn is_retryable(&self, error: Option<&serde_json::Value>) -> bool {
+        let not_idempotent =
+            |e: &serde_json::Value| e.get("idempotent") == Some(&serde_json::Value::Bool(false));
+        if error.is_some_and(|e| not_idempotent(e) || e.get("extra").is_some_and(not_idempotent)) {
+            return false;
+        }
+        match &self.retryable_errors {
+            Some(names) => error
+                .and_then(|e| e.get("name"))
+                .and_then(|name| name.as_str())
+                .is_some_and(|name| names.iter().any(|x| x == name)),
+            None => true,
+        }
+    }
+
     pub fn has_attempts(&self) -> bool {
         self.constant.attempts != 0 || self.exponential.attempts != 0
     }
@@ -33,7 +33,7 @@ pub const DEV_INSTANCE_SETTING: &str = "dev_instance";
 pub const JWT_SECRET_SETTING: &str = "jwt_secret";
 pub const EMAIL_DOMAIN_SETTING: &str = "email_domain";
 
-pub const ENV_SETTINGS: [&str; 51] = [
+pub const ENV_SETTINGS: [&str; 52] = [
     "DISABLE_NSJAIL",
     "MODE",
     "NUM_WORKERS",
@@ -42,6 +42,7 @@ pub const ENV_SETTINGS: [&str; 51] = [
     "BASE_URL",
     "TIMEOUT",
     "ZOMBIE_JOB_TIMEOUT",
+    "ZOMBIE_JOB_INSTALL_TIMEOUT",
     "RESTART_ZOMBIE_JOBS",
     "SLEEP_QUEUE",
     "MAX_LOG_SIZE",
@@ -13,11 +13,83 @@ pub const ENTRYPOINT_OVERRIDE: &str = "_ENTRYPOINT_OVERRIDE";
 
 pub const PREPROCESSOR_FAKE_ENTRYPOINT: &str = "__WM_PREPROCESSOR";
 
+pub const NOTIFY_ARG: &str = "_NOTIFY";
+
+pub const SANDBOX_PROFILE_ARG: &str = "_SANDBOX_PROFILE";
+
+/// Name of the seccomp policy, among the ones defined by the operator, the job is sandboxed
+/// with. Overrides the policy of the sandbox profile, only for the policies the worker lists
+/// in ALLOWED_SECCOMP_POLICIES
+pub const SECCOMP_POLICY_ARG: &str = "_SECCOMP_POLICY";
+
+/// Preview-only args to make a test run reproducible. They are exposed to the script as the
+/// `WM_FIXED_NOW` (RFC 3339 timestamp to use as the current time) and `WM_SEED` (integer seed for
+/// the random generators) env variables. Python's `random` is seeded automatically, and the
+/// clocks of python (`datetime`, `time.time`) and deno (`Date`) are fixed to `WM_FIXED_NOW`.
+pub const FIXED_NOW_ARG: &str = "_WM_FIXED_NOW";
+pub const SEED_ARG: &str = "_WM_SEED";
+
+/// Level (error, warn, info, debug or trace) of the worker's own logs while handling the job
+pub const LOG_LEVEL_ARG: &str = "_LOG_LEVEL";
+
+/// Execution environment (e.g. an image with ffmpeg installed) the job requires. Only the workers
+/// advertising it in WORKER_EXEC_ENVS can claim the job
+pub const EXEC_ENV_ARG: &str = "_EXEC_ENV";
+
+/// Timeout in seconds of the dependency install/compile phases of the job, overriding the
+/// instance-wide dependency timeout
+pub const DEPENDENCY_TIMEOUT_ARG: &str = "_DEPENDENCY_TIMEOUT";
+
+/// HTTP sink (e.g. `"$res:f/etl/ingest_endpoint"`) the `result_item:` lines printed by the job are
+/// forwarded to as NDJSON while it runs
+pub const RESULT_SINK_ARG: &str = "_RESULT_SINK";
+
+/// Condition checked before running the job, the job is completed as skipped when it does not
+/// hold. See `windmill_worker::precondition` for what it can reference
+pub const PRECONDITION_ARG: &str = "_PRECONDITION";
+
+/// Correlation ID of the job in an external system, stored in the indexed `correlation_id` column
+/// so that the jobs can be looked up and canceled by it
+pub const CORRELATION_ID_ARG: &str = "_CORRELATION_ID";
+
+/// Env variables of the job taken from the fields of resources, e.g.
+/// `[{"resource": "$res:f/aws/prod", "env": {"AWS_ACCESS_KEY_ID": "awsAccessKeyId"}}]`. See
+/// `windmill_worker::env_from_resources` for the format
+pub const ENV_FROM_RESOURCES_ARG: &str = "_ENV_FROM_RESOURCES";
+
+/// Run the job under the profiler of its language, only python (cProfile) is supported. The
+/// profile is stored in `job_profile` once the job completes, to download from
+/// `/w/{workspace}/jobs/completed/get_profile/{id}` and view with `python -m pstats`, snakeviz or
+/// flameprof (flamegraph). Profiling adds overhead to the run
+pub const PROFILE_ARG: &str = "_PROFILE";
+
+/// Files fetched into the job dir before the job runs, by name, e.g.
+/// `{"dataset": {"url": "https://example.com/data.csv"}}`. Their paths are in the `WM_INPUTS`
+/// env variable of the job. See `windmill_worker::staged_inputs` for the format
+pub const INPUTS_ARG: &str = "_INPUTS";
+
+/// Toggles of the wrapper the script is run in, e.g. `{"stack_traces": false, "strict_args": true}`.
+/// See `ExecOptions` for the options and their defaults
+pub const EXEC_OPTIONS_ARG: &str = "_EXEC_OPTIONS";
+
+/// Keep the stdin of the script (python, deno and bash) open and write to it, line by line, the
+/// input sent to the job with `/w/{workspace}/jobs/queue/send_stdin/{id}` while it runs. The args
+/// of such jobs are never piped to their stdin
+pub const STDIN_STREAM_ARG: &str = "_STDIN_STREAM";
+
+/// Run the job as that many replicas (hedged execution), picked by different workers. The first
+/// replica to complete settles the job: its result is recorded under the id of the job and the
+/// other replicas are canceled. Only for scripts that are not flow steps, see `MAX_HEDGE_REPLICAS`
+pub const HEDGE_ARG: &str = "_HEDGE";
+
+pub const MAX_HEDGE_REPLICAS: u8 = 5;
+
 use crate::{
     error::{self, to_anyhow, Error},
     flow_status::{FlowStatus, RestartedFrom},
     flows::{FlowValue, Retry},
     get_latest_deployed_hash_for_path,
+    result_compression::{completed_job_result, decompress_result},
     scripts::{ScriptHash, ScriptLang},
     worker::{to_raw_value, TMP_DIR},
 };
@@ -108,6 +180,13 @@ pub struct QueuedJob {
     pub cache_ttl: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub priority: Option<i16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub correlation_id: Option<String>,
+    /// id of the job the replicas of a hedged job (`_HEDGE`) are run for, that job included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub hedge_group: Option<Uuid>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     #[sqlx(skip)]
@@ -153,8 +232,68 @@ impl QueuedJob {
             .as_ref()
             .and_then(|v| serde_json::from_str::<FlowStatus>((**v).get()).ok())
     }
+
+    /// Options of the wrapper of the job, from its `_EXEC_OPTIONS` arg. `_PROFILE` is kept as a
+    /// shorthand for `{"profile": true}`
+    pub fn exec_options(&self) -> error::Result<ExecOptions> {
+        let args = self.args.as_ref().map(|args| &args.0);
+        let mut options = args
+            .and_then(|args| args.get(EXEC_OPTIONS_ARG))
+            .map(|x| serde_json::from_str::<ExecOptions>(x.get()))
+            .transpose()
+            .map_err(|e| Error::BadRequest(format!("Invalid `{EXEC_OPTIONS_ARG}`: {e}")))?
+            .unwrap_or_default();
+        if args
+            .and_then(|args| args.get(PROFILE_ARG))
+            .is_some_and(|x| x.get() == "true")
+        {
+            options.profile = true;
+        }
+        Ok(options)
+    }
+}
+
+/// Per-job toggles of the code the executors generate around the script (`wrapper.py`,
+/// `wrapper.ts`), set with the `_EXEC_OPTIONS` arg. Every option defaults to the behavior of the
+/// wrappers of jobs that do not set it
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExecOptions {
+    /// reject the args whose strings contain control characters (e.g. raw newlines), which are
+    /// accepted by default. Python only, the typescript parser is always strict
+    pub strict_json: bool,
+    /// include the stack trace in the error of a failed job
+    pub stack_traces: bool,
+    /// run the entrypoint under the profiler of the language, see `_PROFILE`. Python only
+    pub profile: bool,
+    /// print `WRAPPER_RESULT_MARKER` once the result has been written, to tell from the logs a job
+    /// killed after its result was written from one killed before
+    pub result_marker: bool,
+    /// fail the job on args that are not parameters of the entrypoint instead of ignoring them.
+    /// The reserved `_UPPERCASE` args are never rejected. Python only
+    pub strict_args: bool,
+    /// do not add the `--- CODE EXECUTION ---` banner to the logs, which then only hold the output
+    /// of the script, for jobs whose logs are consumed verbatim. The result is read from the result
+    /// file either way. Python and deno only
+    pub quiet: bool,
+}
+
+impl Default for ExecOptions {
+    fn default() -> Self {
+        Self {
+            strict_json: false,
+            stack_traces: true,
+            profile: false,
+            result_marker: false,
+            strict_args: false,
+            quiet: false,
+        }
+    }
 }
 
+/// Line printed by the wrapper of the jobs run with the `result_marker` option
+pub const WRAPPER_RESULT_MARKER: &str = "--- WM RESULT WRITTEN ---";
+
 impl Default for QueuedJob {
     fn default() -> Self {
         Self {
@@ -198,6 +337,8 @@ impl Default for QueuedJob {
             flow_step_id: None,
             cache_ttl: None,
             priority: None,
+            correlation_id: None,
+            hedge_group: None,
             self_wait_time_ms: None,
             aggregate_wait_time_ms: None,
         }
@@ -253,6 +394,20 @@ pub struct CompletedJob {
     pub priority: Option<i16>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub labels: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub script_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub correlation_id: Option<String>,
+    /// id of the job the replicas of a hedged job (`_HEDGE`) are run for, that job included
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub hedge_group: Option<Uuid>,
+    /// result of a flow step stored compressed, see `result_compression`. Never sent as is
+    #[serde(skip)]
+    #[sqlx(default)]
+    pub result_compressed: Option<Vec<u8>>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     #[sqlx(skip)]
@@ -264,10 +419,14 @@ pub struct CompletedJob {
 
 impl CompletedJob {
     pub fn json_result(&self) -> Option<serde_json::Value> {
-        self.result
-            .as_ref()
-            .map(|r| serde_json::from_str(r.get()).ok())
-            .flatten()
+        match self.result.as_ref() {
+            Some(r) => serde_json::from_str(r.get()).ok(),
+            None => self
+                .result_compressed
+                .as_deref()
+                .and_then(decompress_result)
+                .and_then(|r| serde_json::from_str(r.get()).ok()),
+        }
     }
 
     pub fn parse_raw_flow(&self) -> Option<FlowValue> {
@@ -285,7 +444,9 @@ impl CompletedJob {
 
 #[derive(sqlx::FromRow)]
 pub struct BranchResults {
-    pub result: sqlx::types::Json<Box<RawValue>>,
+    pub result: Option<sqlx::types::Json<Box<RawValue>>>,
+    #[sqlx(default)]
+    pub result_compressed: Option<Vec<u8>>,
     pub id: Uuid,
 }
 
@@ -591,7 +752,7 @@ pub fn format_completed_job_result(mut cj: CompletedJob) -> CompletedJobWithForm
     let sql_result = format_result(
         cj.language.as_ref(),
         cj.flow_status.clone().map(|x| x.0),
-        cj.result.map(|x| x.0),
+        completed_job_result(cj.result.take(), cj.result_compressed.take()),
     );
     cj.result = None; // very important to avoid sending the result twice
     CompletedJobWithFormattedResult { cj, result: Some(sql_result) }
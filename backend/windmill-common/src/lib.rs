@@ -32,9 +32,11 @@ pub mod job_metrics;
 #[cfg(feature = "parquet")]
 pub mod job_s3_helpers_ee;
 pub mod jobs;
+pub mod log_compression;
 pub mod more_serde;
 pub mod oauth2;
 pub mod queue;
+pub mod result_compression;
 pub mod s3_helpers;
 pub mod schedule;
 pub mod scripts;
@@ -206,28 +208,33 @@ async fn reset() -> () {
     todo!()
 }
 
-pub async fn connect_db(
-    server_mode: bool,
-    indexer_mode: bool,
-) -> anyhow::Result<sqlx::Pool<sqlx::Postgres>> {
-    use anyhow::Context;
+async fn database_url() -> anyhow::Result<String> {
     use std::env::var;
     use tokio::fs::File;
     use tokio::io::AsyncReadExt;
 
-    let database_url = match var("DATABASE_URL_FILE") {
+    match var("DATABASE_URL_FILE") {
         Ok(file_path) => {
             let mut file = File::open(file_path).await?;
             let mut contents = String::new();
             file.read_to_string(&mut contents).await?;
-            contents.trim().to_string()
+            Ok(contents.trim().to_string())
         }
-        Err(_) => var("DATABASE_URL").map_err(|_| {
+        Err(_) => Ok(var("DATABASE_URL").map_err(|_| {
             Error::BadConfig(
                 "Either DATABASE_URL_FILE or DATABASE_URL env var is missing".to_string(),
             )
-        })?,
-    };
+        })?),
+    }
+}
+
+pub async fn connect_db(
+    server_mode: bool,
+    indexer_mode: bool,
+) -> anyhow::Result<sqlx::Pool<sqlx::Postgres>> {
+    use anyhow::Context;
+
+    let database_url = database_url().await?;
 
     let max_connections = match std::env::var("DATABASE_CONNECTIONS") {
         Ok(n) => n.parse::<u32>().context("invalid DATABASE_CONNECTIONS")?,
@@ -253,6 +260,26 @@ pub async fn connect_db(
     Ok(connect(&database_url, max_connections).await?)
 }
 
+/// Second pool of the workers, of `BOOKKEEPING_DATABASE_CONNECTIONS` connections, dedicated to
+/// the high-frequency writes of the running jobs (logs, pings, cancellation checks) so that they
+/// do not compete with pulling and completing jobs for the connections of the main pool. `None`
+/// if not set, the main pool being used for everything
+pub async fn connect_bookkeeping_db() -> anyhow::Result<Option<sqlx::Pool<sqlx::Postgres>>> {
+    use anyhow::Context;
+
+    let max_connections = match std::env::var("BOOKKEEPING_DATABASE_CONNECTIONS") {
+        Ok(n) => n
+            .parse::<u32>()
+            .context("invalid BOOKKEEPING_DATABASE_CONNECTIONS")?,
+        Err(_) => return Ok(None),
+    };
+    if max_connections == 0 {
+        return Ok(None);
+    }
+    let database_url = database_url().await?;
+    Ok(Some(connect(&database_url, max_connections).await?))
+}
+
 pub async fn connect(
     database_url: &str,
     max_connections: u32,
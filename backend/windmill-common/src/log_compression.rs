@@ -0,0 +1,128 @@
+use std::borrow::Cow;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+
+lazy_static::lazy_static! {
+    /// size, in bytes, from which the logs appended to a job in one write are stored compressed.
+    /// Not set, the logs are stored as plain text
+    pub static ref LOG_COMPRESSION_THRESHOLD: Option<usize> = std::env::var("LOG_COMPRESSION_THRESHOLD")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .filter(|x| *x > 0);
+}
+
+/// A compressed chunk is stored within the plain text logs as `CHUNK_START`, the base64 of the
+/// zstd-compressed logs, then `CHUNK_END`. Each appended chunk is compressed on its own so that
+/// the logs can still be appended to, and read from an offset, without decompressing the rest
+const CHUNK_START: &str = "\u{1}wmz:";
+const CHUNK_END: char = '\u{2}';
+
+/// replaces the chunks cut by a truncation of the logs, which cannot be decompressed
+const TRUNCATED_CHUNK: &str = "[windmill] ...truncated compressed logs...\n";
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// Logs to append to the logs of a job, as a compressed chunk if they are larger than
+/// LOG_COMPRESSION_THRESHOLD and the chunk is smaller than them
+pub fn compress_log_chunk(logs: &str) -> Cow<'_, str> {
+    match *LOG_COMPRESSION_THRESHOLD {
+        Some(threshold) if logs.len() >= threshold => {
+            match zstd::encode_all(logs.as_bytes(), ZSTD_LEVEL) {
+                Ok(compressed) => {
+                    let chunk = format!("{CHUNK_START}{}{CHUNK_END}", STANDARD.encode(compressed));
+                    if chunk.len() < logs.len() {
+                        Cow::Owned(chunk)
+                    } else {
+                        Cow::Borrowed(logs)
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("could not compress logs, storing them as plain text: {e:#}");
+                    Cow::Borrowed(logs)
+                }
+            }
+        }
+        _ => Cow::Borrowed(logs),
+    }
+}
+
+fn decompress_chunk(chunk: &str) -> Option<String> {
+    let compressed = STANDARD.decode(chunk).ok()?;
+    let logs = zstd::decode_all(compressed.as_slice()).ok()?;
+    String::from_utf8(logs).ok()
+}
+
+/// Logs as stored, possibly containing compressed chunks, as plain text. A chunk cut by a
+/// truncation of the logs (e.g. to their tail) is replaced by a notice
+pub fn decompress_log_chunks(logs: &str) -> Cow<'_, str> {
+    if !logs.contains(CHUNK_END) && !logs.contains(CHUNK_START) {
+        return Cow::Borrowed(logs);
+    }
+    let mut plain = String::with_capacity(logs.len());
+    let mut rest = logs;
+    if let Some(end) = rest.find(CHUNK_END) {
+        if rest.find(CHUNK_START).map_or(true, |start| start > end) {
+            // tail of a chunk whose start was truncated
+            plain.push_str(TRUNCATED_CHUNK);
+            rest = &rest[end + CHUNK_END.len_utf8()..];
+        }
+    }
+    while let Some(start) = rest.find(CHUNK_START) {
+        plain.push_str(&rest[..start]);
+        let chunk = &rest[start + CHUNK_START.len()..];
+        let Some(end) = chunk.find(CHUNK_END) else {
+            // head of a chunk whose end was truncated
+            plain.push_str(TRUNCATED_CHUNK);
+            return Cow::Owned(plain);
+        };
+        match decompress_chunk(&chunk[..end]) {
+            Some(logs) => plain.push_str(&logs),
+            None => plain.push_str(&rest[start..start + CHUNK_START.len() + end]),
+        }
+        rest = &chunk[end + CHUNK_END.len_utf8()..];
+    }
+    plain.push_str(rest);
+    Cow::Owned(plain)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(logs: &str) -> String {
+        let compressed = zstd::encode_all(logs.as_bytes(), ZSTD_LEVEL).unwrap();
+        format!("{CHUNK_START}{}{CHUNK_END}", STANDARD.encode(compressed))
+    }
+
+    #[test]
+    fn test_decompress_log_chunks() {
+        let logs = format!("start\n{}middle\n{}end\n", chunk("a\nb\n"), chunk("c\n"));
+        assert_eq!(
+            decompress_log_chunks(&logs),
+            "start\na\nb\nmiddle\nc\nend\n"
+        );
+        assert_eq!(decompress_log_chunks("plain\n"), "plain\n");
+    }
+
+    #[test]
+    fn test_decompress_log_chunks_truncated_head() {
+        // the logs read from an offset within the first chunk
+        let logs = format!("{}after\n{}", chunk("first\n"), chunk("second\n"));
+        let truncated = &logs[CHUNK_START.len() + 4..];
+        assert_eq!(
+            decompress_log_chunks(truncated),
+            format!("{TRUNCATED_CHUNK}after\nsecond\n")
+        );
+    }
+
+    #[test]
+    fn test_decompress_log_chunks_truncated_tail() {
+        // the logs cut within the last chunk
+        let logs = format!("before\n{}", chunk("last\n"));
+        let truncated = &logs[..logs.len() - 4];
+        assert_eq!(
+            decompress_log_chunks(truncated),
+            format!("before\n{TRUNCATED_CHUNK}")
+        );
+    }
+}
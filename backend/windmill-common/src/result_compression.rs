@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use serde_json::value::RawValue;
+use sqlx::types::Json;
+
+lazy_static::lazy_static! {
+    /// size, in bytes, from which the successful results of flow steps are stored zstd-compressed
+    /// in `completed_job.result_compressed` instead of as jsonb in `completed_job.result`. Not set,
+    /// all results are stored as jsonb
+    pub static ref FLOW_STEP_RESULT_COMPRESSION_THRESHOLD: Option<usize> = std::env::var("FLOW_STEP_RESULT_COMPRESSION_THRESHOLD")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .filter(|x| *x > 0);
+}
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// The serialized result of a successful flow step, compressed if it is larger than
+/// FLOW_STEP_RESULT_COMPRESSION_THRESHOLD and the compression shrinks it. The json text is
+/// compressed as is, so that it is read back byte for byte, big numbers included. Results with
+/// labels stay jsonb for the labels to be indexed and listed
+pub fn compress_flow_step_result(result: &str) -> Option<Vec<u8>> {
+    let threshold = (*FLOW_STEP_RESULT_COMPRESSION_THRESHOLD)?;
+    if result.len() < threshold || result.contains("\"wm_labels\"") {
+        return None;
+    }
+    match zstd::encode_all(result.as_bytes(), ZSTD_LEVEL) {
+        Ok(compressed) if compressed.len() < result.len() => Some(compressed),
+        Ok(_) => None,
+        Err(e) => {
+            tracing::error!("could not compress flow step result, storing it as jsonb: {e:#}");
+            None
+        }
+    }
+}
+
+/// A result stored compressed by [`compress_flow_step_result`]
+pub fn decompress_result(compressed: &[u8]) -> Option<Box<RawValue>> {
+    let result = zstd::decode_all(compressed)
+        .map_err(|e| tracing::error!("could not decompress result: {e:#}"))
+        .ok()?;
+    let result = String::from_utf8(result).ok()?;
+    RawValue::from_string(result)
+        .map_err(|e| tracing::error!("decompressed result is not valid json: {e:#}"))
+        .ok()
+}
+
+/// The result of a completed job, from `result` or else decompressed from `result_compressed`
+pub fn completed_job_result(
+    result: Option<Json<Box<RawValue>>>,
+    result_compressed: Option<Vec<u8>>,
+) -> Option<Box<RawValue>> {
+    result
+        .map(|x| x.0)
+        .or_else(|| result_compressed.and_then(|x| decompress_result(&x)))
+}
+
+/// A result stored compressed, at `path` like `result #> path` (the whole result for an empty path)
+pub fn decompress_result_at_path(compressed: &[u8], path: &[String]) -> Option<Box<RawValue>> {
+    raw_value_at_path(&decompress_result(compressed)?, path)
+}
+
+/// The value of a decompressed result at `path`, like `result #> path` on a jsonb result. The
+/// value is kept as raw json, without going through f64 for the numbers
+pub fn raw_value_at_path(result: &RawValue, path: &[String]) -> Option<Box<RawValue>> {
+    let mut value = result.to_owned();
+    for key in path {
+        let text = value.get().trim_start();
+        value = if text.starts_with('{') {
+            serde_json::from_str::<HashMap<String, Box<RawValue>>>(text)
+                .ok()?
+                .remove(key)?
+        } else if text.starts_with('[') {
+            let mut items = serde_json::from_str::<Vec<Box<RawValue>>>(text).ok()?;
+            // negative indexes count from the end, like in postgres
+            let idx = key.parse::<i64>().ok()?;
+            let idx = if idx < 0 {
+                items.len() as i64 + idx
+            } else {
+                idx
+            };
+            if idx < 0 || idx as usize >= items.len() {
+                return None;
+            }
+            items.swap_remove(idx as usize)
+        } else {
+            return None;
+        };
+    }
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_result_compression_roundtrip() {
+        // precise big numbers and float representations are kept as is
+        let result = r#"{"big": 123456789012345678901234567890, "f": 1.10, "s": "é"}"#;
+        let compressed = zstd::encode_all(result.as_bytes(), ZSTD_LEVEL).unwrap();
+        assert_eq!(decompress_result(&compressed).unwrap().get(), result);
+        assert!(decompress_result(b"not zstd").is_none());
+    }
+
+    #[test]
+    fn test_raw_value_at_path() {
+        let result =
+            RawValue::from_string(r#"{"a": {"b": [1, 123456789012345678901234567890]}}"#.into())
+                .unwrap();
+        let path = |p: &[&str]| p.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            raw_value_at_path(&result, &path(&["a", "b", "1"]))
+                .unwrap()
+                .get(),
+            "123456789012345678901234567890"
+        );
+        assert_eq!(
+            raw_value_at_path(&result, &path(&["a", "b", "-2"]))
+                .unwrap()
+                .get(),
+            "1"
+        );
+        assert_eq!(raw_value_at_path(&result, &[]).unwrap().get(), result.get());
+        assert!(raw_value_at_path(&result, &path(&["a", "c"])).is_none());
+        assert!(raw_value_at_path(&result, &path(&["a", "b", "2"])).is_none());
+        assert!(raw_value_at_path(&result, &path(&["a", "b", "0", "x"])).is_none());
+    }
+}
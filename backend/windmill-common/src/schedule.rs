@@ -56,6 +56,8 @@ pub struct Schedule {
     pub tag: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub paused_until: Option<DateTime<chrono::Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub overlap_policy: Option<String>,
 }
 
 impl Schedule {
@@ -64,6 +66,42 @@ impl Schedule {
     }
 }
 
+/// What to do when a scheduled job is about to start while a previous run of the same schedule
+/// is still running. When unset, runs are allowed to overlap.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ScheduleOverlapPolicy {
+    /// complete the new run as skipped
+    Skip,
+    /// put the new run back in the queue until the previous run is done
+    Queue,
+    /// cancel the previous run and start the new one
+    CancelPrevious,
+}
+
+impl ScheduleOverlapPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScheduleOverlapPolicy::Skip => "skip",
+            ScheduleOverlapPolicy::Queue => "queue",
+            ScheduleOverlapPolicy::CancelPrevious => "cancel-previous",
+        }
+    }
+}
+
+impl std::str::FromStr for ScheduleOverlapPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "skip" => Ok(ScheduleOverlapPolicy::Skip),
+            "queue" => Ok(ScheduleOverlapPolicy::Queue),
+            "cancel-previous" => Ok(ScheduleOverlapPolicy::CancelPrevious),
+            _ => Err(format!("unknown schedule overlap policy: {s}")),
+        }
+    }
+}
+
 pub fn schedule_to_user(path: &str) -> String {
     format!("schedule-{}", path.replace('/', "-"))
 }
@@ -195,6 +195,10 @@ pub struct Script {
     pub codebase: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub has_preprocessor: Option<bool>,
+    /// human-meaningful version of the script, e.g. the git tag/commit it was synced from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[sqlx(default)]
+    pub version: Option<String>,
 }
 
 #[derive(Serialize, sqlx::FromRow)]
@@ -292,6 +296,7 @@ pub struct NewScript {
     pub no_main_func: Option<bool>,
     pub codebase: Option<String>,
     pub has_preprocessor: Option<bool>,
+    pub version: Option<String>,
 }
 
 fn lock_deserialize<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
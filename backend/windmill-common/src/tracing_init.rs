@@ -9,17 +9,68 @@
 use const_format::concatcp;
 use tracing_appender::non_blocking::{NonBlockingBuilder, WorkerGuard};
 use tracing_subscriber::{
-    fmt::{format, Layer},
+    filter::{dynamic_filter_fn, LevelFilter},
+    fmt::{format, FmtContext, FormatEvent, FormattedFields, Layer},
     prelude::*,
+    registry::LookupSpan,
     EnvFilter,
 };
 
-fn json_layer<S>() -> Layer<S, format::JsonFields, format::Format<format::Json>> {
+fn json_layer<S>() -> Layer<S, format::JsonFields, FlatJsonFormat>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
     tracing_subscriber::fmt::layer()
-        .json()
-        .flatten_event(true)
-        .with_span_list(false)
-        .with_current_span(true)
+        .fmt_fields(format::JsonFields::new())
+        .event_format(FlatJsonFormat)
+}
+
+/// Json format where the fields of all the spans an event is in (e.g. `worker`, `job_id`,
+/// `workspace_id`) are flattened next to the fields of the event, so that every event emitted
+/// while handling a job carries the same fields whatever span it comes from
+struct FlatJsonFormat;
+
+impl<S> FormatEvent<S, format::JsonFields> for FlatJsonFormat
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, format::JsonFields>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let metadata = event.metadata();
+        let mut obj = serde_json::Map::new();
+        obj.insert(
+            "timestamp".to_string(),
+            chrono::Utc::now()
+                .to_rfc3339_opts(chrono::SecondsFormat::Micros, true)
+                .into(),
+        );
+        obj.insert("level".to_string(), metadata.level().as_str().into());
+        obj.insert("target".to_string(), metadata.target().into());
+
+        // from the root so that the fields of the innermost spans take precedence
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<format::JsonFields>>() {
+                    if let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(fields) {
+                        obj.extend(fields);
+                    }
+                }
+            }
+        }
+
+        let mut fields = String::new();
+        ctx.format_fields(format::Writer::new(&mut fields), event)?;
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::from_str(&fields) {
+            obj.extend(fields);
+        }
+
+        writeln!(writer, "{}", serde_json::Value::Object(obj))
+    }
 }
 
 fn compact_layer<S>() -> Layer<S, format::DefaultFields, format::Format<format::Compact>> {
@@ -44,7 +95,15 @@ pub fn initialize_tracing(hostname: &str) -> WorkerGuard {
         )
     }
 
-    let env_filter = EnvFilter::from_default_env();
+    let mut env_filter = EnvFilter::from_default_env();
+    // let the jobs whose log level is bumped emit their debug/trace events whatever RUST_LOG is
+    for level in ["debug", "trace"] {
+        env_filter = env_filter.add_directive(
+            format!("windmill[job{{{JOB_LOG_LEVEL_FIELD}={level}}}]={level}")
+                .parse()
+                .expect("valid job log level directive"),
+        );
+    }
     use tracing_appender::rolling::{RollingFileAppender, Rotation};
 
     let log_dir = format!("{}/{}/", TMP_WINDMILL_LOGS_SERVICE, hostname);
@@ -61,7 +120,9 @@ pub fn initialize_tracing(hostname: &str) -> WorkerGuard {
         .finish(file_appender);
     let stdout_and_log_file_writer = std::io::stdout.and(log_file_writer);
 
-    let ts_base = tracing_subscriber::registry().with(env_filter);
+    let ts_base = tracing_subscriber::registry()
+        .with(env_filter)
+        .with(JobLogLevelLayer);
 
     #[cfg(feature = "loki")]
     let ts_base = {
@@ -77,7 +138,7 @@ pub fn initialize_tracing(hostname: &str) -> WorkerGuard {
             .with(
                 json_layer()
                     .with_writer(stdout_and_log_file_writer)
-                    .flatten_event(true),
+                    .with_filter(dynamic_filter_fn(job_log_level_filter)),
             )
             .with(CountingLayer::new())
             .init(),
@@ -88,7 +149,8 @@ pub fn initialize_tracing(hostname: &str) -> WorkerGuard {
                     .with_ansi(style.to_lowercase() != "never")
                     .with_file(true)
                     .with_line_number(true)
-                    .with_target(false),
+                    .with_target(false)
+                    .with_filter(dynamic_filter_fn(job_log_level_filter)),
             )
             .with(CountingLayer::new())
             .init(),
@@ -96,6 +158,65 @@ pub fn initialize_tracing(hostname: &str) -> WorkerGuard {
     _guard
 }
 
+/// Span field overriding the level of the events emitted while in the span, used to make the
+/// handling of a given job more or less verbose than the rest of the worker
+pub const JOB_LOG_LEVEL_FIELD: &str = "log_level";
+
+#[derive(Clone, Copy)]
+struct JobLogLevel(LevelFilter);
+
+/// Stores the level override of a span in its extensions, see [`JOB_LOG_LEVEL_FIELD`]
+struct JobLogLevelLayer;
+
+impl<S> tracing_subscriber::Layer<S> for JobLogLevelLayer
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(
+        &self,
+        attrs: &tracing::span::Attributes<'_>,
+        id: &tracing::span::Id,
+        ctx: Context<'_, S>,
+    ) {
+        let mut visitor = JobLogLevelVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(level), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(JobLogLevel(level));
+        }
+    }
+}
+
+struct JobLogLevelVisitor(Option<LevelFilter>);
+
+impl tracing::field::Visit for JobLogLevelVisitor {
+    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+        if field.name() == JOB_LOG_LEVEL_FIELD {
+            self.0 = value.parse().ok();
+        }
+    }
+
+    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+        if field.name() == JOB_LOG_LEVEL_FIELD {
+            self.0 = format!("{value:?}").parse().ok();
+        }
+    }
+}
+
+fn job_log_level_filter<S>(metadata: &tracing::Metadata<'_>, cx: &Context<'_, S>) -> bool
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    if !metadata.is_event() {
+        return true;
+    }
+    cx.lookup_current()
+        .and_then(|span| {
+            span.scope()
+                .find_map(|s| s.extensions().get::<JobLogLevel>().copied())
+        })
+        .map_or(true, |JobLogLevel(level)| level >= *metadata.level())
+}
+
 #[cfg(feature = "flamegraph")]
 use tracing_flame::FlameLayer;
 
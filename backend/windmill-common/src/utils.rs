@@ -34,6 +34,11 @@ lazy_static::lazy_static! {
         .timeout(std::time::Duration::from_secs(20))
         .connect_timeout(std::time::Duration::from_secs(10))
         .build().unwrap();
+
+    static ref HOSTNAME: String = gethostname()
+        .to_str()
+        .map(|x| x.to_string())
+        .unwrap_or_else(|| rd_string(5));
 }
 
 #[derive(Deserialize, Clone)]
@@ -63,11 +68,9 @@ pub fn require_admin(is_admin: bool, username: &str) -> Result<()> {
     }
 }
 
+/// Hostname of the process, the same for every call even when it falls back to a random name
 pub fn hostname() -> String {
-    gethostname()
-        .to_str()
-        .map(|x| x.to_string())
-        .unwrap_or_else(|| rd_string(5))
+    HOSTNAME.clone()
 }
 
 pub fn paginate(pagination: Pagination) -> (usize, usize) {
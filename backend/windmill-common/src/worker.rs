@@ -15,7 +15,7 @@ use std::{
 use tokio::sync::RwLock;
 use windmill_macros::annotations;
 
-use crate::{error, global_settings::CUSTOM_TAGS_SETTING, server::Smtp, DB};
+use crate::{error, global_settings::CUSTOM_TAGS_SETTING, jobs::EXEC_ENV_ARG, server::Smtp, DB};
 
 lazy_static::lazy_static! {
     pub static ref WORKER_GROUP: String = std::env::var("WORKER_GROUP").unwrap_or_else(|_| "default".to_string());
@@ -61,6 +61,7 @@ lazy_static::lazy_static! {
         additional_python_paths: Default::default(),
         pip_local_dependencies: Default::default(),
         env_vars: Default::default(),
+        fair_workspace_scheduling: Default::default(),
     }));
 
     pub static ref WORKER_PULL_QUERIES: Arc<RwLock<Vec<String>>> = Arc::new(RwLock::new(vec![]));
@@ -72,6 +73,14 @@ lazy_static::lazy_static! {
 
     pub static ref CLOUD_HOSTED: bool = std::env::var("CLOUD_HOSTED").is_ok();
 
+    /// refuse to start a worker whose name is already used instead of suffixing its name
+    pub static ref REQUIRE_UNIQUE_WORKER_NAMES: bool = std::env::var("REQUIRE_UNIQUE_WORKER_NAMES")
+        .ok()
+        .is_some_and(|x| x == "1" || x == "true");
+
+    /// names of the workers of this process, whose first ping may not be written yet
+    static ref CLAIMED_WORKER_NAMES: std::sync::Mutex<HashSet<String>> = std::sync::Mutex::new(HashSet::new());
+
     pub static ref CUSTOM_TAGS: Vec<String> = std::env::var("CUSTOM_TAGS")
         .ok()
         .map(|x| x.split(',').map(|x| x.to_string()).collect::<Vec<_>>()).unwrap_or_default();
@@ -83,6 +92,22 @@ lazy_static::lazy_static! {
 
     static ref CUSTOM_TAG_REGEX: Regex =  Regex::new(r"^(\w+)\(((?:\w+)\+?)+\)$").unwrap();
 
+    /// Soft max number of dependency jobs running at the same time across all workers, so that a
+    /// flurry of script deployments does not starve normal jobs. Unlimited by default
+    static ref MAX_CONCURRENT_DEPENDENCY_JOBS: Option<i64> = std::env::var("MAX_CONCURRENT_DEPENDENCY_JOBS")
+        .ok()
+        .and_then(|x| x.parse().ok());
+
+    /// execution environments provided by this worker, jobs requiring another one are left in the queue
+    pub static ref WORKER_EXEC_ENVS: Vec<String> = std::env::var("WORKER_EXEC_ENVS")
+        .ok()
+        .map(|x| x
+            .split(',')
+            .map(|x| x.trim().to_string())
+            .filter(|x| !x.is_empty() && x.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_' || c == '.'))
+            .collect::<Vec<_>>())
+        .unwrap_or_default();
+
     pub static ref DISABLE_BUNDLING: bool = std::env::var("DISABLE_BUNDLING")
     .ok()
     .and_then(|x| x.parse::<bool>().ok())
@@ -104,7 +129,7 @@ pub async fn make_suspended_pull_query(wc: &WorkerConfig) {
             WHERE id = (
                 SELECT id
                 FROM queue
-                WHERE suspend_until IS NOT NULL AND (suspend <= 0 OR suspend_until <= now()) AND tag IN ({})
+                WHERE suspend_until IS NOT NULL AND (suspend <= 0 OR suspend_until <= now()) AND tag IN ({}) AND {} AND {COMPUTE_BUDGET_FILTER}
                 ORDER BY priority DESC NULLS LAST, created_at
                 FOR UPDATE SKIP LOCKED
                 LIMIT 1
@@ -115,19 +140,55 @@ pub async fn make_suspended_pull_query(wc: &WorkerConfig) {
             flow_status,  raw_flow,  is_flow_step,  language,  suspend,  suspend_until,
             same_worker,  raw_lock,  pre_run_error,  email,  visible_to_owner,  mem_peak,
              root_job,  leaf_jobs,  tag,  concurrent_limit,  concurrency_time_window_s,
-             timeout,  flow_step_id,  cache_ttl, priority", wc.worker_tags.iter().map(|x| format!("'{x}'")).join(", "));
+             timeout,  flow_step_id,  cache_ttl, priority", wc.worker_tags.iter().map(|x| format!("'{x}'")).join(", "), exec_env_filter());
     let mut l = WORKER_SUSPENDED_PULL_QUERY.write().await;
     *l = query;
 }
 
-pub async fn make_pull_query(wc: &WorkerConfig) {
-    let mut queries = vec![];
-    for tags in wc.priority_tags_sorted.iter() {
-        if tags.tags.len() == 0 {
-            tracing::error!("Empty tags in priority tags, skipping");
-            continue;
-        }
-        let query = format!("UPDATE queue
+/// Filter on the execution environment required by the job, see [`EXEC_ENV_ARG`]
+pub fn exec_env_filter() -> String {
+    if WORKER_EXEC_ENVS.is_empty() {
+        format!("args->>'{EXEC_ENV_ARG}' IS NULL")
+    } else {
+        format!(
+            "(args->>'{EXEC_ENV_ARG}' IS NULL OR args->>'{EXEC_ENV_ARG}' IN ({}))",
+            WORKER_EXEC_ENVS.iter().map(|x| format!("'{x}'")).join(", ")
+        )
+    }
+}
+
+/// Filter leaving the dependency jobs in the queue while MAX_CONCURRENT_DEPENDENCY_JOBS of them are
+/// running. This is a best-effort soft limit: concurrent claims do not see each other's uncommitted
+/// `running = true`, so a burst of workers claiming at the same instant can exceed it
+pub fn dependency_jobs_filter() -> String {
+    match *MAX_CONCURRENT_DEPENDENCY_JOBS {
+        Some(max) => format!(
+            "(job_kind NOT IN ('dependencies', 'flowdependencies', 'appdependencies')
+                OR (SELECT COUNT(*) FROM queue WHERE running = true
+                    AND job_kind IN ('dependencies', 'flowdependencies', 'appdependencies')) < {max})"
+        ),
+        None => "true".to_string(),
+    }
+}
+
+/// Jobs of the workspaces whose compute budget is exhausted are not claimed until it resets
+pub const COMPUTE_BUDGET_FILTER: &str = "workspace_id NOT IN (SELECT workspace_id FROM workspace_settings WHERE compute_budget_exhausted)";
+
+/// Claim query of the jobs of the given tags. By default, jobs are claimed by priority then by
+/// scheduled time. With `fair_workspace_scheduling`, jobs of the same priority are claimed
+/// round-robin across the workspaces with pending jobs, starting with the workspace that was
+/// served the longest ago (tracked in `workspace_pull_turn`), so that a burst of jobs in one
+/// workspace cannot monopolize the workers. This is more expensive as it cannot stop at the first
+/// job of the index.
+pub fn make_pull_query_for_tags(tags: &[String], fair_workspace_scheduling: bool) -> String {
+    let order_by = if fair_workspace_scheduling {
+        "priority DESC NULLS LAST,
+            (SELECT last_pulled_at FROM workspace_pull_turn t WHERE t.workspace_id = queue.workspace_id) NULLS FIRST,
+            scheduled_for"
+    } else {
+        "priority DESC NULLS LAST, scheduled_for"
+    };
+    let query = format!("UPDATE queue
         SET running = true
         , started_at = coalesce(started_at, now())
         , last_ping = now()
@@ -135,8 +196,8 @@ pub async fn make_pull_query(wc: &WorkerConfig) {
         WHERE id = (
             SELECT id
             FROM queue
-            WHERE running = false AND tag IN ({}) AND scheduled_for <= now()
-            ORDER BY priority DESC NULLS LAST, scheduled_for
+            WHERE running = false AND tag IN ({}) AND scheduled_for <= now() AND {} AND {COMPUTE_BUDGET_FILTER} AND {}
+            ORDER BY {order_by}
             FOR UPDATE SKIP LOCKED
             LIMIT 1
         )
@@ -146,9 +207,34 @@ pub async fn make_pull_query(wc: &WorkerConfig) {
         flow_status,  raw_flow,  is_flow_step,  language,  suspend,  suspend_until,
         same_worker,  raw_lock,  pre_run_error,  email,  visible_to_owner,  mem_peak,
          root_job,  leaf_jobs,  tag,  concurrent_limit,  concurrency_time_window_s,
-         timeout,  flow_step_id,  cache_ttl, priority", tags.tags.iter().map(|x| format!("'{x}'")).join(", "));
+         timeout,  flow_step_id,  cache_ttl, priority", tags.iter().map(|x| format!("'{x}'")).join(", "), exec_env_filter(), dependency_jobs_filter());
+
+    if fair_workspace_scheduling {
+        format!(
+            "WITH pulled AS ({query}),
+            turn AS (
+                INSERT INTO workspace_pull_turn (workspace_id, last_pulled_at)
+                SELECT workspace_id, clock_timestamp() FROM pulled
+                ON CONFLICT (workspace_id) DO UPDATE SET last_pulled_at = EXCLUDED.last_pulled_at
+            )
+            SELECT * FROM pulled"
+        )
+    } else {
+        query
+    }
+}
 
-        queries.push(query);
+pub async fn make_pull_query(wc: &WorkerConfig) {
+    let mut queries = vec![];
+    for tags in wc.priority_tags_sorted.iter() {
+        if tags.tags.len() == 0 {
+            tracing::error!("Empty tags in priority tags, skipping");
+            continue;
+        }
+        queries.push(make_pull_query_for_tags(
+            &tags.tags,
+            wc.fair_workspace_scheduling,
+        ));
     }
 
     let mut l = WORKER_PULL_QUERIES.write().await;
@@ -548,6 +634,41 @@ pub fn get_windmill_memory_usage() -> Option<i64> {
     }
 }
 
+/// a worker whose last ping is older than this is considered gone, and its name free
+const WORKER_NAME_PING_EXPIRY_SECS: i64 = 60;
+
+/// Make sure no other running worker has the same name, the pings and the metrics (labeled with
+/// the name) of both would be mixed up, making one of them invisible. The name is suffixed until
+/// unique, or rejected if REQUIRE_UNIQUE_WORKER_NAMES is set
+pub async fn claim_worker_name(db: &DB, worker_name: String) -> error::Result<String> {
+    let mut name = worker_name.clone();
+    loop {
+        let in_use = CLAIMED_WORKER_NAMES.lock().unwrap().contains(&name)
+            || sqlx::query_scalar::<_, bool>(
+                "SELECT EXISTS(SELECT 1 FROM worker_ping WHERE worker = $1 \
+                 AND ping_at > now() - make_interval(secs => $2))",
+            )
+            .bind(&name)
+            .bind(WORKER_NAME_PING_EXPIRY_SECS as f64)
+            .fetch_one(db)
+            .await?;
+        if !in_use && CLAIMED_WORKER_NAMES.lock().unwrap().insert(name.clone()) {
+            if name != worker_name {
+                tracing::warn!(
+                    "worker name {worker_name} is already used by a running worker, starting as {name} instead"
+                );
+            }
+            return Ok(name);
+        }
+        if *REQUIRE_UNIQUE_WORKER_NAMES {
+            return Err(error::Error::BadConfig(format!(
+                "worker name {name} is already used by a running worker and REQUIRE_UNIQUE_WORKER_NAMES is set"
+            )));
+        }
+        name = format!("{worker_name}-{}", crate::utils::rd_string(4));
+    }
+}
+
 pub async fn update_ping(worker_instance: &str, worker_name: &str, ip: &str, db: &DB) {
     let (tags, dw) = {
         let wc = WORKER_CONFIG.read().await.clone();
@@ -767,6 +888,11 @@ pub async fn load_worker_config(
                 .map(|x| x.split(':').map(|x| x.to_string()).collect())
         }),
         env_vars: resolved_env_vars,
+        fair_workspace_scheduling: config.fair_workspace_scheduling.unwrap_or_else(|| {
+            std::env::var("FAIR_WORKSPACE_SCHEDULING")
+                .ok()
+                .is_some_and(|x| x == "1" || x == "true")
+        }),
     })
 }
 
@@ -787,6 +913,7 @@ pub struct WorkerConfigOpt {
     pub pip_local_dependencies: Option<Vec<String>>,
     pub env_vars_static: Option<HashMap<String, String>>,
     pub env_vars_allowlist: Option<Vec<String>>,
+    pub fair_workspace_scheduling: Option<bool>,
 }
 
 impl Default for WorkerConfigOpt {
@@ -801,6 +928,7 @@ impl Default for WorkerConfigOpt {
             pip_local_dependencies: Default::default(),
             env_vars_static: Default::default(),
             env_vars_allowlist: Default::default(),
+            fair_workspace_scheduling: Default::default(),
         }
     }
 }
@@ -815,6 +943,7 @@ pub struct WorkerConfig {
     pub additional_python_paths: Option<Vec<String>>,
     pub pip_local_dependencies: Option<Vec<String>>,
     pub env_vars: HashMap<String, String>,
+    pub fair_workspace_scheduling: bool,
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -0,0 +1,130 @@
+/*
+ * Author: Ruben Fiszel
+ * Copyright: Windmill Labs, Inc 2024
+ * This file and its contents are licensed under the AGPLv3 License.
+ * Please see the included NOTICE for copyright information and
+ * LICENSE-AGPL for a copy of the license.
+ */
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+use windmill_common::{error, jobs::QueuedJob, DB};
+
+use crate::{pull, requeue_job, CanceledBy};
+
+/// State of a running job as seen when renewing its lease
+#[derive(Debug, Clone)]
+pub enum LeaseStatus {
+    Running,
+    Canceled(CanceledBy),
+    /// the job is not in the queue anymore
+    AlreadyCompleted,
+}
+
+/// Claim/lease/cancel-signal surface of the job queue used by the workers.
+///
+/// Only the interactions needed to hand out jobs to workers and to signal cancellation are
+/// abstracted, the job data itself (args, results, logs, ...) stays in Postgres whatever the
+/// backend.
+#[axum::async_trait]
+pub trait JobQueue: Send + Sync {
+    /// Claim the next job that this worker can run and mark it as running.
+    /// Also returns whether the job was a suspended job being resumed.
+    async fn claim(&self, suspend_first: bool) -> error::Result<(Option<QueuedJob>, bool)>;
+
+    /// Renew the lease of a running job, reporting the memory peak, and return whether it has
+    /// been canceled in the meantime
+    async fn renew_lease(&self, job_id: Uuid, mem_peak: i32) -> error::Result<LeaseStatus>;
+
+    /// Signal a running job that it must be canceled, e.g. when it timed out. Unlike an explicit
+    /// cancel (`cancel_job`), the descendants of the job are left running
+    async fn signal_cancel(&self, job_id: Uuid, canceled_by: CanceledBy) -> error::Result<()>;
+
+    /// Put a claimed job back in the queue, to be claimed again from `scheduled_for`
+    async fn requeue(&self, job: &QueuedJob, scheduled_for: DateTime<Utc>) -> error::Result<()>;
+
+    /// Pool dedicated to the background writes of the running jobs (log flushes, pings,
+    /// metrics), if distinct from the main one
+    fn bookkeeping_db(&self) -> Option<&DB> {
+        None
+    }
+}
+
+/// Default backend, the queue is the `queue` table
+pub struct PostgresJobQueue<R> {
+    pub db: DB,
+    pub rsmq: Option<R>,
+    /// see `windmill_common::connect_bookkeeping_db`, the leases and cancel signals go through it
+    /// as well when set
+    pub bookkeeping_db: Option<DB>,
+}
+
+impl<R> PostgresJobQueue<R> {
+    pub fn new(db: DB, rsmq: Option<R>) -> Self {
+        Self { db, rsmq, bookkeeping_db: None }
+    }
+
+    pub fn with_bookkeeping_db(self, bookkeeping_db: Option<DB>) -> Self {
+        Self { bookkeeping_db, ..self }
+    }
+}
+
+#[axum::async_trait]
+impl<R: rsmq_async::RsmqConnection + Send + Sync + Clone> JobQueue for PostgresJobQueue<R> {
+    async fn claim(&self, suspend_first: bool) -> error::Result<(Option<QueuedJob>, bool)> {
+        pull(&self.db, self.rsmq.clone(), suspend_first).await
+    }
+
+    async fn renew_lease(&self, job_id: Uuid, mem_peak: i32) -> error::Result<LeaseStatus> {
+        let db = self.bookkeeping_db.as_ref().unwrap_or(&self.db);
+        renew_job_lease(db, job_id, mem_peak).await
+    }
+
+    async fn signal_cancel(&self, job_id: Uuid, canceled_by: CanceledBy) -> error::Result<()> {
+        let db = self.bookkeeping_db.as_ref().unwrap_or(&self.db);
+        signal_job_cancel(db, job_id, canceled_by).await
+    }
+
+    async fn requeue(&self, job: &QueuedJob, scheduled_for: DateTime<Utc>) -> error::Result<()> {
+        requeue_job(&self.db, self.rsmq.clone(), job, scheduled_for).await
+    }
+
+    fn bookkeeping_db(&self) -> Option<&DB> {
+        self.bookkeeping_db.as_ref()
+    }
+}
+
+/// Postgres implementation of [`JobQueue::renew_lease`], usable without an rsmq connection
+pub async fn renew_job_lease(db: &DB, job_id: Uuid, mem_peak: i32) -> error::Result<LeaseStatus> {
+    let row = sqlx::query_as::<_, (bool, Option<String>, Option<String>)>(
+        "UPDATE queue SET mem_peak = $1, last_ping = now() WHERE id = $2 RETURNING canceled, canceled_by, canceled_reason",
+    )
+    .bind(mem_peak)
+    .bind(job_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(match row {
+        // if the job is not in queue, it can only be in the completed_job so it is already complete
+        None => LeaseStatus::AlreadyCompleted,
+        Some((true, username, reason)) => LeaseStatus::Canceled(CanceledBy { username, reason }),
+        Some((false, _, _)) => LeaseStatus::Running,
+    })
+}
+
+/// Postgres implementation of [`JobQueue::signal_cancel`], usable without an rsmq connection
+pub async fn signal_job_cancel(
+    db: &DB,
+    job_id: Uuid,
+    canceled_by: CanceledBy,
+) -> error::Result<()> {
+    sqlx::query(
+        "UPDATE queue SET canceled = true, canceled_by = $1, canceled_reason = $2 WHERE id = $3",
+    )
+    .bind(canceled_by.username)
+    .bind(canceled_by.reason)
+    .bind(job_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
@@ -6,7 +6,12 @@
  * LICENSE-AGPL for a copy of the license.
  */
 
-use std::{borrow::Borrow, collections::HashMap, sync::Arc, vec};
+use std::{
+    borrow::Borrow,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    vec,
+};
 
 use anyhow::Context;
 use async_recursion::async_recursion;
@@ -52,14 +57,20 @@ use windmill_common::{
     },
     jobs::{
         get_payload_tag_from_prefixed_path, CompletedJob, JobKind, JobPayload, QueuedJob, RawCode,
-        ENTRYPOINT_OVERRIDE, PREPROCESSOR_FAKE_ENTRYPOINT,
+        CORRELATION_ID_ARG, ENTRYPOINT_OVERRIDE, HEDGE_ARG, MAX_HEDGE_REPLICAS,
+        PREPROCESSOR_FAKE_ENTRYPOINT,
+    },
+    log_compression::compress_log_chunk,
+    result_compression::{
+        completed_job_result, compress_flow_step_result, decompress_result_at_path,
     },
     schedule::Schedule,
     scripts::{get_full_hub_script_by_path, ScriptHash, ScriptLang},
     users::{SUPERADMIN_NOTIFICATION_EMAIL, SUPERADMIN_SECRET_EMAIL},
     utils::{not_found_if_none, report_critical_error, StripPath},
     worker::{
-        to_raw_value, DEFAULT_TAGS_PER_WORKSPACE, DEFAULT_TAGS_WORKSPACES, NO_LOGS, WORKER_CONFIG,
+        dependency_jobs_filter, exec_env_filter, to_raw_value, COMPUTE_BUDGET_FILTER,
+        DEFAULT_TAGS_PER_WORKSPACE, DEFAULT_TAGS_WORKSPACES, NO_LOGS, WORKER_CONFIG,
         WORKER_PULL_QUERIES, WORKER_SUSPENDED_PULL_QUERY,
     },
     DB, METRICS_ENABLED,
@@ -119,6 +130,12 @@ lazy_static::lazy_static! {
         .ok()
         .and_then(|x| x.parse().ok())
         .unwrap_or(false);
+
+    /// Results bigger than this get a bounded preview stored alongside them
+    static ref RESULT_PREVIEW_MAX_BYTES: usize = std::env::var("RESULT_PREVIEW_MAX_BYTES")
+        .ok()
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(2048);
 }
 
 #[cfg(feature = "cloud")]
@@ -136,12 +153,19 @@ const SCHEDULE_ERROR_HANDLER_USER_EMAIL: &str = "schedule_error_handler@windmill
 #[cfg(feature = "enterprise")]
 const SCHEDULE_RECOVERY_HANDLER_USER_EMAIL: &str = "schedule_recovery_handler@windmill.dev";
 
+/// Size of the `correlation_id` columns
+const MAX_CORRELATION_ID_LENGTH: usize = 255;
+
 #[derive(Clone, Debug)]
 pub struct CanceledBy {
     pub username: Option<String>,
     pub reason: Option<String>,
 }
 
+/// Maximum depth of the parent_job chain that an explicit cancel is cascaded to. Guards against
+/// malformed (e.g. cyclic) parent chains
+pub const MAX_CANCEL_CASCADE_DEPTH: usize = 100;
+
 pub async fn cancel_single_job<'c>(
     username: &str,
     reason: Option<String>,
@@ -175,6 +199,7 @@ pub async fn cancel_single_job<'c>(
                 &job_running,
                 job_running.mem_peak.unwrap_or(0),
                 Some(CanceledBy { username: Some(username.to_string()), reason: Some(reason) }),
+                JobRunRecords::default(),
                 e,
                 rsmq.clone(),
                 "server",
@@ -253,10 +278,17 @@ pub async fn cancel_job<'c>(
     let job = Arc::new(job);
 
     // get all children
-    let mut jobs = vec![job.id];
+    let mut jobs = vec![(job.id, 0)];
     let mut jobs_to_cancel = vec![];
-    while !jobs.is_empty() {
-        let p_job = jobs.pop();
+    let mut visited = HashSet::from([job.id]);
+    while let Some((p_job, depth)) = jobs.pop() {
+        if depth >= MAX_CANCEL_CASCADE_DEPTH {
+            tracing::warn!(
+                "not cascading cancel of {} to the children of {p_job}: max depth of {MAX_CANCEL_CASCADE_DEPTH} reached",
+                job.id
+            );
+            continue;
+        }
         let new_jobs = sqlx::query_scalar!(
             "SELECT id FROM queue WHERE parent_job = $1 AND workspace_id = $2",
             p_job,
@@ -264,8 +296,13 @@ pub async fn cancel_job<'c>(
         )
         .fetch_all(&mut *tx)
         .await?;
-        jobs.extend(new_jobs.clone());
-        jobs_to_cancel.extend(new_jobs);
+        for new_job in new_jobs {
+            // a malformed parent chain could loop back on an already visited job
+            if visited.insert(new_job) {
+                jobs.push((new_job, depth + 1));
+                jobs_to_cancel.push(new_job);
+            }
+        }
     }
     jobs.reverse();
 
@@ -304,6 +341,36 @@ pub async fn cancel_job<'c>(
     Ok((tx, Some(id)))
 }
 
+lazy_static::lazy_static! {
+    /// logs of the jobs run inline, see [`start_inline_job`], kept in memory instead of `job_logs`
+    static ref INLINE_JOB_LOGS: std::sync::Mutex<HashMap<Uuid, String>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// Mark a job as run inline: it is not in the queue, so nothing about it is written to the
+/// database, and its logs are buffered in memory until [`finish_inline_job`]
+pub fn start_inline_job(job_id: Uuid) {
+    INLINE_JOB_LOGS
+        .lock()
+        .unwrap()
+        .insert(job_id, String::new());
+}
+
+/// Stop tracking a job run inline and return its logs
+pub fn finish_inline_job(job_id: &Uuid) -> String {
+    INLINE_JOB_LOGS
+        .lock()
+        .unwrap()
+        .remove(job_id)
+        .unwrap_or_default()
+}
+
+/// Whether the job only exists on this worker (the nil job id or a job run inline), in which
+/// case its progress must not be written to the database
+pub fn is_local_job(job_id: &Uuid) -> bool {
+    job_id.is_nil() || INLINE_JOB_LOGS.lock().unwrap().contains_key(job_id)
+}
+
 /* TODO retry this? */
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn append_logs(
@@ -321,10 +388,17 @@ pub async fn append_logs(
         return;
     }
 
+    if let Some(inline_logs) = INLINE_JOB_LOGS.lock().unwrap().get_mut(job_id) {
+        inline_logs.push_str(logs.as_ref());
+        return;
+    }
+
     if *NO_LOGS {
         tracing::info!("NO LOGS [{job_id}]: {}", logs.as_ref());
         return;
     }
+    // each append is compressed on its own, see `decompress_log_chunks` for the reads
+    let logs = compress_log_chunk(logs.as_ref());
     if let Err(err) = sqlx::query!(
         "INSERT INTO job_logs (logs, job_id, workspace_id) VALUES ($1, $2, $3) ON CONFLICT (job_id) DO UPDATE SET logs = concat(job_logs.logs, $1::text)",
         logs.as_ref(),
@@ -495,6 +569,7 @@ pub async fn add_completed_job_error<R: rsmq_async::RsmqConnection + Clone + Sen
     queued_job: &QueuedJob,
     mem_peak: i32,
     canceled_by: Option<CanceledBy>,
+    records: JobRunRecords,
     e: serde_json::Value,
     rsmq: Option<R>,
     _worker_name: &str,
@@ -535,6 +610,7 @@ pub async fn add_completed_job_error<R: rsmq_async::RsmqConnection + Clone + Sen
         Json(&result),
         mem_peak,
         canceled_by,
+        records,
         rsmq,
         flow_is_done,
         #[cfg(feature = "benchmark")]
@@ -548,6 +624,392 @@ lazy_static::lazy_static! {
     pub static ref GLOBAL_ERROR_HANDLER_PATH_IN_ADMINS_WORKSPACE: Option<String> = std::env::var("GLOBAL_ERROR_HANDLER_PATH_IN_ADMINS_WORKSPACE").ok();
 }
 
+/// Bounded summary of a large result so that it can be rendered without fetching the whole result:
+/// its size, its first `max_bytes` bytes and, for objects and arrays, its top-level keys or length.
+/// Returns None if the result is small enough to be returned as is.
+pub fn compute_result_preview(result: &str, max_bytes: usize) -> Option<serde_json::Value> {
+    const MAX_PREVIEW_KEYS: usize = 50;
+
+    if result.len() <= max_bytes {
+        return None;
+    }
+    let mut head_len = max_bytes;
+    while !result.is_char_boundary(head_len) {
+        head_len -= 1;
+    }
+    let mut preview = json!({ "size": result.len(), "head": &result[..head_len] });
+
+    if let Ok(obj) = serde_json::from_str::<HashMap<String, &RawValue>>(result) {
+        let keys = obj.keys().sorted().take(MAX_PREVIEW_KEYS).collect_vec();
+        preview["type"] = json!("object");
+        preview["keys"] = json!(keys);
+        preview["keys_count"] = json!(obj.len());
+    } else if let Ok(arr) = serde_json::from_str::<Vec<&RawValue>>(result) {
+        preview["type"] = json!("array");
+        preview["length"] = json!(arr.len());
+    }
+    Some(preview)
+}
+
+/// Prefix of the log lines a script can print to emit a metric: `metric: <name>=<value>` sets it
+/// (gauge), `metric: <name>+=<value>` adds to it (counter). Names are made of alphanumeric
+/// characters, `_`, `.` and `-` and values are finite numbers
+pub const METRIC_LINE_PREFIX: &str = "metric:";
+pub const MAX_SCRIPT_METRICS: usize = 100;
+const MAX_METRIC_NAME_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub struct MetricLine<'a> {
+    pub name: &'a str,
+    pub value: f64,
+    pub increment: bool,
+}
+
+/// Parse a `metric: <name>=<value>` or `metric: <name>+=<value>` line. Returns None if the line
+/// is not a metric line and an error describing the problem if it is a malformed one
+pub fn parse_metric_line(line: &str) -> Option<Result<MetricLine, String>> {
+    let rest = line.trim().strip_prefix(METRIC_LINE_PREFIX)?.trim();
+    let Some((name, value)) = rest.split_once('=') else {
+        return Some(Err(
+            "expected `<name>=<value>` or `<name>+=<value>`".to_string()
+        ));
+    };
+    let (name, increment) = match name.strip_suffix('+') {
+        Some(name) => (name.trim(), true),
+        None => (name.trim(), false),
+    };
+    if name.is_empty()
+        || name.len() > MAX_METRIC_NAME_LEN
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        return Some(Err(format!(
+            "invalid metric name `{name}`, it must be 1 to {MAX_METRIC_NAME_LEN} alphanumeric characters, `_`, `.` or `-`"
+        )));
+    }
+    match value.trim().parse::<f64>() {
+        Ok(value) if value.is_finite() => Some(Ok(MetricLine { name, value, increment })),
+        _ => Some(Err(format!(
+            "invalid value `{}` for metric `{name}`, it must be a number",
+            value.trim()
+        ))),
+    }
+}
+
+fn metric_json_value(value: f64) -> serde_json::Value {
+    if value.fract() == 0.0 && value.abs() < (1u64 << 53) as f64 {
+        json!(value as i64)
+    } else {
+        json!(value)
+    }
+}
+
+/// Prefix of the log lines a script can print to surface a non-fatal warning, shown apart from
+/// the logs and the result: `warning: <message>`. Messages longer than MAX_WARNING_LEN are
+/// truncated and a job retains at most MAX_SCRIPT_WARNINGS of them
+pub const WARNING_LINE_PREFIX: &str = "warning:";
+pub const MAX_SCRIPT_WARNINGS: usize = 50;
+const MAX_WARNING_LEN: usize = 1000;
+
+/// Parse a `warning: <message>` line. Returns None if the line is not a warning line and an
+/// error describing the problem if it is a malformed one
+pub fn parse_warning_line(line: &str) -> Option<Result<String, String>> {
+    let message = line.trim().strip_prefix(WARNING_LINE_PREFIX)?.trim();
+    if message.is_empty() {
+        return Some(Err("expected `warning: <message>`".to_string()));
+    }
+    if message.len() <= MAX_WARNING_LEN {
+        return Some(Ok(message.to_string()));
+    }
+    let mut end = MAX_WARNING_LEN;
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    Some(Ok(format!("{}...", &message[..end])))
+}
+
+/// Package the dependencies of a job resolved to, persisted in `completed_job.installed_packages`
+/// when the job completes
+#[derive(Serialize, Debug, Clone)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub version: String,
+    /// `install` if it was installed for the job, `cache` or `distributed_cache` if an install
+    /// of a previous job was reused
+    pub source: &'static str,
+}
+
+/// Data recorded by a job while it runs, carried with its result to its completion where it is
+/// persisted in `completed_job`
+#[derive(Debug, Clone, Default)]
+pub struct JobRunRecords {
+    metrics: serde_json::Map<String, serde_json::Value>,
+    warnings: Vec<String>,
+    /// warnings dropped over MAX_SCRIPT_WARNINGS
+    dropped_warnings: usize,
+    installed_packages: Vec<InstalledPackage>,
+    profile: Option<Vec<u8>>,
+    /// first and last time the job produced a line of output
+    log_timestamps: Option<(DateTime<Utc>, DateTime<Utc>)>,
+}
+
+impl JobRunRecords {
+    /// Record a metric emitted by the job. Returns an error if the job already emitted
+    /// MAX_SCRIPT_METRICS other metrics
+    pub fn record_metric(&mut self, metric: MetricLine) -> Result<(), String> {
+        let current = self.metrics.get(metric.name).and_then(|v| v.as_f64());
+        if current.is_none() && self.metrics.len() >= MAX_SCRIPT_METRICS {
+            return Err(format!(
+                "ignoring metric `{}`, a job can emit at most {MAX_SCRIPT_METRICS} metrics",
+                metric.name
+            ));
+        }
+        let value = match current {
+            Some(current) if metric.increment => current + metric.value,
+            _ => metric.value,
+        };
+        self.metrics
+            .insert(metric.name.to_string(), metric_json_value(value));
+        Ok(())
+    }
+
+    /// Record a warning emitted by the job. Returns an error the first time the job goes over
+    /// MAX_SCRIPT_WARNINGS, the following warnings being only counted
+    pub fn record_warning(&mut self, warning: String) -> Result<(), String> {
+        if self.warnings.len() < MAX_SCRIPT_WARNINGS {
+            self.warnings.push(warning);
+            return Ok(());
+        }
+        self.dropped_warnings += 1;
+        if self.dropped_warnings == 1 {
+            Err(format!(
+                "a job can emit at most {MAX_SCRIPT_WARNINGS} warnings, the next ones are not retained"
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Record the packages installed for the job, added to the ones it already recorded
+    pub fn record_installed_packages(&mut self, packages: Vec<InstalledPackage>) {
+        self.installed_packages.extend(packages);
+    }
+
+    /// Record the profile of the job, run with `_PROFILE`
+    pub fn record_profile(&mut self, profile: Vec<u8>) {
+        self.profile = Some(profile);
+    }
+
+    /// Record that the job produced a line of output now
+    pub fn record_log_line(&mut self) {
+        let now = Utc::now();
+        match self.log_timestamps.as_mut() {
+            Some((_, last)) => *last = now,
+            None => self.log_timestamps = Some((now, now)),
+        }
+    }
+
+    fn metrics(&self) -> Option<&serde_json::Map<String, serde_json::Value>> {
+        (!self.metrics.is_empty()).then_some(&self.metrics)
+    }
+
+    fn warnings(&self) -> Option<Vec<String>> {
+        if self.warnings.is_empty() {
+            return None;
+        }
+        let mut warnings = self.warnings.clone();
+        if self.dropped_warnings > 0 {
+            warnings.push(format!(
+                "{} more warnings not retained",
+                self.dropped_warnings
+            ));
+        }
+        Some(warnings)
+    }
+
+    fn installed_packages(&self) -> Option<&Vec<InstalledPackage>> {
+        (!self.installed_packages.is_empty()).then_some(&self.installed_packages)
+    }
+}
+
+/// How the completion of a job is recorded, a replica of a hedged job (`_HEDGE`) not always
+/// being recorded under its own id
+enum HedgeCompletion {
+    /// not hedged, the winning replica being the job itself or a losing replica
+    Own,
+    /// the job lost to a replica whose result was already recorded under the id of the job
+    Settled,
+    /// the replica won: its result is recorded under the id of the job it was run for
+    AsPrimary(QueuedJob),
+}
+
+const HEDGE_CANCELED_BY: &str = "hedge";
+
+/// Settle the group of a replica of a hedged job if it is the first to complete. Concurrent
+/// completions are serialized on the primary key of `hedge_group_winner` so that exactly one
+/// replica records the result under the id of the job. The other replicas are soft canceled:
+/// the running ones are killed by their worker and the others are completed as canceled as soon
+/// as they are pulled, both being recorded under their own id
+async fn complete_hedged_job<R: rsmq_async::RsmqConnection + Clone + Send>(
+    tx: &mut QueueTransaction<'_, R>,
+    queued_job: &QueuedJob,
+    canceled: bool,
+) -> error::Result<HedgeCompletion> {
+    let Some(group) = queued_job.hedge_group else {
+        return Ok(HedgeCompletion::Own);
+    };
+    let is_primary = queued_job.id == group;
+    // a canceled replica, other than the job itself, lost the race or was canceled on its own
+    if canceled && !is_primary {
+        return Ok(HedgeCompletion::Own);
+    }
+
+    let won = sqlx::query_scalar::<_, Uuid>(
+        "INSERT INTO hedge_group_winner (group_id, job_id) VALUES ($1, $2)
+        ON CONFLICT (group_id) DO NOTHING RETURNING job_id",
+    )
+    .bind(group)
+    .bind(queued_job.id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .is_some();
+    if !won {
+        return Ok(if is_primary {
+            HedgeCompletion::Settled
+        } else {
+            HedgeCompletion::Own
+        });
+    }
+
+    let siblings = sqlx::query_scalar::<_, Uuid>(
+        "UPDATE queue SET canceled = true, canceled_by = $1, canceled_reason = $2, scheduled_for = now(), suspend = 0
+        WHERE hedge_group = $3 AND workspace_id = $4 AND id != $3 AND id != $5 AND canceled = false
+        RETURNING id",
+    )
+    .bind(HEDGE_CANCELED_BY)
+    .bind(format!("replica {} finished first", queued_job.id))
+    .bind(group)
+    .bind(&queued_job.workspace_id)
+    .bind(queued_job.id)
+    .fetch_all(&mut *tx)
+    .await?;
+    tracing::info!(
+        "hedged job {group} settled by {}, canceled replicas: {siblings:?}",
+        queued_job.id
+    );
+
+    if is_primary {
+        return Ok(HedgeCompletion::Own);
+    }
+
+    // the job itself is not canceled but deleted from the queue when the result is recorded under
+    // its id, its worker then stopping it as already completed
+    let primary = get_queued_job_tx(group, &queued_job.workspace_id, tx.transaction_mut())
+        .await?
+        .ok_or_else(|| {
+            Error::InternalErr(format!(
+                "job {group} of the hedged replica {} is not in the queue anymore",
+                queued_job.id
+            ))
+        })?;
+    sqlx::query("DELETE FROM queue WHERE id = $1 AND workspace_id = $2")
+        .bind(queued_job.id)
+        .bind(&queued_job.workspace_id)
+        .execute(&mut *tx)
+        .await?;
+    if JOB_TOKEN.is_none() {
+        sqlx::query("DELETE FROM job_perms WHERE job_id = $1")
+            .bind(queued_job.id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    let logs = format!(
+        "\n\nresult of the replica {} that finished first, see its logs\n",
+        queued_job.id
+    );
+    sqlx::query(
+        "INSERT INTO job_logs (logs, job_id, workspace_id) VALUES ($1, $2, $3) ON CONFLICT (job_id) DO UPDATE SET logs = concat(job_logs.logs, $1::text)",
+    )
+    .bind(compress_log_chunk(&logs).as_ref())
+    .bind(group)
+    .bind(&queued_job.workspace_id)
+    .execute(&mut *tx)
+    .await?;
+
+    Ok(HedgeCompletion::AsPrimary(QueuedJob {
+        started_at: queued_job.started_at,
+        mem_peak: queued_job.mem_peak,
+        ..primary
+    }))
+}
+
+lazy_static::lazy_static! {
+    /// attempts at writing the completion of a job on transient database errors before giving
+    /// up, the job being then left to the zombie job detection
+    static ref COMPLETION_WRITE_ATTEMPTS: u32 = std::env::var("COMPLETION_WRITE_ATTEMPTS")
+        .ok()
+        .and_then(|x| x.parse::<u32>().ok())
+        .filter(|x| *x > 0)
+        .unwrap_or(5);
+}
+
+/// delay before the first retry of a completion write, doubled at each retry
+const COMPLETION_WRITE_BACKOFF: std::time::Duration = std::time::Duration::from_millis(200);
+const MAX_COMPLETION_WRITE_BACKOFF: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Whether an error is caused by the database being briefly unavailable, the failed operation
+/// being safe to retry
+pub fn is_transient_error(e: &Error) -> bool {
+    match e {
+        Error::SqlErr(sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut) => true,
+        Error::SqlErr(sqlx::Error::Database(e)) => e.code().is_some_and(|code| {
+            // connection exceptions, serialization failures, deadlocks and shutdowns
+            code.starts_with("08") || ["40001", "40P01", "57P01", "57P03"].contains(&&*code)
+        }),
+        _ => false,
+    }
+}
+
+/// Run `write` until it succeeds, fails with a non transient error or `attempts` are exhausted,
+/// with an exponential backoff. A failed attempt may have been committed anyway, `written` is
+/// checked before each retry so that the completion is not written twice
+async fn retry_completion_write<W, WFut, C, CFut>(
+    run_id: Uuid,
+    attempts: u32,
+    backoff: std::time::Duration,
+    mut write: W,
+    mut written: C,
+) -> Result<Uuid, Error>
+where
+    W: FnMut() -> WFut,
+    WFut: std::future::Future<Output = Result<Uuid, Error>>,
+    C: FnMut() -> CFut,
+    CFut: std::future::Future<Output = bool>,
+{
+    let mut attempt = 1;
+    loop {
+        match write().await {
+            Err(e) if attempt < attempts && is_transient_error(&e) => {
+                let delay =
+                    (backoff * 2u32.saturating_pow(attempt - 1)).min(MAX_COMPLETION_WRITE_BACKOFF);
+                tracing::warn!(
+                    job_id = %run_id,
+                    "transient error writing the completion of job {run_id} (attempt {attempt}/{attempts}), retrying in {}ms: {e:#}",
+                    delay.as_millis()
+                );
+                sleep(delay).await;
+                attempt += 1;
+                if written().await {
+                    tracing::info!(job_id = %run_id, "completion of job {run_id} was written by the failed attempt");
+                    return Ok(run_id);
+                }
+            }
+            r => return r,
+        }
+    }
+}
+
 #[instrument(level = "trace", skip_all, name = "add_completed_job")]
 pub async fn add_completed_job<
     T: Serialize + Send + Sync + ValidableJson,
@@ -560,22 +1022,99 @@ pub async fn add_completed_job<
     result: Json<&T>,
     mem_peak: i32,
     canceled_by: Option<CanceledBy>,
+    records: JobRunRecords,
     rsmq: Option<R>,
     flow_is_done: bool,
     #[cfg(feature = "benchmark")] bench: &mut windmill_common::bench::BenchmarkIter,
 ) -> Result<Uuid, Error> {
-    // tracing::error!("Start");
-    // let start = tokio::time::Instant::now();
-
-    add_time!(bench, "add_completed_job start");
     if !result.is_valid_json() {
         return Err(Error::InternalErr(
             "Result of job is invalid json (empty)".to_string(),
         ));
     }
+    let run_id = queued_job.id;
+
+    // benchmarks measure a single write
+    #[cfg(feature = "benchmark")]
+    return add_completed_job_inner(
+        db,
+        queued_job,
+        success,
+        skipped,
+        result,
+        mem_peak,
+        canceled_by,
+        rsmq,
+        flow_is_done,
+        &records,
+        bench,
+    )
+    .await;
+
+    #[cfg(not(feature = "benchmark"))]
+    retry_completion_write(
+        run_id,
+        *COMPLETION_WRITE_ATTEMPTS,
+        COMPLETION_WRITE_BACKOFF,
+        || {
+            add_completed_job_inner(
+                db,
+                queued_job,
+                success,
+                skipped,
+                Json(result.0),
+                mem_peak,
+                canceled_by.clone(),
+                rsmq.clone(),
+                flow_is_done,
+                &records,
+            )
+        },
+        // not whether the job left the queue, which it can do without its completion being written
+        move || async move {
+            job_is_complete(db, run_id, &queued_job.workspace_id)
+                .await
+                .unwrap_or(false)
+        },
+    )
+    .await
+}
+
+async fn add_completed_job_inner<
+    T: Serialize + Send + Sync + ValidableJson,
+    R: rsmq_async::RsmqConnection + Clone + Send,
+>(
+    db: &Pool<Postgres>,
+    queued_job: &QueuedJob,
+    success: bool,
+    skipped: bool,
+    result: Json<&T>,
+    mem_peak: i32,
+    canceled_by: Option<CanceledBy>,
+    rsmq: Option<R>,
+    flow_is_done: bool,
+    records: &JobRunRecords,
+    #[cfg(feature = "benchmark")] bench: &mut windmill_common::bench::BenchmarkIter,
+) -> Result<Uuid, Error> {
+    // tracing::error!("Start");
+    // let start = tokio::time::Instant::now();
+
+    add_time!(bench, "add_completed_job start");
 
     let mut tx: QueueTransaction<'_, R> = (rsmq.clone(), db.begin().await?).into();
 
+    // id the job was run as, that of a replica when a replica of a hedged job settles it
+    let run_id = queued_job.id;
+    let hedge_primary: Option<QueuedJob>;
+    let queued_job = match complete_hedged_job(&mut tx, queued_job, canceled_by.is_some()).await? {
+        HedgeCompletion::Own => queued_job,
+        HedgeCompletion::Settled => return Ok(run_id),
+        HedgeCompletion::AsPrimary(primary) => {
+            hedge_primary = Some(primary);
+            hedge_primary.as_ref().unwrap()
+        }
+    };
+
     let job_id = queued_job.id;
     // tracing::error!("1 {:?}", start.elapsed());
 
@@ -586,6 +1125,15 @@ pub async fn add_completed_job<
     );
 
     let mem_peak = mem_peak.max(queued_job.mem_peak.unwrap_or(0));
+    let result_str = serde_json::to_string(&result).ok();
+    let result_preview = result_str
+        .as_ref()
+        .and_then(|r| compute_result_preview(r, *RESULT_PREVIEW_MAX_BYTES));
+    // stored instead of the jsonb result, which is then null
+    let result_compressed = result_str
+        .as_deref()
+        .filter(|_| queued_job.is_flow_step && success)
+        .and_then(compress_flow_step_result);
     add_time!(bench, "add_completed_job query START");
     let _duration: i64 = sqlx::query_scalar!(
         "INSERT INTO completed_job AS cj
@@ -619,10 +1167,21 @@ pub async fn add_completed_job<
                    , mem_peak
                    , tag
                    , priority
+                   , result_preview
+                   , script_version
+                   , correlation_id
+                   , hedge_group
+                   , metrics
+                   , warnings
+                   , installed_packages
+                   , first_log_at
+                   , last_log_at
+                   , result_compressed
                 )
             VALUES ($1, $2, $3, $4, $5, COALESCE($6, now()), (EXTRACT('epoch' FROM (now())) - EXTRACT('epoch' FROM (COALESCE($6, now()))))*1000, $7, $8, $9,\
-                    $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29)
-         ON CONFLICT (id) DO UPDATE SET success = $7, result = $11 RETURNING duration_ms",
+                    $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29,\
+                    $30, (SELECT version FROM script WHERE hash = $8 AND workspace_id = $1), $31, $32, $33, $34, $35, $36, $37, $38)
+         ON CONFLICT (id) DO UPDATE SET success = $7, result = $11, result_preview = $30, result_compressed = $38 RETURNING duration_ms",
         queued_job.workspace_id,
         queued_job.id,
         queued_job.parent_job,
@@ -633,7 +1192,7 @@ pub async fn add_completed_job<
         queued_job.script_hash.map(|x| x.0),
         queued_job.script_path,
         &queued_job.args as &Option<Json<HashMap<String, Box<RawValue>>>>,
-        result as Json<&T>,
+        result_compressed.is_none().then_some(result) as Option<Json<&T>>,
         queued_job.raw_code,
         queued_job.raw_lock,
         canceled_by.is_some(),
@@ -652,10 +1211,22 @@ pub async fn add_completed_job<
         if mem_peak > 0 { Some(mem_peak) } else { None },
         queued_job.tag,
         queued_job.priority,
+        result_preview.map(Json) as Option<Json<serde_json::Value>>,
+        queued_job.correlation_id,
+        queued_job.hedge_group,
+        records.metrics().map(Json) as Option<Json<&serde_json::Map<String, serde_json::Value>>>,
+        records.warnings().map(Json) as Option<Json<Vec<String>>>,
+        records.installed_packages().map(Json) as Option<Json<&Vec<InstalledPackage>>>,
+        records.log_timestamps.map(|(first, _)| first),
+        records.log_timestamps.map(|(_, last)| last),
+        result_compressed,
     )
     .fetch_one(&mut tx)
     .await
-    .map_err(|e| Error::InternalErr(format!("Could not add completed job {job_id}: {e:#}")))?;
+    .map_err(|e| match Error::SqlErr(e) {
+        e if is_transient_error(&e) => e,
+        e => Error::InternalErr(format!("Could not add completed job {job_id}: {e:#}")),
+    })?;
     // tracing::error!("2 {:?}", start.elapsed());
 
     add_time!(bench, "add_completed_job query END");
@@ -879,6 +1450,22 @@ pub async fn add_completed_job<
         queued_job.id
     );
 
+    // stored once the job is completed, failing to store it must not fail the completion
+    if let Some(profile) = records.profile.as_ref() {
+        if let Err(e) = sqlx::query(
+            "INSERT INTO job_profile (job_id, workspace_id, profile) VALUES ($1, $2, $3)
+            ON CONFLICT (job_id) DO UPDATE SET profile = EXCLUDED.profile",
+        )
+        .bind(job_id)
+        .bind(&queued_job.workspace_id)
+        .bind(profile)
+        .execute(db)
+        .await
+        {
+            tracing::error!("Could not store profile of {job_id}: {e:#}");
+        }
+    }
+
     #[cfg(feature = "cloud")]
     if *CLOUD_HOSTED && !queued_job.is_flow() && _duration > 1000 {
         let additional_usage = _duration / 1000;
@@ -1804,6 +2391,7 @@ pub async fn pull<R: rsmq_async::RsmqConnection + Send + Clone>(
 
         // concurrency check. If more than X jobs for this path are already running, we re-queue and pull another job from the queue
         let pulled_job = job.unwrap();
+
         if pulled_job.script_path.is_none() || !has_concurent_limit || pulled_job.canceled {
             #[cfg(feature = "prometheus")]
             if METRICS_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
@@ -2030,6 +2618,45 @@ pub async fn pull<R: rsmq_async::RsmqConnection + Send + Clone>(
     }
 }
 
+/// Put a pulled job back in the queue, to be pulled again from `scheduled_for`. Canceled jobs are
+/// left as they are
+pub async fn requeue_job<R: rsmq_async::RsmqConnection + Send + Clone>(
+    db: &Pool<Postgres>,
+    rsmq: Option<R>,
+    job: &QueuedJob,
+    scheduled_for: DateTime<Utc>,
+) -> error::Result<()> {
+    let mut tx: QueueTransaction<'_, _> = (rsmq, db.begin().await?).into();
+    let requeued = sqlx::query(
+        "UPDATE queue SET running = false, started_at = null, scheduled_for = $1, last_ping = null,
+         installing_deps = false
+         WHERE id = $2 AND canceled = false",
+    )
+    .bind(scheduled_for)
+    .bind(job.id)
+    .execute(&mut tx)
+    .await
+    .map_err(|e| Error::InternalErr(format!("Could not re-queue job {}: {e:#}", job.id)))?
+    .rows_affected()
+        > 0;
+    if requeued {
+        if let Some(ref mut rsmq) = tx.rsmq {
+            rsmq.send_message(
+                job.id.to_bytes_le().to_vec(),
+                Option::Some(scheduled_for),
+                job.tag.clone(),
+            );
+        }
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Delay before the redis message of a job that cannot be claimed yet (the compute budget of its
+/// workspace is exhausted, too many dependency jobs are running, it requires an execution
+/// environment this worker does not provide) is received again
+const DEFERRED_JOB_RETRY_SECS: u64 = 30;
+
 async fn pull_single_job_and_mark_as_running_no_concurrency_limit<
     'c,
     R: rsmq_async::RsmqConnection + Send + Clone,
@@ -2070,13 +2697,13 @@ async fn pull_single_job_and_mark_as_running_no_concurrency_limit<
                     .map_err(|_| anyhow::anyhow!("Failed to parsed Redis message"))?,
             );
 
-            let m2r = sqlx::query_as::<_, QueuedJob>(
+            let m2r = sqlx::query_as::<_, QueuedJob>(&format!(
                 "UPDATE queue
             SET running = true
             , started_at = coalesce(started_at, now())
             , last_ping = now()
             , suspend_until = null
-            WHERE id = $1
+            WHERE id = $1 AND {} AND {COMPUTE_BUDGET_FILTER} AND {}
             RETURNING  id,  workspace_id,  parent_job,  created_by,  created_at,  started_at,  scheduled_for,
                 running,  script_hash,  script_path,  args,   right(logs, 900000) as logs,  raw_code,  canceled,  canceled_by,  
                 canceled_reason,  last_ping,  job_kind,  schedule_path,  permissioned_as, 
@@ -2084,14 +2711,30 @@ async fn pull_single_job_and_mark_as_running_no_concurrency_limit<
                 same_worker,  raw_lock,  pre_run_error,  email,  visible_to_owner,  mem_peak, 
                  root_job,  leaf_jobs,  tag,  concurrent_limit,  concurrency_time_window_s,  
                  timeout,  flow_step_id,  cache_ttl, priority",
-            )
+                exec_env_filter(),
+                dependency_jobs_filter()
+            ))
             .bind(uuid)
             .fetch_optional(db)
             .await?;
 
-            rsmq.delete_message(&tag.unwrap(), &msg.id)
-                .await
-                .map_err(|e| anyhow::anyhow!(e))?;
+            let deferred = m2r.is_none()
+                && sqlx::query_scalar::<_, bool>(
+                    "SELECT EXISTS (SELECT 1 FROM queue WHERE id = $1 AND running = false)",
+                )
+                .bind(uuid)
+                .fetch_one(db)
+                .await?;
+            if deferred {
+                // kept in the queue, the job is received again once its message is visible again
+                rsmq.change_message_visibility(&tag.unwrap(), &msg.id, DEFERRED_JOB_RETRY_SECS)
+                    .await
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            } else {
+                rsmq.delete_message(&tag.unwrap(), &msg.id)
+                    .await
+                    .map_err(|e| anyhow::anyhow!(e))?;
+            }
 
             #[cfg(feature = "benchmark")]
             println!("rsmq 2: {:?}", instant.elapsed());
@@ -2257,11 +2900,25 @@ fn fullpath_with_workspace(
 #[derive(FromRow)]
 pub struct ResultR {
     result: Option<Json<Box<RawValue>>>,
+    #[sqlx(default)]
+    result_compressed: Option<Vec<u8>>,
+}
+
+impl ResultR {
+    /// the result selected as `result #> path`, a compressed result being read at `path` too
+    fn at_path(self, path: &[String]) -> Option<Box<RawValue>> {
+        self.result.map(|x| x.0).or_else(|| {
+            self.result_compressed
+                .and_then(|x| decompress_result_at_path(&x, path))
+        })
+    }
 }
 
 #[derive(FromRow)]
 pub struct ResultWithId {
     result: Option<Json<Box<RawValue>>>,
+    #[sqlx(default)]
+    result_compressed: Option<Vec<u8>>,
     id: Uuid,
 }
 
@@ -2478,57 +3135,57 @@ async fn extract_result_from_job_result(
                 let Some(job_id) = job_ids.get(idx).cloned() else {
                     return Ok(to_raw_value(&serde_json::Value::Null));
                 };
+                let path = parts.map(|x| x.to_string()).collect::<Vec<_>>();
                 Ok(sqlx::query_as::<_, ResultR>(
-                    "SELECT result #> $3 as result FROM completed_job WHERE id = $1 AND workspace_id = $2",
+                    "SELECT result #> $3 as result, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
                 )
                 .bind(job_id)
                 .bind(w_id)
-                .bind(
-                    parts.map(|x| x.to_string()).collect::<Vec<_>>()
-                )
+                .bind(&path)
                 .fetch_optional(db)
                 .await?
-                .map(|r| r.result.map(|x| x.0))
+                .map(|r| r.at_path(&path))
                 .flatten()
                 .unwrap_or_else(|| to_raw_value(&serde_json::Value::Null)))
             }
             None => {
                 let rows = sqlx::query_as::<_, ResultWithId>(
-                    "SELECT id, result FROM completed_job WHERE id = ANY($1) AND workspace_id = $2",
+                    "SELECT id, result, result_compressed FROM completed_job WHERE id = ANY($1) AND workspace_id = $2",
                 )
                 .bind(job_ids.as_slice())
                 .bind(w_id)
                 .fetch_all(db)
                 .await?
                 .into_iter()
-                .filter_map(|x| x.result.map(|y| (x.id, y)))
-                .collect::<HashMap<Uuid, Json<Box<RawValue>>>>();
+                .filter_map(|x| completed_job_result(x.result, x.result_compressed).map(|y| (x.id, y)))
+                .collect::<HashMap<Uuid, Box<RawValue>>>();
                 let result = job_ids
                     .into_iter()
                     .map(|id| {
                         rows.get(&id)
-                            .map(|x| x.0.clone())
+                            .cloned()
                             .unwrap_or_else(|| to_raw_value(&serde_json::Value::Null))
                     })
                     .collect::<Vec<_>>();
                 Ok(to_raw_value(&result))
             }
         },
-        JobResult::SingleJob(x) => Ok(sqlx::query_as::<_, ResultR>(
-            "SELECT result #> $3 as result FROM completed_job WHERE id = $1 AND workspace_id = $2",
-        )
-        .bind(x)
-        .bind(w_id)
-        .bind(
-            json_path
+        JobResult::SingleJob(x) => {
+            let path = json_path
                 .map(|x| x.split(".").map(|x| x.to_string()).collect::<Vec<_>>())
-                .unwrap_or_default(),
-        )
-        .fetch_optional(db)
-        .await?
-        .map(|r| r.result.map(|x| x.0))
-        .flatten()
-        .unwrap_or_else(|| to_raw_value(&serde_json::Value::Null))),
+                .unwrap_or_default();
+            Ok(sqlx::query_as::<_, ResultR>(
+                "SELECT result #> $3 as result, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
+            )
+            .bind(x)
+            .bind(w_id)
+            .bind(&path)
+            .fetch_optional(db)
+            .await?
+            .map(|r| r.at_path(&path))
+            .flatten()
+            .unwrap_or_else(|| to_raw_value(&serde_json::Value::Null)))
+        }
     }
 }
 
@@ -2876,6 +3533,163 @@ mod tests {
             "1.5"
         );
     }
+
+    #[test]
+    fn test_result_preview() {
+        assert!(compute_result_preview(r#"{"a": 1}"#, 100).is_none());
+
+        let obj = format!(r#"{{"b": "{}", "a": [1, 2]}}"#, "x".repeat(200));
+        let preview = compute_result_preview(&obj, 100).unwrap();
+        assert_eq!(preview["type"], "object");
+        assert_eq!(preview["keys"], json!(["a", "b"]));
+        assert_eq!(preview["keys_count"], 2);
+        assert_eq!(preview["size"], obj.len());
+        assert_eq!(preview["head"].as_str().unwrap().len(), 100);
+
+        let arr = format!("[{}]", vec!["1"; 100].join(","));
+        let preview = compute_result_preview(&arr, 10).unwrap();
+        assert_eq!(preview["type"], "array");
+        assert_eq!(preview["length"], 100);
+
+        // the head is never cut in the middle of a character
+        let s = format!("\"{}\"", "é".repeat(20));
+        let preview = compute_result_preview(&s, 10).unwrap();
+        assert_eq!(
+            preview["head"].as_str().unwrap(),
+            format!("\"{}", "é".repeat(4))
+        );
+        assert!(preview.get("type").is_none());
+    }
+
+    #[test]
+    fn test_parse_metric_line() {
+        assert_eq!(parse_metric_line("rows: 12"), None);
+        assert_eq!(
+            parse_metric_line("metric: rows_processed=12"),
+            Some(Ok(MetricLine {
+                name: "rows_processed",
+                value: 12.0,
+                increment: false
+            }))
+        );
+        assert_eq!(
+            parse_metric_line("  metric: failed.count += 0.5 "),
+            Some(Ok(MetricLine {
+                name: "failed.count",
+                value: 0.5,
+                increment: true
+            }))
+        );
+        assert!(matches!(parse_metric_line("metric: rows"), Some(Err(_))));
+        assert!(matches!(parse_metric_line("metric: =1"), Some(Err(_))));
+        assert!(matches!(parse_metric_line("metric: a b=1"), Some(Err(_))));
+        assert!(matches!(
+            parse_metric_line("metric: rows=abc"),
+            Some(Err(_))
+        ));
+        assert!(matches!(
+            parse_metric_line("metric: rows=NaN"),
+            Some(Err(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_script_metric() {
+        let mut records = JobRunRecords::default();
+        assert!(records.metrics().is_none());
+        let metric = |line| parse_metric_line(line).unwrap().unwrap();
+        records.record_metric(metric("metric: rows=10")).unwrap();
+        records.record_metric(metric("metric: rows+=5")).unwrap();
+        records.record_metric(metric("metric: ratio=0.25")).unwrap();
+        records.record_metric(metric("metric: ratio=0.5")).unwrap();
+        let metrics = records.metrics().unwrap();
+        assert_eq!(json!(metrics), json!({"rows": 15, "ratio": 0.5}));
+    }
+
+    #[test]
+    fn test_parse_warning_line() {
+        assert_eq!(parse_warning_line("processing rows"), None);
+        assert_eq!(
+            parse_warning_line("  warning: deprecated api, use v2 "),
+            Some(Ok("deprecated api, use v2".to_string()))
+        );
+        assert!(matches!(parse_warning_line("warning:   "), Some(Err(_))));
+        let long = parse_warning_line(&format!("warning: {}", "é".repeat(MAX_WARNING_LEN)))
+            .unwrap()
+            .unwrap();
+        assert!(long.ends_with("...") && long.len() <= MAX_WARNING_LEN + 3);
+    }
+
+    #[test]
+    fn test_record_script_warning() {
+        let mut records = JobRunRecords::default();
+        assert!(records.warnings().is_none());
+        for i in 0..MAX_SCRIPT_WARNINGS {
+            records.record_warning(format!("w{i}")).unwrap();
+        }
+        assert!(records.record_warning("over".to_string()).is_err());
+        assert!(records.record_warning("over".to_string()).is_ok());
+        let warnings = records.warnings().unwrap();
+        assert_eq!(warnings.len(), MAX_SCRIPT_WARNINGS + 1);
+        assert_eq!(warnings[0], "w0");
+        assert_eq!(warnings.last().unwrap(), "2 more warnings not retained");
+    }
+
+    /// a completion write failing `failures` times with `error` before being committed
+    async fn retry_failing_write(
+        failures: usize,
+        error: fn() -> Error,
+        attempts: u32,
+    ) -> (Result<Uuid, Error>, usize, Option<Uuid>) {
+        let job_id = Uuid::new_v4();
+        let tries = std::sync::Mutex::new(0);
+        let persisted = std::sync::Mutex::new(None);
+        let r = retry_completion_write(
+            job_id,
+            attempts,
+            std::time::Duration::from_millis(1),
+            || async {
+                let mut tries = tries.lock().unwrap();
+                *tries += 1;
+                if *tries <= failures {
+                    return Err(error());
+                }
+                *persisted.lock().unwrap() = Some(job_id);
+                Ok(job_id)
+            },
+            || async { persisted.lock().unwrap().is_some() },
+        )
+        .await;
+        let tries = *tries.lock().unwrap();
+        let persisted = *persisted.lock().unwrap();
+        (r, tries, persisted)
+    }
+
+    #[tokio::test]
+    async fn test_retry_completion_write_transient_error() {
+        let (r, tries, persisted) =
+            retry_failing_write(2, || Error::SqlErr(sqlx::Error::PoolTimedOut), 5).await;
+        assert_eq!(tries, 3);
+        assert_eq!(persisted, Some(r.unwrap()));
+    }
+
+    #[tokio::test]
+    async fn test_retry_completion_write_non_transient_error() {
+        let (r, tries, persisted) =
+            retry_failing_write(1, || Error::InternalErr("invalid".to_string()), 5).await;
+        assert!(matches!(r, Err(Error::InternalErr(_))));
+        assert_eq!(tries, 1);
+        assert_eq!(persisted, None);
+    }
+
+    #[tokio::test]
+    async fn test_retry_completion_write_attempts_exhausted() {
+        let (r, tries, persisted) =
+            retry_failing_write(5, || Error::SqlErr(sqlx::Error::PoolTimedOut), 3).await;
+        assert!(matches!(r, Err(Error::SqlErr(sqlx::Error::PoolTimedOut))));
+        assert_eq!(tries, 3);
+        assert_eq!(persisted, None);
+    }
 }
 
 #[axum::async_trait]
@@ -3895,6 +4709,52 @@ pub async fn push<'c, 'd, R: rsmq_async::RsmqConnection + Send + 'c>(
         None
     };
 
+    let correlation_id = args
+        .extra
+        .as_ref()
+        .and_then(|extra| extra.get(CORRELATION_ID_ARG))
+        .or_else(|| args.args.get(CORRELATION_ID_ARG))
+        .map(|x| serde_json::from_str::<String>(x.get()))
+        .transpose()
+        .map_err(|e| Error::BadRequest(format!("`{CORRELATION_ID_ARG}` must be a string: {e}")))?;
+    if correlation_id
+        .as_ref()
+        .is_some_and(|x| x.chars().count() > MAX_CORRELATION_ID_LENGTH)
+    {
+        return Err(Error::BadRequest(format!(
+            "`{CORRELATION_ID_ARG}` must be at most {MAX_CORRELATION_ID_LENGTH} characters"
+        )));
+    }
+
+    let hedge_replicas = args
+        .extra
+        .as_ref()
+        .and_then(|extra| extra.get(HEDGE_ARG))
+        .or_else(|| args.args.get(HEDGE_ARG))
+        .map(|x| serde_json::from_str::<u8>(x.get()))
+        .transpose()
+        .map_err(|e| {
+            Error::BadRequest(format!("`{HEDGE_ARG}` must be a number of replicas: {e}"))
+        })?;
+    if let Some(replicas) = hedge_replicas {
+        if !(2..=MAX_HEDGE_REPLICAS).contains(&replicas) {
+            return Err(Error::BadRequest(format!(
+                "`{HEDGE_ARG}` must be between 2 and {MAX_HEDGE_REPLICAS} replicas"
+            )));
+        }
+        if parent_job.is_some()
+            || is_flow_step
+            || !matches!(
+                job_kind,
+                JobKind::Script | JobKind::Preview | JobKind::Script_Hub
+            )
+        {
+            return Err(Error::BadRequest(format!(
+                "`{HEDGE_ARG}` is only supported for scripts that are not run as part of a flow"
+            )));
+        }
+    }
+
     tracing::debug!("Pushing job {job_id} with tag {tag}, schedule_path {schedule_path:?}, script_path: {script_path:?}, email {email}, workspace_id {workspace_id}");
     let uuid = sqlx::query_scalar!(
         "INSERT INTO queue
@@ -3902,8 +4762,8 @@ pub async fn push<'c, 'd, R: rsmq_async::RsmqConnection + Send + 'c>(
                 script_hash, script_path, raw_code, raw_lock, args, job_kind, schedule_path, raw_flow, \
                 flow_status, is_flow_step, language, started_at, same_worker, pre_run_error, email, \
                 visible_to_owner, root_job, tag, concurrent_limit, concurrency_time_window_s, timeout, \
-                flow_step_id, cache_ttl, priority, last_ping)
-            VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, now()), $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, CASE WHEN $3 THEN now() END, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, NULL) \
+                flow_step_id, cache_ttl, priority, correlation_id, hedge_group, last_ping)
+            VALUES ($1, $2, $3, $4, $5, $6, COALESCE($7, now()), $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, CASE WHEN $3 THEN now() END, $19, $20, $21, $22, $23, $24, $25, $26, $27, $28, $29, $30, $31, $32, NULL) \
          RETURNING id",
         workspace_id,
         job_id,
@@ -3935,6 +4795,9 @@ pub async fn push<'c, 'd, R: rsmq_async::RsmqConnection + Send + 'c>(
         flow_step_id,
         cache_ttl,
         final_priority,
+        correlation_id,
+        // the job is the first replica of its group, the id of the group being its own
+        hedge_replicas.map(|_| job_id),
     )
     .fetch_one(&mut tx)
     .await
@@ -3996,6 +4859,50 @@ pub async fn push<'c, 'd, R: rsmq_async::RsmqConnection + Send + 'c>(
         }
     }
 
+    // the replicas are copies of the job that are not tied to its schedule nor its concurrency
+    // limit, the job itself being the first replica and its id the id of the group
+    let hedge_replica_ids = if let Some(replicas) = hedge_replicas {
+        let ids = (1..replicas)
+            .map(|_| Ulid::new().into())
+            .collect::<Vec<Uuid>>();
+        sqlx::query(
+            "INSERT INTO queue
+                (workspace_id, id, running, parent_job, created_by, permissioned_as, scheduled_for,
+                    script_hash, script_path, raw_code, raw_lock, args, job_kind, raw_flow,
+                    flow_status, is_flow_step, language, same_worker, pre_run_error, email,
+                    visible_to_owner, tag, timeout, flow_step_id, cache_ttl, priority,
+                    correlation_id, hedge_group)
+            SELECT workspace_id, replica_id, false, parent_job, created_by, permissioned_as,
+                scheduled_for, script_hash, script_path, raw_code, raw_lock, args, job_kind,
+                raw_flow, flow_status, is_flow_step, language, same_worker, pre_run_error, email,
+                visible_to_owner, tag, timeout, flow_step_id, cache_ttl, priority, correlation_id,
+                hedge_group
+            FROM queue, UNNEST($1::uuid[]) AS replica_id WHERE id = $2",
+        )
+        .bind(&ids)
+        .bind(job_id)
+        .execute(&mut tx)
+        .await
+        .map_err(|e| {
+            Error::InternalErr(format!("Could not insert the replicas of {job_id}: {e:#}"))
+        })?;
+        if JOB_TOKEN.is_none() {
+            sqlx::query(
+                "INSERT INTO job_perms (job_id, email, username, is_admin, is_operator, folders, groups, workspace_id)
+                SELECT replica_id, email, username, is_admin, is_operator, folders, groups, workspace_id
+                FROM job_perms, UNNEST($1::uuid[]) AS replica_id WHERE job_id = $2
+                ON CONFLICT (job_id) DO NOTHING",
+            )
+            .bind(&ids)
+            .bind(job_id)
+            .execute(&mut tx)
+            .await?;
+        }
+        ids
+    } else {
+        vec![]
+    };
+
     {
         let uuid_string = job_id.to_string();
         let uuid_str = uuid_string.as_str();
@@ -4053,6 +4960,13 @@ pub async fn push<'c, 'd, R: rsmq_async::RsmqConnection + Send + 'c>(
         .await?;
     }
     if let Some(ref mut rsmq) = tx.rsmq {
+        for replica_id in hedge_replica_ids {
+            rsmq.send_message(
+                replica_id.to_bytes_le().to_vec(),
+                scheduled_for_o,
+                tag.clone(),
+            );
+        }
         rsmq.send_message(job_id.to_bytes_le().to_vec(), scheduled_for_o, tag);
     }
 
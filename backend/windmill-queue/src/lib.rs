@@ -6,9 +6,11 @@
  * LICENSE-AGPL for a copy of the license.
  */
 
+mod job_queue;
 mod jobs;
 mod queue_transaction;
 pub mod schedule;
 
+pub use job_queue::*;
 pub use jobs::*;
 pub use queue_transaction::*;
@@ -19,6 +19,15 @@ then
       echo "\$TRUSTED_HOST is set to $TRUSTED_HOST"
 fi
 
-CMD="/usr/local/bin/python3 -m pip install -v \"$REQ\" -I -t \"$TARGET\" --no-cache --no-color --no-deps --isolated --no-warn-conflicts --disable-pip-version-check $INDEX_URL_ARG $EXTRA_INDEX_URL_ARG $TRUSTED_HOST_ARG"
+# hashes can only be given in a requirements file
+if [ ! -z "$REQ_HASHES" ]
+then
+      echo "$REQ $REQ_HASHES" > /tmp/requirements.txt
+      REQ_ARG="-r /tmp/requirements.txt --require-hashes"
+else
+      REQ_ARG="\"$REQ\""
+fi
+
+CMD="/usr/local/bin/python3 -m pip install -v $REQ_ARG -I -t \"$TARGET\" --no-cache --no-color --no-deps --isolated --no-warn-conflicts --disable-pip-version-check $INDEX_URL_ARG $EXTRA_INDEX_URL_ARG $TRUSTED_HOST_ARG"
 echo $CMD
 eval $CMD
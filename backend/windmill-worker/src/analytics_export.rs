@@ -0,0 +1,364 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::{value::RawValue, Value};
+use tokio::sync::mpsc;
+use tokio_postgres::Client;
+use uuid::Uuid;
+use windmill_common::{
+    error::{self, Error},
+    jobs::QueuedJob,
+    DB,
+};
+use windmill_queue::compute_result_preview;
+
+#[cfg(feature = "prometheus")]
+use windmill_common::METRICS_ENABLED;
+
+use crate::{create_token_for_owner, pg_executor::connect_pg_resource, AuthedClient};
+
+/// results larger than this are exported as their preview, see `compute_result_preview`
+const MAX_EXPORTED_RESULT_SIZE: usize = 1024;
+
+const WAREHOUSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Export of a record per completed job to a data warehouse, for analytics. Set as json in the
+/// `JOB_ANALYTICS_EXPORT` env variable, e.g.
+///
+/// ```json
+/// {"workspace_id": "admins", "resource": "$res:f/analytics/warehouse", "owner": "u/admin"}
+/// ```
+///
+/// - `resource` is the connection to the warehouse, resolved in `workspace_id` with the
+///   permissions of `owner`. Only postgresql resources are supported
+/// - records are buffered (`buffer_size`) and inserted by batches of at most `batch_size` every
+///   `flush_interval_secs` into `table`, records completed while the buffer is full are dropped
+///
+/// The table is expected to have the columns `job_id uuid, workspace_id text, script_path text,
+/// success boolean, duration_ms bigint, completed_at timestamptz, result_summary jsonb,
+/// labels jsonb`
+#[derive(Deserialize, Debug)]
+struct AnalyticsExportConfig {
+    workspace_id: String,
+    resource: String,
+    owner: String,
+    #[serde(default = "default_table")]
+    table: String,
+    #[serde(default = "default_buffer_size")]
+    buffer_size: usize,
+    #[serde(default = "default_batch_size")]
+    batch_size: usize,
+    #[serde(default = "default_flush_interval_secs")]
+    flush_interval_secs: u64,
+}
+
+fn default_table() -> String {
+    "job_analytics".to_string()
+}
+
+fn default_buffer_size() -> usize {
+    10000
+}
+
+fn default_batch_size() -> usize {
+    500
+}
+
+fn default_flush_interval_secs() -> u64 {
+    10
+}
+
+lazy_static::lazy_static! {
+    static ref JOB_ANALYTICS_EXPORT: Option<AnalyticsExportConfig> =
+        std::env::var("JOB_ANALYTICS_EXPORT").ok().and_then(|x| {
+            serde_json::from_str::<AnalyticsExportConfig>(&x)
+                .map_err(|e| tracing::error!("Invalid JOB_ANALYTICS_EXPORT, not exporting job analytics: {e}"))
+                .ok()
+                .filter(|config| {
+                    let valid = is_valid_table_name(&config.table);
+                    if !valid {
+                        tracing::error!("Invalid JOB_ANALYTICS_EXPORT table `{}`, not exporting job analytics", config.table);
+                    }
+                    valid
+                })
+        });
+
+    /// buffer between the completion of the jobs and the export task, the receiver being taken by
+    /// `start_analytics_export`
+    static ref ANALYTICS_BUFFER: (mpsc::Sender<JobAnalyticsRecord>, Mutex<Option<mpsc::Receiver<JobAnalyticsRecord>>>) = {
+        let (tx, rx) = mpsc::channel(
+            JOB_ANALYTICS_EXPORT
+                .as_ref()
+                .map(|x| x.buffer_size.max(1))
+                .unwrap_or(1),
+        );
+        (tx, Mutex::new(Some(rx)))
+    };
+}
+
+#[cfg(feature = "prometheus")]
+lazy_static::lazy_static! {
+    static ref ANALYTICS_EXPORT_DROPPED: Option<prometheus::IntCounter> = if METRICS_ENABLED.load(Ordering::Relaxed) {
+        Some(prometheus::register_int_counter!(
+            "worker_analytics_export_dropped",
+            "Total number of job analytics records dropped because the export buffer was full or the warehouse unreachable"
+        )
+        .unwrap())
+    } else {
+        None
+    };
+
+    static ref ANALYTICS_EXPORTED: Option<prometheus::IntCounter> = if METRICS_ENABLED.load(Ordering::Relaxed) {
+        Some(prometheus::register_int_counter!(
+            "worker_analytics_exported",
+            "Total number of job analytics records inserted in the warehouse"
+        )
+        .unwrap())
+    } else {
+        None
+    };
+}
+
+/// records dropped since the last flush, logged by the export task
+static DROPPED_SINCE_FLUSH: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug)]
+struct JobAnalyticsRecord {
+    job_id: Uuid,
+    workspace_id: String,
+    script_path: Option<String>,
+    success: bool,
+    duration_ms: i64,
+    completed_at: DateTime<Utc>,
+    result_summary: Value,
+    labels: Option<Value>,
+}
+
+/// labels of a job, set in the `wm_labels` field of its result
+#[derive(Deserialize)]
+struct ResultLabels {
+    wm_labels: Option<Value>,
+}
+
+fn is_valid_table_name(table: &str) -> bool {
+    let parts = table.split('.').collect::<Vec<_>>();
+    parts.len() <= 2
+        && parts.iter().all(|part| {
+            part.chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        })
+}
+
+fn record_dropped(count: u64) {
+    DROPPED_SINCE_FLUSH.fetch_add(count, Ordering::Relaxed);
+    #[cfg(feature = "prometheus")]
+    if let Some(counter) = ANALYTICS_EXPORT_DROPPED.as_ref() {
+        counter.inc_by(count);
+    }
+}
+
+/// Buffer the analytics record of a completed job for the export, if enabled. Never blocks: the
+/// record is dropped if the buffer is full
+pub fn export_job_analytics(job: &QueuedJob, success: bool, result: &RawValue) {
+    if JOB_ANALYTICS_EXPORT.is_none() {
+        return;
+    }
+    let completed_at = Utc::now();
+    let result_summary = compute_result_preview(result.get(), MAX_EXPORTED_RESULT_SIZE)
+        .or_else(|| serde_json::from_str(result.get()).ok())
+        .unwrap_or(Value::Null);
+    let labels = serde_json::from_str::<ResultLabels>(result.get())
+        .ok()
+        .and_then(|x| x.wm_labels);
+    let record = JobAnalyticsRecord {
+        job_id: job.id,
+        workspace_id: job.workspace_id.clone(),
+        script_path: job.script_path.clone(),
+        success,
+        duration_ms: job
+            .started_at
+            .map(|x| (completed_at - x).num_milliseconds())
+            .unwrap_or(0),
+        completed_at,
+        result_summary,
+        labels,
+    };
+    if ANALYTICS_BUFFER.0.try_send(record).is_err() {
+        record_dropped(1);
+    }
+}
+
+/// Start the task inserting the buffered records in the warehouse, once per process, if the
+/// export is enabled
+pub fn start_analytics_export(
+    db: DB,
+    base_internal_url: String,
+    mut killpill_rx: tokio::sync::broadcast::Receiver<()>,
+) {
+    let Some(config) = JOB_ANALYTICS_EXPORT.as_ref() else {
+        return;
+    };
+    let Some(mut rx) = ANALYTICS_BUFFER.1.lock().unwrap().take() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut warehouse = None;
+        let mut batch = Vec::with_capacity(config.batch_size);
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(config.flush_interval_secs.max(1)));
+        loop {
+            let killed = tokio::select! {
+                _ = killpill_rx.recv() => true,
+                _ = interval.tick() => false,
+            };
+            loop {
+                while batch.len() < config.batch_size {
+                    match rx.try_recv() {
+                        Ok(record) => batch.push(record),
+                        Err(_) => break,
+                    }
+                }
+                if batch.is_empty() {
+                    break;
+                }
+                let full_batch = batch.len() == config.batch_size;
+                flush(&db, &base_internal_url, config, &mut warehouse, &mut batch).await;
+                if !full_batch {
+                    break;
+                }
+            }
+            let dropped = DROPPED_SINCE_FLUSH.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                tracing::warn!("{dropped} job analytics records were dropped, the export buffer being full or the warehouse unreachable");
+            }
+            if killed {
+                return;
+            }
+        }
+    });
+}
+
+async fn flush(
+    db: &DB,
+    base_internal_url: &str,
+    config: &AnalyticsExportConfig,
+    warehouse: &mut Option<Client>,
+    batch: &mut Vec<JobAnalyticsRecord>,
+) {
+    let count = batch.len() as u64;
+    let result = async {
+        if warehouse.as_ref().map_or(true, |x| x.is_closed()) {
+            *warehouse = Some(connect_warehouse(db, base_internal_url, config).await?);
+        }
+        insert_records(warehouse.as_ref().unwrap(), &config.table, batch).await
+    }
+    .await;
+    batch.clear();
+    match result {
+        Ok(()) =>
+        {
+            #[cfg(feature = "prometheus")]
+            if let Some(counter) = ANALYTICS_EXPORTED.as_ref() {
+                counter.inc_by(count);
+            }
+        }
+        Err(e) => {
+            tracing::error!("Error exporting {count} job analytics records: {e:#}");
+            *warehouse = None;
+            record_dropped(count);
+        }
+    }
+}
+
+async fn connect_warehouse(
+    db: &DB,
+    base_internal_url: &str,
+    config: &AnalyticsExportConfig,
+) -> error::Result<Client> {
+    let token = create_token_for_owner(
+        db,
+        &config.workspace_id,
+        &config.owner,
+        "analytics-export",
+        WAREHOUSE_TIMEOUT.as_secs(),
+        "analytics_export@windmill.dev",
+        &Uuid::nil(),
+    )
+    .await?;
+    let client = AuthedClient {
+        base_internal_url: base_internal_url.to_string(),
+        workspace: config.workspace_id.clone(),
+        token,
+        force_client: None,
+    };
+    let path = config
+        .resource
+        .strip_prefix("$res:")
+        .unwrap_or(&config.resource);
+    let resource = client
+        .get_resource_value_interpolated::<Value>(path, None)
+        .await
+        .map_err(|e| Error::BadConfig(format!("could not resolve the warehouse {path}: {e:#}")))?;
+    let (client, _connection) = connect_pg_resource(resource, WAREHOUSE_TIMEOUT)
+        .await
+        .map_err(|e| {
+            Error::BadConfig(format!(
+                "only postgresql warehouses are supported, could not connect to {path}: {e:#}"
+            ))
+        })?;
+    Ok(client)
+}
+
+/// Insert the batch in a single statement, one array per column
+async fn insert_records(
+    client: &Client,
+    table: &str,
+    batch: &[JobAnalyticsRecord],
+) -> error::Result<()> {
+    let job_ids = batch.iter().map(|x| x.job_id).collect::<Vec<_>>();
+    let workspace_ids = batch
+        .iter()
+        .map(|x| x.workspace_id.as_str())
+        .collect::<Vec<_>>();
+    let script_paths = batch
+        .iter()
+        .map(|x| x.script_path.as_deref())
+        .collect::<Vec<_>>();
+    let successes = batch.iter().map(|x| x.success).collect::<Vec<_>>();
+    let durations = batch.iter().map(|x| x.duration_ms).collect::<Vec<_>>();
+    let completed_ats = batch.iter().map(|x| x.completed_at).collect::<Vec<_>>();
+    let result_summaries = batch.iter().map(|x| &x.result_summary).collect::<Vec<_>>();
+    let labels = batch.iter().map(|x| x.labels.as_ref()).collect::<Vec<_>>();
+    tokio::time::timeout(
+        WAREHOUSE_TIMEOUT,
+        client.execute(
+            &format!(
+                "INSERT INTO {table} (job_id, workspace_id, script_path, success, duration_ms, completed_at, result_summary, labels) \
+                 SELECT * FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::bool[], $5::bigint[], $6::timestamptz[], $7::jsonb[], $8::jsonb[])"
+            ),
+            &[
+                &job_ids,
+                &workspace_ids,
+                &script_paths,
+                &successes,
+                &durations,
+                &completed_ats,
+                &result_summaries,
+                &labels,
+            ],
+        ),
+    )
+    .await
+    .map_err(|_| Error::ExecutionErr("warehouse insert timed out".to_string()))?
+    .map_err(|e| Error::ExecutionErr(format!("warehouse insert failed: {e}")))?;
+    Ok(())
+}
@@ -16,6 +16,7 @@ use std::{
 use anyhow::anyhow;
 use itertools::Itertools;
 use serde_json::value::RawValue;
+use std::sync::Arc;
 use tokio::process::Command;
 use uuid::Uuid;
 use windmill_common::{
@@ -24,7 +25,7 @@ use windmill_common::{
     worker::{to_raw_value, write_file, write_file_at_user_defined_location, WORKER_CONFIG},
 };
 use windmill_parser_yaml::{AnsibleRequirements, ResourceOrVariablePath};
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 use crate::{
     bash_executor::BIN_BASH,
@@ -32,8 +33,9 @@ use crate::{
         get_reserved_variables, read_and_check_result, start_child_process, transform_json,
         OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
     python_executor::{create_dependencies_dir, handle_python_reqs, uv_pip_compile},
+    sandbox_profile::apply_sandbox_profile,
     AuthedClientBackgroundTask, DISABLE_NSJAIL, DISABLE_NUSER, HOME_ENV, NSJAIL_PATH, PATH_ENV,
     PROXY_ENVS, TZ_ENV,
 };
@@ -55,10 +57,12 @@ async fn handle_ansible_python_deps(
     w_id: &str,
     job_id: &Uuid,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     occupancy_metrics: &mut OccupancyMetrics,
 ) -> error::Result<Vec<String>> {
     create_dependencies_dir(job_dir).await;
@@ -85,8 +89,10 @@ async fn handle_ansible_python_deps(
                     &requirements,
                     mem_peak,
                     canceled_by,
+                    run_records,
                     job_dir,
                     db,
+                    job_queue,
                     worker_name,
                     w_id,
                     &mut Some(occupancy_metrics),
@@ -111,7 +117,9 @@ async fn handle_ansible_python_deps(
             w_id,
             mem_peak,
             canceled_by,
+            run_records,
             db,
+            job_queue,
             worker_name,
             job_dir,
             worker_dir,
@@ -131,7 +139,9 @@ async fn install_galaxy_collections(
     w_id: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     occupancy_metrics: &mut OccupancyMetrics,
 ) -> anyhow::Result<()> {
     write_file(job_dir, "requirements.yml", collections_yml)?;
@@ -167,13 +177,16 @@ async fn install_galaxy_collections(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         w_id,
         "ansible galaxy install",
+        ChildPhase::DependencyInstall,
         None,
         false,
         &mut Some(occupancy_metrics),
@@ -209,7 +222,9 @@ pub async fn handle_ansible_job(
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     inner_content: &String,
     shared_mount: &str,
@@ -230,10 +245,12 @@ pub async fn handle_ansible_job(
         &job.workspace_id,
         &job.id,
         db,
+        job_queue,
         worker_name,
         worker_dir,
         mem_peak,
         canceled_by,
+        run_records,
         occupancy_metrics,
     )
     .await?;
@@ -307,7 +324,9 @@ pub async fn handle_ansible_job(
                 &job.workspace_id,
                 mem_peak,
                 canceled_by,
+                run_records,
                 db,
+                job_queue,
                 occupancy_metrics,
             )
             .await?;
@@ -332,7 +351,8 @@ remote_tmp={job_dir}/.ansible/tmp
     );
     write_file(job_dir, "ansible.cfg", &ansible_cfg_content)?;
 
-    let mut reserved_variables = get_reserved_variables(job, &authed_client.token, db).await?;
+    let mut reserved_variables =
+        get_reserved_variables(job, &authed_client.token, db, job_dir, worker_name).await?;
     let additional_python_paths_folders = additional_python_paths.join(":");
 
     if !*DISABLE_NSJAIL {
@@ -354,7 +374,7 @@ mount {{
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_ANSIBLE_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_ANSIBLE_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
                 .replace("{SHARED_MOUNT}", shared_mount)
@@ -437,13 +457,16 @@ fi
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "python run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
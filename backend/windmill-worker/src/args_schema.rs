@@ -0,0 +1,157 @@
+use serde_json::{json, Map, Value};
+use windmill_common::{
+    error::{self, Error},
+    scripts::ScriptLang,
+};
+use windmill_parser::{MainArgSignature, ObjectProperty, Typ};
+
+/// Signature of the entrypoint of a script, `main` or `main_override`. The typescript defaults are
+/// only evaluated with `with_defaults`, the executors not needing them
+pub fn parse_main_signature(
+    language: &ScriptLang,
+    code: &str,
+    main_override: Option<String>,
+    with_defaults: bool,
+) -> error::Result<MainArgSignature> {
+    let sig = match language {
+        ScriptLang::Python3 => windmill_parser_py::parse_python_signature(code, main_override)?,
+        ScriptLang::Deno | ScriptLang::Bun | ScriptLang::Bunnative | ScriptLang::Nativets => {
+            windmill_parser_ts::parse_deno_signature(code, !with_defaults, main_override)?
+        }
+        ScriptLang::Go => windmill_parser_go::parse_go_sig(code)?,
+        ScriptLang::Bash => windmill_parser_bash::parse_bash_sig(code)?,
+        _ => {
+            return Err(Error::BadRequest(format!(
+                "signature parsing is not supported for {} scripts",
+                language.as_str()
+            )))
+        }
+    };
+    Ok(sig)
+}
+
+/// JSON schema of the args of the entrypoint of a script, in the format of the schemas stored
+/// with the scripts, to validate the args of a job before it is queued
+pub fn infer_args_schema(
+    language: &ScriptLang,
+    code: &str,
+    main_override: Option<String>,
+) -> error::Result<Value> {
+    let sig = parse_main_signature(language, code, main_override, true)?;
+    if sig.no_main_func == Some(true) {
+        return Err(Error::BadRequest(
+            "the script has no main function to infer the args from".to_string(),
+        ));
+    }
+
+    let mut properties = Map::new();
+    for arg in &sig.args {
+        let mut property = typ_to_schema(&arg.typ);
+        if let Some(default) = arg.default.as_ref().filter(|x| !x.is_null()) {
+            property["default"] = default.clone();
+        }
+        properties.insert(arg.name.clone(), property);
+    }
+    let required = sig
+        .args
+        .iter()
+        .filter(|x| !x.has_default)
+        .map(|x| x.name.clone())
+        .collect::<Vec<_>>();
+    let order = sig.args.iter().map(|x| x.name.clone()).collect::<Vec<_>>();
+
+    Ok(json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "order": order,
+    }))
+}
+
+fn typ_to_schema(typ: &Typ) -> Value {
+    match typ {
+        Typ::Str(None) => json!({ "type": "string" }),
+        Typ::Str(Some(options)) => json!({ "type": "string", "enum": options }),
+        Typ::Int => json!({ "type": "integer" }),
+        Typ::Float => json!({ "type": "number" }),
+        Typ::Bool => json!({ "type": "boolean" }),
+        Typ::List(typ) => json!({ "type": "array", "items": typ_to_schema(typ) }),
+        Typ::Bytes => json!({ "type": "string", "contentEncoding": "base64" }),
+        Typ::Datetime => json!({ "type": "string", "format": "date-time" }),
+        Typ::Resource(name) => json!({ "type": "object", "format": format!("resource-{name}") }),
+        Typ::Email => json!({ "type": "string", "format": "email" }),
+        Typ::Sql => json!({ "type": "string", "format": "sql" }),
+        Typ::DynSelect(name) => json!({ "type": "object", "format": format!("dynselect-{name}") }),
+        Typ::Object(props) => json!({ "type": "object", "properties": properties_schema(props) }),
+        Typ::OneOf(variants) => json!({
+            "type": "object",
+            "oneOf": variants
+                .iter()
+                .map(|v| json!({
+                    "type": "object",
+                    "title": v.label,
+                    "properties": properties_schema(&v.properties),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+        Typ::Unknown => json!({}),
+    }
+}
+
+fn properties_schema(props: &[ObjectProperty]) -> Map<String, Value> {
+    props
+        .iter()
+        .map(|p| (p.key.clone(), typ_to_schema(&p.typ)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_infer_python_args_schema() {
+        let code = r#"
+from typing import List, Literal
+
+def main(name: str, tags: List[str], count: int = 3, mode: Literal["a", "b"] = "a"):
+    pass
+"#;
+        let schema = infer_args_schema(&ScriptLang::Python3, code, None).unwrap();
+        assert_eq!(schema["required"], json!(["name", "tags"]));
+        assert_eq!(schema["order"], json!(["name", "tags", "count", "mode"]));
+        assert_eq!(schema["properties"]["name"], json!({ "type": "string" }));
+        assert_eq!(
+            schema["properties"]["count"],
+            json!({ "type": "integer", "default": 3 })
+        );
+        assert_eq!(schema["properties"]["mode"]["enum"], json!(["a", "b"]));
+        assert_eq!(
+            schema["properties"]["tags"]["items"],
+            json!({ "type": "string" })
+        );
+    }
+
+    #[test]
+    fn test_infer_deno_args_schema() {
+        let code = r#"
+export async function main(url: string, retries: number = 2, dry_run?: boolean) {}
+"#;
+        let schema = infer_args_schema(&ScriptLang::Deno, code, None).unwrap();
+        assert_eq!(schema["required"], json!(["url"]));
+        assert_eq!(
+            schema["properties"]["retries"],
+            json!({ "type": "number", "default": 2 })
+        );
+        assert_eq!(
+            schema["properties"]["dry_run"],
+            json!({ "type": "boolean" })
+        );
+    }
+
+    #[test]
+    fn test_infer_args_schema_unsupported_language() {
+        assert!(infer_args_schema(&ScriptLang::Postgresql, "SELECT 1", None).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::HashMap, fs, process::Stdio};
+use std::{collections::HashMap, fs, process::Stdio, sync::Arc};
 
 use regex::Regex;
 use serde_json::{json, value::RawValue};
@@ -9,7 +9,7 @@ use windmill_common::{
     jobs::QueuedJob,
     worker::{to_raw_value, write_file},
 };
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 lazy_static::lazy_static! {
     pub static ref BIN_BASH: String = std::env::var("BASH_PATH").unwrap_or_else(|_| "/bin/bash".to_string());
@@ -24,10 +24,12 @@ lazy_static::lazy_static! {
 
 use crate::{
     common::{
-        build_args_map, get_reserved_variables, read_file, read_file_content, start_child_process,
-        OccupancyMetrics,
+        build_args_map, get_reserved_variables, read_and_check_file, read_file_content,
+        start_child_process, OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
+    stdin_stream::stdin_stream_enabled,
     AuthedClientBackgroundTask, DISABLE_NSJAIL, DISABLE_NUSER, HOME_ENV, NSJAIL_PATH, PATH_ENV,
     POWERSHELL_CACHE_DIR, POWERSHELL_PATH, PROXY_ENVS, TZ_ENV,
 };
@@ -44,8 +46,10 @@ lazy_static::lazy_static! {
 pub async fn handle_bash_job(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     content: &str,
     job_dir: &str,
@@ -107,7 +111,8 @@ exit $exit_status
     write_file(job_dir, "wrapper.sh", &script)?;
 
     let token = client.get_token().await;
-    let mut reserved_variables = get_reserved_variables(job, &token, db).await?;
+    let mut reserved_variables =
+        get_reserved_variables(job, &token, db, job_dir, worker_name).await?;
     reserved_variables.insert("RUST_LOG".to_string(), "info".to_string());
 
     let args = build_args_map(job, client, db).await?.map(Json);
@@ -135,7 +140,7 @@ exit $exit_status
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_BASH_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_BASH_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
                 .replace("{SHARED_MOUNT}", shared_mount),
@@ -159,6 +164,9 @@ exit $exit_status
             .args(cmd_args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        if stdin_stream_enabled(job) {
+            nsjail_cmd.stdin(Stdio::piped());
+        }
         start_child_process(nsjail_cmd, NSJAIL_PATH.as_str()).await?
     } else {
         let mut cmd_args = vec!["wrapper.sh"];
@@ -175,18 +183,24 @@ exit $exit_status
             .args(cmd_args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        if stdin_stream_enabled(job) {
+            bash_cmd.stdin(Stdio::piped());
+        }
         start_child_process(bash_cmd, BIN_BASH.as_str()).await?
     };
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "bash run",
+        ChildPhase::Run,
         job.timeout,
         true,
         &mut Some(occupancy_metrics),
@@ -196,7 +210,7 @@ exit $exit_status
     let result_json_path = format!("{job_dir}/result.json");
     if let Ok(metadata) = tokio::fs::metadata(&result_json_path).await {
         if metadata.len() > 0 {
-            return Ok(read_file(&result_json_path).await?);
+            return Ok(read_and_check_file(&result_json_path).await?);
         }
     }
 
@@ -233,8 +247,10 @@ fn raw_to_string(x: &str) -> String {
 pub async fn handle_powershell_job(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     content: &str,
     job_dir: &str,
@@ -312,22 +328,25 @@ pub async fn handle_powershell_job(
     if !install_string.is_empty() {
         logs1.push_str("\n\nInstalling modules...");
         append_logs(&job.id, &job.workspace_id, logs1, db).await;
-        let child = Command::new(POWERSHELL_PATH.as_str())
-            .args(&["-Command", &install_string])
+        let mut cmd = Command::new(POWERSHELL_PATH.as_str());
+        cmd.args(&["-Command", &install_string])
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
+            .stderr(Stdio::piped());
+        let child = start_child_process(cmd, POWERSHELL_PATH.as_str()).await?;
 
         handle_child(
             &job.id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child,
             false,
             worker_name,
             &job.workspace_id,
             "powershell install",
+            ChildPhase::DependencyInstall,
             job.timeout,
             false,
             &mut Some(occupancy_metrics),
@@ -418,7 +437,8 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
     )?;
 
     let token = client.get_token().await;
-    let mut reserved_variables = get_reserved_variables(job, &token, db).await?;
+    let mut reserved_variables =
+        get_reserved_variables(job, &token, db, job_dir, worker_name).await?;
     reserved_variables.insert("RUST_LOG".to_string(), "info".to_string());
 
     let _ = write_file(job_dir, "result.json", "")?;
@@ -429,7 +449,7 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_POWERSHELL_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_POWERSHELL_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
                 .replace("{SHARED_MOUNT}", shared_mount)
@@ -443,7 +463,8 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
             "wrapper.sh",
         ];
         cmd_args.extend(pwsh_args.iter().map(|x| x.as_str()));
-        Command::new(NSJAIL_PATH.as_str())
+        let mut nsjail_cmd = Command::new(NSJAIL_PATH.as_str());
+        nsjail_cmd
             .current_dir(job_dir)
             .env_clear()
             .envs(PROXY_ENVS.clone())
@@ -453,8 +474,8 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
             .env("BASE_INTERNAL_URL", base_internal_url)
             .args(cmd_args)
             .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?
+            .stderr(Stdio::piped());
+        start_child_process(nsjail_cmd, NSJAIL_PATH.as_str()).await?
     } else {
         let mut cmd;
         let mut cmd_args;
@@ -525,19 +546,22 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
                 );
         }
 
-        cmd.spawn()?
+        start_child_process(cmd, POWERSHELL_PATH.as_str()).await?
     };
 
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "powershell run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
@@ -547,7 +571,7 @@ $env:PSModulePath = \"{};$PSModulePathBackup\"",
     let result_json_path = format!("{job_dir}/result.json");
     if let Ok(metadata) = tokio::fs::metadata(&result_json_path).await {
         if metadata.len() > 0 {
-            return Ok(read_file(&result_json_path).await?);
+            return Ok(read_and_check_file(&result_json_path).await?);
         }
     }
 
@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use futures::future::BoxFuture;
 use futures::{FutureExt, TryFutureExt};
@@ -9,7 +10,7 @@ use windmill_common::{error::Error, worker::to_raw_value};
 use windmill_parser_sql::{
     parse_bigquery_sig, parse_db_resource, parse_sql_blocks, parse_sql_statement_named_params,
 };
-use windmill_queue::{CanceledBy, HTTP_CLIENT};
+use windmill_queue::{CanceledBy, JobQueue, HTTP_CLIENT};
 
 use serde::Deserialize;
 
@@ -206,6 +207,7 @@ pub async fn do_bigquery(
     client: &AuthedClientBackgroundTask,
     query: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -360,6 +362,7 @@ pub async fn do_bigquery(
         job.id,
         job.timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         result_f.map_err(to_anyhow),
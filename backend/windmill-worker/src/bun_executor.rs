@@ -1,3 +1,4 @@
+use std::sync::Arc;
 #[cfg(feature = "deno_core")]
 use std::time::Instant;
 use std::{collections::HashMap, fs, io, path::Path, process::Stdio};
@@ -12,7 +13,7 @@ use serde_json::value::RawValue;
 use sha2::Digest;
 use uuid::Uuid;
 use windmill_parser_ts::remove_pinned_imports;
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 #[cfg(feature = "enterprise")]
 use crate::common::build_envs_map;
@@ -23,7 +24,8 @@ use crate::{
         read_file, read_file_content, read_result, start_child_process, write_file_binary,
         OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
     AuthedClientBackgroundTask, BUNFIG_INSTALL_SCOPES, BUN_BUNDLE_CACHE_DIR, BUN_CACHE_DIR,
     BUN_DEPSTAR_CACHE_DIR, BUN_PATH, DISABLE_NSJAIL, DISABLE_NUSER, HOME_ENV, NODE_BIN_PATH,
     NODE_PATH, NPM_CONFIG_REGISTRY, NPM_PATH, NSJAIL_PATH, PATH_ENV, PROXY_ENVS, TZ_ENV,
@@ -86,9 +88,11 @@ fn split_lockfile(lockfile: &str) -> (&str, Option<&str>, bool) {
 pub async fn gen_bun_lockfile(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_id: &Uuid,
     w_id: &str,
     db: Option<&sqlx::Pool<sqlx::Postgres>>,
+    job_queue: Option<&Arc<dyn JobQueue>>,
     token: &str,
     script_path: &str,
     job_dir: &str,
@@ -144,17 +148,20 @@ pub async fn gen_bun_lockfile(
 
         let mut child_process = start_child_process(child_cmd, &*BUN_PATH).await?;
 
-        if let Some(db) = db {
+        if let (Some(db), Some(job_queue)) = (db, job_queue) {
             handle_child(
                 job_id,
                 db,
+                job_queue,
                 mem_peak,
                 canceled_by,
+                run_records,
                 child_process,
                 false,
                 worker_name,
                 w_id,
                 "bun build",
+                ChildPhase::Run,
                 None,
                 false,
                 occupancy_metrics,
@@ -175,9 +182,11 @@ pub async fn gen_bun_lockfile(
         install_bun_lockfile(
             mem_peak,
             canceled_by,
+            run_records,
             job_id,
             w_id,
             db,
+            job_queue,
             job_dir,
             worker_name,
             common_bun_proc_envs,
@@ -262,9 +271,11 @@ registry = {}
 pub async fn install_bun_lockfile(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_id: &Uuid,
     w_id: &str,
     db: Option<&sqlx::Pool<sqlx::Postgres>>,
+    job_queue: Option<&Arc<dyn JobQueue>>,
     job_dir: &str,
     worker_name: &str,
     common_bun_proc_envs: HashMap<String, String>,
@@ -327,17 +338,20 @@ pub async fn install_bun_lockfile(
     let mut child_process = start_child_process(child_cmd, &*BUN_PATH).await?;
 
     gen_bunfig(job_dir).await?;
-    if let Some(db) = db {
+    if let (Some(db), Some(job_queue)) = (db, job_queue) {
         handle_child(
             job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             w_id,
             "bun install",
+            ChildPhase::DependencyInstall,
             None,
             false,
             occupancy_metrics,
@@ -477,9 +491,11 @@ pub async fn generate_wrapper_mjs(
     job_id: &Uuid,
     worker_name: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     timeout: Option<i32>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     common_bun_proc_envs: &HashMap<String, String>,
     occupancy_metrics: &mut Option<&mut OccupancyMetrics>,
 ) -> Result<()> {
@@ -500,13 +516,16 @@ pub async fn generate_wrapper_mjs(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child_process,
         false,
         worker_name,
         w_id,
         "bun build",
+        ChildPhase::Run,
         timeout,
         false,
         occupancy_metrics,
@@ -526,9 +545,11 @@ pub async fn generate_bun_bundle(
     job_id: &Uuid,
     worker_name: &str,
     db: Option<sqlx::Pool<sqlx::Postgres>>,
+    job_queue: Option<&Arc<dyn JobQueue>>,
     timeout: Option<i32>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     common_bun_proc_envs: &HashMap<String, String>,
     occupancy_metrics: &mut Option<&mut OccupancyMetrics>,
 ) -> Result<()> {
@@ -546,17 +567,20 @@ pub async fn generate_bun_bundle(
     child.env("SystemRoot", SYSTEM_ROOT.as_str());
 
     let mut child_process = start_child_process(child, &*BUN_PATH).await?;
-    if let Some(db) = db {
+    if let (Some(db), Some(job_queue)) = (db, job_queue) {
         handle_child(
             job_id,
             &db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             w_id,
             "bun build",
+            ChildPhase::Run,
             timeout,
             false,
             occupancy_metrics,
@@ -670,6 +694,7 @@ pub async fn prebundle_bun_script(
     job_id: &Uuid,
     w_id: &str,
     db: Option<DB>,
+    job_queue: Option<&Arc<dyn JobQueue>>,
     job_dir: &str,
     base_internal_url: &str,
     worker_name: &str,
@@ -718,9 +743,11 @@ pub async fn prebundle_bun_script(
         job_id,
         worker_name,
         db.clone(),
+        job_queue,
         None,
         &mut 0,
         &mut None,
+        &mut JobRunRecords::default(),
         &common_bun_proc_envs,
         occupancy_metrics,
     )
@@ -815,8 +842,10 @@ pub async fn handle_bun_job(
     codebase: Option<String>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     job_dir: &str,
     inner_content: &String,
@@ -943,9 +972,11 @@ pub async fn handle_bun_job(
                 install_bun_lockfile(
                     mem_peak,
                     canceled_by,
+                    run_records,
                     &job.id,
                     &job.workspace_id,
                     Some(db),
+                    Some(job_queue),
                     job_dir,
                     worker_name,
                     common_bun_proc_envs.clone(),
@@ -981,9 +1012,11 @@ pub async fn handle_bun_job(
         let _ = gen_bun_lockfile(
             mem_peak,
             canceled_by,
+            run_records,
             &job.id,
             &job.workspace_id,
             Some(db),
+            Some(job_queue),
             &client.get_token().await,
             &job.script_path(),
             job_dir,
@@ -1128,7 +1161,7 @@ try {{
     await run();
 }} catch(e) {{
     console.error(e);
-    let err = {{ message: e.message, name: e.name, stack: e.stack }};
+    let err = {{ message: e.message, name: e.name, stack: e.stack, idempotent: e?.idempotent }};
     let step_id = process.env.WM_FLOW_STEP_ID;
     if (step_id) {{
         err["step_id"] = step_id;
@@ -1161,7 +1194,7 @@ try {{
         };
         let reserved_variables_f = async {
             let client = client.get_authed().await;
-            let vars = get_reserved_variables(job, &client.token, db).await?;
+            let vars = get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
             Ok(vars) as Result<HashMap<String, String>>
         };
         let (_, reserved_variables) = tokio::try_join!(args_and_out_f, reserved_variables_f)?;
@@ -1224,9 +1257,11 @@ try {{
                 &job.id,
                 worker_name,
                 Some(db.clone()),
+                Some(job_queue),
                 job.timeout,
                 mem_peak,
                 canceled_by,
+                run_records,
                 &common_bun_proc_envs,
                 &mut Some(occupancy_metrics),
             )
@@ -1266,9 +1301,11 @@ try {{
                 &job.id,
                 worker_name,
                 db,
+                job_queue,
                 job.timeout,
                 mem_peak,
                 canceled_by,
+                run_records,
                 &common_bun_proc_envs,
                 &mut Some(occupancy_metrics),
             )
@@ -1314,6 +1351,7 @@ try {{
                 job.id,
                 job.timeout,
                 db,
+                job_queue,
                 mem_peak,
                 canceled_by,
                 worker_name,
@@ -1336,7 +1374,7 @@ try {{
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_BUN_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_BUN_CONTENT, job)?
                 .replace("{LANG}", if annotation.nodejs { "nodejs" } else { "bun" })
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CACHE_DIR}", BUN_CACHE_DIR)
@@ -1463,13 +1501,16 @@ try {{
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "bun run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
@@ -1533,6 +1574,7 @@ pub async fn start_worker(
     requirements_o: Option<String>,
     codebase: Option<String>,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     inner_content: &str,
     base_internal_url: &str,
     job_dir: &str,
@@ -1607,9 +1649,11 @@ pub async fn start_worker(
             install_bun_lockfile(
                 &mut mem_peak,
                 &mut canceled_by,
+                &mut JobRunRecords::default(),
                 &Uuid::nil(),
                 &w_id,
                 Some(db),
+                Some(job_queue),
                 job_dir,
                 worker_name,
                 common_bun_proc_envs.clone(),
@@ -1624,9 +1668,11 @@ pub async fn start_worker(
         let _ = gen_bun_lockfile(
             &mut mem_peak,
             &mut canceled_by,
+            &mut JobRunRecords::default(),
             &Uuid::nil(),
             &w_id,
             Some(db),
+            Some(job_queue),
             token,
             &script_path,
             job_dir,
@@ -1697,7 +1743,7 @@ for await (const line of Readline.createInterface({{ input: process.stdin }})) {
         let res = await Main.main(...[ {spread} ]);
         console.log("wm_res[success]:" + JSON.stringify(res ?? null, (key, value) => typeof value === 'undefined' ? null : value));
     }} catch (e) {{
-        console.log("wm_res[error]:" + JSON.stringify({{ message: e.message, name: e.name, stack: e.stack, line: line }}));
+        console.log("wm_res[error]:" + JSON.stringify({{ message: e.message, name: e.name, stack: e.stack, idempotent: e?.idempotent, line: line }}));
     }}
 }}
 "#,
@@ -1728,9 +1774,11 @@ for await (const line of Readline.createInterface({{ input: process.stdin }})) {
             &Uuid::nil(),
             worker_name,
             db,
+            job_queue,
             None,
             &mut mem_peak,
             &mut canceled_by,
+            &mut JobRunRecords::default(),
             &common_bun_proc_envs,
             &mut None,
         )
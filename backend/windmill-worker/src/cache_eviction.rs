@@ -0,0 +1,359 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    os::fd::AsRawFd,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+
+#[cfg(feature = "prometheus")]
+use std::sync::atomic::Ordering;
+
+use const_format::concatcp;
+use nix::fcntl::{flock, FlockArg};
+use uuid::Uuid;
+use windmill_common::worker::ROOT_CACHE_DIR;
+#[cfg(feature = "prometheus")]
+use windmill_common::METRICS_ENABLED;
+
+use crate::{DENO_CACHE_DIR, PIP_CACHE_DIR};
+
+lazy_static::lazy_static! {
+    /// evict the least recently used entries of the pip and deno caches when they grow over their
+    /// size cap. Disabled by default
+    pub static ref CACHE_EVICTION_ENABLED: bool = std::env::var("CACHE_EVICTION_ENABLED")
+        .ok()
+        .and_then(|x| x.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    static ref PIP_CACHE_MAX_SIZE_MB: u64 = std::env::var("PIP_CACHE_MAX_SIZE_MB")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(20 * 1024);
+
+    static ref DENO_CACHE_MAX_SIZE_MB: u64 = std::env::var("DENO_CACHE_MAX_SIZE_MB")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(10 * 1024);
+
+    static ref CACHE_EVICTION_INTERVAL: Duration = Duration::from_secs(
+        std::env::var("CACHE_EVICTION_INTERVAL_SECS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .filter(|x| *x > 0)
+            .unwrap_or(3600),
+    );
+
+    /// entries used or written more recently than this are never evicted, so that an entry being
+    /// installed is not deleted from under it. The pip entries used by the running jobs are
+    /// locked, see `lock_cache_entry`
+    static ref CACHE_EVICTION_MIN_AGE: Duration = Duration::from_secs(
+        std::env::var("CACHE_EVICTION_MIN_AGE_SECS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(3600),
+    );
+
+    /// pause between each batch of CACHE_EVICTION_IO_BATCH filesystem operations of a pass, so
+    /// that the maintenance does not starve the running jobs of IO
+    static ref CACHE_EVICTION_IO_PAUSE: Duration = Duration::from_millis(
+        std::env::var("CACHE_EVICTION_IO_PAUSE_MS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(20),
+    );
+
+    /// shared locks held on the cache entries used by the running jobs of the process, by job
+    static ref CACHE_ENTRY_LOCKS: Mutex<HashMap<Uuid, Vec<File>>> = Mutex::new(HashMap::new());
+}
+
+#[cfg(feature = "prometheus")]
+lazy_static::lazy_static! {
+    static ref CACHE_EVICTED_BYTES: Option<prometheus::IntCounterVec> = if METRICS_ENABLED.load(Ordering::Relaxed) {
+        Some(prometheus::register_int_counter_vec!(
+            "worker_cache_evicted_bytes",
+            "Total number of bytes reclaimed by evicting cache entries",
+            &["cache"]
+        )
+        .unwrap())
+    } else {
+        None
+    };
+}
+
+const CACHE_EVICTION_IO_BATCH: usize = 256;
+
+/// held by the worker process evicting the caches of the host, the workers of a host sharing their
+/// cache dirs. Considered stale, from a crashed process, after 2 intervals
+const CACHE_EVICTION_LOCK_FILE: &str = concatcp!(ROOT_CACHE_DIR, ".cache_eviction.lock");
+
+/// A size-capped cache and the dirs of its entries, an entry being a path at the given depth of
+/// one of the dirs. Entries are evicted as a whole
+struct EvictedCache {
+    name: &'static str,
+    roots: &'static [(&'static str, usize)],
+    max_size_mb: fn() -> u64,
+}
+
+const EVICTED_CACHES: &[EvictedCache] = &[
+    // one dir per requirement
+    EvictedCache {
+        name: "pip",
+        roots: &[(PIP_CACHE_DIR, 1)],
+        max_size_mb: || *PIP_CACHE_MAX_SIZE_MB,
+    },
+    // remote modules are `deps/<scheme>/<host>/<hash>` files, npm packages
+    // `npm/<registry>/<package>` dirs
+    EvictedCache {
+        name: "deno",
+        roots: &[
+            (concatcp!(DENO_CACHE_DIR, "/deps"), 3),
+            (concatcp!(DENO_CACHE_DIR, "/npm"), 2),
+        ],
+        max_size_mb: || *DENO_CACHE_MAX_SIZE_MB,
+    },
+];
+
+struct CacheEntry {
+    path: PathBuf,
+    size: u64,
+    last_used: SystemTime,
+}
+
+/// Mark a cache entry as used by the job, its last use ordering the eviction, and hold a shared
+/// lock on it until [`release_cache_entries`] so that it is not evicted while the job uses it.
+/// Returns false if the entry was evicted in the meantime
+pub fn lock_cache_entry(job_id: &Uuid, path: &str) -> bool {
+    if !*CACHE_EVICTION_ENABLED {
+        return true;
+    }
+    let Ok(file) = File::open(path) else {
+        return false;
+    };
+    // only blocks while the entry is being evicted
+    if let Err(e) = flock(file.as_raw_fd(), FlockArg::LockShared) {
+        tracing::warn!("could not lock cache entry {path}: {e:#}");
+    }
+    if !Path::new(path).exists() {
+        return false;
+    }
+    if let Err(e) = file.set_modified(SystemTime::now()) {
+        tracing::debug!("could not mark cache entry {path} as used: {e:#}");
+    }
+    CACHE_ENTRY_LOCKS
+        .lock()
+        .unwrap()
+        .entry(*job_id)
+        .or_default()
+        .push(file);
+    true
+}
+
+/// Release the locks held on the cache entries used by the job, see [`lock_cache_entry`]
+pub fn release_cache_entries(job_id: &Uuid) {
+    CACHE_ENTRY_LOCKS.lock().unwrap().remove(job_id);
+}
+
+/// Exclusive lock on the entry, held while it is evicted. None if a job holds it
+fn lock_for_eviction(path: &Path) -> Option<File> {
+    let file = File::open(path).ok()?;
+    flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock).ok()?;
+    Some(file)
+}
+
+/// Periodically evict the least recently used entries of the caches over their size cap
+pub async fn evict_caches_periodically(mut killpill_rx: tokio::sync::broadcast::Receiver<()>) {
+    if !*CACHE_EVICTION_ENABLED {
+        return;
+    }
+    loop {
+        tokio::select! {
+            _ = killpill_rx.recv() => return,
+            _ = tokio::time::sleep(*CACHE_EVICTION_INTERVAL) => (),
+        }
+        match tokio::task::spawn_blocking(evict_caches).await {
+            Ok(Ok(())) => (),
+            Ok(Err(e)) => tracing::error!("Error evicting the caches: {e:#}"),
+            Err(e) => tracing::error!("Cache eviction task panicked: {e:#}"),
+        }
+    }
+}
+
+fn evict_caches() -> std::io::Result<()> {
+    let Some(_lock) = EvictionLock::acquire()? else {
+        tracing::debug!("caches already being evicted by another worker of the host");
+        return Ok(());
+    };
+    let mut throttle = IoThrottle::default();
+    for cache in EVICTED_CACHES {
+        let max_size = (cache.max_size_mb)() * 1024 * 1024;
+        let reclaimed = evict_cache(cache, max_size, &mut throttle);
+        if reclaimed > 0 {
+            tracing::info!("evicted {reclaimed} bytes from the {} cache", cache.name);
+            #[cfg(feature = "prometheus")]
+            if let Some(counter) = CACHE_EVICTED_BYTES.as_ref() {
+                counter.with_label_values(&[cache.name]).inc_by(reclaimed);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete the least recently used entries of the cache until it fits in `max_size`, returning
+/// the reclaimed bytes
+fn evict_cache(cache: &EvictedCache, max_size: u64, throttle: &mut IoThrottle) -> u64 {
+    let mut entries = vec![];
+    for (root, depth) in cache.roots {
+        collect_entries(Path::new(root), *depth, &mut entries, throttle);
+    }
+    let mut size = entries.iter().map(|e| e.size).sum::<u64>();
+    if size <= max_size {
+        return 0;
+    }
+
+    let min_last_used = SystemTime::now() - *CACHE_EVICTION_MIN_AGE;
+    entries.sort_by_key(|e| e.last_used);
+    let mut reclaimed = 0;
+    for entry in entries {
+        if size <= max_size || entry.last_used > min_last_used {
+            break;
+        }
+        throttle.tick();
+        let Some(_lock) = lock_for_eviction(&entry.path) else {
+            tracing::debug!("not evicting {}, in use by a job", entry.path.display());
+            continue;
+        };
+        let removed = if entry.path.is_dir() {
+            std::fs::remove_dir_all(&entry.path)
+        } else {
+            std::fs::remove_file(&entry.path)
+        };
+        match removed {
+            Ok(()) => {
+                size -= entry.size;
+                reclaimed += entry.size;
+            }
+            Err(e) => tracing::warn!("could not evict {}: {e:#}", entry.path.display()),
+        }
+    }
+    if size > max_size {
+        tracing::warn!(
+            "the {} cache is still over its cap of {max_size} bytes ({size} bytes), its remaining entries are in use or were used too recently to be evicted",
+            cache.name
+        );
+    }
+    reclaimed
+}
+
+fn collect_entries(
+    dir: &Path,
+    depth: usize,
+    entries: &mut Vec<CacheEntry>,
+    throttle: &mut IoThrottle,
+) {
+    throttle.tick();
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for child in read_dir.flatten() {
+        let path = child.path();
+        if depth > 1 {
+            if child.file_type().is_ok_and(|t| t.is_dir()) {
+                collect_entries(&path, depth - 1, entries, throttle);
+            }
+            continue;
+        }
+        throttle.tick();
+        let Ok(metadata) = std::fs::symlink_metadata(&path) else {
+            continue;
+        };
+        // the entry is as recent as its most recently used or written file
+        let mut last_used = last_used(&metadata);
+        let size = if metadata.is_dir() {
+            dir_size(&path, &mut last_used, throttle)
+        } else {
+            metadata.len()
+        };
+        entries.push(CacheEntry { path, size, last_used });
+    }
+}
+
+fn dir_size(dir: &Path, last_used: &mut SystemTime, throttle: &mut IoThrottle) -> u64 {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut size = 0;
+    for child in read_dir.flatten() {
+        throttle.tick();
+        let Ok(metadata) = child.metadata() else {
+            continue;
+        };
+        *last_used = (*last_used).max(self::last_used(&metadata));
+        size += if metadata.is_dir() {
+            dir_size(&child.path(), last_used, throttle)
+        } else {
+            metadata.len()
+        };
+    }
+    size
+}
+
+fn last_used(metadata: &std::fs::Metadata) -> SystemTime {
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    metadata
+        .accessed()
+        .map_or(modified, |accessed| accessed.max(modified))
+}
+
+#[derive(Default)]
+struct IoThrottle {
+    ops: usize,
+}
+
+impl IoThrottle {
+    fn tick(&mut self) {
+        self.ops += 1;
+        if self.ops % CACHE_EVICTION_IO_BATCH == 0 {
+            std::thread::sleep(*CACHE_EVICTION_IO_PAUSE);
+        }
+    }
+}
+
+struct EvictionLock;
+
+impl EvictionLock {
+    fn acquire() -> std::io::Result<Option<Self>> {
+        let create = || {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(CACHE_EVICTION_LOCK_FILE)
+        };
+        match create() {
+            Ok(_) => Ok(Some(EvictionLock)),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let stale = std::fs::metadata(CACHE_EVICTION_LOCK_FILE)?
+                    .modified()?
+                    .elapsed()
+                    .is_ok_and(|x| x > *CACHE_EVICTION_INTERVAL * 2);
+                if !stale {
+                    return Ok(None);
+                }
+                std::fs::remove_file(CACHE_EVICTION_LOCK_FILE)?;
+                match create() {
+                    Ok(_) => Ok(Some(EvictionLock)),
+                    Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => Ok(None),
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for EvictionLock {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(CACHE_EVICTION_LOCK_FILE);
+    }
+}
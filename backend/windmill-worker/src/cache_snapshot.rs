@@ -0,0 +1,366 @@
+use std::{path::Path as FsPath, sync::Arc, time::Duration};
+
+use futures::{StreamExt, TryStreamExt};
+use object_store::{path::Path, ObjectStore, WriteMultipart};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    time::Instant,
+};
+use windmill_common::{
+    error::{self, to_anyhow, Error},
+    s3_helpers::OBJECT_STORE_CACHE_SETTINGS,
+    worker::ROOT_CACHE_DIR,
+    DB,
+};
+
+use crate::{python_executor::PIP_ENTRY_COMPLETE_MARKER, DENO_CACHE_DIR, PIP_CACHE_DIR};
+
+lazy_static::lazy_static! {
+    /// how often, in seconds, a snapshot of the pip and deno caches of a worker is pushed to the
+    /// instance object storage. When set, the workers also restore the latest snapshots at
+    /// startup so that they come up with warm caches. Disabled if not set
+    pub static ref CACHE_SNAPSHOT_INTERVAL_SECS: Option<u64> = std::env::var("CACHE_SNAPSHOT_INTERVAL_SECS")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .filter(|x| *x > 0);
+}
+
+/// advisory lock held by the worker pushing the cache snapshots, so that a single worker of the
+/// instance produces each version
+const CACHE_SNAPSHOT_LOCK_ID: i64 = 4244;
+
+const CACHE_SNAPSHOT_PREFIX: &str = "cache_snapshots";
+
+/// versions of each snapshot kept in the object storage, the older ones are deleted
+const KEPT_CACHE_SNAPSHOT_VERSIONS: usize = 3;
+
+const ZSTD_LEVEL: i32 = 3;
+
+/// size of the chunks the snapshots are read and uploaded by, and number of parts uploaded at once
+const UPLOAD_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+const MAX_CONCURRENT_UPLOAD_PARTS: usize = 4;
+
+/// the snapshotted cache dirs, by snapshot name
+const SNAPSHOT_CACHE_DIRS: &[(&str, &str)] = &[("pip", PIP_CACHE_DIR), ("deno", DENO_CACHE_DIR)];
+
+/// Whether an entry of the top level of a cache dir is complete and can be snapshotted. A pip
+/// requirement dir is only complete once its install wrote the completion marker, deno writing
+/// each of its files atomically
+fn is_complete_entry(name: &str, entry: &FsPath) -> bool {
+    match name {
+        "pip" => entry.join(PIP_ENTRY_COMPLETE_MARKER).exists(),
+        _ => true,
+    }
+}
+
+/// Latest version of a snapshot, only written once the snapshot itself is fully uploaded so that a
+/// partial upload is never restored
+#[derive(Serialize, Deserialize, Debug)]
+struct CacheSnapshotManifest {
+    version: i64,
+    size: u64,
+    sha256: String,
+}
+
+fn snapshot_path(name: &str, version: i64) -> Path {
+    Path::from(format!("{CACHE_SNAPSHOT_PREFIX}/{name}/{version}.tar.zst"))
+}
+
+fn manifest_path(name: &str) -> Path {
+    Path::from(format!("{CACHE_SNAPSHOT_PREFIX}/{name}/latest.json"))
+}
+
+/// Restore the latest snapshot of each cache dir that holds no file yet. A missing, partial or
+/// corrupt snapshot leaves the cache dir empty, the worker starting cold
+pub async fn restore_cache_snapshots() {
+    if CACHE_SNAPSHOT_INTERVAL_SECS.is_none() {
+        return;
+    }
+    let Some(os) = OBJECT_STORE_CACHE_SETTINGS.read().await.clone() else {
+        tracing::warn!("CACHE_SNAPSHOT_INTERVAL_SECS is set but no object storage is configured, not restoring the cache snapshots");
+        return;
+    };
+    for (name, dir) in SNAPSHOT_CACHE_DIRS {
+        if has_files(FsPath::new(dir)) {
+            tracing::info!("{name} cache is not empty, not restoring its snapshot");
+            continue;
+        }
+        let start = Instant::now();
+        match restore_cache_snapshot(os.clone(), name, dir).await {
+            Ok(Some(version)) => tracing::info!(
+                "restored version {version} of the {name} cache snapshot in {}ms",
+                start.elapsed().as_millis()
+            ),
+            Ok(None) => tracing::info!("no {name} cache snapshot to restore"),
+            Err(e) => {
+                tracing::warn!("could not restore the {name} cache snapshot, starting with an empty cache: {e:#}")
+            }
+        }
+    }
+}
+
+async fn restore_cache_snapshot(
+    os: Arc<dyn ObjectStore>,
+    name: &'static str,
+    dir: &'static str,
+) -> error::Result<Option<i64>> {
+    let manifest = match os.get(&manifest_path(name)).await {
+        Ok(manifest) => manifest.bytes().await.map_err(to_anyhow)?,
+        Err(object_store::Error::NotFound { .. }) => return Ok(None),
+        Err(e) => return Err(to_anyhow(e).into()),
+    };
+    let manifest = serde_json::from_slice::<CacheSnapshotManifest>(&manifest)
+        .map_err(|e| Error::ExecutionErr(format!("invalid snapshot manifest: {e}")))?;
+
+    // streamed to disk rather than held in memory, the snapshots being as large as the caches
+    let archive_path = format!("{ROOT_CACHE_DIR}{name}_snapshot_restore.tar.zst");
+    let downloaded = download_snapshot(os, name, &manifest, &archive_path).await;
+    if let Err(e) = downloaded {
+        let _ = tokio::fs::remove_file(&archive_path).await;
+        return Err(e);
+    }
+
+    // unpacked next to the cache dir and only then swapped in, a failure leaving it untouched
+    let tmp_dir = format!("{ROOT_CACHE_DIR}{name}_snapshot_restore");
+    let unpacked = {
+        let tmp_dir = tmp_dir.clone();
+        let archive_path = archive_path.clone();
+        tokio::task::spawn_blocking(move || {
+            let _ = std::fs::remove_dir_all(&tmp_dir);
+            let decoder = zstd::stream::read::Decoder::new(std::fs::File::open(&archive_path)?)?;
+            tar::Archive::new(decoder).unpack(&tmp_dir)?;
+            std::fs::remove_dir_all(dir)?;
+            std::fs::rename(&tmp_dir, dir)
+        })
+        .await
+        .map_err(to_anyhow)?
+    };
+    let _ = tokio::fs::remove_file(&archive_path).await;
+    if let Err(e) = unpacked {
+        let _ = tokio::fs::remove_dir_all(&tmp_dir).await;
+        tokio::fs::create_dir_all(dir).await?;
+        return Err(Error::ExecutionErr(format!(
+            "could not unpack version {} of the snapshot: {e}",
+            manifest.version
+        )));
+    }
+    Ok(Some(manifest.version))
+}
+
+/// Download a snapshot to `archive_path`, checking it against its manifest
+async fn download_snapshot(
+    os: Arc<dyn ObjectStore>,
+    name: &str,
+    manifest: &CacheSnapshotManifest,
+    archive_path: &str,
+) -> error::Result<()> {
+    let mut chunks = os
+        .get(&snapshot_path(name, manifest.version))
+        .await
+        .map_err(to_anyhow)?
+        .into_stream();
+    let mut file = tokio::fs::File::create(archive_path).await?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    while let Some(chunk) = chunks.try_next().await.map_err(to_anyhow)? {
+        hasher.update(&chunk);
+        size += chunk.len() as u64;
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    if size != manifest.size || format!("{:x}", hasher.finalize()) != manifest.sha256 {
+        return Err(Error::ExecutionErr(format!(
+            "version {} of the snapshot does not match its manifest",
+            manifest.version
+        )));
+    }
+    Ok(())
+}
+
+fn has_files(dir: &FsPath) -> bool {
+    std::fs::read_dir(dir).is_ok_and(|entries| {
+        entries.flatten().any(|entry| {
+            entry
+                .file_type()
+                .is_ok_and(|t| !t.is_dir() || has_files(&entry.path()))
+        })
+    })
+}
+
+/// Periodically push a new version of the snapshot of each cache dir. Started on every worker
+/// process but only one of them pushes each version
+pub async fn snapshot_caches_periodically(
+    db: DB,
+    mut killpill_rx: tokio::sync::broadcast::Receiver<()>,
+) {
+    let Some(interval) = *CACHE_SNAPSHOT_INTERVAL_SECS else {
+        return;
+    };
+    loop {
+        tokio::select! {
+            _ = killpill_rx.recv() => return,
+            _ = tokio::time::sleep(Duration::from_secs(interval)) => (),
+        }
+        if let Err(e) = snapshot_caches(&db).await {
+            tracing::error!("Error pushing the cache snapshots: {e:#}");
+        }
+    }
+}
+
+async fn snapshot_caches(db: &DB) -> error::Result<()> {
+    let Some(os) = OBJECT_STORE_CACHE_SETTINGS.read().await.clone() else {
+        return Ok(());
+    };
+
+    // advisory locks are held by the session so the lock and unlock must use the same connection
+    let mut conn = db.acquire().await?;
+    let locked = sqlx::query_scalar::<_, bool>("SELECT pg_try_advisory_lock($1)")
+        .bind(CACHE_SNAPSHOT_LOCK_ID)
+        .fetch_one(&mut *conn)
+        .await?;
+    if !locked {
+        tracing::debug!("cache snapshots already being pushed by another worker");
+        return Ok(());
+    }
+
+    for (name, dir) in SNAPSHOT_CACHE_DIRS {
+        if !has_files(FsPath::new(dir)) {
+            continue;
+        }
+        let start = Instant::now();
+        match push_cache_snapshot(os.clone(), name, dir).await {
+            Ok(manifest) => tracing::info!(
+                "pushed version {} of the {name} cache snapshot ({} bytes) in {}ms",
+                manifest.version,
+                manifest.size,
+                start.elapsed().as_millis()
+            ),
+            Err(e) => tracing::error!("Error pushing the {name} cache snapshot: {e:#}"),
+        }
+    }
+
+    sqlx::query("SELECT pg_advisory_unlock($1)")
+        .bind(CACHE_SNAPSHOT_LOCK_ID)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+async fn push_cache_snapshot(
+    os: Arc<dyn ObjectStore>,
+    name: &'static str,
+    dir: &'static str,
+) -> error::Result<CacheSnapshotManifest> {
+    // archived to disk and uploaded from there in parts, never held in memory as a whole
+    let archive_path = format!("{ROOT_CACHE_DIR}{name}_snapshot.tar.zst");
+    let archived = {
+        let archive_path = archive_path.clone();
+        tokio::task::spawn_blocking(move || archive_completed_entries(name, dir, &archive_path))
+            .await
+            .map_err(to_anyhow)?
+    };
+    let version = chrono::Utc::now().timestamp_millis();
+    let uploaded = match archived {
+        Ok(()) => upload_snapshot(os.clone(), &snapshot_path(name, version), &archive_path).await,
+        Err(e) => Err(Error::ExecutionErr(format!("could not archive {dir}: {e}"))),
+    };
+    let _ = tokio::fs::remove_file(&archive_path).await;
+    let (size, sha256) = uploaded?;
+
+    let manifest = CacheSnapshotManifest { version, size, sha256 };
+    os.put(
+        &manifest_path(name),
+        serde_json::to_vec(&manifest).map_err(to_anyhow)?.into(),
+    )
+    .await
+    .map_err(to_anyhow)?;
+
+    prune_cache_snapshots(os, name).await;
+    Ok(manifest)
+}
+
+fn archive_completed_entries(name: &str, dir: &str, archive_path: &str) -> std::io::Result<()> {
+    let file = std::fs::File::create(archive_path)?;
+    let mut tar = tar::Builder::new(zstd::stream::write::Encoder::new(file, ZSTD_LEVEL)?);
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        if !is_complete_entry(name, &path) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            tar.append_dir_all(entry.file_name(), &path)?;
+        } else {
+            tar.append_path_with_name(&path, entry.file_name())?;
+        }
+    }
+    tar.into_inner()?.finish()?.sync_all()
+}
+
+/// Upload the archive as a multipart upload, returning its size and sha256
+async fn upload_snapshot(
+    os: Arc<dyn ObjectStore>,
+    path: &Path,
+    archive_path: &str,
+) -> error::Result<(u64, String)> {
+    let mut file = tokio::fs::File::open(archive_path).await?;
+    let mut upload = WriteMultipart::new_with_chunk_size(
+        os.put_multipart(path).await.map_err(to_anyhow)?,
+        UPLOAD_CHUNK_SIZE,
+    );
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    let written: error::Result<()> = async {
+        let mut buf = vec![0; UPLOAD_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                return Ok(());
+            }
+            hasher.update(&buf[..read]);
+            size += read as u64;
+            upload
+                .wait_for_capacity(MAX_CONCURRENT_UPLOAD_PARTS)
+                .await
+                .map_err(to_anyhow)?;
+            upload.write(&buf[..read]);
+        }
+    }
+    .await;
+    match written {
+        Ok(()) => {
+            upload.finish().await.map_err(to_anyhow)?;
+            Ok((size, format!("{:x}", hasher.finalize())))
+        }
+        Err(e) => {
+            let _ = upload.abort().await;
+            Err(e)
+        }
+    }
+}
+
+async fn prune_cache_snapshots(os: Arc<dyn ObjectStore>, name: &str) {
+    let prefix = Path::from(format!("{CACHE_SNAPSHOT_PREFIX}/{name}"));
+    let mut versions = os
+        .list(Some(&prefix))
+        .filter_map(|meta| async move {
+            let location = meta.ok()?.location;
+            location
+                .filename()?
+                .strip_suffix(".tar.zst")?
+                .parse::<i64>()
+                .ok()
+        })
+        .collect::<Vec<_>>()
+        .await;
+    versions.sort_unstable_by(|a, b| b.cmp(a));
+    for version in versions.into_iter().skip(KEPT_CACHE_SNAPSHOT_VERSIONS) {
+        if let Err(e) = os.delete(&snapshot_path(name, version)).await {
+            tracing::warn!(
+                "could not delete version {version} of the {name} cache snapshot: {e:#}"
+            );
+        }
+    }
+}
@@ -10,7 +10,9 @@ use sqlx::types::Json;
 use sqlx::{Pool, Postgres};
 use tokio::process::Command;
 use tokio::{fs::File, io::AsyncReadExt};
-use windmill_common::jobs::ENTRYPOINT_OVERRIDE;
+use windmill_common::jobs::{
+    JobKind, DEPENDENCY_TIMEOUT_ARG, ENTRYPOINT_OVERRIDE, FIXED_NOW_ARG, SEED_ARG,
+};
 
 #[cfg(feature = "parquet")]
 use windmill_common::s3_helpers::{
@@ -23,13 +25,15 @@ use windmill_common::worker::{
 use windmill_common::{
     error::{self, Error},
     jobs::QueuedJob,
+    scripts::{ScriptHash, ScriptLang},
+    utils::{calculate_hash, hostname},
     variables::ContextualVariable,
 };
 
 use anyhow::{anyhow, Result};
 
 use std::{
-    collections::{hash_map::DefaultHasher, HashMap},
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
     hash::{Hash, Hasher},
     time::Duration,
 };
@@ -40,8 +44,10 @@ use windmill_common::{variables, DB};
 use tokio::{io::AsyncWriteExt, process::Child, time::Instant};
 
 use crate::{
-    AuthedClient, AuthedClientBackgroundTask, JOB_DEFAULT_TIMEOUT, MAX_RESULT_SIZE,
-    MAX_TIMEOUT_DURATION,
+    env_from_resources::env_from_resources, staged_inputs::staged_inputs_env,
+    stdin_stream::stdin_stream_enabled, AuthedClient, AuthedClientBackgroundTask, ARGS_VIA_STDIN,
+    DEPENDENCY_TIMEOUT, DEPENDENCY_TIMEOUT_MULTIPLIER, DISABLE_NSJAIL, JOB_DEFAULT_TIMEOUT,
+    JOB_UMASK, MAX_RESULT_SIZE, MAX_TIMEOUT_DURATION, TOKEN_FILE,
 };
 
 pub async fn build_args_map<'a>(
@@ -67,35 +73,72 @@ pub async fn build_args_values(
     }
 }
 
-#[tracing::instrument(level = "trace", skip_all)]
-pub async fn create_args_and_out_file(
+async fn build_args_json(
     client: &AuthedClientBackgroundTask,
     job: &QueuedJob,
-    job_dir: &str,
     db: &Pool<Postgres>,
-) -> Result<(), Error> {
-    if let Some(args) = job.args.as_ref() {
+) -> Result<String, Error> {
+    let args_json = if let Some(args) = job.args.as_ref() {
         if let Some(x) = transform_json(client, &job.workspace_id, &args.0, job, db).await? {
-            write_file(
-                job_dir,
-                "args.json",
-                &serde_json::to_string(&x).unwrap_or_else(|_| "{}".to_string()),
-            )?;
+            serde_json::to_string(&x).unwrap_or_else(|_| "{}".to_string())
         } else {
-            write_file(
-                job_dir,
-                "args.json",
-                &serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string()),
-            )?;
+            serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string())
         }
     } else {
-        write_file(job_dir, "args.json", "{}")?;
+        "{}".to_string()
     };
+    Ok(args_json)
+}
 
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn create_args_and_out_file(
+    client: &AuthedClientBackgroundTask,
+    job: &QueuedJob,
+    job_dir: &str,
+    db: &Pool<Postgres>,
+) -> Result<(), Error> {
+    write_file(
+        job_dir,
+        "args.json",
+        &build_args_json(client, job, db).await?,
+    )?;
     write_file(job_dir, "result.json", "")?;
     Ok(())
 }
 
+/// Like [`create_args_and_out_file`] but, if ARGS_VIA_STDIN is set, the args are not written to
+/// args.json and are returned instead, to be piped to the child with [`pipe_args_to_stdin`]
+#[tracing::instrument(level = "trace", skip_all)]
+pub async fn create_args_stdin_or_file(
+    client: &AuthedClientBackgroundTask,
+    job: &QueuedJob,
+    job_dir: &str,
+    db: &Pool<Postgres>,
+) -> Result<Option<String>, Error> {
+    if !*ARGS_VIA_STDIN || stdin_stream_enabled(job) {
+        create_args_and_out_file(client, job, job_dir, db).await?;
+        return Ok(None);
+    }
+    let args_json = build_args_json(client, job, db).await?;
+    write_file(job_dir, "result.json", "")?;
+    Ok(Some(args_json))
+}
+
+/// Write the args to the stdin of the child, which must have been spawned with a piped stdin, and
+/// close it. This is done in the background so that the worker is not blocked on large args until
+/// the child reads them
+pub fn pipe_args_to_stdin(child: &mut Child, args_json: String) {
+    if let Some(mut stdin) = child.stdin.take() {
+        tokio::spawn(async move {
+            if let Err(e) = stdin.write_all(args_json.as_bytes()).await {
+                tracing::error!("could not write args to the stdin of the job process: {e:#}");
+            }
+        });
+    } else {
+        tracing::error!("job process has no piped stdin to write args to");
+    }
+}
+
 pub async fn write_file_binary(dir: &str, path: &str, content: &[u8]) -> error::Result<File> {
     let path = format!("{}/{}", dir, path);
     let mut file = File::create(&path).await?;
@@ -106,6 +149,67 @@ pub async fn write_file_binary(dir: &str, path: &str, content: &[u8]) -> error::
 
 lazy_static::lazy_static! {
     static ref RE_RES_VAR: Regex = Regex::new(r#"\$(?:var|res|encrypted)\:"#).unwrap();
+
+    /// env variables set for all the jobs of a language, e.g.
+    /// `{"python3": {"PYTHONDONTWRITEBYTECODE": "1"}, "deno": {"DENO_NO_UPDATE_CHECK": "1"}}`.
+    /// See `merge_job_envs` for how they combine with the other variables of a job
+    static ref LANGUAGE_BASE_ENVS: HashMap<ScriptLang, HashMap<String, String>> =
+        match std::env::var("LANGUAGE_BASE_ENVS") {
+            Ok(envs) => serde_json::from_str(&envs).unwrap_or_else(|e| {
+                tracing::error!("Invalid LANGUAGE_BASE_ENVS, ignoring it: {e}");
+                HashMap::new()
+            }),
+            Err(_) => HashMap::new(),
+        };
+
+    /// `WM_EMAIL` of the jobs whose user email could not be resolved, e.g. an empty string to
+    /// set it explicitly empty. Not set, these jobs keep the `WM_EMAIL` they were pushed with
+    static ref MISSING_EMAIL_FALLBACK: Option<String> = std::env::var("MISSING_EMAIL_FALLBACK").ok();
+
+    /// set WM_WORKER_NAME and WM_WORKER_HOST in the env of the jobs. Disabled by default, the
+    /// names of the workers and hosts not being something every script should see
+    static ref EXPOSE_WORKER_IDENTITY: bool = std::env::var("EXPOSE_WORKER_IDENTITY")
+        .ok()
+        .and_then(|x| x.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    /// secret values resolved for the args of the running jobs, scrubbed from their results
+    static ref JOB_SECRETS: std::sync::Mutex<HashMap<Uuid, HashSet<String>>> =
+        std::sync::Mutex::new(HashMap::new());
+}
+
+/// Remember the strings of `value` as secrets of the job, so that they are scrubbed from its result
+fn register_job_secrets(job_id: &Uuid, value: &Value) {
+    fn collect(value: &Value, secrets: &mut HashSet<String>) {
+        match value {
+            Value::String(s) if !s.is_empty() => {
+                secrets.insert(s.clone());
+            }
+            Value::Array(values) => values.iter().for_each(|v| collect(v, secrets)),
+            Value::Object(values) => values.values().for_each(|v| collect(v, secrets)),
+            _ => (),
+        }
+    }
+    let mut job_secrets = JOB_SECRETS.lock().unwrap();
+    collect(value, job_secrets.entry(*job_id).or_default());
+}
+
+/// Secrets resolved for the args of the job, which are forgotten
+pub fn take_job_secrets(job_id: &Uuid) -> HashSet<String> {
+    JOB_SECRETS
+        .lock()
+        .unwrap()
+        .remove(job_id)
+        .unwrap_or_default()
+}
+
+#[cfg(feature = "prometheus")]
+lazy_static::lazy_static! {
+    static ref RESULT_SERIALIZATION_ERROR_COUNT: prometheus::IntCounter = prometheus::register_int_counter!(
+        "worker_result_serialization_error_count",
+        "Total number of jobs whose result could not be parsed as json."
+    )
+    .unwrap();
 }
 
 pub async fn transform_json<'a>(
@@ -127,15 +231,23 @@ pub async fn transform_json<'a>(
         return Ok(None);
     }
     let mut r = HashMap::new();
+    let mut resolved = ResolvedReferences::default();
     for (k, v) in vs {
         let inner_vs = v.get();
         if (*RE_RES_VAR).is_match(inner_vs) {
             let value = serde_json::from_str(inner_vs).map_err(|e| {
                 error::Error::InternalErr(format!("Error while parsing inner arg: {e:#}"))
             })?;
-            let transformed =
-                transform_json_value(&k, &client.get_authed().await, workspace, value, job, db)
-                    .await?;
+            let transformed = transform_json_value_with_cache(
+                &k,
+                &client.get_authed().await,
+                workspace,
+                value,
+                job,
+                db,
+                &mut resolved,
+            )
+            .await?;
             let as_raw = serde_json::from_value(transformed).map_err(|e| {
                 error::Error::InternalErr(format!("Error while parsing inner arg: {e:#}"))
             })?;
@@ -155,15 +267,23 @@ pub async fn transform_json_as_values<'a>(
     db: &Pool<Postgres>,
 ) -> error::Result<HashMap<String, serde_json::Value>> {
     let mut r: HashMap<String, serde_json::Value> = HashMap::new();
+    let mut resolved = ResolvedReferences::default();
     for (k, v) in vs {
         let inner_vs = v.get();
         if (*RE_RES_VAR).is_match(inner_vs) {
             let value = serde_json::from_str(inner_vs).map_err(|e| {
                 error::Error::InternalErr(format!("Error while parsing inner arg: {e:#}"))
             })?;
-            let transformed =
-                transform_json_value(&k, &client.get_authed().await, workspace, value, job, db)
-                    .await?;
+            let transformed = transform_json_value_with_cache(
+                &k,
+                &client.get_authed().await,
+                workspace,
+                value,
+                job,
+                db,
+                &mut resolved,
+            )
+            .await?;
             let as_raw = serde_json::from_value(transformed).map_err(|e| {
                 error::Error::InternalErr(format!("Error while parsing inner arg: {e:#}"))
             })?;
@@ -196,7 +316,28 @@ pub fn parse_npm_config(s: &str) -> (String, Option<String>) {
     return (url, token_opt);
 }
 
-#[async_recursion]
+/// Values of the `$var:`/`$res:`/`$step:` references already resolved while transforming the
+/// args of a job, keyed by the full reference so that a variable and a resource sharing a path
+/// are distinct. Resources are fetched interpolated, so the references they contain are resolved
+/// along with them and never looked up here.
+#[derive(Default)]
+pub struct ResolvedReferences(HashMap<String, Value>);
+
+impl ResolvedReferences {
+    /// Return the cached value of `reference` or resolve it, failed resolutions are not cached
+    pub async fn get_or_resolve<F>(&mut self, reference: &str, resolve: F) -> error::Result<Value>
+    where
+        F: std::future::Future<Output = error::Result<Value>>,
+    {
+        if let Some(value) = self.0.get(reference) {
+            return Ok(value.clone());
+        }
+        let value = resolve.await?;
+        self.0.insert(reference.to_string(), value.clone());
+        Ok(value)
+    }
+}
+
 pub async fn transform_json_value(
     name: &str,
     client: &AuthedClient,
@@ -204,17 +345,50 @@ pub async fn transform_json_value(
     v: Value,
     job: &QueuedJob,
     db: &Pool<Postgres>,
+) -> error::Result<Value> {
+    let mut resolved = ResolvedReferences::default();
+    transform_json_value_with_cache(name, client, workspace, v, job, db, &mut resolved).await
+}
+
+#[async_recursion]
+async fn transform_json_value_with_cache(
+    name: &str,
+    client: &AuthedClient,
+    workspace: &str,
+    v: Value,
+    job: &QueuedJob,
+    db: &Pool<Postgres>,
+    resolved: &mut ResolvedReferences,
 ) -> error::Result<Value> {
     match v {
         Value::String(y) if y.starts_with("$var:") => {
             let path = y.strip_prefix("$var:").unwrap();
-            client
-                .get_variable_value(path)
-                .await
-                .map(|x| json!(x))
-                .map_err(|e| {
-                    Error::NotFound(format!("Variable {path} not found for `{name}`: {e:#}"))
+            resolved
+                .get_or_resolve(&y, async {
+                    let value = client
+                        .get_variable_value(path)
+                        .await
+                        .map(|x| json!(x))
+                        .map_err(|e| {
+                            Error::NotFound(format!(
+                                "Variable {path} not found for `{name}`: {e:#}"
+                            ))
+                        })?;
+                    // a variable whose secrecy cannot be checked is scrubbed too
+                    let is_secret = sqlx::query_scalar::<_, bool>(
+                        "SELECT is_secret FROM variable WHERE workspace_id = $1 AND path = $2",
+                    )
+                    .bind(workspace)
+                    .bind(path)
+                    .fetch_optional(db)
+                    .await
+                    .map_or(true, |x| x.unwrap_or(true));
+                    if is_secret {
+                        register_job_secrets(&job.id, &value);
+                    }
+                    Ok(value)
                 })
+                .await
         }
         Value::String(y) if y.starts_with("$res:") => {
             let path = y.strip_prefix("$res:").unwrap();
@@ -223,27 +397,39 @@ pub async fn transform_json_value(
                     "Argument `{name}` is an invalid resource path: {path}",
                 )));
             }
-            client
-                .get_resource_value_interpolated::<serde_json::Value>(
-                    path,
-                    Some(job.id.to_string()),
-                )
-                .await
-                .map_err(|e| {
-                    Error::NotFound(format!("Resource {path} not found for `{name}`: {e:#}"))
+            resolved
+                .get_or_resolve(&y, async {
+                    client
+                        .get_resource_value_interpolated::<serde_json::Value>(
+                            path,
+                            Some(job.id.to_string()),
+                        )
+                        .await
+                        .map_err(|e| {
+                            Error::NotFound(format!(
+                                "Resource {path} not found for `{name}`: {e:#}"
+                            ))
+                        })
                 })
+                .await
+        }
+        Value::String(y) if y.starts_with("$step:") => {
+            let reference = y.strip_prefix("$step:").unwrap();
+            resolved
+                .get_or_resolve(&y, resolve_step_reference(name, reference, job, db))
+                .await
         }
         Value::String(y) if y.starts_with("$encrypted:") => {
             let encrypted = y.strip_prefix("$encrypted:").unwrap();
             let mc =
                 build_crypt_with_key_suffix(&db, &job.workspace_id, &job.id.to_string()).await?;
-            decrypt_value_with_mc(encrypted.to_string(), mc)
+            let value = decrypt_value_with_mc(encrypted.to_string(), mc)
                 .await
                 .and_then(|x| {
                     serde_json::from_str(&x).map_err(|e| Error::InternalErr(e.to_string()))
-                })
-
-            // let path = y.strip_prefix("$res:").unwrap();
+                })?;
+            register_job_secrets(&job.id, &value);
+            Ok(value)
         }
         Value::String(y) if y.starts_with("$") => {
             let flow_path = if let Some(uuid) = job.parent_job {
@@ -287,7 +473,8 @@ pub async fn transform_json_value(
             for (a, b) in m.clone().into_iter() {
                 m.insert(
                     a.clone(),
-                    transform_json_value(&a, client, workspace, b, job, &db).await?,
+                    transform_json_value_with_cache(&a, client, workspace, b, job, &db, resolved)
+                        .await?,
                 );
             }
             Ok(Value::Object(m))
@@ -296,6 +483,74 @@ pub async fn transform_json_value(
     }
 }
 
+/// Resolve a `$step:<step id>.<path>` reference of a flow step arg to a field of the result of a
+/// prior step of the flow. The path is made of `.` separated keys, array items being addressed by
+/// their index (e.g. `$step:b.response.data.items.0.id`); without a path, the whole result is
+/// taken. For the for-loops and branches, the first part of the path is the index of the iteration
+/// or branch. Only the addressed value is fetched, a missing step or key fails the job.
+async fn resolve_step_reference(
+    name: &str,
+    reference: &str,
+    job: &QueuedJob,
+    db: &DB,
+) -> error::Result<Value> {
+    let (step, path) = match reference.split_once('.') {
+        Some((step, path)) => (step, Some(path)),
+        None => (reference, None),
+    };
+    let flow_id = job.parent_job.ok_or_else(|| {
+        Error::BadRequest(format!(
+            "`{name}`: `$step:{reference}` can only be used in the args of a flow step"
+        ))
+    })?;
+    if step.is_empty() || path.is_some_and(|p| p.split('.').any(|k| k.is_empty())) {
+        return Err(Error::BadRequest(format!(
+            "`{name}`: invalid step reference `$step:{reference}`, expected `$step:<step id>.<key>.<key>...`"
+        )));
+    }
+
+    let get_result = |path: Option<&str>| {
+        windmill_queue::get_result_by_id(
+            db.clone(),
+            job.workspace_id.clone(),
+            flow_id,
+            step.to_string(),
+            path.map(|p| p.to_string()),
+        )
+    };
+    let value = get_result(path).await.map_err(|e| {
+        Error::NotFound(format!(
+            "`{name}`: no result for step `{step}` referenced by `$step:{reference}`: {e:#}"
+        ))
+    })?;
+    let value = serde_json::from_str::<Value>(value.get())
+        .map_err(|e| Error::InternalErr(format!("invalid result of step `{step}`: {e}")))?;
+
+    // a null is either a null value or a missing key, the whole result tells which
+    if let (Value::Null, Some(path)) = (&value, path) {
+        let result = get_result(None).await?;
+        let mut current = serde_json::from_str::<Value>(result.get())
+            .map_err(|e| Error::InternalErr(format!("invalid result of step `{step}`: {e}")))?;
+        for key in path.split('.') {
+            let next = match &mut current {
+                Value::Object(m) => m.remove(key),
+                Value::Array(a) => key
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|i| *i < a.len())
+                    .map(|i| a.swap_remove(i)),
+                _ => None,
+            };
+            current = next.ok_or_else(|| {
+                Error::NotFound(format!(
+                    "`{name}`: the result of step `{step}` has no `{key}` at `$step:{reference}`"
+                ))
+            })?;
+        }
+    }
+    Ok(value)
+}
+
 pub async fn read_file_content(path: &str) -> error::Result<String> {
     let mut file = File::open(path).await?;
     let mut content = "".to_string();
@@ -342,16 +597,51 @@ pub async fn read_result(job_dir: &str) -> error::Result<Box<RawValue>> {
     return read_file(&format!("{job_dir}/result.json")).await;
 }
 
+/// The content is only validated and kept as a `RawValue`, it is never converted to a
+/// `serde_json::Value`, so numbers (e.g. integers beyond 2^53) are stored exactly as written
 pub async fn read_and_check_file(path: &str) -> error::Result<Box<RawValue>> {
     let content = read_file_content(path).await?;
 
     check_result_too_big(content.len())?;
 
-    let raw_value: Box<RawValue> =
-        serde_json::from_str(&content).map_err(|e| anyhow!("{} is not valid json: {}", path, e))?;
+    let raw_value: Box<RawValue> = serde_json::from_str(&content)
+        .map_err(|e| result_serialization_error(path, &content, e))?;
     Ok(raw_value)
 }
 
+const RESULT_ERROR_TAIL_LINES: usize = 10;
+const RESULT_ERROR_TAIL_MAX_CHARS: usize = 2000;
+
+/// Error of a result that is not valid json, with the end of the offending output so that it can
+/// be debugged without access to the job dir
+fn result_serialization_error(path: &str, content: &str, e: serde_json::Error) -> Error {
+    #[cfg(feature = "prometheus")]
+    if windmill_common::METRICS_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        RESULT_SERIALIZATION_ERROR_COUNT.inc();
+    }
+
+    let lines = content.lines().collect_vec();
+    let tail = lines[lines.len().saturating_sub(RESULT_ERROR_TAIL_LINES)..].join("\n");
+    let tail_chars = tail.chars().count();
+    let tail = if tail_chars > RESULT_ERROR_TAIL_MAX_CHARS {
+        format!(
+            "...{}",
+            tail.chars()
+                .skip(tail_chars - RESULT_ERROR_TAIL_MAX_CHARS)
+                .collect::<String>()
+        )
+    } else {
+        tail
+    };
+    Error::JsonErr(json!({
+        "name": "ResultSerializationError",
+        "message": format!(
+            "{path} is not valid json: {e}. The result is expected to be a single json value \
+            (e.g. `{{\"key\": \"value\"}}`, `[1, 2]` or `\"a string\"`), last lines of the result:\n{tail}"
+        ),
+    }))
+}
+
 /// Use this to read `result.json` that were user-generated
 pub async fn read_and_check_result(job_dir: &str) -> error::Result<Box<RawValue>> {
     let result_path = format!("{job_dir}/result.json");
@@ -360,7 +650,10 @@ pub async fn read_and_check_result(job_dir: &str) -> error::Result<Box<RawValue>
         if metadata.len() > 0 {
             return read_and_check_file(&result_path)
                 .await
-                .map_err(|e| anyhow!("Failed to read result: {}", e).into());
+                .map_err(|e| match e {
+                    e @ Error::JsonErr(_) => e,
+                    e => anyhow!("Failed to read result: {}", e).into(),
+                });
         }
     }
     Ok(to_raw_value(&json!("null")))
@@ -379,6 +672,8 @@ pub async fn get_reserved_variables(
     job: &QueuedJob,
     token: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_dir: &str,
+    worker_name: &str,
 ) -> Result<HashMap<String, String>, Error> {
     let flow_path = if let Some(uuid) = job.parent_job {
         sqlx::query_scalar!("SELECT script_path FROM queue WHERE id = $1", uuid)
@@ -409,7 +704,95 @@ pub async fn get_reserved_variables(
     .await
     .to_vec();
 
-    Ok(build_envs_map(variables).await)
+    let mut envs = build_envs_map(variables).await;
+    if let Some(fallback) = MISSING_EMAIL_FALLBACK.as_ref() {
+        if !is_resolved_email(&job.email) {
+            tracing::warn!(
+                job_id = %job.id,
+                "could not resolve the email of user {} (permissioned as {}), using MISSING_EMAIL_FALLBACK as WM_EMAIL",
+                job.created_by,
+                job.permissioned_as
+            );
+            envs.insert("WM_EMAIL".to_string(), fallback.clone());
+        }
+    }
+    // WM_TOKEN expires, the token kept fresh by the worker for long running jobs is in this file
+    let token_file = if *DISABLE_NSJAIL {
+        format!("{job_dir}/{TOKEN_FILE}")
+    } else {
+        format!("/tmp/{TOKEN_FILE}")
+    };
+    envs.insert("WM_TOKEN_FILE".to_string(), token_file);
+    if *EXPOSE_WORKER_IDENTITY {
+        // the same values as the worker and hostname of the worker_ping row and the worker metrics
+        envs.insert("WM_WORKER_NAME".to_string(), worker_name.to_string());
+        envs.insert("WM_WORKER_HOST".to_string(), hostname());
+    }
+    if job.job_kind == JobKind::Preview {
+        envs.extend(get_deterministic_run_variables(job)?);
+    }
+    if let Some(inputs) = staged_inputs_env(job) {
+        envs.insert("WM_INPUTS".to_string(), inputs);
+    }
+    Ok(merge_job_envs(
+        job.language
+            .as_ref()
+            .and_then(|lang| LANGUAGE_BASE_ENVS.get(lang)),
+        env_from_resources(&job.id).unwrap_or_default(),
+        envs,
+    ))
+}
+
+/// Whether the email a job was pushed with is an actual email, and not a missing one. The
+/// `anonymous` email of the jobs of public apps and webhooks is not a missing one
+fn is_resolved_email(email: &str) -> bool {
+    email == "anonymous"
+        || email
+            .split_once('@')
+            .is_some_and(|(user, domain)| !user.trim().is_empty() && !domain.trim().is_empty())
+}
+
+/// Env of a job, by increasing precedence:
+/// 1. the base env of its language (`LANGUAGE_BASE_ENVS`)
+/// 2. the env set for the job itself (`_ENV_FROM_RESOURCES`)
+/// 3. the reserved variables (`WM_*`, `BASE_URL`, ...), that nothing overrides
+fn merge_job_envs(
+    language_base_env: Option<&HashMap<String, String>>,
+    job_env: HashMap<String, String>,
+    reserved: HashMap<String, String>,
+) -> HashMap<String, String> {
+    let mut envs = language_base_env.cloned().unwrap_or_default();
+    envs.extend(job_env);
+    envs.extend(reserved);
+    envs
+}
+
+/// `WM_FIXED_NOW` and `WM_SEED` for the test runs that asked for them, see [`FIXED_NOW_ARG`]
+fn get_deterministic_run_variables(job: &QueuedJob) -> error::Result<HashMap<String, String>> {
+    let mut envs = HashMap::new();
+    let Some(args) = job.args.as_ref() else {
+        return Ok(envs);
+    };
+    if let Some(now) = args.0.get(FIXED_NOW_ARG) {
+        let now = serde_json::from_str::<String>(now.get())
+            .ok()
+            .and_then(|x| chrono::DateTime::parse_from_rfc3339(&x).ok())
+            .ok_or_else(|| {
+                Error::BadRequest(format!("`{FIXED_NOW_ARG}` must be an RFC 3339 timestamp"))
+            })?;
+        // microseconds at most, what python's `datetime.fromisoformat` parses
+        envs.insert(
+            "WM_FIXED_NOW".to_string(),
+            now.to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+        );
+    }
+    if let Some(seed) = args.0.get(SEED_ARG) {
+        let seed = serde_json::from_str::<u64>(seed.get()).map_err(|_| {
+            Error::BadRequest(format!("`{SEED_ARG}` must be a non-negative integer"))
+        })?;
+        envs.insert("WM_SEED".to_string(), seed.to_string());
+    }
+    Ok(envs)
 }
 
 pub async fn build_envs_map(context: Vec<ContextualVariable>) -> HashMap<String, String> {
@@ -552,6 +935,18 @@ impl OccupancyMetrics {
 }
 
 pub async fn start_child_process(mut cmd: Command, executable: &str) -> Result<Child, Error> {
+    #[cfg(unix)]
+    if let Some(umask) = *JOB_UMASK {
+        // SAFETY: umask is async-signal-safe and does not allocate
+        unsafe {
+            cmd.pre_exec(move || {
+                nix::sys::stat::umask(nix::sys::stat::Mode::from_bits_truncate(
+                    umask as nix::libc::mode_t,
+                ));
+                Ok(())
+            });
+        }
+    }
     return cmd
         .spawn()
         .map_err(|err| tentatively_improve_error(Error::IoErr(err), executable));
@@ -615,6 +1010,57 @@ pub async fn resolve_job_timeout(
     }
 }
 
+/// Timeout of the dependency install/compile phases, separate from the execution timeout since
+/// resolving and installing dependencies can legitimately take much longer than running the code.
+/// In order: the `_DEPENDENCY_TIMEOUT` arg of the job, DEPENDENCY_TIMEOUT, then a multiple of the
+/// execution timeout. Never greater than the max timeout of the worker
+pub async fn resolve_dependency_timeout(
+    db: &Pool<Postgres>,
+    w_id: &str,
+    job_id: Uuid,
+    custom_timeout_secs: Option<i32>,
+) -> (Duration, Option<String>, bool) {
+    let job_timeout = if job_id == Uuid::nil() {
+        None
+    } else {
+        sqlx::query_scalar::<_, Option<Json<Value>>>("SELECT args->$2 FROM queue WHERE id = $1")
+            .bind(job_id)
+            .bind(DEPENDENCY_TIMEOUT_ARG)
+            .fetch_optional(db)
+            .await
+            .map_err(|e| {
+                tracing::error!(%e, "error getting dependency timeout of job {job_id}: {e:#}");
+            })
+            .ok()
+            .flatten()
+            .flatten()
+            .and_then(|x| x.0.as_u64())
+    };
+    let (timeout, warn_msg, is_job_specific) = if let Some(secs) = job_timeout {
+        (Duration::from_secs(secs), None, true)
+    } else if let Some(secs) = *DEPENDENCY_TIMEOUT {
+        (Duration::from_secs(secs), None, false)
+    } else {
+        let (timeout, warn_msg, is_job_specific) =
+            resolve_job_timeout(db, w_id, job_id, custom_timeout_secs).await;
+        (
+            timeout.saturating_mul(*DEPENDENCY_TIMEOUT_MULTIPLIER),
+            warn_msg,
+            is_job_specific,
+        )
+    };
+    if timeout <= *MAX_TIMEOUT_DURATION {
+        return (timeout, warn_msg, is_job_specific);
+    }
+    let clamp_msg = format!("WARNING: Dependency timeout of {} seconds was greater than the maximum timeout. The max timeout will be used instead", timeout.as_secs());
+    tracing::warn!(clamp_msg);
+    let warn_msg = match warn_msg {
+        Some(warn_msg) => format!("{warn_msg}\n{clamp_msg}"),
+        None => clamp_msg,
+    };
+    (*MAX_TIMEOUT_DURATION, Some(warn_msg), false)
+}
+
 pub async fn hash_args(
     _db: &DB,
     _client: &AuthedClient,
@@ -876,6 +1322,56 @@ pub async fn clean_cache() -> error::Result<()> {
     Ok(())
 }
 
+/// How the content of a deployed script is checked against the hash stored with it before it is
+/// run, to catch a corrupted or tampered script row
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ScriptIntegrityCheck {
+    Off,
+    /// log the mismatch and run the script anyway
+    Warn,
+    /// fail the job
+    Enforce,
+}
+
+lazy_static::lazy_static! {
+    pub static ref SCRIPT_INTEGRITY_CHECK: ScriptIntegrityCheck =
+        match std::env::var("SCRIPT_INTEGRITY_CHECK").ok().as_deref() {
+            None | Some("enforce") => ScriptIntegrityCheck::Enforce,
+            Some("warn") => ScriptIntegrityCheck::Warn,
+            Some("off") => ScriptIntegrityCheck::Off,
+            Some(x) => {
+                tracing::error!("Invalid SCRIPT_INTEGRITY_CHECK `{x}`, expected off, warn or enforce. Enforcing the check");
+                ScriptIntegrityCheck::Enforce
+            }
+        };
+}
+
+/// Check the content of a deployed script against its stored sha256 hash. Scripts without a
+/// stored hash (raw code previews, rows not written by the API) are not checked
+pub fn check_script_integrity(
+    mode: ScriptIntegrityCheck,
+    script_hash: &ScriptHash,
+    content: &str,
+    content_hash: Option<&str>,
+) -> error::Result<()> {
+    let Some(content_hash) = content_hash else {
+        return Ok(());
+    };
+    if mode == ScriptIntegrityCheck::Off || calculate_hash(content) == content_hash {
+        return Ok(());
+    }
+    if mode == ScriptIntegrityCheck::Warn {
+        tracing::warn!(
+            "content of script {script_hash} does not match its stored hash, running it anyway"
+        );
+        return Ok(());
+    }
+    tracing::error!("content of script {script_hash} does not match its stored hash");
+    Err(Error::ExecutionErr(
+        "script content failed integrity check".to_string(),
+    ))
+}
+
 lazy_static::lazy_static! {
     static ref RE_FLOW_ROOT: Regex = Regex::new(r"(?i)(.*?)(?:/branchone-\d+/|/branchall-\d+/|/loop-\d+/)").unwrap();
 
@@ -891,3 +1387,113 @@ pub fn use_flow_root_path(flow_path: &str) -> String {
         return flow_path.to_string();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    fn envs(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_job_envs_precedence() {
+        let base = envs(&[
+            ("PYTHONDONTWRITEBYTECODE", "1"),
+            ("PYTHONHASHSEED", "0"),
+            ("WM_WORKSPACE", "base"),
+        ]);
+        let merged = merge_job_envs(
+            Some(&base),
+            envs(&[("PYTHONHASHSEED", "42"), ("API_KEY", "job")]),
+            envs(&[("WM_WORKSPACE", "demo"), ("BASE_URL", "http://localhost")]),
+        );
+        assert_eq!(
+            merged,
+            envs(&[
+                ("PYTHONDONTWRITEBYTECODE", "1"),
+                ("PYTHONHASHSEED", "42"),
+                ("API_KEY", "job"),
+                ("WM_WORKSPACE", "demo"),
+                ("BASE_URL", "http://localhost"),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_merge_job_envs_without_base_env() {
+        let merged = merge_job_envs(
+            None,
+            envs(&[("API_KEY", "job")]),
+            envs(&[("WM_WORKSPACE", "demo")]),
+        );
+        assert_eq!(
+            merged,
+            envs(&[("API_KEY", "job"), ("WM_WORKSPACE", "demo")])
+        );
+    }
+
+    #[test]
+    fn test_is_resolved_email() {
+        assert!(is_resolved_email("alice@example.com"));
+        assert!(is_resolved_email("worker@windmill.dev"));
+        assert!(is_resolved_email("anonymous"));
+        assert!(!is_resolved_email(""));
+        assert!(!is_resolved_email("alice"));
+        assert!(!is_resolved_email("@example.com"));
+        assert!(!is_resolved_email("alice@"));
+    }
+
+    #[test]
+    fn test_check_script_integrity() {
+        let hash = ScriptHash(42);
+        let content = "def main():\n    return 1\n";
+        let stored = calculate_hash(content);
+        let tampered = "import os\ndef main():\n    return 1\n";
+        let check = |mode, content, stored: Option<&str>| {
+            check_script_integrity(mode, &hash, content, stored)
+        };
+
+        assert!(check(ScriptIntegrityCheck::Enforce, content, Some(&stored)).is_ok());
+        assert!(check(ScriptIntegrityCheck::Enforce, tampered, None).is_ok());
+        assert!(check(ScriptIntegrityCheck::Warn, tampered, Some(&stored)).is_ok());
+        assert!(check(ScriptIntegrityCheck::Off, tampered, Some(&stored)).is_ok());
+        let err = check(ScriptIntegrityCheck::Enforce, tampered, Some(&stored)).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("script content failed integrity check"));
+    }
+
+    #[tokio::test]
+    async fn test_resolved_references_fetch_once() {
+        let fetches = AtomicUsize::new(0);
+        // only counts the fetches actually awaited
+        let fetch = |value: Value| {
+            let fetches = &fetches;
+            async move {
+                fetches.fetch_add(1, Ordering::SeqCst);
+                Ok(value)
+            }
+        };
+        let mut resolved = ResolvedReferences::default();
+
+        for _ in 0..3 {
+            let value = resolved
+                .get_or_resolve("$var:f/foo/bar", fetch(json!("secret")))
+                .await
+                .unwrap();
+            assert_eq!(value, json!("secret"));
+        }
+        let value = resolved
+            .get_or_resolve("$res:f/foo/bar", fetch(json!({"url": "x"})))
+            .await
+            .unwrap();
+        assert_eq!(value, json!({"url": "x"}));
+        assert_eq!(fetches.load(Ordering::SeqCst), 2);
+    }
+}
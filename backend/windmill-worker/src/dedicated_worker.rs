@@ -24,7 +24,7 @@ use windmill_common::{
     worker::to_raw_value,
     DB,
 };
-use windmill_queue::append_logs;
+use windmill_queue::{append_logs, JobQueue, JobRunRecords};
 
 use anyhow::Context;
 
@@ -185,14 +185,14 @@ pub async fn handle_dedicated_process(
                                 let result = Arc::new(result);
                                 append_logs(&job.id, &job.workspace_id,  logs.clone(), db).await;
                                 if line.starts_with("wm_res[success]:") {
-                                    job_completed_tx.send(JobCompleted { job , result, mem_peak: 0, canceled_by: None, success: true, cached_res_path: None, token: token.to_string() }).await.unwrap()
+                                    job_completed_tx.send(JobCompleted { job , result, mem_peak: 0, canceled_by: None, records: JobRunRecords::default(), success: true, cached_res_path: None, token: token.to_string() }).await.unwrap()
                                 } else {
-                                    job_completed_tx.send(JobCompleted { job , result, mem_peak: 0, canceled_by: None, success: false, cached_res_path: None, token: token.to_string() }).await.unwrap()
+                                    job_completed_tx.send(JobCompleted { job , result, mem_peak: 0, canceled_by: None, records: JobRunRecords::default(), success: false, cached_res_path: None, token: token.to_string() }).await.unwrap()
                                 }
                             },
                             Err(e) => {
                                 tracing::error!("Could not deserialize job result `{line}`: {e:?}");
-                                job_completed_tx.send(JobCompleted { job , result: Arc::new(to_raw_value(&serde_json::json!({"error": format!("Could not deserialize job result `{line}`: {e:?}")}))),  mem_peak: 0, canceled_by: None, success: false, cached_res_path: None, token: token.to_string() }).await.unwrap();
+                                job_completed_tx.send(JobCompleted { job , result: Arc::new(to_raw_value(&serde_json::json!({"error": format!("Could not deserialize job result `{line}`: {e:?}")}))),  mem_peak: 0, canceled_by: None, records: JobRunRecords::default(), success: false, cached_res_path: None, token: token.to_string() }).await.unwrap();
                             },
                         };
                         logs = init_log.clone();
@@ -255,6 +255,7 @@ async fn spawn_dedicated_workers_for_flow(
     killpill_tx: tokio::sync::broadcast::Sender<()>,
     killpill_rx: &tokio::sync::broadcast::Receiver<()>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     worker_dir: &str,
     base_internal_url: &str,
     worker_name: &str,
@@ -284,6 +285,7 @@ async fn spawn_dedicated_workers_for_flow(
                             killpill_tx.clone(),
                             killpill_rx,
                             db,
+                            job_queue,
                             worker_dir,
                             base_internal_url,
                             worker_name,
@@ -305,6 +307,7 @@ async fn spawn_dedicated_workers_for_flow(
                         killpill_tx.clone(),
                         killpill_rx,
                         db,
+                        job_queue,
                         worker_dir,
                         base_internal_url,
                         worker_name,
@@ -321,6 +324,7 @@ async fn spawn_dedicated_workers_for_flow(
                         killpill_tx.clone(),
                         killpill_rx,
                         db,
+                        job_queue,
                         worker_dir,
                         base_internal_url,
                         worker_name,
@@ -342,6 +346,7 @@ async fn spawn_dedicated_workers_for_flow(
                             killpill_tx.clone(),
                             killpill_rx,
                             db,
+                            job_queue,
                             worker_dir,
                             base_internal_url,
                             worker_name,
@@ -360,6 +365,7 @@ async fn spawn_dedicated_workers_for_flow(
                             killpill_tx.clone(),
                             killpill_rx,
                             db,
+                            job_queue,
                             worker_dir,
                             base_internal_url,
                             worker_name,
@@ -381,6 +387,7 @@ async fn spawn_dedicated_workers_for_flow(
                         killpill_tx.clone(),
                         killpill_rx,
                         db,
+                        job_queue,
                         worker_dir,
                         base_internal_url,
                         worker_name,
@@ -406,6 +413,7 @@ pub async fn create_dedicated_worker_map(
     killpill_tx: &tokio::sync::broadcast::Sender<()>,
     killpill_rx: &tokio::sync::broadcast::Receiver<()>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     worker_dir: &str,
     base_internal_url: &str,
     worker_name: &str,
@@ -447,6 +455,7 @@ pub async fn create_dedicated_worker_map(
                             killpill_tx.clone(),
                             &killpill_rx,
                             db,
+                            job_queue,
                             &worker_dir,
                             base_internal_url,
                             &worker_name,
@@ -478,6 +487,7 @@ pub async fn create_dedicated_worker_map(
                 killpill_tx.clone(),
                 &killpill_rx,
                 db,
+                job_queue,
                 &worker_dir,
                 base_internal_url,
                 &worker_name,
@@ -519,6 +529,7 @@ async fn spawn_dedicated_worker(
     killpill_tx: tokio::sync::broadcast::Sender<()>,
     killpill_rx: &tokio::sync::broadcast::Receiver<()>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     worker_dir: &str,
     base_internal_url: &str,
     worker_name: &str,
@@ -546,6 +557,7 @@ async fn spawn_dedicated_worker(
             tokio::sync::mpsc::channel::<std::sync::Arc<QueuedJob>>(MAX_BUFFERED_DEDICATED_JOBS);
         let killpill_rx = killpill_rx.resubscribe();
         let db = db.clone();
+        let job_queue = job_queue.clone();
         let base_internal_url = base_internal_url.to_string();
         let worker_name = worker_name.to_string();
         let job_completed_tx = job_completed_tx.clone();
@@ -647,6 +659,7 @@ async fn spawn_dedicated_worker(
                     crate::python_executor::start_worker(
                         lock,
                         &db,
+                        &job_queue,
                         &content,
                         &base_internal_url,
                         &job_dir,
@@ -666,6 +679,7 @@ async fn spawn_dedicated_worker(
                         lock,
                         codebase,
                         &db,
+                        &job_queue,
                         &content,
                         &base_internal_url,
                         &job_dir,
@@ -694,6 +708,7 @@ async fn spawn_dedicated_worker(
                         dedicated_worker_rx,
                         killpill_rx,
                         &db,
+                        &job_queue,
                     )
                     .await
                 }
@@ -1,26 +1,33 @@
-use std::{collections::HashMap, process::Stdio};
+use std::{collections::HashMap, process::Stdio, sync::Arc};
 
 use itertools::Itertools;
 use serde_json::value::RawValue;
 use uuid::Uuid;
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 use crate::{
+    args_schema::parse_main_signature,
     common::{
-        create_args_and_out_file, get_main_override, get_reserved_variables, parse_npm_config,
-        read_file, read_result, start_child_process, OccupancyMetrics,
+        create_args_stdin_or_file, get_main_override, get_reserved_variables, parse_npm_config,
+        pipe_args_to_stdin, read_and_check_result, read_file, read_result, start_child_process,
+        OccupancyMetrics,
     },
-    handle_child::handle_child,
-    AuthedClientBackgroundTask, DENO_CACHE_DIR, DENO_PATH, DISABLE_NSJAIL, HOME_ENV,
-    NPM_CONFIG_REGISTRY, PATH_ENV, TZ_ENV,
+    handle_child::{handle_child, ChildPhase},
+    stdin_stream::stdin_stream_enabled,
+    AuthedClientBackgroundTask, ARGS_VIA_STDIN, DENO_CACHE_DIR, DENO_MAX_HEAP_SIZE_MB, DENO_PATH,
+    DISABLE_NSJAIL, HOME_ENV, NPM_CONFIG_REGISTRY, PATH_ENV, TZ_ENV, WARMUP_TIMEOUT,
 };
 use tokio::{fs::File, io::AsyncReadExt, process::Command};
 use windmill_common::{
-    error::Result, jobs::PREPROCESSOR_FAKE_ENTRYPOINT, worker::write_file, BASE_URL,
+    error::Result,
+    jobs::{ExecOptions, PREPROCESSOR_FAKE_ENTRYPOINT, WRAPPER_RESULT_MARKER},
+    worker::write_file,
+    BASE_URL,
 };
 use windmill_common::{
     error::{self},
     jobs::QueuedJob,
+    scripts::ScriptLang,
 };
 use windmill_parser::Typ;
 
@@ -90,8 +97,10 @@ pub async fn generate_deno_lock(
     code: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: Option<&sqlx::Pool<sqlx::Postgres>>,
+    job_queue: Option<&Arc<dyn JobQueue>>,
     w_id: &str,
     worker_name: &str,
     base_internal_url: &str,
@@ -136,17 +145,20 @@ pub async fn generate_deno_lock(
         .stderr(Stdio::piped());
     let mut child_process = start_child_process(child_cmd, DENO_PATH.as_str()).await?;
 
-    if let Some(db) = db {
+    if let (Some(db), Some(job_queue)) = (db, job_queue) {
         handle_child(
             job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             w_id,
             "deno cache",
+            ChildPhase::DependencyInstall,
             None,
             false,
             occupancy_metrics,
@@ -166,13 +178,56 @@ pub async fn generate_deno_lock(
     }
 }
 
+/// Run an empty script once, for deno to populate its cache dir and for its binary to be in the
+/// page cache when the first deno job runs
+pub async fn warmup_deno(
+    db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
+    worker_name: &str,
+    warmup_dir: &str,
+) -> error::Result<()> {
+    write_file(warmup_dir, "warmup.ts", "export {};\n")?;
+    let mut deno_cmd = Command::new(DENO_PATH.as_str());
+    deno_cmd
+        .current_dir(warmup_dir)
+        .env_clear()
+        .env("PATH", PATH_ENV.as_str())
+        .env("HOME", HOME_ENV.as_str())
+        .env("TZ", TZ_ENV.as_str())
+        .env("DENO_DIR", DENO_CACHE_DIR)
+        .args(["run", "--no-prompt", "warmup.ts"])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    let child = start_child_process(deno_cmd, DENO_PATH.as_str()).await?;
+    handle_child(
+        &Uuid::nil(),
+        db,
+        job_queue,
+        &mut 0,
+        &mut None,
+        &mut JobRunRecords::default(),
+        child,
+        false,
+        worker_name,
+        "",
+        "deno warmup",
+        ChildPhase::Run,
+        Some(WARMUP_TIMEOUT),
+        false,
+        &mut None,
+    )
+    .await
+}
+
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn handle_deno_job(
     requirements_o: Option<String>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     job_dir: &str,
     inner_content: &String,
@@ -183,9 +238,11 @@ pub async fn handle_deno_job(
     occupancy_metrics: &mut OccupancyMetrics,
 ) -> error::Result<Box<RawValue>> {
     // let mut start = Instant::now();
-    let logs1 = "\n\n--- DENO CODE EXECUTION ---\n".to_string();
-    append_logs(&job.id, &job.workspace_id, logs1, db).await;
-
+    let exec_options = job.exec_options()?;
+    if !exec_options.quiet {
+        let logs1 = "\n\n--- DENO CODE EXECUTION ---\n".to_string();
+        append_logs(&job.id, &job.workspace_id, logs1, db).await;
+    }
     let (main_override, apply_preprocessor) = match get_main_override(job.args.as_ref()) {
         Some(main_override) => {
             if main_override == PREPROCESSOR_FAKE_ENTRYPOINT {
@@ -201,16 +258,21 @@ pub async fn handle_deno_job(
 
     let write_wrapper_f = async {
         // let mut start = Instant::now();
-        let args =
-            windmill_parser_ts::parse_deno_signature(inner_content, true, main_override.clone())?
-                .args;
+        let args = parse_main_signature(
+            &ScriptLang::Deno,
+            inner_content,
+            main_override.clone(),
+            false,
+        )?
+        .args;
 
         let pre_args = if apply_preprocessor {
             Some(
-                windmill_parser_ts::parse_deno_signature(
+                parse_main_signature(
+                    &ScriptLang::Deno,
                     inner_content,
-                    true,
                     Some("preprocessor".to_string()),
+                    false,
                 )?
                 .args,
             )
@@ -257,47 +319,20 @@ pub async fn handle_deno_job(
             ("".to_string(), "".to_string())
         };
 
-        let wrapper_content: String = format!(
-            r#"
-import {{ {main_name} }} from "./main.ts";
-{preprocessor_import}
-
-let args = await Deno.readTextFile("args.json")
-    .then(JSON.parse);
-
-function argsObjToArr({{ {spread} }}) {{
-    return [ {spread} ];
-}}
-
-BigInt.prototype.toJSON = function () {{
-    return this.toString();
-}};
-
-async function run() {{
-    {dates}
-    {preprocessor}
-    const argsArr = argsObjToArr(args);
-    if ({main_name} === undefined || typeof {main_name} !== 'function') {{
-        throw new Error("{main_name} function is missing");
-    }}
-    let res: any = await {main_name}(...argsArr);
-    const res_json = JSON.stringify(res ?? null, (key, value) => typeof value === 'undefined' ? null : value);
-    await Deno.writeTextFile("result.json", res_json);
-    Deno.exit(0);
-}}
-try {{
-    await run();
-}} catch(e) {{
-    let err = {{ message: e.message, name: e.name, stack: e.stack }};
-    let step_id = Deno.env.get("WM_FLOW_STEP_ID");
-    if (step_id) {{
-        err["step_id"] = step_id;
-    }}
-    await Deno.writeTextFile("result.json", JSON.stringify(err));
-    Deno.exit(1);
-}}
-    "#,
-        );
+        let read_args = if *ARGS_VIA_STDIN && !stdin_stream_enabled(job) {
+            "new Response(Deno.stdin.readable).text()"
+        } else {
+            "Deno.readTextFile(\"args.json\")"
+        };
+        let wrapper_content = DenoWrapper {
+            main_name: &main_name,
+            preprocessor_import: &preprocessor_import,
+            read_args,
+            spread: &spread,
+            dates: &dates,
+            preprocessor: &preprocessor,
+        }
+        .content(&exec_options);
         write_file(job_dir, "wrapper.ts", &wrapper_content)?;
         Ok(()) as error::Result<()>
     };
@@ -310,20 +345,19 @@ try {{
     );
 
     let reserved_variables_args_out_f = async {
-        let args_and_out_f = async {
-            create_args_and_out_file(&client, job, job_dir, db).await?;
-            Ok(()) as Result<()>
-        };
+        let args_and_out_f = create_args_stdin_or_file(&client, job, job_dir, db);
         let reserved_variables_f = async {
             let client = client.get_authed().await;
-            let vars = get_reserved_variables(job, &client.token, db).await?;
+            let vars = get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
             Ok((vars, client.token)) as Result<(HashMap<String, String>, String)>
         };
-        let (_, reserved_variables) = tokio::try_join!(args_and_out_f, reserved_variables_f)?;
-        Ok(reserved_variables) as error::Result<(HashMap<String, String>, String)>
+        let (args_stdin, reserved_variables) =
+            tokio::try_join!(args_and_out_f, reserved_variables_f)?;
+        Ok((args_stdin, reserved_variables))
+            as error::Result<(Option<String>, (HashMap<String, String>, String))>
     };
 
-    let ((reserved_variables, token), _, _) = tokio::try_join!(
+    let ((args_stdin, (reserved_variables, token)), _, _) = tokio::try_join!(
         reserved_variables_args_out_f,
         write_wrapper_f,
         write_import_map_f
@@ -335,7 +369,7 @@ try {{
     }
 
     //do not cache local dependencies
-    let child = {
+    let mut child = {
         let reload = format!("--reload={base_internal_url}");
         let script_path = format!("{job_dir}/wrapper.ts");
         let import_map_path = format!("{job_dir}/import_map.json");
@@ -359,6 +393,11 @@ try {{
                 args.push("--frozen=false");
             }
         }
+        let max_heap_size =
+            DENO_MAX_HEAP_SIZE_MB.map(|x| format!("--v8-flags=--max-old-space-size={x}"));
+        if let Some(max_heap_size) = max_heap_size.as_ref() {
+            args.push(max_heap_size);
+        }
         let allow_read = format!(
             "--allow-read=./,/tmp/windmill/cache/deno/,{}",
             DENO_PATH.as_str()
@@ -389,20 +428,29 @@ try {{
             .args(args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        if args_stdin.is_some() || stdin_stream_enabled(job) {
+            deno_cmd.stdin(Stdio::piped());
+        }
         start_child_process(deno_cmd, DENO_PATH.as_str()).await?
     };
+    if let Some(args_json) = args_stdin {
+        pipe_args_to_stdin(&mut child, args_json);
+    }
     // logs.push_str(format!("prepare: {:?}\n", start.elapsed().as_micros()).as_str());
     // start = Instant::now();
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         false,
         worker_name,
         &job.workspace_id,
         "deno run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
@@ -429,7 +477,150 @@ try {{
             })?;
         *new_args = Some(args.clone());
     }
-    read_result(job_dir).await
+    if tokio::fs::metadata(format!("{job_dir}/result.streamed"))
+        .await
+        .is_ok()
+    {
+        // streamed results are written by the script itself and are not guaranteed to be valid json
+        read_and_check_result(job_dir).await
+    } else {
+        read_result(job_dir).await
+    }
+}
+
+/// The parts of `wrapper.ts` computed from the script and its signature
+struct DenoWrapper<'a> {
+    main_name: &'a str,
+    preprocessor_import: &'a str,
+    read_args: &'a str,
+    spread: &'a str,
+    dates: &'a str,
+    preprocessor: &'a str,
+}
+
+impl DenoWrapper<'_> {
+    /// Content of `wrapper.ts`, with the blocks toggled by the exec options of the job
+    fn content(&self, options: &ExecOptions) -> String {
+        let DenoWrapper { main_name, preprocessor_import, read_args, spread, dates, preprocessor } =
+            self;
+        let stack = if options.stack_traces {
+            "stack: e.stack, "
+        } else {
+            ""
+        };
+        let result_marker = if options.result_marker {
+            "writeResultMarker();"
+        } else {
+            ""
+        };
+        format!(
+            r#"
+import {{ {main_name} }} from "./main.ts";
+{preprocessor_import}
+
+let args = await {read_args}
+    .then(JSON.parse);
+
+// WM_FIXED_NOW pins the clock of the test runs that set it: `Date.now()`, `new Date()` and `Date()`
+// return it once the script is loaded
+const wmFixedNow = Deno.env.get("WM_FIXED_NOW");
+if (wmFixedNow) {{
+    const fixedNow = new Date(wmFixedNow).getTime();
+    globalThis.Date = new Proxy(Date, {{
+        construct(target, args) {{
+            return args.length === 0 ? new target(fixedNow) : new target(...args);
+        }},
+        apply(target) {{
+            return new target(fixedNow).toString();
+        }},
+        get(target, prop, receiver) {{
+            return prop === "now" ? () => fixedNow : Reflect.get(target, prop, receiver);
+        }},
+    }});
+}}
+
+function argsObjToArr({{ {spread} }}) {{
+    return [ {spread} ];
+}}
+
+BigInt.prototype.toJSON = function () {{
+    return this.toString();
+}};
+
+// Streaming result writer, exposed to the script as `globalThis.wmResult`, so that large results
+// can be written to result.json incrementally instead of being held whole in memory:
+//   await wmResult.write(chunk: string)       append raw json text to the result
+//   await wmResult.writeArray(items)          write an (async) iterable as a json array
+// Once the writer has been used, the value returned by main is ignored and the streamed content,
+// which must be valid json once the script returns, is the result of the job.
+const wmResult = {{
+    file: undefined as Deno.FsFile | undefined,
+    encoder: new TextEncoder(),
+    async write(chunk: string) {{
+        if (!this.file) {{
+            this.file = await Deno.open("result.json", {{ write: true, create: true, truncate: true }});
+            await Deno.writeTextFile("result.streamed", "");
+        }}
+        const bytes = this.encoder.encode(chunk);
+        let written = 0;
+        while (written < bytes.length) {{
+            written += await this.file.write(bytes.subarray(written));
+        }}
+    }},
+    async writeArray(items: Iterable<any> | AsyncIterable<any>) {{
+        await this.write("[");
+        let first = true;
+        for await (const item of items) {{
+            await this.write((first ? "" : ",") + JSON.stringify(item ?? null, (key, value) => typeof value === 'undefined' ? null : value));
+            first = false;
+        }}
+        await this.write("]");
+    }},
+}};
+(globalThis as any).wmResult = wmResult;
+
+// written synchronously and on its own line right before exiting, so that the marker follows all
+// the output of the script, even output it did not end with a newline
+function writeResultMarker() {{
+    const bytes = new TextEncoder().encode("\n{WRAPPER_RESULT_MARKER}\n");
+    let written = 0;
+    while (written < bytes.length) {{
+        written += Deno.stdout.writeSync(bytes.subarray(written));
+    }}
+}}
+
+async function run() {{
+    {dates}
+    {preprocessor}
+    const argsArr = argsObjToArr(args);
+    if ({main_name} === undefined || typeof {main_name} !== 'function') {{
+        throw new Error("{main_name} function is missing");
+    }}
+    let res: any = await {main_name}(...argsArr);
+    if (wmResult.file) {{
+        wmResult.file.close();
+        {result_marker}
+        Deno.exit(0);
+    }}
+    const res_json = JSON.stringify(res ?? null, (key, value) => typeof value === 'undefined' ? null : value);
+    await Deno.writeTextFile("result.json", res_json);
+    {result_marker}
+    Deno.exit(0);
+}}
+try {{
+    await run();
+}} catch(e) {{
+    let err = {{ message: e.message, name: e.name, {stack}idempotent: e?.idempotent }};
+    let step_id = Deno.env.get("WM_FLOW_STEP_ID");
+    if (step_id) {{
+        err["step_id"] = step_id;
+    }}
+    await Deno.writeTextFile("result.json", JSON.stringify(err));
+    Deno.exit(1);
+}}
+    "#
+        )
+    }
 }
 
 async fn build_import_map(
@@ -495,6 +686,7 @@ pub async fn start_worker(
     jobs_rx: Receiver<std::sync::Arc<QueuedJob>>,
     killpill_rx: tokio::sync::broadcast::Receiver<()>,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
 ) -> Result<()> {
     use windmill_common::variables;
 
@@ -616,3 +808,64 @@ for await (const chunk of Deno.stdin.readable) {{
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapper(options: &ExecOptions) -> String {
+        DenoWrapper {
+            main_name: "main",
+            preprocessor_import: "",
+            read_args: "Deno.readTextFile(\"args.json\")",
+            spread: "x",
+            dates: "",
+            preprocessor: "",
+        }
+        .content(options)
+    }
+
+    #[test]
+    fn test_default_exec_options_wrapper() {
+        let content = wrapper(&ExecOptions::default());
+        assert!(content.contains(r#"import { main } from "./main.ts";"#));
+        assert!(content.contains("let res: any = await main(...argsArr);"));
+        assert!(content.contains("stack: e.stack, "));
+        assert!(!content.contains("writeResultMarker();"));
+    }
+
+    #[test]
+    fn test_no_stack_traces_wrapper() {
+        let content = wrapper(&ExecOptions { stack_traces: false, ..Default::default() });
+        assert!(content.contains("let err = { message: e.message, name: e.name, idempotent"));
+        assert!(!content.contains("e.stack"));
+    }
+
+    #[test]
+    fn test_result_marker_wrapper() {
+        let content = wrapper(&ExecOptions { result_marker: true, ..Default::default() });
+        assert!(content.contains(WRAPPER_RESULT_MARKER));
+        // after the result is written, whether it was returned or streamed
+        let written = content
+            .rfind("await Deno.writeTextFile(\"result.json\", res_json);")
+            .unwrap();
+        let streamed = content.find("wmResult.file.close();").unwrap();
+        let markers = content
+            .match_indices("writeResultMarker();")
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(markers.len(), 2);
+        assert!(markers[0] > streamed && markers[1] > written);
+    }
+
+    #[test]
+    fn test_fixed_now_wrapper() {
+        let content = wrapper(&ExecOptions::default());
+        let patched = content
+            .find(r#"const wmFixedNow = Deno.env.get("WM_FIXED_NOW");"#)
+            .unwrap();
+        assert!(content.contains("globalThis.Date = new Proxy(Date, {"));
+        assert!(content.contains(r#"return prop === "now" ? () => fixedNow"#));
+        assert!(patched < content.find("await run();").unwrap());
+    }
+}
@@ -0,0 +1,152 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use serde::Deserialize;
+use serde_json::Value;
+use uuid::Uuid;
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, ENV_FROM_RESOURCES_ARG},
+    DB,
+};
+
+use crate::{common::transform_json_value, AuthedClientBackgroundTask};
+
+/// values shorter than this are not masked in the logs, they would mask too much of the output
+const MIN_MASKED_VALUE_LEN: usize = 4;
+
+lazy_static::lazy_static! {
+    /// env variables resolved for the running jobs, picked up by `get_reserved_variables` and by
+    /// `handle_child` to mask them in the logs
+    static ref ENVS_FROM_RESOURCES: Mutex<HashMap<Uuid, HashMap<String, String>>> = Mutex::new(HashMap::new());
+}
+
+/// One entry of the `_ENV_FROM_RESOURCES` arg of a job:
+///
+/// ```json
+/// {"resource": "$res:f/aws/prod", "env": {"AWS_ACCESS_KEY_ID": "awsAccessKeyId", "AWS_REGION": "config.region"}}
+/// ```
+///
+/// - `resource` is a `$res:` path (or an inline object), resolved with the permissions of the job
+/// - `env` maps the name of each env variable to the field of the resource it is set to. Nested
+///   fields are separated by dots, non-string values are set as their JSON representation
+///
+/// The values are masked (`***`) in the logs of the job.
+#[derive(Deserialize, Debug)]
+struct EnvFromResource {
+    resource: Value,
+    env: HashMap<String, String>,
+}
+
+/// Env variables of a running job, set in the job process by `get_reserved_variables` until
+/// dropped
+pub struct JobEnvFromResources {
+    job_id: Uuid,
+}
+
+impl Drop for JobEnvFromResources {
+    fn drop(&mut self) {
+        ENVS_FROM_RESOURCES.lock().unwrap().remove(&self.job_id);
+    }
+}
+
+/// Resolve the resources of the `_ENV_FROM_RESOURCES` arg of the job, if any, into the env
+/// variables of the job
+pub async fn resolve_env_from_resources(
+    db: &DB,
+    client: &AuthedClientBackgroundTask,
+    job: &QueuedJob,
+) -> error::Result<Option<JobEnvFromResources>> {
+    let Some(decl) = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(ENV_FROM_RESOURCES_ARG))
+    else {
+        return Ok(None);
+    };
+    let decl = serde_json::from_str::<Vec<EnvFromResource>>(decl.get()).map_err(|e| {
+        Error::BadRequest(format!(
+            "`{ENV_FROM_RESOURCES_ARG}` must be a list of {{\"resource\", \"env\"}} objects: {e}"
+        ))
+    })?;
+
+    let mut envs = HashMap::new();
+    for EnvFromResource { resource, env } in decl {
+        let resource = transform_json_value(
+            ENV_FROM_RESOURCES_ARG,
+            &client.get_authed().await,
+            &job.workspace_id,
+            resource,
+            job,
+            db,
+        )
+        .await?;
+        for (name, field) in env {
+            check_env_name(&name)?;
+            let value = field
+                .split('.')
+                .try_fold(&resource, |v, k| v.get(k))
+                .ok_or_else(|| {
+                    Error::BadRequest(format!(
+                        "`{ENV_FROM_RESOURCES_ARG}`: the resource of {name} has no field `{field}`"
+                    ))
+                })?;
+            let value = match value {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            };
+            envs.insert(name, value);
+        }
+    }
+
+    ENVS_FROM_RESOURCES.lock().unwrap().insert(job.id, envs);
+    Ok(Some(JobEnvFromResources { job_id: job.id }))
+}
+
+fn check_env_name(name: &str) -> error::Result<()> {
+    let valid = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        return Err(Error::BadRequest(format!(
+            "`{ENV_FROM_RESOURCES_ARG}`: `{name}` is not a valid env variable name"
+        )));
+    }
+    if name.starts_with("WM_") || name == "BASE_URL" || name == "BASE_INTERNAL_URL" {
+        return Err(Error::BadRequest(format!(
+            "`{ENV_FROM_RESOURCES_ARG}`: `{name}` is reserved by windmill"
+        )));
+    }
+    Ok(())
+}
+
+/// Env variables resolved from resources for the job, if it declared any
+pub fn env_from_resources(job_id: &Uuid) -> Option<HashMap<String, String>> {
+    ENVS_FROM_RESOURCES.lock().unwrap().get(job_id).cloned()
+}
+
+/// Values to mask in the logs of the job, longest first so that a value containing another is
+/// masked as a whole
+pub fn masked_env_values(job_id: &Uuid) -> Vec<String> {
+    let mut values = env_from_resources(job_id)
+        .map(|envs| {
+            envs.into_values()
+                .filter(|v| v.len() >= MIN_MASKED_VALUE_LEN)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    values.dedup();
+    values
+}
+
+/// Replace the values in `masked` by `***` in a log line
+pub fn mask_env_values(line: String, masked: &[String]) -> String {
+    if !masked.iter().any(|v| line.contains(v.as_str())) {
+        return line;
+    }
+    masked
+        .iter()
+        .fold(line, |line, v| line.replace(v.as_str(), "***"))
+}
@@ -1,5 +1,5 @@
 use crate::PROXY_ENVS;
-use std::{collections::HashMap, fs::DirBuilder, process::Stdio};
+use std::{collections::HashMap, fs::DirBuilder, process::Stdio, sync::Arc};
 
 use itertools::Itertools;
 use serde_json::value::RawValue;
@@ -12,14 +12,15 @@ use windmill_common::{
     worker::{save_cache, write_file},
 };
 use windmill_parser_go::{parse_go_imports, REQUIRE_PARSE};
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 use crate::{
     common::{
         capitalize, create_args_and_out_file, get_reserved_variables, read_result,
         start_child_process, OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
     AuthedClientBackgroundTask, DISABLE_NSJAIL, DISABLE_NUSER, GOPRIVATE, GOPROXY,
     GO_BIN_CACHE_DIR, GO_CACHE_DIR, HOME_ENV, NSJAIL_PATH, PATH_ENV, TZ_ENV,
 };
@@ -36,8 +37,10 @@ pub const GO_OBJECT_STORE_PREFIX: &str = "gobin/";
 pub async fn handle_go_job(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     inner_content: &str,
     job_dir: &str,
@@ -84,8 +87,10 @@ pub async fn handle_go_job(
             inner_content,
             mem_peak,
             canceled_by,
+            run_records,
             job_dir,
             db,
+            job_queue,
             true,
             skip_go_mod,
             skip_tidy,
@@ -198,13 +203,16 @@ func Run(req Req) (interface{{}}, error){{
         handle_child(
             &job.id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             build_go_process,
             false,
             worker_name,
             &job.workspace_id,
             "go build",
+            ChildPhase::Run,
             None,
             false,
             &mut Some(occupation_metrics),
@@ -247,13 +255,14 @@ func Run(req Req) (interface{{}}, error){{
 
     let client = &client.get_authed().await;
 
-    let reserved_variables = get_reserved_variables(job, &client.token, db).await?;
+    let reserved_variables =
+        get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
 
     let child = if !*DISABLE_NSJAIL {
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_GO_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_GO_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CACHE_DIR}", GO_CACHE_DIR)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
@@ -299,13 +308,16 @@ func Run(req Req) (interface{{}}, error){{
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "go run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupation_metrics),
@@ -341,8 +353,10 @@ pub async fn install_go_dependencies(
     code: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     non_dep_job: bool,
     skip_go_mod: bool,
     has_sum: bool,
@@ -363,13 +377,16 @@ pub async fn install_go_dependencies(
         handle_child(
             job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             w_id,
             "go init",
+            ChildPhase::DependencyInstall,
             None,
             false,
             &mut Some(occupation_metrics),
@@ -429,13 +446,16 @@ pub async fn install_go_dependencies(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child_process,
         false,
         worker_name,
         &w_id,
         &format!("go {mod_command}"),
+        ChildPhase::DependencyInstall,
         None,
         false,
         &mut Some(occupation_metrics),
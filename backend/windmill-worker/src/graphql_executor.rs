@@ -1,4 +1,5 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::anyhow;
 use futures::TryStreamExt;
@@ -8,7 +9,7 @@ use windmill_common::jobs::QueuedJob;
 use windmill_common::worker::to_raw_value;
 use windmill_common::{error::Error, worker::CLOUD_HOSTED};
 use windmill_parser_graphql::parse_graphql_sig;
-use windmill_queue::{CanceledBy, HTTP_CLIENT};
+use windmill_queue::{CanceledBy, JobQueue, HTTP_CLIENT};
 
 use serde::Deserialize;
 
@@ -39,6 +40,7 @@ pub async fn do_graphql(
     client: &AuthedClientBackgroundTask,
     query: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -148,6 +150,7 @@ pub async fn do_graphql(
         job.id,
         job.timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         result_f,
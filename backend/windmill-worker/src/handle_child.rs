@@ -17,7 +17,10 @@ use windmill_common::error::{self, Error};
 
 use windmill_common::worker::{get_windmill_memory_usage, get_worker_memory_usage, CLOUD_HOSTED};
 
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{
+    append_logs, is_local_job, parse_metric_line, parse_warning_line, CanceledBy, JobQueue,
+    JobRunRecords, LeaseStatus,
+};
 
 #[cfg(any(target_os = "linux", target_os = "macos"))]
 use std::os::unix::process::ExitStatusExt;
@@ -37,7 +40,7 @@ use windmill_common::job_metrics;
 use tokio::{
     io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     process::Child,
-    sync::{broadcast, watch},
+    sync::{broadcast, mpsc::error::SendTimeoutError, watch, Semaphore, SemaphorePermit},
     time::{interval, sleep, Instant, MissedTickBehavior},
 };
 
@@ -46,12 +49,225 @@ use futures::{
     stream, StreamExt,
 };
 
-use crate::common::{resolve_job_timeout, OccupancyMetrics};
+use crate::common::{resolve_dependency_timeout, resolve_job_timeout, OccupancyMetrics};
+use crate::env_from_resources::{mask_env_values, masked_env_values};
 use crate::job_logger::{append_job_logs, append_with_limit, LARGE_LOG_THRESHOLD_SIZE};
-use crate::{MAX_RESULT_SIZE, MAX_WAIT_FOR_SIGINT, MAX_WAIT_FOR_SIGTERM};
+use crate::result_sink::{result_sink_sender, RESULT_ITEM_PREFIX, RESULT_SINK_TIMEOUT};
+use crate::stdin_stream::{clear_stdin, stream_stdin};
+use crate::termination_notice::{requeue_job, wait_for_requeue_on_termination};
+use crate::{DENO_MAX_HEAP_SIZE_MB, MAX_RESULT_SIZE, MAX_WAIT_FOR_SIGINT, MAX_WAIT_FOR_SIGTERM};
 
 lazy_static::lazy_static! {
     pub static ref SLOW_LOGS: bool = std::env::var("SLOW_LOGS").ok().is_some_and(|x| x == "1" || x == "true");
+    pub static ref LOG_LINES_SOURCE_PREFIX: bool = std::env::var("LOG_LINES_SOURCE_PREFIX").ok().is_some_and(|x| x == "1" || x == "true");
+    pub static ref LOG_LINES_TIMESTAMP: bool = std::env::var("LOG_LINES_TIMESTAMP").ok().is_some_and(|x| x == "1" || x == "true");
+    pub static ref TEE_JOB_LOGS_TO_STDOUT: bool = std::env::var("TEE_JOB_LOGS_TO_STDOUT").ok().is_some_and(|x| x == "1" || x == "true");
+
+    /// what to do when checking whether a running job got canceled fails, see [`CancelCheckFailurePolicy`]
+    pub static ref CANCEL_CHECK_FAILURE_POLICY: CancelCheckFailurePolicy = match std::env::var("CANCEL_CHECK_FAILURE_POLICY").ok().as_deref() {
+        None | Some("lenient") => CancelCheckFailurePolicy::Lenient,
+        Some("kill") => CancelCheckFailurePolicy::Kill,
+        Some(other) => {
+            tracing::error!("Invalid CANCEL_CHECK_FAILURE_POLICY {other}, expected `lenient` or `kill`, using `lenient`");
+            CancelCheckFailurePolicy::Lenient
+        }
+    };
+    /// number of consecutive failed cancel checks after which the job is killed with the `kill` policy
+    pub static ref CANCEL_CHECK_MAX_FAILURES: u32 = std::env::var("CANCEL_CHECK_MAX_FAILURES")
+        .ok()
+        .and_then(|x| x.parse::<u32>().ok())
+        .filter(|x| *x > 0)
+        .unwrap_or(3);
+    /// when set, the logs of a job are buffered until it has run for this many milliseconds, so
+    /// that short jobs write their logs once at completion. Longer jobs stream their logs as
+    /// usual past the threshold. Pings and cancellation checks are not delayed
+    pub static ref FINAL_ONLY_LOGS_THRESHOLD: Option<Duration> = std::env::var("FINAL_ONLY_LOGS_THRESHOLD_MS")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .filter(|x| *x > 0)
+        .map(Duration::from_millis);
+    /// maximum number of database operations the background tasks of the running jobs (log
+    /// flushes, pings and cancellation checks) run at once, so that a burst of concurrent jobs
+    /// cannot take all the connections of the pool away from pulling and completing jobs.
+    /// Unbounded if not set
+    static ref JOB_BOOKKEEPING_DB_PERMITS: Option<Semaphore> = std::env::var("MAX_JOB_BOOKKEEPING_DB_CONNECTIONS")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .filter(|x| *x > 0)
+        .map(Semaphore::new);
+    /// CPU time, in seconds, after which a running job is killed whatever its wall-clock
+    /// duration, summed over all its processes. Catches the jobs spinning on the CPU while letting
+    /// the ones waiting on IO run until their timeout. Only enforced on linux, unlimited if not set
+    static ref JOB_CPU_TIME_LIMIT: Option<Duration> = std::env::var("JOB_CPU_TIME_LIMIT")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .filter(|x| *x > 0)
+        .map(Duration::from_secs);
+    /// output rate, in lines per second, above which a running job sustaining it for
+    /// OUTPUT_RATE_GRACE_PERIOD_SECS is killed as most likely stuck in a loop printing output.
+    /// Not set or 0, the check is disabled
+    static ref MAX_OUTPUT_LINES_PER_SEC: u64 = std::env::var("MAX_OUTPUT_LINES_PER_SEC")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(0);
+    /// same as MAX_OUTPUT_LINES_PER_SEC, in bytes per second
+    static ref MAX_OUTPUT_BYTES_PER_SEC: u64 = std::env::var("MAX_OUTPUT_BYTES_PER_SEC")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(0);
+    static ref OUTPUT_RATE_GRACE_PERIOD: Duration = Duration::from_secs(
+        std::env::var("OUTPUT_RATE_GRACE_PERIOD_SECS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(30),
+    );
+}
+
+/// Tracks the output rate of a job over windows of a second, to detect the jobs sustaining an
+/// excessive output rate for longer than a grace period, see MAX_OUTPUT_LINES_PER_SEC
+struct OutputRateGuard {
+    max_lines_per_sec: u64,
+    max_bytes_per_sec: u64,
+    grace_period: Duration,
+    window_start: Instant,
+    lines: u64,
+    bytes: u64,
+    exceeding_since: Option<Instant>,
+}
+
+impl OutputRateGuard {
+    const WINDOW: Duration = Duration::from_secs(1);
+
+    fn new(
+        max_lines_per_sec: u64,
+        max_bytes_per_sec: u64,
+        grace_period: Duration,
+        now: Instant,
+    ) -> Self {
+        Self {
+            max_lines_per_sec,
+            max_bytes_per_sec,
+            grace_period,
+            window_start: now,
+            lines: 0,
+            bytes: 0,
+            exceeding_since: None,
+        }
+    }
+
+    /// Record a line of `len` bytes received at `now`, returns whether the output rate has been
+    /// excessive for longer than the grace period
+    fn record_line(&mut self, len: usize, now: Instant) -> bool {
+        self.lines += 1;
+        self.bytes += len as u64;
+        let window_start = self.window_start;
+        let elapsed = now.saturating_duration_since(window_start);
+        if elapsed < Self::WINDOW {
+            return false;
+        }
+        let secs = elapsed.as_secs_f64();
+        let exceeding = (self.max_lines_per_sec > 0
+            && self.lines as f64 / secs > self.max_lines_per_sec as f64)
+            || (self.max_bytes_per_sec > 0
+                && self.bytes as f64 / secs > self.max_bytes_per_sec as f64);
+        self.window_start = now;
+        self.lines = 0;
+        self.bytes = 0;
+        if !exceeding {
+            self.exceeding_since = None;
+            return false;
+        }
+        // the window that went over the threshold started the excessive output
+        let since = *self.exceeding_since.get_or_insert(window_start);
+        now.saturating_duration_since(since) > self.grace_period
+    }
+}
+
+/// Wait for one of the MAX_JOB_BOOKKEEPING_DB_CONNECTIONS slots, if bounded, before a database
+/// operation of the background tasks of a running job. The slot is released on drop
+pub(crate) async fn acquire_bookkeeping_db_permit() -> Option<SemaphorePermit<'static>> {
+    match JOB_BOOKKEEPING_DB_PERMITS.as_ref() {
+        Some(permits) => permits.acquire().await.ok(),
+        None => None,
+    }
+}
+
+/// Pool of the database operations of the background tasks of a running job (log flushes, pings
+/// and cancellation checks): the dedicated bookkeeping pool of the job queue if it has one, `db`
+/// otherwise
+fn bookkeeping_db<'a>(db: &'a DB, job_queue: &'a Arc<dyn JobQueue>) -> &'a DB {
+    job_queue.bookkeeping_db().unwrap_or(db)
+}
+
+/// Remaining time of the final-only logs window of a job started at `start`, if it is still in
+/// it, see [`FINAL_ONLY_LOGS_THRESHOLD`]
+fn final_only_logs_remaining(start: Instant) -> Option<Duration> {
+    FINAL_ONLY_LOGS_THRESHOLD.and_then(|threshold| threshold.checked_sub(start.elapsed()))
+}
+
+/// Behavior of a running job when its cancel check cannot reach the database
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CancelCheckFailurePolicy {
+    /// assume the job was not canceled and keep it running
+    Lenient,
+    /// kill the job after CANCEL_CHECK_MAX_FAILURES consecutive failed checks, so that a job
+    /// canceled during a database outage does not keep running unnoticed
+    Kill,
+}
+
+const MAX_STDERR_IN_ERROR: usize = 5000;
+
+/// The output of a child is read until the end of its stdout and stderr, so that the lines still
+/// in the pipes when it exits are not lost. Processes it spawned can keep the pipes open past its
+/// exit: the output is read for at most this long after the exit
+const MAX_OUTPUT_DRAIN_AFTER_EXIT: Duration = Duration::from_secs(2);
+
+/// Messages printed by V8 when it aborts because the heap limit was reached
+const V8_OOM_MESSAGES: &[&str] = &[
+    "JavaScript heap out of memory",
+    "Fatal JavaScript out of memory",
+    "Fatal javascript OOM",
+    "Fatal process out of memory",
+];
+
+fn is_v8_oom_line(line: &str) -> bool {
+    V8_OOM_MESSAGES.iter().any(|msg| line.contains(msg))
+}
+
+#[cfg(feature = "prometheus")]
+lazy_static::lazy_static! {
+    static ref V8_OUT_OF_MEMORY_COUNT: prometheus::IntCounter = prometheus::register_int_counter!(
+        "worker_v8_out_of_memory_count",
+        "Total number of deno jobs aborted because they exceeded the V8 heap limit."
+    )
+    .unwrap();
+}
+
+fn v8_out_of_memory_error() -> Error {
+    #[cfg(feature = "prometheus")]
+    if windmill_common::METRICS_ENABLED.load(std::sync::atomic::Ordering::Relaxed) {
+        V8_OUT_OF_MEMORY_COUNT.inc();
+    }
+
+    let limit = DENO_MAX_HEAP_SIZE_MB
+        .map(|x| format!("the {x}MB"))
+        .unwrap_or_else(|| "the default V8".to_string());
+    Error::JsonErr(serde_json::json!({
+        "name": "OutOfMemoryError",
+        "message": format!(
+            "script exceeded {limit} heap memory limit of the deno runtime and was aborted. \
+            Reduce the memory used by the script (e.g. by streaming large data instead of loading it at once)"
+        ),
+    }))
+}
+
+/// phase of a job the child process belongs to, used to give context to kill reasons
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ChildPhase {
+    /// resolving or installing dependencies, the user code has not run yet
+    DependencyInstall,
+    /// building or running the user code
+    Run,
 }
 
 //  - kill windows process along with all child processes
@@ -90,13 +306,16 @@ async fn kill_process_tree(pid: Option<u32>) -> Result<(), String> {
 pub async fn handle_child(
     job_id: &Uuid,
     db: &Pool<Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by_ref: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     mut child: Child,
     nsjail: bool,
     worker: &str,
     w_id: &str,
     child_name: &str,
+    phase: ChildPhase,
     custom_timeout: Option<i32>,
     sigterm: bool,
     occupancy_metrics: &mut Option<&mut OccupancyMetrics>,
@@ -122,6 +341,8 @@ pub async fn handle_child(
         tracing::info!("could not get child pid");
     }
     let (set_too_many_logs, mut too_many_logs) = watch::channel::<bool>(false);
+    let (set_excessive_output_rate, mut excessive_output_rate) = watch::channel::<bool>(false);
+    let (set_result_sink_failure, mut result_sink_failure) = watch::channel::<Option<String>>(None);
     let (tx, rx) = broadcast::channel::<()>(3);
     let mut rx2 = tx.subscribe();
 
@@ -134,6 +355,7 @@ pub async fn handle_child(
     let update_job = update_job_poller(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by_ref,
         || get_mem_peak(pid, nsjail),
@@ -145,22 +367,45 @@ pub async fn handle_child(
 
     enum KillReason {
         TooManyLogs,
-        Timeout { is_job_specific: bool },
-        Cancelled(Option<CanceledBy>),
+        ExcessiveOutputRate,
+        ResultSink(String),
+        Timeout { is_job_specific: bool, phase: ChildPhase },
+        Cancelled(Option<CanceledBy>, ChildPhase),
         AlreadyCompleted,
+        HostTermination,
+        CpuTimeLimit(Duration),
     }
 
     impl std::fmt::Debug for KillReason {
         fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
             match self {
                 KillReason::TooManyLogs => f.write_str("too many logs (max size: 2MB)"),
-                KillReason::Timeout { is_job_specific } => f.write_str(if *is_job_specific {
-                    "timeout after exceeding job-specific duration limit"
-                } else {
-                    "timeout after exceeding instance-wide job duration limit"
-                }),
-                KillReason::Cancelled(canceled_by) => {
-                    let mut reason = "cancelled".to_string();
+                KillReason::ExcessiveOutputRate => {
+                    f.write_str("excessive output rate (likely infinite loop)")
+                }
+                KillReason::ResultSink(reason) => f.write_str(reason),
+                KillReason::Timeout { is_job_specific, phase } => {
+                    f.write_str(match (phase, is_job_specific) {
+                        (ChildPhase::DependencyInstall, true) => {
+                            "timeout after exceeding job-specific dependency install duration limit"
+                        }
+                        (ChildPhase::DependencyInstall, false) => {
+                            "timeout after exceeding instance-wide dependency install duration limit"
+                        }
+                        (ChildPhase::Run, true) => {
+                            "timeout after exceeding job-specific duration limit"
+                        }
+                        (ChildPhase::Run, false) => {
+                            "timeout after exceeding instance-wide job duration limit"
+                        }
+                    })
+                }
+                KillReason::Cancelled(canceled_by, phase) => {
+                    let mut reason = if *phase == ChildPhase::DependencyInstall {
+                        "cancelled during dependency install".to_string()
+                    } else {
+                        "cancelled".to_string()
+                    };
                     if let Some(canceled_by) = canceled_by {
                         if let Some(by) = canceled_by.username.as_ref() {
                             reason.push_str(&format!(" by {}", by));
@@ -172,15 +417,56 @@ pub async fn handle_child(
                     f.write_str(&reason)
                 }
                 KillReason::AlreadyCompleted => f.write_str("already completed"),
+                KillReason::HostTermination => f.write_str("host termination"),
+                KillReason::CpuTimeLimit(limit) => f.write_str(&format!(
+                    "exceeding the CPU time limit of {} CPU-seconds",
+                    limit.as_secs()
+                )),
             }
         }
     }
 
-    let (timeout_duration, timeout_warn_msg, is_job_specific) =
-        resolve_job_timeout(&db, w_id, job_id, custom_timeout).await;
+    let (timeout_duration, timeout_warn_msg, is_job_specific) = match phase {
+        ChildPhase::DependencyInstall => {
+            resolve_dependency_timeout(&db, w_id, job_id, custom_timeout).await
+        }
+        ChildPhase::Run => resolve_job_timeout(&db, w_id, job_id, custom_timeout).await,
+    };
+    let fail_on_stderr = phase == ChildPhase::Run
+        && job_id != Uuid::nil()
+        && sqlx::query_scalar::<_, bool>(
+            "SELECT fail_on_stderr FROM workspace_settings WHERE workspace_id = $1",
+        )
+        .bind(w_id)
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or(false);
     if let Some(msg) = timeout_warn_msg {
         append_logs(&job_id, w_id, msg.as_str(), db).await;
     }
+    let record_metrics = phase == ChildPhase::Run && !is_local_job(&job_id);
+    let detect_v8_oom = phase == ChildPhase::Run && child_name.starts_with("deno");
+    let result_sink = if phase == ChildPhase::Run && job_id != Uuid::nil() {
+        result_sink_sender(&job_id)
+    } else {
+        None
+    };
+    let masked_env_values = masked_env_values(&job_id);
+    let track_install = phase == ChildPhase::DependencyInstall && !is_local_job(&job_id);
+    if track_install {
+        set_installing_deps(db, &job_id, true).await;
+    }
+    /* the executors only leave the stdin of the child piped for the jobs streaming their stdin */
+    let stdin_stream = if phase == ChildPhase::Run && !is_local_job(&job_id) {
+        child
+            .stdin
+            .take()
+            .map(|stdin| tokio::spawn(stream_stdin(db.clone(), job_id, stdin)))
+    } else {
+        None
+    };
 
     /* a future that completes when the child process exits */
     let wait_on_child = async {
@@ -188,11 +474,21 @@ pub async fn handle_child(
 
         let kill_reason = tokio::select! {
             biased;
-            result = child.wait() => return result.map(Ok),
+            result = child.wait() => {
+                // the output is still read until the end of the pipes, see MAX_OUTPUT_DRAIN_AFTER_EXIT
+                let _ = tx.send(());
+                return result.map(Ok);
+            },
             Ok(()) = too_many_logs.changed() => KillReason::TooManyLogs,
-            _ = sleep(timeout_duration) => KillReason::Timeout { is_job_specific },
-            ex = update_job, if job_id != Uuid::nil() => match ex {
-                UpdateJobPollingExit::Done(canceled_by) => KillReason::Cancelled(canceled_by),
+            Ok(()) = excessive_output_rate.changed() => KillReason::ExcessiveOutputRate,
+            Ok(()) = result_sink_failure.changed() => KillReason::ResultSink(
+                result_sink_failure.borrow().clone().unwrap_or_default()
+            ),
+            _ = sleep(timeout_duration) => KillReason::Timeout { is_job_specific, phase },
+            _ = wait_for_requeue_on_termination(db, job_id, start, timeout_duration), if !is_local_job(&job_id) => KillReason::HostTermination,
+            limit = wait_for_cpu_time_limit(pid, nsjail), if phase == ChildPhase::Run => KillReason::CpuTimeLimit(limit),
+            ex = update_job, if !is_local_job(&job_id) => match ex {
+                UpdateJobPollingExit::Done(canceled_by) => KillReason::Cancelled(canceled_by, phase),
                 UpdateJobPollingExit::AlreadyCompleted => KillReason::AlreadyCompleted,
             },
         };
@@ -201,19 +497,35 @@ pub async fn handle_child(
 
         let set_reason = async {
             if matches!(kill_reason, KillReason::Timeout { .. }) {
-                if let Err(err) = sqlx::query(
-                    r#"
-                       UPDATE queue
-                          SET canceled = true
-                            , canceled_by = 'timeout'
-                            , canceled_reason = $1
-                        WHERE id = $2
-                    "#,
-                )
-                .bind(format!("duration > {}", timeout_duration.as_secs()))
-                .bind(job_id)
-                .execute(&db)
-                .await
+                if let Err(err) = job_queue
+                    .signal_cancel(
+                        job_id,
+                        CanceledBy {
+                            username: Some("timeout".to_string()),
+                            reason: Some(if phase == ChildPhase::DependencyInstall {
+                                format!(
+                                    "dependency install duration > {}",
+                                    timeout_duration.as_secs()
+                                )
+                            } else {
+                                format!("duration > {}", timeout_duration.as_secs())
+                            }),
+                        },
+                    )
+                    .await
+                {
+                    tracing::error!(%job_id, %err, "error setting cancelation reason for job {job_id}: {err}");
+                }
+            } else if let KillReason::CpuTimeLimit(limit) = kill_reason {
+                if let Err(err) = job_queue
+                    .signal_cancel(
+                        job_id,
+                        CanceledBy {
+                            username: Some("cpu time limit".to_string()),
+                            reason: Some(format!("cpu time > {}", limit.as_secs())),
+                        },
+                    )
+                    .await
                 {
                     tracing::error!(%job_id, %err, "error setting cancelation reason for job {job_id}: {err}");
                 }
@@ -292,8 +604,8 @@ pub async fn handle_child(
         let mut result = io::Result::Ok(());
         let mut output = output.take_until(async {
             let _ = rx2.recv().await;
-            //wait at most 50ms after end of a script for output stream to end
-            tokio::time::sleep(Duration::from_millis(50)).await;
+            // the stream usually ends before, when the child and its descendants closed the pipes
+            tokio::time::sleep(MAX_OUTPUT_DRAIN_AFTER_EXIT).await;
          }).boxed();
         /* `do_write` resolves the task, but does not contain the Result.
          * It's useful to know if the task completed. */
@@ -302,6 +614,23 @@ pub async fn handle_child(
         let mut log_total_size: u64 = 0;
         let pg_log_total_size = Arc::new(AtomicU32::new(0));
 
+        /* only tracked for deno, whose V8 aborts with a fatal error when the heap limit is hit */
+        let mut v8_oom = false;
+
+        /* only tracked if the workspace fails jobs writing to stderr */
+        let mut stderr_output = String::new();
+        let mut stderr_remaining = MAX_STDERR_IN_ERROR;
+
+        let output_rate_checked = *MAX_OUTPUT_LINES_PER_SEC > 0 || *MAX_OUTPUT_BYTES_PER_SEC > 0;
+        let mut output_rate_guard = (phase == ChildPhase::Run && output_rate_checked).then(|| {
+            OutputRateGuard::new(
+                *MAX_OUTPUT_LINES_PER_SEC,
+                *MAX_OUTPUT_BYTES_PER_SEC,
+                *OUTPUT_RATE_GRACE_PERIOD,
+                Instant::now(),
+            )
+        });
+
         while let Some(line) =  output.by_ref().next().await {
 
             let do_write_ = do_write.shared();
@@ -320,6 +649,9 @@ pub async fn handle_child(
                 delay
             };
 
+            /* keep buffering until the job completes or the final-only window ends */
+            let delay = final_only_logs_remaining(start).unwrap_or(delay);
+
             let mut read_lines = stream::once(async { line })
                 .chain(output.by_ref())
                 /* after receiving a line, continue until some delay has passed
@@ -334,11 +666,81 @@ pub async fn handle_child(
             while let Some(line) = read_lines.next().await {
 
                 match line {
-                    Ok(line) => {
+                    Ok((is_stderr, line)) => {
+                        /* counted before skipping the empty lines, a loop printing blank lines is as runaway */
+                        if output_rate_guard.as_mut().is_some_and(|guard| guard.record_line(line.len(), Instant::now())) {
+                            tracing::info!(%job_id, "Excessive output rate for job {job_id}");
+                            let _ = set_excessive_output_rate.send(true);
+                            joined.push_str(&format!(
+                                "Job output rate exceeded {} lines/s or {} bytes/s for more than {}s (likely infinite loop); killing job.",
+                                *MAX_OUTPUT_LINES_PER_SEC, *MAX_OUTPUT_BYTES_PER_SEC, OUTPUT_RATE_GRACE_PERIOD.as_secs()
+                            ));
+                            break;
+                        }
                         if line.is_empty() {
                             continue;
                         }
+                        let line = mask_env_values(line, &masked_env_values);
+                        if record_metrics {
+                            run_records.record_log_line();
+                        }
+                        if fail_on_stderr && is_stderr && stderr_remaining > 0 {
+                            append_with_limit(&mut stderr_output, &line, &mut stderr_remaining);
+                            stderr_output.push('\n');
+                        }
+                        if detect_v8_oom && !v8_oom && is_v8_oom_line(&line) {
+                            v8_oom = true;
+                        }
+                        let mut result_item_warning = None;
+                        if let (Some(sink), Some(item)) = (result_sink.as_ref(), line.strip_prefix(RESULT_ITEM_PREFIX)) {
+                            let item = item.trim();
+                            match serde_json::from_str::<&serde_json::value::RawValue>(item) {
+                                Ok(_) => {
+                                    /* a full buffer blocks reading the output, and so the job, until the sink catches up */
+                                    if let Err(e) = sink.send_timeout(item.to_string(), *RESULT_SINK_TIMEOUT).await {
+                                        let reason = match e {
+                                            SendTimeoutError::Timeout(_) => format!(
+                                                "result sink not keeping up, result items could not be buffered for {}s",
+                                                RESULT_SINK_TIMEOUT.as_secs()
+                                            ),
+                                            SendTimeoutError::Closed(_) => "result sink failure".to_string(),
+                                        };
+                                        tracing::info!(%job_id, "{reason} for job {job_id}");
+                                        let _ = set_result_sink_failure.send(Some(reason));
+                                        break;
+                                    }
+                                    continue;
+                                }
+                                Err(e) => result_item_warning = Some(format!("malformed result item not forwarded to the result sink: {e}")),
+                            }
+                        }
+                        let metric_warning = if record_metrics {
+                            parse_metric_line(&line).and_then(|metric| match metric {
+                                Ok(metric) => run_records.record_metric(metric).err(),
+                                Err(e) => Some(format!("ignoring malformed metric line: {e}")),
+                            })
+                        } else {
+                            None
+                        };
+                        let warning_line_warning = if record_metrics {
+                            parse_warning_line(&line).and_then(|warning| match warning {
+                                Ok(warning) => run_records.record_warning(warning).err(),
+                                Err(e) => Some(format!("ignoring malformed warning line: {e}")),
+                            })
+                        } else {
+                            None
+                        };
+                        let line = decorate_log_line(line, is_stderr);
+                        if *TEE_JOB_LOGS_TO_STDOUT {
+                            println!("[{w_id}/{job_id}] {line}");
+                        }
                         append_with_limit(&mut joined, &line, &mut log_remaining);
+                        if let Some(warning) = metric_warning
+                            .or(warning_line_warning)
+                            .or(result_item_warning)
+                        {
+                            append_with_limit(&mut joined, &format!("WARNING: {warning}"), &mut log_remaining);
+                        }
                         if log_remaining == 0 {
                             tracing::info!(%job_id, "Too many logs lines for job {job_id}");
                             let _ = set_too_many_logs.send(true);
@@ -383,7 +785,7 @@ pub async fn handle_child(
 
             let worker_name = worker.to_string();
             let w_id2 = w_id.to_string();
-            (do_write, write_result) = tokio::spawn(append_job_logs(job_id, w_id2, joined, db.clone(), compact_logs, pg_log_total_size.clone(), worker_name)).remote_handle();
+            (do_write, write_result) = tokio::spawn(append_job_logs(job_id, w_id2, joined, bookkeeping_db(db, job_queue).clone(), compact_logs, pg_log_total_size.clone(), worker_name)).remote_handle();
 
 
 
@@ -392,7 +794,10 @@ pub async fn handle_child(
                 break;
             }
 
-            if *set_too_many_logs.borrow() {
+            if *set_too_many_logs.borrow()
+                || *set_excessive_output_rate.borrow()
+                || set_result_sink_failure.borrow().is_some()
+            {
                 break;
             }
         }
@@ -408,9 +813,18 @@ pub async fn handle_child(
         {
             panic::resume_unwind(p);
         }
+
+        (stderr_output, v8_oom)
     }.instrument(trace_span!("child_lines"));
 
-    let (wait_result, _) = tokio::join!(wait_on_child, lines);
+    let (wait_result, (stderr_output, v8_oom)) = tokio::join!(wait_on_child, lines);
+    if track_install {
+        set_installing_deps(db, &job_id, false).await;
+    }
+    if let Some(stdin_stream) = stdin_stream {
+        stdin_stream.abort();
+        clear_stdin(db, &job_id).await;
+    }
 
     let success = wait_result.is_ok()
         && wait_result.as_ref().unwrap().is_ok()
@@ -421,11 +835,30 @@ pub async fn handle_child(
         _ if *too_many_logs.borrow() => Err(Error::ExecutionErr(format!(
             "logs or result reached limit. (current max size: {MAX_RESULT_SIZE} characters)"
         ))),
+        Ok(Ok(status)) if status.success() && !stderr_output.is_empty() => {
+            Err(Error::ExecutionErr(format!(
+                "process exited successfully but wrote to stderr, which fails jobs in this workspace:\n{stderr_output}"
+            )))
+        }
+        Ok(Ok(status)) if !status.success() && v8_oom => Err(v8_out_of_memory_error()),
         Ok(Ok(status)) => process_status(status),
         Ok(Err(kill_reason)) => match kill_reason {
             KillReason::AlreadyCompleted => {
                 Err(Error::AlreadyCompleted("Job already completed".to_string()))
             }
+            KillReason::HostTermination => {
+                requeue_job(db, job_queue, &job_id, w_id).await?;
+                // not completed, the worker that picks up the job again will complete it
+                Err(Error::AlreadyCompleted(
+                    "Job re-queued because the host is being terminated".to_string(),
+                ))
+            }
+            KillReason::Cancelled(_, ChildPhase::DependencyInstall)
+            | KillReason::Timeout { phase: ChildPhase::DependencyInstall, .. } => {
+                Err(Error::ExecutionErr(format!(
+                    "job process terminated due to {kill_reason:#?}, no user code was run"
+                )))
+            }
             _ => Err(Error::ExecutionErr(format!(
                 "job process terminated due to {kill_reason:#?}"
             ))),
@@ -434,6 +867,76 @@ pub async fn handle_child(
     }
 }
 
+/// Make the phase of the job visible to the zombie job detection, which gives more time to the jobs
+/// installing their dependencies (ZOMBIE_JOB_INSTALL_TIMEOUT)
+async fn set_installing_deps(db: &Pool<Postgres>, job_id: &Uuid, installing: bool) {
+    if let Err(e) = sqlx::query("UPDATE queue SET installing_deps = $1 WHERE id = $2")
+        .bind(installing)
+        .bind(job_id)
+        .execute(db)
+        .await
+    {
+        tracing::error!(%job_id, "could not update the dependency install phase of the job: {e:#}");
+    }
+}
+
+/// Completes with the limit once the processes of the job used more than JOB_CPU_TIME_LIMIT of
+/// CPU time. Never completes if there is no limit or the CPU time cannot be read
+async fn wait_for_cpu_time_limit(pid: Option<u32>, nsjail: bool) -> Duration {
+    #[cfg(target_os = "linux")]
+    if let (Some(limit), Some(pid)) = (*JOB_CPU_TIME_LIMIT, pid) {
+        // the nsjail process itself barely uses any CPU, its children are the job
+        let clk_tck = unsafe { nix::libc::sysconf(nix::libc::_SC_CLK_TCK) };
+        if clk_tck > 0 {
+            let mut interval = interval(Duration::from_secs(1));
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                interval.tick().await;
+                let Some(ticks) = process_tree_cpu_ticks(pid) else {
+                    tracing::debug!(nsjail, "could not read the CPU time of process {pid}");
+                    break;
+                };
+                if Duration::from_millis(ticks * 1000 / clk_tck as u64) > limit {
+                    return limit;
+                }
+            }
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = (pid, nsjail);
+    future::pending().await
+}
+
+/// CPU time, in clock ticks, used by a process and its descendants, including the ones that
+/// already exited
+#[cfg(target_os = "linux")]
+fn process_tree_cpu_ticks(pid: u32) -> Option<u64> {
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // the command name can contain spaces and parentheses, the fields start after the last `)`
+    let fields = stat
+        .rsplit_once(')')?
+        .1
+        .split_whitespace()
+        .collect::<Vec<_>>();
+    // utime, stime, cutime and cstime, fields 14 to 17 of proc(5) counted from the pid
+    let mut ticks = fields
+        .get(11..15)?
+        .iter()
+        .filter_map(|x| x.parse::<u64>().ok())
+        .sum::<u64>();
+    let tasks = std::fs::read_dir(format!("/proc/{pid}/task")).ok()?;
+    for task in tasks.flatten() {
+        let children = std::fs::read_to_string(task.path().join("children")).unwrap_or_default();
+        for child in children
+            .split_whitespace()
+            .filter_map(|x| x.parse::<u32>().ok())
+        {
+            ticks += process_tree_cpu_ticks(child).unwrap_or(0);
+        }
+    }
+    Some(ticks)
+}
+
 async fn get_mem_peak(pid: Option<u32>, nsjail: bool) -> i32 {
     if pid.is_none() {
         return -1;
@@ -489,6 +992,7 @@ pub async fn run_future_with_polling_update_job_poller<Fut, T>(
     job_id: Uuid,
     timeout: Option<i32>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by_ref: &mut Option<CanceledBy>,
     result_f: Fut,
@@ -504,6 +1008,7 @@ where
     let update_job = update_job_poller(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by_ref,
         || async { 0 },
@@ -528,7 +1033,7 @@ where
             tracing::error!("Query timeout: {}", e);
             Error::ExecutionErr(format!("Query timeout after (>{}s)", timeout_ms/1000))
         })?,
-        ex = update_job, if job_id != Uuid::nil() => {
+        ex = update_job, if !is_local_job(&job_id) => {
             match ex {
                 UpdateJobPollingExit::Done(canceled_by) => {
                     let (by, reason) = canceled_by.as_ref().map_or(("unknown".to_string(), "unknown".to_string()), |x| (x.username.clone().unwrap_or("".to_string()), x.reason.clone().unwrap_or("".to_string())));
@@ -550,6 +1055,7 @@ pub enum UpdateJobPollingExit {
 pub async fn update_job_poller<F, Fut>(
     job_id: Uuid,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by_ref: &mut Option<CanceledBy>,
     get_mem: F,
@@ -564,12 +1070,13 @@ where
 {
     let update_job_interval = Duration::from_millis(500);
 
-    let db = db.clone();
+    let db = bookkeeping_db(db, job_queue).clone();
 
     let mut interval = interval(update_job_interval);
     interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
 
     let mut i = 0;
+    let mut cancel_check_failures = 0;
 
     #[cfg(feature = "enterprise")]
     let mut memory_metric_id: Result<String, Error> =
@@ -587,6 +1094,7 @@ where
                     tracing::info!("job {job_id} on {worker_name} in {w_id} worker memory snapshot {}kB/{}kB", memory_usage.unwrap_or_default()/1024, wm_memory_usage.unwrap_or_default()/1024);
                     let occupancy = occupancy_metrics.as_mut().map(|x| x.update_occupancy_metrics());
                     if job_id != Uuid::nil() {
+                        let _permit = acquire_bookkeeping_db_permit().await;
                         sqlx::query!(
                             "UPDATE worker_ping SET ping_at = now(), current_job_id = $1, current_job_workspace_id = $2, memory_usage = $3, wm_memory_usage = $4,
                             occupancy_rate = $6, occupancy_rate_15s = $7, occupancy_rate_5m = $8, occupancy_rate_30m = $9 WHERE worker = $5",
@@ -614,55 +1122,63 @@ where
 
                 let update_job_row = i == 2 || (!*SLOW_LOGS && (i < 20 || (i < 120 && i % 5 == 0) || i % 10 == 0)) || i % 20 == 0;
                 if update_job_row {
-                #[cfg(feature = "enterprise")]
-                {
-                    if job_id != Uuid::nil() {
-
-                        // tracking metric starting at i >= 2 b/c first point it useless and we don't want to track metric for super fast jobs
-                        if i == 2 {
-                            memory_metric_id = job_metrics::register_metric_for_job(
-                                &db,
-                                w_id.to_string(),
-                                job_id,
-                                "memory_kb".to_string(),
-                                job_metrics::MetricKind::TimeseriesInt,
-                                Some("Job Memory Footprint (kB)".to_string()),
-                            )
-                            .await;
-                        }
-                        if let Ok(ref metric_id) = memory_metric_id {
-                            if let Err(err) = job_metrics::record_metric(&db, w_id.to_string(), job_id, metric_id.to_owned(), job_metrics::MetricNumericValue::Integer(current_mem)).await {
-                                tracing::error!("Unable to save memory stat for job {} in workspace {}. Error was: {:?}", job_id, w_id, err);
+                    let _permit = acquire_bookkeeping_db_permit().await;
+                    #[cfg(feature = "enterprise")]
+                    {
+                        if job_id != Uuid::nil() {
+
+                            // tracking metric starting at i >= 2 b/c first point it useless and we don't want to track metric for super fast jobs
+                            if i == 2 {
+                                memory_metric_id = job_metrics::register_metric_for_job(
+                                    &db,
+                                    w_id.to_string(),
+                                    job_id,
+                                    "memory_kb".to_string(),
+                                    job_metrics::MetricKind::TimeseriesInt,
+                                    Some("Job Memory Footprint (kB)".to_string()),
+                                )
+                                .await;
+                            }
+                            if let Ok(ref metric_id) = memory_metric_id {
+                                if let Err(err) = job_metrics::record_metric(&db, w_id.to_string(), job_id, metric_id.to_owned(), job_metrics::MetricNumericValue::Integer(current_mem)).await {
+                                    tracing::error!("Unable to save memory stat for job {} in workspace {}. Error was: {:?}", job_id, w_id, err);
+                                }
                             }
                         }
                     }
-                }
-                if job_id != Uuid::nil() {
-                    let (canceled, canceled_by, canceled_reason, already_completed) = sqlx::query_as::<_, (bool, Option<String>, Option<String>, bool)>("UPDATE queue SET mem_peak = $1, last_ping = now() WHERE id = $2 RETURNING canceled, canceled_by, canceled_reason, false")
-                        .bind(*mem_peak)
-                        .bind(job_id)
-                        .fetch_optional(&db)
-                        .await
-                        .unwrap_or_else(|e| {
-                            tracing::error!(%e, "error updating job {job_id}: {e:#}");
-                            Some((false, None, None, false))
-                        })
-                        .unwrap_or_else(|| {
-                            // if the job is not in queue, it can only be in the completed_job so it is already complete
-                            (false, None, None, true)
-                        });
-                    if already_completed {
-                        return UpdateJobPollingExit::AlreadyCompleted
-                    }
-                    if canceled {
-                        canceled_by_ref.replace(CanceledBy {
-                            username: canceled_by.clone(),
-                            reason: canceled_reason.clone(),
-                        });
-                        break
+                    if job_id != Uuid::nil() {
+                        let lease_status = match job_queue.renew_lease(job_id, *mem_peak).await {
+                            Ok(lease_status) => {
+                                cancel_check_failures = 0;
+                                lease_status
+                            }
+                            Err(e) => {
+                                cancel_check_failures += 1;
+                                tracing::error!(%e, "error updating job {job_id} ({cancel_check_failures} consecutive failure(s)): {e:#}");
+                                if *CANCEL_CHECK_FAILURE_POLICY == CancelCheckFailurePolicy::Kill
+                                    && cancel_check_failures >= *CANCEL_CHECK_MAX_FAILURES
+                                {
+                                    canceled_by_ref.replace(CanceledBy {
+                                        username: Some("worker".to_string()),
+                                        reason: Some(format!(
+                                            "could not check if the job was canceled {cancel_check_failures} consecutive times, last error: {e}"
+                                        )),
+                                    });
+                                    break
+                                }
+                                LeaseStatus::Running
+                            }
+                        };
+                        match lease_status {
+                            LeaseStatus::AlreadyCompleted => return UpdateJobPollingExit::AlreadyCompleted,
+                            LeaseStatus::Canceled(canceled_by) => {
+                                canceled_by_ref.replace(canceled_by);
+                                break
+                            }
+                            LeaseStatus::Running => (),
+                        }
                     }
                 }
-            }
             },
         );
     }
@@ -671,12 +1187,33 @@ where
     UpdateJobPollingExit::Done(canceled_by_ref.clone())
 }
 
+/// prefix a captured line with its source and/or the time it was read at if configured,
+/// stdout and stderr being read independently, their relative order is only best-effort
+fn decorate_log_line(line: String, is_stderr: bool) -> String {
+    if !*LOG_LINES_SOURCE_PREFIX && !*LOG_LINES_TIMESTAMP {
+        return line;
+    }
+    let mut decorated = String::with_capacity(line.len() + 34);
+    if *LOG_LINES_TIMESTAMP {
+        decorated.push_str(&format!(
+            "{} ",
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.3fZ")
+        ));
+    }
+    if *LOG_LINES_SOURCE_PREFIX {
+        decorated.push_str(if is_stderr { "[stderr] " } else { "[stdout] " });
+    }
+    decorated.push_str(&line);
+    decorated
+}
+
 /// takes stdout and stderr from Child, panics if either are not present
 ///
-/// builds a stream joining both stdout and stderr each read line by line
+/// builds a stream joining both stdout and stderr each read line by line,
+/// each line being tagged with whether it was read from stderr
 fn child_joined_output_stream(
     child: &mut Child,
-) -> impl stream::FusedStream<Item = io::Result<String>> {
+) -> impl stream::FusedStream<Item = io::Result<(bool, String)>> {
     let stderr = child
         .stderr
         .take()
@@ -689,7 +1226,10 @@ fn child_joined_output_stream(
 
     let stdout = BufReader::new(stdout).lines();
     let stderr = BufReader::new(stderr).lines();
-    stream::select(lines_to_stream(stderr), lines_to_stream(stdout))
+    stream::select(
+        lines_to_stream(stderr).map(|line| line.map(|line| (true, line))),
+        lines_to_stream(stdout).map(|line| line.map(|line| (false, line))),
+    )
 }
 
 pub fn lines_to_stream<R: tokio::io::AsyncBufRead + Unpin>(
@@ -722,3 +1262,70 @@ pub fn process_status(status: ExitStatus) -> error::Result<()> {
         )));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `lines_per_sec` lines of `len` bytes per second for `secs` seconds, returns whether the
+    /// guard tripped
+    fn feed(
+        guard: &mut OutputRateGuard,
+        now: &mut Instant,
+        secs: u64,
+        lines_per_sec: u64,
+        len: usize,
+    ) -> bool {
+        let step = Duration::from_secs(1) / lines_per_sec as u32;
+        for _ in 0..secs * lines_per_sec {
+            *now += step;
+            if guard.record_line(len, *now) {
+                return true;
+            }
+        }
+        false
+    }
+
+    #[test]
+    fn test_is_v8_oom_line() {
+        // as printed by V8 on the stderr of the deno process when the heap limit is reached
+        assert!(is_v8_oom_line(
+            "FATAL ERROR: Reached heap limit Allocation failed - JavaScript heap out of memory"
+        ));
+        assert!(is_v8_oom_line(
+            "#  Fatal JavaScript out of memory: Reached heap limit"
+        ));
+        assert!(is_v8_oom_line(
+            "#  Fatal javascript OOM in GC during deserialization"
+        ));
+        assert!(!is_v8_oom_line(
+            "error: Uncaught RangeError: Invalid array length"
+        ));
+    }
+
+    #[test]
+    fn test_output_rate_guard_trips_after_grace_period() {
+        let mut now = Instant::now();
+        let mut guard = OutputRateGuard::new(100, 0, Duration::from_secs(5), now);
+        assert!(!feed(&mut guard, &mut now, 5, 1000, 10));
+        assert!(feed(&mut guard, &mut now, 2, 1000, 10));
+    }
+
+    #[test]
+    fn test_output_rate_guard_bytes_threshold() {
+        let mut now = Instant::now();
+        let mut guard = OutputRateGuard::new(0, 1000, Duration::from_secs(2), now);
+        assert!(!feed(&mut guard, &mut now, 10, 10, 50));
+        assert!(feed(&mut guard, &mut now, 4, 10, 500));
+    }
+
+    #[test]
+    fn test_output_rate_guard_resets_on_bursts() {
+        let mut now = Instant::now();
+        let mut guard = OutputRateGuard::new(100, 0, Duration::from_secs(3), now);
+        for _ in 0..5 {
+            assert!(!feed(&mut guard, &mut now, 2, 1000, 10));
+            assert!(!feed(&mut guard, &mut now, 2, 10, 10));
+        }
+    }
+}
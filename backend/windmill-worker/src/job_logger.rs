@@ -9,9 +9,10 @@ use regex::Regex;
 use windmill_common::s3_helpers::OBJECT_STORE_CACHE_SETTINGS;
 
 use windmill_common::error::{self};
+use windmill_common::log_compression::decompress_log_chunks;
 use windmill_common::worker::{CLOUD_HOSTED, TMP_DIR};
 
-use windmill_queue::append_logs;
+use windmill_queue::{append_logs, is_local_job};
 
 use std::sync::atomic::AtomicU32;
 use std::sync::Arc;
@@ -19,6 +20,8 @@ use std::sync::Arc;
 use uuid::Uuid;
 use windmill_common::DB;
 
+use crate::handle_child::acquire_bookkeeping_db_permit;
+
 pub enum CompactLogs {
     #[cfg(not(all(feature = "enterprise", feature = "parquet")))]
     NotEE,
@@ -37,7 +40,7 @@ async fn compact_logs(
     compact_kind: CompactLogs,
     _worker_name: &str,
 ) -> error::Result<(String, String)> {
-    let mut prev_logs = sqlx::query_scalar!(
+    let stored_logs = sqlx::query_scalar!(
         "SELECT logs FROM job_logs WHERE job_id = $1 AND workspace_id = $2",
         job_id,
         w_id
@@ -46,7 +49,9 @@ async fn compact_logs(
     .await?
     .flatten()
     .unwrap_or_default();
-    let size = prev_logs.char_indices().count() as i32;
+    // the size accounts for the logs as stored, the offsets of the reads being on them
+    let size = stored_logs.char_indices().count() as i32;
+    let mut prev_logs = decompress_log_chunks(&stored_logs).into_owned();
     let nlogs_len = nlogs.char_indices().count();
     let to_keep_in_db = usize::max(
         usize::min(nlogs_len, 3000),
@@ -137,17 +142,20 @@ async fn default_disk_log_storage(
     compact_kind: CompactLogs,
     worker_name: &str,
 ) {
-    match compact_logs(
-        job_id,
-        &w_id,
-        &db,
-        nlogs,
-        total_size,
-        compact_kind,
-        worker_name,
-    )
-    .await
-    {
+    let compacted = {
+        let _permit = acquire_bookkeeping_db_permit().await;
+        compact_logs(
+            job_id,
+            &w_id,
+            &db,
+            nlogs,
+            total_size,
+            compact_kind,
+            worker_name,
+        )
+        .await
+    };
+    match compacted {
         Err(e) => tracing::error!("Could not compact logs for job {job_id}: {e:?}",),
         Ok((prev_logs, path)) => {
             let path = format!("{}/{}", TMP_DIR, path);
@@ -182,20 +190,24 @@ pub(crate) async fn append_job_logs(
     total_size: Arc<AtomicU32>,
     worker_name: String,
 ) -> () {
-    if must_compact_logs {
+    // the permit is only held for the database statements, not the uploads of the compacted logs
+    if must_compact_logs && !is_local_job(&job_id) {
         #[cfg(all(feature = "enterprise", feature = "parquet"))]
         if let Some(os) = OBJECT_STORE_CACHE_SETTINGS.read().await.clone() {
-            match compact_logs(
-                job_id,
-                &w_id,
-                &db,
-                logs,
-                total_size,
-                CompactLogs::S3,
-                &worker_name,
-            )
-            .await
-            {
+            let compacted = {
+                let _permit = acquire_bookkeeping_db_permit().await;
+                compact_logs(
+                    job_id,
+                    &w_id,
+                    &db,
+                    logs,
+                    total_size,
+                    CompactLogs::S3,
+                    &worker_name,
+                )
+                .await
+            };
+            match compacted {
                 Err(e) => tracing::error!("Could not compact logs for job {job_id}: {e:?}",),
                 Ok((prev_logs, path)) => {
                     tracing::info!("Logs length of {job_id} has exceeded a threshold. Previous logs have been saved to object storage at {path}");
@@ -236,6 +248,7 @@ pub(crate) async fn append_job_logs(
             .await;
         }
     } else {
+        let _permit = acquire_bookkeeping_db_permit().await;
         append_logs(&job_id, w_id, logs, db).await;
     }
 }
@@ -0,0 +1,91 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, NOTIFY_ARG},
+    BASE_URL, DB,
+};
+use windmill_queue::HTTP_CLIENT;
+
+use crate::{common::transform_json_value, AuthedClient};
+
+/// Targets a job can be notified to once completed. The target is resolved from the resource
+/// passed in the `_NOTIFY` arg (e.g. `"_NOTIFY": "$res:f/alerts/slack_webhook"`)
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum NotificationTarget {
+    /// Slack incoming webhook, the resource is expected to contain a `webhook_url` (or `url`)
+    Slack {
+        #[serde(alias = "url")]
+        webhook_url: String,
+    },
+}
+
+/// Post a completion summary to the notification target of the job, if any.
+/// This is fire-and-forget: any error is only logged and never affects the job status.
+pub fn send_completion_notification(
+    db: &DB,
+    client: &AuthedClient,
+    job: &Arc<QueuedJob>,
+    success: bool,
+) {
+    let Some(target) = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(NOTIFY_ARG))
+        .and_then(|x| serde_json::from_str::<Value>(x.get()).ok())
+    else {
+        return;
+    };
+
+    let db = db.clone();
+    let client = client.clone();
+    let job = job.clone();
+    tokio::spawn(async move {
+        if let Err(e) = notify(&db, &client, &job, target, success).await {
+            tracing::warn!(job_id = %job.id, "could not send completion notification: {e:#}");
+        }
+    });
+}
+
+async fn notify(
+    db: &DB,
+    client: &AuthedClient,
+    job: &QueuedJob,
+    target: Value,
+    success: bool,
+) -> error::Result<()> {
+    let resource =
+        transform_json_value(NOTIFY_ARG, client, &job.workspace_id, target, job, db).await?;
+    let target = serde_json::from_value::<NotificationTarget>(resource).map_err(|e| {
+        Error::BadRequest(format!(
+            "`{NOTIFY_ARG}` is not a supported notification target: {e}"
+        ))
+    })?;
+
+    let summary = format!(
+        "Job {} ({}) {} in workspace {}: {}/run/{}?workspace={}",
+        job.id,
+        job.script_path(),
+        if success { "succeeded" } else { "failed" },
+        job.workspace_id,
+        BASE_URL.read().await.clone(),
+        job.id,
+        job.workspace_id,
+    );
+
+    match target {
+        NotificationTarget::Slack { webhook_url } => {
+            HTTP_CLIENT
+                .post(webhook_url)
+                .json(&json!({ "text": summary }))
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|e| Error::InternalErr(format!("slack webhook: {e}")))?;
+        }
+    }
+    Ok(())
+}
@@ -53,7 +53,7 @@ use uuid::Uuid;
 use windmill_common::error::Error;
 
 use windmill_common::{flow_status::JobResult, DB};
-use windmill_queue::CanceledBy;
+use windmill_queue::{CanceledBy, JobQueue};
 
 use crate::{common::OccupancyMetrics, AuthedClient};
 
@@ -748,6 +748,7 @@ pub async fn eval_fetch_timeout(
     _job_id: Uuid,
     _job_timeout: Option<i32>,
     _db: &DB,
+    _job_queue: &Arc<dyn JobQueue>,
     _mem_peak: &mut i32,
     _canceled_by: &mut Option<CanceledBy>,
     _worker_name: &str,
@@ -768,6 +769,7 @@ pub async fn eval_fetch_timeout(
     job_id: Uuid,
     job_timeout: Option<i32>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -924,6 +926,7 @@ pub async fn eval_fetch_timeout(
         job_id,
         job_timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         async { result_f.await? },
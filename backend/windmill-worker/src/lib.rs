@@ -5,27 +5,40 @@ mod mssql_executor;
 #[cfg(feature = "enterprise")]
 mod snowflake_executor;
 
+mod analytics_export;
 mod ansible_executor;
+mod args_schema;
 mod bash_executor;
 
 mod bun_executor;
+mod cache_eviction;
+#[cfg(feature = "parquet")]
+mod cache_snapshot;
 pub mod common;
 mod config;
 #[cfg(feature = "enterprise")]
 mod dedicated_worker;
 mod deno_executor;
+mod env_from_resources;
 mod global_cache;
 mod go_executor;
 mod graphql_executor;
 mod handle_child;
 mod job_logger;
+mod job_notification;
 mod js_eval;
 mod mysql_executor;
 mod pg_executor;
 mod php_executor;
+mod precondition;
 mod python_executor;
 mod result_processor;
+mod result_sink;
 mod rust_executor;
+mod sandbox_profile;
+mod staged_inputs;
+mod stdin_stream;
+mod termination_notice;
 mod worker;
 mod worker_flow;
 mod worker_lockfiles;
@@ -34,7 +47,15 @@ pub use worker::*;
 
 pub use result_processor::handle_job_error;
 
+pub use args_schema::{infer_args_schema, parse_main_signature};
+
 pub use bun_executor::{
     get_common_bun_proc_envs, install_bun_lockfile, prebundle_bun_script, prepare_job_dir,
 };
 pub use deno_executor::generate_deno_lock;
+
+pub use analytics_export::start_analytics_export;
+pub use cache_eviction::evict_caches_periodically;
+
+#[cfg(feature = "parquet")]
+pub use cache_snapshot::{restore_cache_snapshots, snapshot_caches_periodically};
@@ -19,7 +19,7 @@ use windmill_parser_sql::{
     parse_db_resource, parse_mysql_sig, parse_sql_blocks, parse_sql_statement_named_params,
     RE_ARG_MYSQL_NAMED,
 };
-use windmill_queue::CanceledBy;
+use windmill_queue::{CanceledBy, JobQueue};
 
 use crate::{
     common::{build_args_map, OccupancyMetrics},
@@ -107,6 +107,7 @@ pub async fn do_mysql(
     client: &AuthedClientBackgroundTask,
     query: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -292,6 +293,7 @@ pub async fn do_mysql(
         job.id,
         job.timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         result_f,
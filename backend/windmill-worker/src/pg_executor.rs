@@ -18,6 +18,7 @@ use serde_json::value::RawValue;
 use serde_json::Map;
 use serde_json::Value;
 use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
 use tokio_postgres::Client;
 use tokio_postgres::{types::ToSql, NoTls, Row};
 use tokio_postgres::{
@@ -32,7 +33,7 @@ use windmill_parser::{Arg, Typ};
 use windmill_parser_sql::{
     parse_db_resource, parse_pg_statement_arg_indices, parse_pgsql_sig, parse_sql_blocks,
 };
-use windmill_queue::CanceledBy;
+use windmill_queue::{CanceledBy, JobQueue};
 
 use crate::common::{build_args_values, sizeof_val, OccupancyMetrics};
 use crate::handle_child::run_future_with_polling_update_job_poller;
@@ -59,6 +60,106 @@ lazy_static! {
     pub static ref RUNNING: AtomicBool = AtomicBool::new(false);
 }
 
+/// sslmode to connect with and connection string of the database
+fn database_connection_string(database: &PgDatabase) -> (String, String) {
+    let sslmode = match database.sslmode.as_deref() {
+        Some("allow") => "prefer".to_string(),
+        Some("verify-ca") | Some("verify-full") => "require".to_string(),
+        Some(s) => s.to_string(),
+        None => "prefer".to_string(),
+    };
+    let database_string = format!(
+        "postgres://{user}:{password}@{host}:{port}/{dbname}?sslmode={sslmode}",
+        user = encode(database.user.as_deref().unwrap_or("postgres")),
+        password = encode(database.password.as_deref().unwrap_or("")),
+        host = encode(&database.host),
+        port = database.port.unwrap_or(5432),
+        dbname = database.dbname,
+        sslmode = sslmode
+    );
+    (sslmode, database_string)
+}
+
+fn tls_connector(root_certificate_pem: Option<&str>) -> error::Result<MakeTlsConnector> {
+    let mut connector = TlsConnector::builder();
+    if let Some(root_certificate_pem) = root_certificate_pem {
+        if !root_certificate_pem.is_empty() {
+            connector.add_root_certificate(
+                Certificate::from_pem(root_certificate_pem.as_bytes())
+                    .map_err(|e| error::Error::BadConfig(format!("Invalid Certs: {e:#}")))?,
+            );
+        } else {
+            connector.danger_accept_invalid_certs(true);
+            connector.danger_accept_invalid_hostnames(true);
+        }
+    } else {
+        connector
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true);
+    }
+    Ok(MakeTlsConnector::new(connector.build().map_err(to_anyhow)?))
+}
+
+/// Connect to the database of a postgresql resource, outside of a job. The connection is driven
+/// by the returned task, to abort once the client is dropped
+pub async fn connect_pg_resource(
+    database: Value,
+    timeout: Duration,
+) -> error::Result<(Client, JoinHandle<Result<(), tokio_postgres::Error>>)> {
+    let database = serde_json::from_value::<PgDatabase>(database)
+        .map_err(|e| Error::BadRequest(format!("invalid postgresql resource: {e}")))?;
+    let (sslmode, database_string) = database_connection_string(&database);
+
+    if sslmode == "require" {
+        let (client, connection) = tokio::time::timeout(
+            timeout,
+            tokio_postgres::connect(
+                &database_string,
+                tls_connector(database.root_certificate_pem.as_deref())?,
+            ),
+        )
+        .await
+        .map_err(to_anyhow)?
+        .map_err(to_anyhow)?;
+        Ok((client, tokio::spawn(connection)))
+    } else {
+        let (client, connection) =
+            tokio::time::timeout(timeout, tokio_postgres::connect(&database_string, NoTls))
+                .await
+                .map_err(to_anyhow)?
+                .map_err(to_anyhow)?;
+        Ok((client, tokio::spawn(connection)))
+    }
+}
+
+/// Run the SQL check of a job precondition against a postgresql resource. The query runs in a
+/// read-only transaction, with a statement timeout, and must return a single boolean
+pub async fn eval_pg_precondition(
+    database: Value,
+    query: &str,
+    timeout: Duration,
+) -> error::Result<bool> {
+    let (mut client, handle) = connect_pg_resource(database, timeout).await?;
+
+    let result = async {
+        let tx = client.build_transaction().read_only(true).start().await?;
+        tx.batch_execute(&format!(
+            "SET LOCAL statement_timeout = {}",
+            timeout.as_millis()
+        ))
+        .await?;
+        let row = tx.query_one(query, &[]).await?;
+        let met = row.try_get::<_, bool>(0)?;
+        tx.rollback().await?;
+        Ok::<_, tokio_postgres::Error>(met)
+    }
+    .await
+    .map_err(|e| Error::ExecutionErr(format!("precondition query failed: {e}")));
+    drop(client);
+    handle.abort();
+    result
+}
+
 fn do_postgresql_inner<'a>(
     mut query: String,
     param_idx_to_arg_and_value: &HashMap<i32, (&Arg, Option<&Value>)>,
@@ -160,6 +261,7 @@ pub async fn do_postgresql(
     client: &AuthedClientBackgroundTask,
     query: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -194,21 +296,7 @@ pub async fn do_postgresql(
 
     let annotations = windmill_common::worker::SqlAnnotations::parse(query);
 
-    let sslmode = match database.sslmode.as_deref() {
-        Some("allow") => "prefer".to_string(),
-        Some("verify-ca") | Some("verify-full") => "require".to_string(),
-        Some(s) => s.to_string(),
-        None => "prefer".to_string(),
-    };
-    let database_string = format!(
-        "postgres://{user}:{password}@{host}:{port}/{dbname}?sslmode={sslmode}",
-        user = encode(&database.user.unwrap_or("postgres".to_string())),
-        password = encode(&database.password.unwrap_or("".to_string())),
-        host = encode(&database.host),
-        port = database.port.unwrap_or(5432),
-        dbname = database.dbname,
-        sslmode = sslmode
-    );
+    let (sslmode, database_string) = database_connection_string(&database);
     let database_string_clone = database_string.clone();
 
     RUNNING.store(true, std::sync::atomic::Ordering::Relaxed);
@@ -231,28 +319,11 @@ pub async fn do_postgresql(
         None
     } else if sslmode == "require" {
         tracing::info!("Creating new connection");
-        let mut connector = TlsConnector::builder();
-        if let Some(root_certificate_pem) = database.root_certificate_pem {
-            if !root_certificate_pem.is_empty() {
-                connector.add_root_certificate(
-                    Certificate::from_pem(root_certificate_pem.as_bytes())
-                        .map_err(|e| error::Error::BadConfig(format!("Invalid Certs: {e:#}")))?,
-                );
-            } else {
-                connector.danger_accept_invalid_certs(true);
-                connector.danger_accept_invalid_hostnames(true);
-            }
-        } else {
-            connector
-                .danger_accept_invalid_certs(true)
-                .danger_accept_invalid_hostnames(true);
-        }
-
         let (client, connection) = tokio::time::timeout(
             std::time::Duration::from_secs(20),
             tokio_postgres::connect(
                 &database_string,
-                MakeTlsConnector::new(connector.build().map_err(to_anyhow)?),
+                tls_connector(database.root_certificate_pem.as_deref())?,
             ),
         )
         .await
@@ -349,6 +420,7 @@ pub async fn do_postgresql(
         job.id,
         job.timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         result_f,
@@ -2,7 +2,7 @@ use convert_case::{Case, Casing};
 use itertools::Itertools;
 use regex::Regex;
 use serde_json::value::RawValue;
-use std::{collections::HashMap, path::Path, process::Stdio};
+use std::{collections::HashMap, path::Path, process::Stdio, sync::Arc};
 use tokio::{fs::File, io::AsyncReadExt, process::Command};
 use uuid::Uuid;
 use windmill_common::{
@@ -11,14 +11,15 @@ use windmill_common::{
     worker::write_file,
 };
 use windmill_parser::Typ;
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 use crate::{
     common::{
         create_args_and_out_file, get_main_override, get_reserved_variables, read_result,
         start_child_process, OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
     AuthedClientBackgroundTask, COMPOSER_CACHE_DIR, COMPOSER_PATH, DISABLE_NSJAIL, DISABLE_NUSER,
     NSJAIL_PATH, PHP_PATH,
 };
@@ -64,9 +65,11 @@ pub fn parse_php_imports(code: &str) -> anyhow::Result<Option<String>> {
 pub async fn composer_install(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_id: &Uuid,
     w_id: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     job_dir: &str,
     worker_name: &str,
     requirements: String,
@@ -94,13 +97,16 @@ pub async fn composer_install(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child_process,
         false,
         worker_name,
         w_id,
         "composer install",
+        ChildPhase::DependencyInstall,
         None,
         false,
         &mut Some(occupancy_metrics),
@@ -154,8 +160,10 @@ pub async fn handle_php_job(
     requirements_o: Option<String>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     job_dir: &str,
     inner_content: &String,
@@ -187,9 +195,11 @@ pub async fn handle_php_job(
         composer_install(
             mem_peak,
             canceled_by,
+            run_records,
             &job.id,
             &job.workspace_id,
             db,
+            job_queue,
             job_dir,
             worker_name,
             composer_json,
@@ -280,7 +290,7 @@ try {{
         };
         let reserved_variables_f = async {
             let client = client.get_authed().await;
-            let vars = get_reserved_variables(job, &client.token, db).await?;
+            let vars = get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
             Ok(vars) as Result<HashMap<String, String>>
         };
         let (_, reserved_variables) = tokio::try_join!(args_and_out_f, reserved_variables_f)?;
@@ -293,7 +303,7 @@ try {{
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_PHP_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_PHP_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
                 .replace("{SHARED_MOUNT}", shared_mount),
@@ -342,13 +352,16 @@ try {{
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "php run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
@@ -0,0 +1,99 @@
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use serde_json::Value;
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, PRECONDITION_ARG},
+    worker::to_raw_value,
+    DB,
+};
+
+use crate::{
+    common::transform_json_value, js_eval::eval_timeout, pg_executor::eval_pg_precondition,
+    AuthedClientBackgroundTask,
+};
+
+/// timeout of the evaluation of a precondition, connection included for the SQL checks
+const PRECONDITION_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Precondition set in the `_PRECONDITION` arg of a job.
+///
+/// - a string is a javascript expression evaluated in the isolated expression evaluator, with the
+///   args of the job as `args` (e.g. `args.rows > 0`). It cannot fetch variables or resources.
+/// - `{"database": "$res:f/db/pg", "query": "SELECT count(*) > 0 FROM ..."}` is a SQL check
+///   against a postgresql resource (or an inline postgresql object). The query runs in a
+///   read-only transaction and must return a single row whose first column is a boolean.
+///
+/// Both must evaluate to a boolean, anything else fails the job.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum Precondition {
+    Expr(String),
+    Sql { database: Value, query: String },
+}
+
+/// Check the precondition of the job, if any. Returns whether the job must run
+pub async fn check_precondition(
+    job: &QueuedJob,
+    client: &AuthedClientBackgroundTask,
+    db: &DB,
+) -> error::Result<bool> {
+    let Some(precondition) = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(PRECONDITION_ARG))
+    else {
+        return Ok(true);
+    };
+    let precondition = serde_json::from_str::<Precondition>(precondition.get()).map_err(|e| {
+        Error::BadRequest(format!(
+            "`{PRECONDITION_ARG}` must be an expression or a {{\"database\", \"query\"}} object: {e}"
+        ))
+    })?;
+
+    match precondition {
+        Precondition::Expr(expr) => {
+            let args = job
+                .args
+                .as_ref()
+                .map(|args| {
+                    args.0
+                        .iter()
+                        .filter(|(k, _)| k.as_str() != PRECONDITION_ARG)
+                        .collect::<HashMap<_, _>>()
+                })
+                .unwrap_or_default();
+            let context = HashMap::from([("args".to_string(), Arc::new(to_raw_value(&args)))]);
+            let r = tokio::time::timeout(
+                PRECONDITION_TIMEOUT,
+                eval_timeout(expr.clone(), context, None, None, None, None),
+            )
+            .await
+            .map_err(|_| Error::ExecutionErr("precondition evaluation timed out".to_string()))?
+            .map_err(|e| {
+                Error::ExecutionErr(format!(
+                    "Error during evaluation of the precondition `{expr}`:\n{e:#}"
+                ))
+            })?;
+            serde_json::from_str::<bool>(r.get()).map_err(|_| {
+                Error::ExecutionErr(format!(
+                    "The precondition `{expr}` must evaluate to a boolean, got: {}",
+                    r.get()
+                ))
+            })
+        }
+        Precondition::Sql { database, query } => {
+            let database = transform_json_value(
+                PRECONDITION_ARG,
+                &client.get_authed().await,
+                &job.workspace_id,
+                database,
+                job,
+                db,
+            )
+            .await?;
+            eval_pg_precondition(database, &query, PRECONDITION_TIMEOUT).await
+        }
+    }
+}
@@ -1,8 +1,13 @@
-use std::{collections::HashMap, process::Stdio};
+use std::{
+    collections::HashMap,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
 
 use itertools::Itertools;
 use regex::Regex;
 use serde_json::value::RawValue;
+use sha2::{Digest, Sha256};
 use sqlx::{types::Json, Pool, Postgres};
 use tokio::{
     fs::{metadata, DirBuilder, File},
@@ -14,7 +19,8 @@ use uuid::Uuid;
 use windmill_common::ee::{get_license_plan, LicensePlan};
 use windmill_common::{
     error::{self, Error},
-    jobs::{QueuedJob, PREPROCESSOR_FAKE_ENTRYPOINT},
+    jobs::{ExecOptions, QueuedJob, PREPROCESSOR_FAKE_ENTRYPOINT, WRAPPER_RESULT_MARKER},
+    scripts::ScriptLang,
     utils::calculate_hash,
     worker::{write_file, WORKER_CONFIG},
     DB,
@@ -23,7 +29,9 @@ use windmill_common::{
 #[cfg(feature = "enterprise")]
 use windmill_common::variables::get_secret_value_as_admin;
 
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{
+    append_logs, is_local_job, CanceledBy, InstalledPackage, JobQueue, JobRunRecords,
+};
 
 lazy_static::lazy_static! {
     static ref PYTHON_PATH: String =
@@ -47,12 +55,30 @@ lazy_static::lazy_static! {
 
     static ref EPHEMERAL_TOKEN_CMD: Option<String> = std::env::var("EPHEMERAL_TOKEN_CMD").ok();
 
+    /// dependency trees, by workspace and venv paths, this worker already verified the checksum of,
+    /// with the fingerprint of the tree at the time and when it was verified
+    static ref VERIFIED_DEPENDENCY_TREES: Mutex<HashMap<String, (String, std::time::Instant)>> =
+        Mutex::new(HashMap::new());
 }
 
+/// `python --version` of the interpreter the dependencies are installed for
+static PYTHON_VERSION: tokio::sync::OnceCell<String> = tokio::sync::OnceCell::const_new();
+
 const NSJAIL_CONFIG_DOWNLOAD_PY_CONTENT: &str = include_str!("../nsjail/download.py.config.proto");
 const NSJAIL_CONFIG_RUN_PYTHON3_CONTENT: &str = include_str!("../nsjail/run.python3.config.proto");
 const RELATIVE_PYTHON_LOADER: &str = include_str!("../loader.py");
 
+/// file written in the dir of a requirement of the pip cache once it is fully installed, the
+/// other dirs being partial installs the cache snapshots leave out
+pub const PIP_ENTRY_COMPLETE_MARKER: &str = ".wm_complete";
+
+/// a verified dependency tree is hashed again after this long even if its fingerprint is unchanged,
+/// the fingerprint only covering the file metadata
+const VERIFIED_DEPENDENCY_TREE_TTL: std::time::Duration = std::time::Duration::from_secs(3600);
+
+/// profiles larger than this are not stored with the job
+const MAX_PROFILE_SIZE: u64 = 16 * 1024 * 1024;
+
 #[cfg(all(feature = "enterprise", feature = "parquet"))]
 use crate::global_cache::{build_tar_and_push, pull_from_tar};
 
@@ -60,14 +86,18 @@ use crate::global_cache::{build_tar_and_push, pull_from_tar};
 use windmill_common::s3_helpers::OBJECT_STORE_CACHE_SETTINGS;
 
 use crate::{
+    args_schema::parse_main_signature,
+    cache_eviction::lock_cache_entry,
     common::{
-        create_args_and_out_file, get_main_override, get_reserved_variables, read_file,
-        read_result, start_child_process, OccupancyMetrics,
+        create_args_stdin_or_file, get_main_override, get_reserved_variables, pipe_args_to_stdin,
+        read_file, read_result, start_child_process, OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
+    stdin_stream::stdin_stream_enabled,
     AuthedClientBackgroundTask, DISABLE_NSJAIL, DISABLE_NUSER, HOME_ENV, LOCK_CACHE_DIR,
     NSJAIL_PATH, PATH_ENV, PIP_CACHE_DIR, PIP_EXTRA_INDEX_URL, PIP_INDEX_URL, PROXY_ENVS, TZ_ENV,
-    UV_CACHE_DIR,
+    UV_CACHE_DIR, WARMUP_TIMEOUT,
 };
 
 #[cfg(windows)]
@@ -107,8 +137,10 @@ pub async fn uv_pip_compile(
     requirements: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &Pool<Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     w_id: &str,
     occupancy_metrics: &mut Option<&mut OccupancyMetrics>,
@@ -165,6 +197,11 @@ pub async fn uv_pip_compile(
         // Will be in format:
         //     py-000..000-no_uv
     }
+    let require_hashes = pip_require_hashes(db, w_id).await?;
+    if require_hashes {
+        // a lock without hashes must not be reused
+        req_hash.push_str("-hashes");
+    }
     if !no_cache {
         if let Some(cached) = sqlx::query_scalar!(
             "SELECT lockfile FROM pip_resolution_cache WHERE hash = $1",
@@ -192,6 +229,9 @@ pub async fn uv_pip_compile(
             "--resolver=backtracking",
             "--strip-extras",
         ];
+        if require_hashes {
+            args.push("--generate-hashes");
+        }
         let mut pip_args = vec![];
         let pip_extra_index_url = PIP_EXTRA_INDEX_URL
             .read()
@@ -234,13 +274,16 @@ pub async fn uv_pip_compile(
         handle_child(
             job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             &w_id,
             "pip-compile",
+            ChildPhase::DependencyInstall,
             None,
             false,
             occupancy_metrics,
@@ -273,6 +316,9 @@ pub async fn uv_pip_compile(
         if no_cache {
             args.extend(["--no-cache"]);
         }
+        if require_hashes {
+            args.push("--generate-hashes");
+        }
         let pip_extra_index_url = PIP_EXTRA_INDEX_URL
             .read()
             .await
@@ -314,14 +360,17 @@ pub async fn uv_pip_compile(
         handle_child(
             job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child_process,
             false,
             worker_name,
             &w_id,
             // TODO: Rename to uv-pip-compile?
             "uv",
+            ChildPhase::DependencyInstall,
             None,
             false,
             occupancy_metrics,
@@ -334,12 +383,7 @@ pub async fn uv_pip_compile(
     let mut file = File::open(path_lock).await?;
     let mut req_content = "".to_string();
     file.read_to_string(&mut req_content).await?;
-    let lockfile = req_content
-        .lines()
-        .filter(|x| !x.trim_start().starts_with('#'))
-        .map(|x| x.to_string())
-        .collect::<Vec<String>>()
-        .join("\n");
+    let lockfile = join_lock_lines(&req_content);
     sqlx::query!(
         "INSERT INTO pip_resolution_cache (hash, lockfile, expiration) VALUES ($1, $2, now() + ('3 days')::interval) ON CONFLICT (hash) DO UPDATE SET lockfile = $2",
         req_hash,
@@ -348,6 +392,53 @@ pub async fn uv_pip_compile(
     Ok(lockfile)
 }
 
+/// Modules of the standard library imported by the wrapper and by most scripts
+const PYTHON_WARMUP_IMPORTS: &str =
+    "import json, os, sys, base64, datetime, traceback, inspect, asyncio, urllib.request";
+
+/// Start the interpreter once and import the common modules, for the interpreter and their
+/// bytecode to be in the page cache when the first python job runs
+pub async fn warmup_python(
+    db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
+    worker_name: &str,
+    warmup_dir: &str,
+) -> error::Result<()> {
+    let mut python_cmd = Command::new(PYTHON_PATH.as_str());
+    python_cmd
+        .current_dir(warmup_dir)
+        .env_clear()
+        .env("PATH", PATH_ENV.as_str())
+        .env("TZ", TZ_ENV.as_str())
+        .env("HOME", HOME_ENV.as_str())
+        .args(["-c", PYTHON_WARMUP_IMPORTS])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    #[cfg(windows)]
+    python_cmd.env("SystemRoot", SYSTEM_ROOT.as_str());
+
+    let child = start_child_process(python_cmd, PYTHON_PATH.as_str()).await?;
+    handle_child(
+        &Uuid::nil(),
+        db,
+        job_queue,
+        &mut 0,
+        &mut None,
+        &mut JobRunRecords::default(),
+        child,
+        false,
+        worker_name,
+        "",
+        "python warmup",
+        ChildPhase::Run,
+        Some(WARMUP_TIMEOUT),
+        false,
+        &mut None,
+    )
+    .await
+}
+
 #[tracing::instrument(level = "trace", skip_all)]
 pub async fn handle_python_job(
     requirements_o: Option<String>,
@@ -357,7 +448,9 @@ pub async fn handle_python_job(
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     inner_content: &String,
     shared_mount: &str,
@@ -366,6 +459,8 @@ pub async fn handle_python_job(
     new_args: &mut Option<HashMap<String, Box<RawValue>>>,
     occupancy_metrics: &mut OccupancyMetrics,
 ) -> windmill_common::error::Result<Box<RawValue>> {
+    let mut exec_options = job.exec_options()?;
+    exec_options.profile &= !is_local_job(&job.id);
     let script_path = crate::common::use_flow_root_path(job.script_path());
     let additional_python_paths = handle_python_deps(
         job_dir,
@@ -375,21 +470,25 @@ pub async fn handle_python_job(
         &script_path,
         &job.id,
         db,
+        job_queue,
         worker_name,
         worker_dir,
         mem_peak,
         canceled_by,
+        run_records,
         &mut Some(occupancy_metrics),
     )
     .await?;
 
-    append_logs(
-        &job.id,
-        &job.workspace_id,
-        "\n\n--- PYTHON CODE EXECUTION ---\n".to_string(),
-        db,
-    )
-    .await;
+    if !exec_options.quiet {
+        append_logs(
+            &job.id,
+            &job.workspace_id,
+            "\n\n--- PYTHON CODE EXECUTION ---\n".to_string(),
+            db,
+        )
+        .await;
+    }
 
     let (
         import_loader,
@@ -413,7 +512,7 @@ pub async fn handle_python_job(
 
     let apply_preprocessor = pre_spread.is_some();
 
-    create_args_and_out_file(&client, job, job_dir, db).await?;
+    let args_stdin = create_args_stdin_or_file(&client, job, job_dir, db).await?;
 
     let preprocessor = if let Some(pre_spread) = pre_spread {
         format!(
@@ -434,86 +533,24 @@ pub async fn handle_python_job(
         "".to_string()
     };
 
-    let os_main_override = if let Some(main_override) = main_name.as_ref() {
-        format!("os.environ[\"MAIN_OVERRIDE\"] = \"{main_override}\"\n")
-    } else {
-        String::new()
-    };
-    let main_override = main_name.unwrap_or_else(|| "main".to_string());
-    let wrapper_content: String = format!(
-        r#"
-import os
-import json
-{import_loader}
-{import_base64}
-{import_datetime}
-import traceback
-import sys
-{os_main_override}
-from {module_dir_dot} import {last} as inner_script
-import re
-
-with open("args.json") as f:
-    kwargs = json.load(f, strict=False)
-args = {{}}
-{transforms}
-
-def to_b_64(v: bytes):
-    import base64
-    b64 = base64.b64encode(v)
-    return b64.decode('ascii')
-
-replace_nan = re.compile(r'(?:\bNaN\b|\\*\\u0000)')
-
-result_json = os.path.join(os.path.abspath(os.path.dirname(__file__)), "result.json")
-
-def res_to_json(res):
-    typ = type(res)
-    if typ.__name__ == 'DataFrame':
-        if typ.__module__ == 'pandas.core.frame':
-            res = res.values.tolist()
-        elif typ.__module__ == 'polars.dataframe.frame':
-            res = res.rows()
-    elif typ.__name__ == 'bytes':
-        res = to_b_64(res)
-    elif typ.__name__ == 'dict':
-        for k, v in res.items():
-            if type(v).__name__ == 'bytes':
-                res[k] = to_b_64(v)
-    return re.sub(replace_nan, ' null ', json.dumps(res, separators=(',', ':'), default=str).replace('\n', ''))
-
-try:
-    {preprocessor}
-    {spread}
-    for k, v in list(args.items()):
-        if v == '<function call>':
-            del args[k]
-    if inner_script.{main_override} is None or not callable(inner_script.{main_override}):
-        raise ValueError("{main_override} function is missing")
-    res = inner_script.{main_override}(**args)
-    res_json = res_to_json(res)
-    with open(result_json, 'w') as f:
-        f.write(res_json)
-except BaseException as e:
-    exc_type, exc_value, exc_traceback = sys.exc_info()
-    tb = traceback.format_tb(exc_traceback)
-    with open(result_json, 'w') as f:
-        err = {{ "message": str(e), "name": e.__class__.__name__, "stack": '\n'.join(tb[1:]) }}
-        extra = e.__dict__ 
-        if extra and len(extra) > 0:
-            err['extra'] = extra
-        flow_node_id = os.environ.get('WM_FLOW_STEP_ID')
-        if flow_node_id:
-            err['step_id'] = flow_node_id
-        err_json = json.dumps(err, separators=(',', ':'), default=str).replace('\n', '')
-        f.write(err_json)
-        sys.exit(1)
-"#,
-    );
+    let wrapper_content = PythonWrapper {
+        import_loader,
+        import_base64,
+        import_datetime,
+        module_dir_dot: &module_dir_dot,
+        last: &last,
+        transforms: &transforms,
+        spread: &spread,
+        main_override: main_name.as_deref(),
+        preprocessor: &preprocessor,
+        args_stdin: args_stdin.is_some(),
+    }
+    .content(&exec_options);
     write_file(job_dir, "wrapper.py", &wrapper_content)?;
 
     let client = client.get_authed().await;
-    let mut reserved_variables = get_reserved_variables(job, &client.token, db).await?;
+    let mut reserved_variables =
+        get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
     let additional_python_paths_folders = additional_python_paths.iter().join(":");
 
     #[cfg(windows)]
@@ -538,7 +575,7 @@ mount {{
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_PYTHON3_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_PYTHON3_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
                 .replace("{SHARED_MOUNT}", shared_mount)
@@ -558,7 +595,7 @@ mount {{
         "started python code execution {}",
         job.id
     );
-    let child = if !*DISABLE_NSJAIL {
+    let mut child = if !*DISABLE_NSJAIL {
         let mut nsjail_cmd = Command::new(NSJAIL_PATH.as_str());
         nsjail_cmd
             .current_dir(job_dir)
@@ -581,6 +618,9 @@ mount {{
             ])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        if args_stdin.is_some() || stdin_stream_enabled(job) {
+            nsjail_cmd.stdin(Stdio::piped());
+        }
         start_child_process(nsjail_cmd, NSJAIL_PATH.as_str()).await?
     } else {
         let mut python_cmd = Command::new(PYTHON_PATH.as_str());
@@ -596,28 +636,41 @@ mount {{
             .args(vec!["-u", "-m", "wrapper"])
             .stdout(Stdio::piped())
             .stderr(Stdio::piped());
+        if args_stdin.is_some() || stdin_stream_enabled(job) {
+            python_cmd.stdin(Stdio::piped());
+        }
 
         #[cfg(windows)]
         python_cmd.env("SystemRoot", SYSTEM_ROOT.as_str());
 
         start_child_process(python_cmd, PYTHON_PATH.as_str()).await?
     };
+    if let Some(args_json) = args_stdin {
+        pipe_args_to_stdin(&mut child, args_json);
+    }
 
-    handle_child(
+    let run = handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "python run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
     )
-    .await?;
+    .await;
+    if exec_options.profile {
+        store_profile(job, job_dir, db, run_records).await;
+    }
+    run?;
 
     if apply_preprocessor {
         let args = read_file(&format!("{job_dir}/args.json"))
@@ -639,6 +692,213 @@ mount {{
     read_result(job_dir).await
 }
 
+/// Store the cProfile stats written by the wrapper of a job run with `_PROFILE` with the job
+async fn store_profile(job: &QueuedJob, job_dir: &str, db: &DB, run_records: &mut JobRunRecords) {
+    let path = format!("{job_dir}/profile.prof");
+    let msg = match metadata(&path).await {
+        Ok(m) if m.len() > MAX_PROFILE_SIZE => format!(
+            "\nprofile of {}MB not stored, the maximum size is {}MB\n",
+            m.len() / 1024 / 1024,
+            MAX_PROFILE_SIZE / 1024 / 1024
+        ),
+        Ok(_) => match tokio::fs::read(&path).await {
+            Ok(profile) => {
+                run_records.record_profile(profile);
+                "\nprofile of the run stored when the job completes, see `get_profile`\n"
+                    .to_string()
+            }
+            Err(e) => format!("\ncould not read the profile of the run: {e}\n"),
+        },
+        Err(_) => "\nno profile was written by the run\n".to_string(),
+    };
+    append_logs(&job.id, &job.workspace_id, msg, db).await;
+}
+
+/// The parts of `wrapper.py` computed from the script and its signature by `prepare_wrapper`
+struct PythonWrapper<'a> {
+    import_loader: &'a str,
+    import_base64: &'a str,
+    import_datetime: &'a str,
+    module_dir_dot: &'a str,
+    last: &'a str,
+    transforms: &'a str,
+    spread: &'a str,
+    main_override: Option<&'a str>,
+    preprocessor: &'a str,
+    args_stdin: bool,
+}
+
+impl PythonWrapper<'_> {
+    /// Content of `wrapper.py`, with the blocks toggled by the exec options of the job
+    fn content(&self, options: &ExecOptions) -> String {
+        let PythonWrapper {
+            import_loader,
+            import_base64,
+            import_datetime,
+            module_dir_dot,
+            last,
+            transforms,
+            spread,
+            preprocessor,
+            ..
+        } = self;
+        let os_main_override = if let Some(main_override) = self.main_override {
+            format!("os.environ[\"MAIN_OVERRIDE\"] = \"{main_override}\"\n")
+        } else {
+            String::new()
+        };
+        let main_override = self.main_override.unwrap_or("main");
+        let strict = if options.strict_json { "True" } else { "False" };
+        let read_args = if self.args_stdin {
+            format!("kwargs = json.load(sys.stdin, strict={strict})")
+        } else {
+            format!("with open(\"args.json\") as f:\n    kwargs = json.load(f, strict={strict})")
+        };
+        let call_main = if options.profile {
+            format!(
+                r#"import cProfile
+    profiler = cProfile.Profile()
+    try:
+        res = profiler.runcall(inner_script.{main_override}, **args)
+    finally:
+        profiler.dump_stats(os.path.join(os.path.dirname(result_json), "profile.prof"))"#
+            )
+        } else {
+            format!("res = inner_script.{main_override}(**args)")
+        };
+        let check_args = if options.strict_args {
+            format!(
+                r#"import inspect
+    params = inspect.signature(inner_script.{main_override}).parameters
+    if not any(p.kind == p.VAR_KEYWORD for p in params.values()):
+        unexpected = [k for k in kwargs if k not in params and not (k.startswith('_') and k.isupper())]
+        if unexpected:
+            raise ValueError("unexpected args for {main_override}: " + ", ".join(unexpected))"#
+            )
+        } else {
+            String::new()
+        };
+        let result_marker = if options.result_marker {
+            format!("print(\"{WRAPPER_RESULT_MARKER}\", flush=True)")
+        } else {
+            String::new()
+        };
+        let stack = if options.stack_traces {
+            r#", "stack": '\n'.join(tb[1:])"#
+        } else {
+            ""
+        };
+        format!(
+            r#"
+import os
+import json
+{import_loader}
+{import_base64}
+{import_datetime}
+import traceback
+import sys
+{os_main_override}
+if os.environ.get("WM_SEED"):
+    import random
+    random.seed(int(os.environ["WM_SEED"]))
+if os.environ.get("WM_FIXED_NOW"):
+    # patched before the script is imported so that it picks up the fixed clock
+    import datetime as wm_datetime
+    import time as wm_time
+    wm_fixed_ts = wm_datetime.datetime.fromisoformat(os.environ["WM_FIXED_NOW"]).timestamp()
+    wm_time.time = lambda: wm_fixed_ts
+    wm_time.time_ns = lambda: int(wm_fixed_ts * 1_000_000_000)
+
+    def wm_fixed_clock(base):
+        class FixedClockMeta(type):
+            def __instancecheck__(cls, obj):
+                return isinstance(obj, base)
+
+            def __subclasscheck__(cls, sub):
+                return issubclass(sub, base)
+
+        class FixedClock(base, metaclass=FixedClockMeta):
+            @classmethod
+            def today(cls):
+                return cls.fromtimestamp(wm_fixed_ts)
+
+            @classmethod
+            def now(cls, tz=None):
+                return cls.fromtimestamp(wm_fixed_ts, tz)
+
+            @classmethod
+            def utcnow(cls):
+                return cls.fromtimestamp(wm_fixed_ts, wm_datetime.timezone.utc).replace(tzinfo=None)
+
+        FixedClock.__name__ = FixedClock.__qualname__ = base.__name__
+        return FixedClock
+
+    wm_datetime.date = wm_fixed_clock(wm_datetime.date)
+    wm_datetime.datetime = wm_fixed_clock(wm_datetime.datetime)
+from {module_dir_dot} import {last} as inner_script
+import re
+
+{read_args}
+args = {{}}
+{transforms}
+
+def to_b_64(v: bytes):
+    import base64
+    b64 = base64.b64encode(v)
+    return b64.decode('ascii')
+
+replace_nan = re.compile(r'(?:\bNaN\b|\\*\\u0000)')
+
+result_json = os.path.join(os.path.abspath(os.path.dirname(__file__)), "result.json")
+
+def res_to_json(res):
+    typ = type(res)
+    if typ.__name__ == 'DataFrame':
+        if typ.__module__ == 'pandas.core.frame':
+            res = res.values.tolist()
+        elif typ.__module__ == 'polars.dataframe.frame':
+            res = res.rows()
+    elif typ.__name__ == 'bytes':
+        res = to_b_64(res)
+    elif typ.__name__ == 'dict':
+        for k, v in res.items():
+            if type(v).__name__ == 'bytes':
+                res[k] = to_b_64(v)
+    return re.sub(replace_nan, ' null ', json.dumps(res, separators=(',', ':'), default=str).replace('\n', ''))
+
+try:
+    {preprocessor}
+    {spread}
+    for k, v in list(args.items()):
+        if v == '<function call>':
+            del args[k]
+    if inner_script.{main_override} is None or not callable(inner_script.{main_override}):
+        raise ValueError("{main_override} function is missing")
+    {check_args}
+    {call_main}
+    res_json = res_to_json(res)
+    with open(result_json, 'w') as f:
+        f.write(res_json)
+    {result_marker}
+except BaseException as e:
+    exc_type, exc_value, exc_traceback = sys.exc_info()
+    tb = traceback.format_tb(exc_traceback)
+    with open(result_json, 'w') as f:
+        err = {{ "message": str(e), "name": e.__class__.__name__{stack} }}
+        extra = e.__dict__ 
+        if extra and len(extra) > 0:
+            err['extra'] = extra
+        flow_node_id = os.environ.get('WM_FLOW_STEP_ID')
+        if flow_node_id:
+            err['step_id'] = flow_node_id
+        err_json = json.dumps(err, separators=(',', ':'), default=str).replace('\n', '')
+        f.write(err_json)
+        sys.exit(1)
+"#,
+        )
+    }
+}
+
 async fn prepare_wrapper(
     job_dir: &str,
     inner_content: &str,
@@ -706,12 +966,19 @@ async fn prepare_wrapper(
         let _ = write_file(job_dir, "loader.py", RELATIVE_PYTHON_LOADER)?;
     }
 
-    let sig = windmill_parser_py::parse_python_signature(inner_content, main_override.clone())?;
+    let sig = parse_main_signature(
+        &ScriptLang::Python3,
+        inner_content,
+        main_override.clone(),
+        false,
+    )?;
 
     let pre_sig = if apply_preprocessor {
-        Some(windmill_parser_py::parse_python_signature(
+        Some(parse_main_signature(
+            &ScriptLang::Python3,
             inner_content,
             Some("preprocessor".to_string()),
+            false,
         )?)
     } else {
         None
@@ -880,10 +1147,12 @@ async fn handle_python_deps(
     script_path: &str,
     job_id: &Uuid,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     occupancy_metrics: &mut Option<&mut OccupancyMetrics>,
 ) -> error::Result<Vec<String>> {
     create_dependencies_dir(job_dir).await;
@@ -919,8 +1188,10 @@ async fn handle_python_deps(
                     &requirements,
                     mem_peak,
                     canceled_by,
+                    run_records,
                     job_dir,
                     db,
+                    job_queue,
                     worker_name,
                     w_id,
                     occupancy_metrics,
@@ -945,29 +1216,259 @@ async fn handle_python_deps(
             w_id,
             mem_peak,
             canceled_by,
+            run_records,
             db,
+            job_queue,
             worker_name,
             job_dir,
             worker_dir,
             occupancy_metrics,
         )
         .await?;
+        verify_dependencies_checksum(db, w_id, job_id, &requirements, &venv_path).await?;
         additional_python_paths.append(&mut venv_path);
     }
     Ok(additional_python_paths)
 }
 
+/// Files of the installed dependency trees, with their path relative to their tree, ignoring the
+/// bytecode python writes lazily at import time
+fn dependency_tree_files(paths: &[String]) -> std::io::Result<Vec<(String, std::path::PathBuf)>> {
+    fn collect_files(
+        root: &std::path::Path,
+        dir: &std::path::Path,
+        files: &mut Vec<(String, std::path::PathBuf)>,
+    ) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            if file_type.is_dir() {
+                if entry.file_name() != "__pycache__" {
+                    collect_files(root, &path, files)?;
+                }
+            } else if path.extension().is_some_and(|ext| ext == "pyc") {
+                continue;
+            } else {
+                let relative = path.strip_prefix(root).unwrap_or(&path);
+                files.push((relative.to_string_lossy().to_string(), path));
+            }
+        }
+        Ok(())
+    }
+
+    let mut all_files = vec![];
+    for root in paths.iter().sorted() {
+        let root = std::path::Path::new(root);
+        let mut files = vec![];
+        collect_files(root, root, &mut files)?;
+        files.sort();
+        all_files.append(&mut files);
+    }
+    Ok(all_files)
+}
+
+/// Hash the files of the installed dependency trees (relative path and content)
+fn hash_dependency_tree(paths: &[String]) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    for (relative, path) in dependency_tree_files(paths)? {
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        hasher.update(std::fs::read(&path)?);
+        hasher.update([0]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Cheap fingerprint of the dependency trees (relative path, size and mtime of the files), telling
+/// whether a tree verified earlier may have changed since
+fn fingerprint_dependency_tree(paths: &[String]) -> std::io::Result<String> {
+    let mut hasher = Sha256::new();
+    for (relative, path) in dependency_tree_files(paths)? {
+        let metadata = std::fs::metadata(&path)?;
+        let mtime = metadata
+            .modified()?
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default();
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        hasher.update(metadata.len().to_le_bytes());
+        hasher.update(mtime.as_nanos().to_le_bytes());
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+async fn python_version() -> &'static str {
+    PYTHON_VERSION
+        .get_or_init(|| async {
+            match Command::new(PYTHON_PATH.as_str())
+                .arg("--version")
+                .output()
+                .await
+            {
+                Ok(output) => String::from_utf8_lossy(&output.stdout).trim().to_string(),
+                Err(e) => {
+                    tracing::error!("could not get the version of {}: {e:#}", *PYTHON_PATH);
+                    "unknown".to_string()
+                }
+            }
+        })
+        .await
+}
+
+/// If the workspace opted into it, compare the hash of the installed dependency tree against the
+/// one recorded on the first install of the same lock, interpreter version and platform, failing
+/// the job on mismatch. A tree verified by this worker is only hashed again once its fingerprint
+/// changed or after VERIFIED_DEPENDENCY_TREE_TTL
+async fn verify_dependencies_checksum(
+    db: &DB,
+    w_id: &str,
+    job_id: &Uuid,
+    requirements: &str,
+    venv_paths: &[String],
+) -> error::Result<()> {
+    let verified_key = format!("{w_id}:{}", venv_paths.join(":"));
+    let verified = VERIFIED_DEPENDENCY_TREES
+        .lock()
+        .unwrap()
+        .get(&verified_key)
+        .cloned();
+    // only trees of workspaces that opted in are in the memo, their fingerprint is worth computing
+    if let Some((verified_fingerprint, verified_at)) = verified {
+        let paths = venv_paths.to_vec();
+        let fingerprint = tokio::task::spawn_blocking(move || fingerprint_dependency_tree(&paths))
+            .await
+            .map_err(|e| Error::InternalErr(format!("fingerprinting dependency tree: {e}")))?
+            .ok();
+        if fingerprint.as_ref() == Some(&verified_fingerprint)
+            && verified_at.elapsed() < VERIFIED_DEPENDENCY_TREE_TTL
+        {
+            return Ok(());
+        }
+    }
+    let enabled = sqlx::query_scalar::<_, bool>(
+        "SELECT verify_dependency_checksums FROM workspace_settings WHERE workspace_id = $1",
+    )
+    .bind(w_id)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or(false);
+    if !enabled {
+        VERIFIED_DEPENDENCY_TREES
+            .lock()
+            .unwrap()
+            .remove(&verified_key);
+        return Ok(());
+    }
+
+    // the same lock installs different files for another interpreter or platform
+    let lock_hash = calculate_hash(&format!(
+        "{requirements}\n{}\n{}-{}",
+        python_version().await,
+        std::env::consts::OS,
+        std::env::consts::ARCH
+    ));
+    let paths = venv_paths.to_vec();
+    let (tree_hash, fingerprint) = tokio::task::spawn_blocking(move || {
+        // fingerprinted first, a change while hashing makes the next job verify the tree again
+        let fingerprint = fingerprint_dependency_tree(&paths)?;
+        hash_dependency_tree(&paths).map(|tree_hash| (tree_hash, fingerprint))
+    })
+    .await
+    .map_err(|e| Error::InternalErr(format!("hashing dependency tree: {e}")))?
+    .map_err(|e| Error::InternalErr(format!("hashing dependency tree: {e}")))?;
+
+    // on conflict, the no-op update makes the existing baseline returned
+    let expected = sqlx::query_scalar::<_, String>(
+        "INSERT INTO dependency_checksum (workspace_id, lock_hash, tree_hash) VALUES ($1, $2, $3)
+        ON CONFLICT (workspace_id, lock_hash) DO UPDATE SET workspace_id = EXCLUDED.workspace_id
+        RETURNING tree_hash",
+    )
+    .bind(w_id)
+    .bind(&lock_hash)
+    .bind(&tree_hash)
+    .fetch_one(db)
+    .await?;
+
+    if expected != tree_hash {
+        return Err(Error::ExecutionErr(format!(
+            "Installed dependencies do not match the checksum of their first install for this lock (expected {expected}, got {tree_hash}). The dependency cache may have been tampered with."
+        )));
+    }
+    VERIFIED_DEPENDENCY_TREES
+        .lock()
+        .unwrap()
+        .insert(verified_key, (fingerprint, std::time::Instant::now()));
+    append_logs(
+        job_id,
+        w_id,
+        format!("\ndependency tree checksum verified: {tree_hash}\n"),
+        db,
+    )
+    .await;
+    Ok(())
+}
+
 lazy_static::lazy_static! {
     static ref PIP_SECRET_VARIABLE: Regex = Regex::new(r"\$\{PIP_SECRET:([^\s\}]+)\}").unwrap();
 }
 
+/// Whether the workspace opted into pinning the hashes of the python packages, in which case the
+/// locks are generated with `--generate-hashes` and installed with `--require-hashes`
+async fn pip_require_hashes(db: &DB, w_id: &str) -> error::Result<bool> {
+    Ok(sqlx::query_scalar::<_, bool>(
+        "SELECT pip_require_hashes FROM workspace_settings WHERE workspace_id = $1",
+    )
+    .bind(w_id)
+    .fetch_optional(db)
+    .await?
+    .unwrap_or(false))
+}
+
+/// One requirement per line, without the comments. The `--hash` options a requirement is
+/// continued with (`\` line endings) are kept on its line
+fn join_lock_lines(content: &str) -> String {
+    let mut lines: Vec<String> = vec![];
+    let mut continued = false;
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('#') {
+            continue;
+        }
+        let (part, continues) = match trimmed.strip_suffix('\\') {
+            Some(part) => (part.trim_end(), true),
+            None => (trimmed, false),
+        };
+        match lines.last_mut() {
+            Some(last) if continued => {
+                last.push(' ');
+                last.push_str(part);
+            }
+            _ if continues => lines.push(part.to_string()),
+            _ => lines.push(line.to_string()),
+        }
+        continued = continues;
+    }
+    lines.join("\n")
+}
+
+/// Split a lock line into the requirement and its `--hash=` options, if any
+fn split_req_hashes(req: &str) -> (&str, Option<&str>) {
+    match req.find(" --hash=") {
+        Some(i) => (req[..i].trim(), Some(req[i..].trim())),
+        None => (req, None),
+    }
+}
+
 pub async fn handle_python_reqs(
     requirements: Vec<&str>,
     job_id: &Uuid,
     w_id: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     job_dir: &str,
     worker_dir: &str,
@@ -1016,16 +1517,30 @@ pub async fn handle_python_reqs(
     };
 
     let mut req_with_penv: Vec<(String, String)> = vec![];
+    // target dir of each requirement and where its install comes from
+    let mut req_sources: Vec<(String, &'static str)> = vec![];
+    let require_hashes = pip_require_hashes(db, w_id).await?;
 
     for req in requirements {
         if req.starts_with('#') {
             continue;
         }
-        let venv_p = format!(
+        let (base_req, hashes) = split_req_hashes(req);
+        if require_hashes && hashes.is_none() {
+            return Err(Error::ExecutionErr(format!(
+                "`{base_req}` has no pinned hashes but the workspace requires them, redeploy the script to regenerate its lock"
+            )));
+        }
+        let mut venv_p = format!(
             "{PIP_CACHE_DIR}/{}",
-            req.replace(' ', "").replace('/', "").replace(':', "")
+            base_req.replace(' ', "").replace('/', "").replace(':', "")
         );
-        if metadata(&venv_p).await.is_ok() {
+        if let Some(hashes) = hashes {
+            // installs checked against different hashes are not interchangeable
+            venv_p.push_str(&format!("-h{}", &calculate_hash(hashes)[..16]));
+        }
+        if metadata(&venv_p).await.is_ok() && lock_cache_entry(job_id, &venv_p) {
+            req_sources.push((venv_p.clone(), "cache"));
             req_paths.push(venv_p);
         } else {
             req_with_penv.push((req.to_string(), venv_p));
@@ -1083,7 +1598,10 @@ pub async fn handle_python_reqs(
             for result in results {
                 match result {
                     PullFromTar::Pulled(venv_p) => {
+                        mark_pip_entry_complete(&venv_p).await;
+                        lock_cache_entry(job_id, &venv_p);
                         pulled.push(venv_p.split("/").last().unwrap_or_default().to_string());
+                        req_sources.push((venv_p.clone(), "distributed_cache"));
                         req_paths.push(venv_p);
                     }
                     PullFromTar::NotPulled(req, venv_p) => {
@@ -1124,8 +1642,11 @@ pub async fn handle_python_reqs(
                 "starting nsjail"
             );
             let mut vars = vars.clone();
-            let req = req.to_string();
-            vars.push(("REQ", &req));
+            let (base_req, hashes) = split_req_hashes(&req);
+            vars.push(("REQ", base_req));
+            if let Some(hashes) = hashes {
+                vars.push(("REQ_HASHES", hashes));
+            }
             vars.push(("TARGET", &venv_p));
             let mut nsjail_cmd = Command::new(NSJAIL_PATH.as_str());
             nsjail_cmd
@@ -1138,19 +1659,26 @@ pub async fn handle_python_reqs(
                 .stderr(Stdio::piped());
             start_child_process(nsjail_cmd, NSJAIL_PATH.as_str()).await?
         } else {
-            let fssafe_req = NON_ALPHANUM_CHAR.replace_all(&req, "_").to_string();
+            let (base_req, hashes) = split_req_hashes(&req);
+            let fssafe_req = NON_ALPHANUM_CHAR.replace_all(base_req, "_").to_string();
+            // hashes can only be given in a requirements file
+            let hashed_req_file = format!("{job_dir}/requirements_{fssafe_req}.txt");
+            if hashes.is_some() {
+                write_file(job_dir, &format!("requirements_{fssafe_req}.txt"), &req)?;
+            }
             #[cfg(unix)]
-            let req = format!("'{}'", req);
+            let base_req = format!("'{}'", base_req);
 
             #[cfg(windows)]
-            let req = format!("{}", req);
+            let base_req = format!("{}", base_req);
 
-            let mut command_args = vec![
-                PYTHON_PATH.as_str(),
-                "-m",
-                "pip",
-                "install",
-                &req,
+            let mut command_args = vec![PYTHON_PATH.as_str(), "-m", "pip", "install"];
+            if hashes.is_some() {
+                command_args.extend(["-r", hashed_req_file.as_str(), "--require-hashes"]);
+            } else {
+                command_args.push(&base_req);
+            }
+            command_args.extend([
                 "-I",
                 "--no-deps",
                 "--no-color",
@@ -1159,7 +1687,7 @@ pub async fn handle_python_reqs(
                 "--disable-pip-version-check",
                 "-t",
                 venv_p.as_str(),
-            ];
+            ]);
             let pip_extra_index_url = PIP_EXTRA_INDEX_URL
                 .read()
                 .await
@@ -1227,13 +1755,16 @@ pub async fn handle_python_reqs(
         let child = handle_child(
             &job_id,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
+            run_records,
             child,
             false,
             worker_name,
             &w_id,
             &format!("pip install {req}"),
+            ChildPhase::DependencyInstall,
             None,
             false,
             occupancy_metrics,
@@ -1246,6 +1777,8 @@ pub async fn handle_python_reqs(
             job_id
         );
         child?;
+        mark_pip_entry_complete(&venv_p).await;
+        lock_cache_entry(job_id, &venv_p);
 
         #[cfg(all(feature = "enterprise", feature = "parquet"))]
         if let Some(os) = OBJECT_STORE_CACHE_SETTINGS.read().await.clone() {
@@ -1256,11 +1789,52 @@ pub async fn handle_python_reqs(
                 tokio::spawn(build_tar_and_push(os, venv_p));
             }
         }
+        req_sources.push((venv_p.clone(), "install"));
         req_paths.push(venv_p);
     }
+
+    if !is_local_job(job_id) {
+        let mut installed = vec![];
+        for (venv_p, source) in req_sources {
+            installed.extend(read_installed_packages(&venv_p, source).await);
+        }
+        run_records.record_installed_packages(installed);
+    }
     Ok(req_paths)
 }
 
+async fn mark_pip_entry_complete(venv_p: &str) {
+    if let Err(e) = File::create(format!("{venv_p}/{PIP_ENTRY_COMPLETE_MARKER}")).await {
+        tracing::warn!("could not mark {venv_p} as fully installed: {e:#}");
+    }
+}
+
+/// The packages installed in the target dir of a requirement, from their
+/// `{name}-{version}.dist-info` metadata dirs. Unlike the pip output, they are also there when
+/// the install of a previous job is reused
+async fn read_installed_packages(venv_p: &str, source: &'static str) -> Vec<InstalledPackage> {
+    let mut packages = vec![];
+    let Ok(mut entries) = tokio::fs::read_dir(venv_p).await else {
+        return packages;
+    };
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let file_name = entry.file_name();
+        let Some((name, version)) = file_name
+            .to_str()
+            .and_then(|x| x.strip_suffix(".dist-info"))
+            .and_then(|x| x.rsplit_once('-'))
+        else {
+            continue;
+        };
+        packages.push(InstalledPackage {
+            name: name.to_string(),
+            version: version.to_string(),
+            source,
+        });
+    }
+    packages
+}
+
 #[cfg(feature = "enterprise")]
 use crate::JobCompletedSender;
 #[cfg(feature = "enterprise")]
@@ -1274,6 +1848,7 @@ use windmill_common::variables;
 pub async fn start_worker(
     requirements_o: Option<String>,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     inner_content: &str,
     base_internal_url: &str,
     job_dir: &str,
@@ -1317,10 +1892,12 @@ pub async fn start_worker(
         script_path,
         &Uuid::nil(),
         db,
+        job_queue,
         worker_name,
         job_dir,
         &mut mem_peak,
         &mut canceled_by,
+        &mut JobRunRecords::default(),
         &mut None,
     )
     .await?;
@@ -1452,3 +2029,116 @@ for line in sys.stdin:
     )
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wrapper(options: &ExecOptions) -> String {
+        PythonWrapper {
+            import_loader: "",
+            import_base64: "",
+            import_datetime: "",
+            module_dir_dot: "f.test",
+            last: "main",
+            transforms: "",
+            spread: "args[\"x\"] = kwargs.get(\"x\")",
+            main_override: None,
+            preprocessor: "",
+            args_stdin: false,
+        }
+        .content(options)
+    }
+
+    #[test]
+    fn test_default_exec_options_wrapper() {
+        let content = wrapper(&ExecOptions::default());
+        assert!(content.contains("kwargs = json.load(f, strict=False)"));
+        assert!(content.contains("res = inner_script.main(**args)"));
+        assert!(content.contains(r#""stack": '\n'.join(tb[1:])"#));
+        assert!(!content.contains("cProfile"));
+        assert!(!content.contains("inspect.signature"));
+        assert!(!content.contains(WRAPPER_RESULT_MARKER));
+    }
+
+    #[test]
+    fn test_strict_json_wrapper() {
+        let content = wrapper(&ExecOptions { strict_json: true, ..Default::default() });
+        assert!(content.contains("kwargs = json.load(f, strict=True)"));
+        assert!(!content.contains("strict=False"));
+    }
+
+    #[test]
+    fn test_no_stack_traces_wrapper() {
+        let content = wrapper(&ExecOptions { stack_traces: false, ..Default::default() });
+        assert!(content.contains(r#"err = { "message": str(e), "name": e.__class__.__name__ }"#));
+        assert!(!content.contains(r#""stack""#));
+    }
+
+    #[test]
+    fn test_profile_wrapper() {
+        let content = wrapper(&ExecOptions { profile: true, ..Default::default() });
+        assert!(content.contains("res = profiler.runcall(inner_script.main, **args)"));
+        assert!(content.contains("profile.prof"));
+    }
+
+    #[test]
+    fn test_result_marker_wrapper() {
+        let content = wrapper(&ExecOptions { result_marker: true, ..Default::default() });
+        let written = content.find("f.write(res_json)").unwrap();
+        let marker = content
+            .find(&format!("print(\"{WRAPPER_RESULT_MARKER}\", flush=True)"))
+            .unwrap();
+        assert!(marker > written);
+    }
+
+    #[test]
+    fn test_fixed_now_wrapper() {
+        let content = wrapper(&ExecOptions::default());
+        let patched = content
+            .find("wm_datetime.datetime = wm_fixed_clock(wm_datetime.datetime)")
+            .unwrap();
+        let imported = content
+            .find("from f.test import main as inner_script")
+            .unwrap();
+        assert!(content.contains(r#"if os.environ.get("WM_FIXED_NOW"):"#));
+        assert!(content.contains("wm_time.time = lambda: wm_fixed_ts"));
+        assert!(patched < imported);
+    }
+
+    #[test]
+    fn test_strict_args_wrapper() {
+        let content = wrapper(&ExecOptions { strict_args: true, ..Default::default() });
+        let missing = content.find("main function is missing").unwrap();
+        let check = content
+            .find("inspect.signature(inner_script.main)")
+            .unwrap();
+        let call = content.find("res = inner_script.main(**args)").unwrap();
+        assert!(missing < check && check < call);
+        assert!(content.contains("unexpected args for main"));
+    }
+
+    #[test]
+    fn test_hash_dependency_tree() {
+        let root = std::env::temp_dir().join(format!("dependency_tree_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("pkg")).unwrap();
+        std::fs::write(root.join("pkg/__init__.py"), "x = 1\n").unwrap();
+        std::fs::write(root.join(PIP_ENTRY_COMPLETE_MARKER), "").unwrap();
+        let paths = vec![root.to_string_lossy().to_string()];
+        let hash = hash_dependency_tree(&paths).unwrap();
+
+        // bytecode written at import time does not change the hash
+        std::fs::create_dir_all(root.join("pkg/__pycache__")).unwrap();
+        std::fs::write(root.join("pkg/__pycache__/__init__.pyc"), "bytecode").unwrap();
+        std::fs::write(root.join("pkg/module.pyc"), "bytecode").unwrap();
+        assert_eq!(hash_dependency_tree(&paths).unwrap(), hash);
+
+        let fingerprint = fingerprint_dependency_tree(&paths).unwrap();
+        std::fs::write(root.join("pkg/__init__.py"), "x = 22\n").unwrap();
+        let tampered = hash_dependency_tree(&paths).unwrap();
+        assert_ne!(tampered, hash);
+        assert_ne!(fingerprint_dependency_tree(&paths).unwrap(), fingerprint);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
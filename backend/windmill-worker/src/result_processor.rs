@@ -1,7 +1,7 @@
 use serde::Serialize;
 use sqlx::{types::Json, Pool, Postgres};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{
         atomic::{AtomicBool, AtomicU16, Ordering},
         Arc,
@@ -14,6 +14,7 @@ use windmill_common::{
     add_time,
     error::{self, Error},
     jobs::{JobKind, QueuedJob},
+    log_compression::{decompress_log_chunks, LOG_COMPRESSION_THRESHOLD},
     worker::{to_raw_value, WORKER_GROUP},
     DB,
 };
@@ -21,12 +22,15 @@ use windmill_common::{
 #[cfg(feature = "benchmark")]
 use windmill_common::bench::{BenchmarkInfo, BenchmarkIter};
 
-use windmill_queue::{append_logs, get_queued_job, CanceledBy, WrappedError};
+use windmill_queue::{append_logs, get_queued_job, CanceledBy, JobRunRecords, WrappedError};
 
 #[cfg(feature = "prometheus")]
 use windmill_queue::register_metric;
 
-use serde_json::{json, value::RawValue};
+#[cfg(feature = "prometheus")]
+use windmill_common::METRICS_ENABLED;
+
+use serde_json::{json, value::RawValue, Value};
 
 use tokio::{
     sync::{
@@ -39,8 +43,10 @@ use tokio::{
 use windmill_queue::{add_completed_job, add_completed_job_error};
 
 use crate::{
+    analytics_export::export_job_analytics,
     bash_executor::ANSI_ESCAPE_RE,
-    common::{read_result, save_in_cache},
+    common::{read_result, save_in_cache, take_job_secrets},
+    job_notification::send_completion_notification,
     worker_flow::update_flow_status_after_job_completion,
     AuthedClient, JobCompleted, JobCompletedSender, SameWorkerSender, SendResult, INIT_SCRIPT_TAG,
 };
@@ -62,6 +68,23 @@ pub fn start_background_processor<R>(
 where
     R: rsmq_async::RsmqConnection + Send + Sync + Clone + 'static,
 {
+    #[cfg(feature = "prometheus")]
+    let job_end_to_end_duration = if METRICS_ENABLED.load(Ordering::Relaxed) {
+        Some(
+            prometheus::register_histogram_vec!(
+                prometheus::HistogramOpts::new(
+                    "job_end_to_end_seconds",
+                    "Duration between the creation of a job (including queue time) and its completion",
+                )
+                .const_label("name", &worker_name),
+                &["workspace", "language", "job_kind"]
+            )
+            .expect("register prometheus metric"),
+        )
+    } else {
+        None
+    };
+
     tokio::spawn(async move {
         let mut has_been_killed = false;
 
@@ -90,6 +113,20 @@ where
                         JobKind::Dependencies | JobKind::FlowDependencies
                     );
 
+                    #[cfg(feature = "prometheus")]
+                    let end_to_end_labels = job_end_to_end_duration.as_ref().map(|_| {
+                        (
+                            jc.job.created_at,
+                            jc.job.workspace_id.clone(),
+                            jc.job
+                                .language
+                                .as_ref()
+                                .map(|l| l.as_str())
+                                .unwrap_or("none"),
+                            format!("{:?}", jc.job.job_kind).to_lowercase(),
+                        )
+                    });
+
                     handle_receive_completed_job(
                         jc,
                         &base_internal_url,
@@ -104,6 +141,16 @@ where
                     )
                     .await;
 
+                    #[cfg(feature = "prometheus")]
+                    if let (Some(histogram), Some((created_at, w_id, language, job_kind))) =
+                        (job_end_to_end_duration.as_ref(), end_to_end_labels)
+                    {
+                        let duration = (chrono::Utc::now() - created_at).num_milliseconds() as f64;
+                        histogram
+                            .with_label_values(&[&w_id, language, &job_kind])
+                            .observe(duration / 1000.0);
+                    }
+
                     if is_init_script_and_failure {
                         tracing::error!("init script errored, exiting");
                         killpill_tx.send(()).unwrap_or_default();
@@ -185,17 +232,96 @@ where
     })
 }
 
+lazy_static::lazy_static! {
+    /// replace the token of the job and the secrets resolved for its args (`$var:` of secret
+    /// variables and `$encrypted:`) by `***` in its result before it is persisted, so that a
+    /// script returning its environment or its args does not leak them.
+    /// Disabled with SCRUB_TOKEN_FROM_RESULT=false
+    static ref SCRUB_TOKEN_FROM_RESULT: bool = std::env::var("SCRUB_TOKEN_FROM_RESULT")
+        .ok()
+        .map(|x| x != "false" && x != "0")
+        .unwrap_or(true);
+}
+
+/// Replace every occurrence of `token` and of the `secrets` in the result, in strings and keys at
+/// any depth, by `***`
+fn scrub_secrets_from_result(
+    result: Arc<Box<RawValue>>,
+    token: &str,
+    secrets: &HashSet<String>,
+) -> Arc<Box<RawValue>> {
+    if !*SCRUB_TOKEN_FROM_RESULT {
+        return result;
+    }
+    // only the secrets that appear, as encoded inside a json string, are replaced. The longest
+    // first so that a secret containing another one is replaced whole
+    let mut secrets = std::iter::once(token)
+        .chain(secrets.iter().map(String::as_str))
+        .filter(|secret| {
+            !secret.is_empty()
+                && serde_json::to_string(secret)
+                    .is_ok_and(|encoded| result.get().contains(encoded.trim_matches('"')))
+        })
+        .collect::<Vec<_>>();
+    if secrets.is_empty() {
+        return result;
+    }
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+
+    fn scrub(s: &str, secrets: &[&str]) -> String {
+        secrets
+            .iter()
+            .fold(s.to_string(), |s, secret| s.replace(secret, "***"))
+    }
+    fn scrub_value(value: Value, secrets: &[&str]) -> Value {
+        match value {
+            Value::String(s) => Value::String(scrub(&s, secrets)),
+            Value::Array(values) => Value::Array(
+                values
+                    .into_iter()
+                    .map(|v| scrub_value(v, secrets))
+                    .collect(),
+            ),
+            Value::Object(values) => Value::Object(
+                values
+                    .into_iter()
+                    .map(|(k, v)| (scrub(&k, secrets), scrub_value(v, secrets)))
+                    .collect(),
+            ),
+            value => value,
+        }
+    }
+    match serde_json::from_str::<Value>(result.get()) {
+        Ok(value) => Arc::new(to_raw_value(&scrub_value(value, &secrets))),
+        Err(e) => {
+            tracing::error!("could not scrub the secrets from the result, redacting it all: {e}");
+            Arc::new(to_raw_value(&json!("***")))
+        }
+    }
+}
+
 async fn send_job_completed(
     job_completed_tx: JobCompletedSender,
     job: Arc<QueuedJob>,
     result: Arc<Box<RawValue>>,
     mem_peak: i32,
     canceled_by: Option<CanceledBy>,
+    records: JobRunRecords,
     success: bool,
     cached_res_path: Option<String>,
     token: String,
 ) {
-    let jc = JobCompleted { job, result, mem_peak, canceled_by, success, cached_res_path, token };
+    let result = scrub_secrets_from_result(result, &token, &take_job_secrets(&job.id));
+    let jc = JobCompleted {
+        job,
+        result,
+        mem_peak,
+        canceled_by,
+        records,
+        success,
+        cached_res_path,
+        token,
+    };
     job_completed_tx.send(jc).await.expect("send job completed")
 }
 
@@ -206,6 +332,7 @@ pub async fn process_result(
     job_completed_tx: JobCompletedSender,
     mem_peak: i32,
     canceled_by: Option<CanceledBy>,
+    records: JobRunRecords,
     cached_res_path: Option<String>,
     token: String,
     column_order: Option<Vec<String>>,
@@ -262,6 +389,7 @@ pub async fn process_result(
                 r,
                 mem_peak,
                 canceled_by,
+                records,
                 true,
                 cached_res_path,
                 token,
@@ -277,16 +405,29 @@ pub async fn process_result(
                     if res.as_ref().is_some_and(|x| !x.get().is_empty()) {
                         res.unwrap()
                     } else {
-                        let last_10_log_lines = sqlx::query_scalar!(
-                            "SELECT right(logs, 600) FROM job_logs WHERE job_id = $1 AND workspace_id = $2 ORDER BY created_at DESC LIMIT 1",
-                            &job.id,
-                            &job.workspace_id
-                        ).fetch_one(db).await.ok().flatten().unwrap_or("".to_string());
+                        // compressed logs can only be cut once decompressed
+                        let logs_expr = if LOG_COMPRESSION_THRESHOLD.is_some() {
+                            "logs"
+                        } else {
+                            "right(logs, 600)"
+                        };
+                        let last_10_log_lines = sqlx::query_scalar::<_, Option<String>>(&format!(
+                            "SELECT {logs_expr} FROM job_logs WHERE job_id = $1 AND workspace_id = $2 ORDER BY created_at DESC LIMIT 1"
+                        ))
+                        .bind(&job.id)
+                        .bind(&job.workspace_id)
+                        .fetch_one(db).await.ok().flatten().unwrap_or("".to_string());
+                        let last_10_log_lines = decompress_log_chunks(&last_10_log_lines);
+                        let last_10_log_lines = last_10_log_lines
+                            .char_indices()
+                            .rev()
+                            .nth(599)
+                            .map_or(&*last_10_log_lines, |(i, _)| &last_10_log_lines[i..]);
 
                         let log_lines = last_10_log_lines
                             .split("CODE EXECUTION ---")
                             .last()
-                            .unwrap_or(&last_10_log_lines);
+                            .unwrap_or(last_10_log_lines);
 
                         extract_error_value(log_lines, i, job.flow_step_id.clone())
                     }
@@ -305,6 +446,7 @@ pub async fn process_result(
                 Arc::new(to_raw_value(&error_value)),
                 mem_peak,
                 canceled_by,
+                records,
                 false,
                 cached_res_path,
                 token,
@@ -375,7 +517,9 @@ pub async fn handle_receive_completed_job<
 
 #[tracing::instrument(name = "completed_job", level = "info", skip_all, fields(job_id = %job.id))]
 pub async fn process_completed_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
-    JobCompleted { job, result, mem_peak, success, cached_res_path, canceled_by, .. }: JobCompleted,
+    JobCompleted {
+        job, result, mem_peak, success, cached_res_path, canceled_by, records, ..
+    }: JobCompleted,
     client: &AuthedClient,
     db: &DB,
     worker_dir: &str,
@@ -385,6 +529,7 @@ pub async fn process_completed_job<R: rsmq_async::RsmqConnection + Send + Sync +
     job_completed_tx: Sender<SendResult>,
     #[cfg(feature = "benchmark")] bench: &mut BenchmarkIter,
 ) -> windmill_common::error::Result<()> {
+    export_job_analytics(&job, success, &result);
     if success {
         // println!("bef completed job{:?}",  SystemTime::now());
         if let Some(cached_path) = cached_res_path {
@@ -404,12 +549,14 @@ pub async fn process_completed_job<R: rsmq_async::RsmqConnection + Send + Sync +
             Json(&result),
             mem_peak.to_owned(),
             canceled_by,
+            records,
             rsmq.clone(),
             false,
             #[cfg(feature = "benchmark")]
             bench,
         )
         .await?;
+        send_completion_notification(db, client, &job, true);
         drop(job);
 
         add_time!(bench, "add_completed_job END");
@@ -445,6 +592,7 @@ pub async fn process_completed_job<R: rsmq_async::RsmqConnection + Send + Sync +
             &job,
             mem_peak.to_owned(),
             canceled_by,
+            records,
             serde_json::from_str(result.get()).unwrap_or_else(
                 |_| json!({ "message": format!("Non serializable error: {}", result.get()) }),
             ),
@@ -455,6 +603,7 @@ pub async fn process_completed_job<R: rsmq_async::RsmqConnection + Send + Sync +
             bench,
         )
         .await?;
+        send_completion_notification(db, client, &job, false);
         if job.is_flow_step {
             if let Some(parent_job) = job.parent_job {
                 tracing::error!(parent_flow = %parent_job, subflow = %job.id, "process completed job error, updating flow status");
@@ -499,6 +648,8 @@ pub async fn handle_job_error<R: rsmq_async::RsmqConnection + Send + Sync + Clon
     job_completed_tx: Sender<SendResult>,
     #[cfg(feature = "benchmark")] bench: &mut BenchmarkIter,
 ) {
+    // the job failed before its result was processed, its secrets are not needed anymore
+    take_job_secrets(&job.id);
     let err = match err {
         Error::JsonErr(err) => err,
         _ => json!({"message": err.to_string(), "name": "InternalErr"}),
@@ -518,6 +669,7 @@ pub async fn handle_job_error<R: rsmq_async::RsmqConnection + Send + Sync + Clon
             job,
             mem_peak,
             canceled_by.clone(),
+            JobRunRecords::default(),
             err.clone(),
             rsmq_2,
             worker_name,
@@ -581,6 +733,7 @@ pub async fn handle_job_error<R: rsmq_async::RsmqConnection + Send + Sync + Clon
                         &parent_job,
                         mem_peak,
                         canceled_by.clone(),
+                        JobRunRecords::default(),
                         e,
                         rsmq,
                         worker_name,
@@ -623,3 +776,35 @@ pub fn extract_error_value(log_lines: &str, i: i32, step_id: Option<String>) ->
         exit_code: Some(i),
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scrub_secrets_from_result() {
+        let result = Arc::new(to_raw_value(&json!({
+            "token": "tok",
+            "nested": [{"password": "hunter2", "msg": "login hunter2 with tok"}],
+            "hunter2": 12,
+            "kept": "hunter",
+        })));
+        let secrets = HashSet::from(["hunter2".to_string(), "12".to_string()]);
+        let scrubbed = scrub_secrets_from_result(result, "tok", &secrets);
+        let scrubbed: Value = serde_json::from_str(scrubbed.get()).unwrap();
+        // numbers are not strings of the result and are kept
+        assert_eq!(
+            scrubbed,
+            json!({
+                "token": "***",
+                "nested": [{"password": "***", "msg": "login *** with ***"}],
+                "***": 12,
+                "kept": "hunter",
+            })
+        );
+
+        let result = Arc::new(to_raw_value(&json!({"a": 1})));
+        let unchanged = scrub_secrets_from_result(result.clone(), "tok", &secrets);
+        assert!(Arc::ptr_eq(&result, &unchanged));
+    }
+}
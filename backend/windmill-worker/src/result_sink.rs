@@ -0,0 +1,162 @@
+use std::{collections::HashMap, sync::Mutex, time::Duration};
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::{sync::mpsc, task::JoinHandle};
+use uuid::Uuid;
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, RESULT_SINK_ARG},
+    DB,
+};
+use windmill_queue::HTTP_CLIENT;
+
+use crate::{common::transform_json_value, AuthedClientBackgroundTask};
+
+/// Lines printed by the job with this prefix are result items: json values forwarded to the
+/// result sink of the job instead of being appended to its logs
+pub const RESULT_ITEM_PREFIX: &str = "result_item:";
+
+/// maximum number of items posted to the sink in a single request
+const MAX_ITEMS_PER_REQUEST: usize = 500;
+
+lazy_static::lazy_static! {
+    /// number of result items buffered between the job output and the sink
+    static ref RESULT_SINK_BUFFER_SIZE: usize = std::env::var("RESULT_SINK_BUFFER_SIZE")
+        .ok()
+        .and_then(|x| x.parse::<usize>().ok())
+        .filter(|x| *x > 0)
+        .unwrap_or(1000);
+
+    /// how long, in seconds, reading the job output can be blocked by a full buffer, and a
+    /// request to the sink can take, before the job is failed
+    pub static ref RESULT_SINK_TIMEOUT: Duration = Duration::from_secs(
+        std::env::var("RESULT_SINK_TIMEOUT")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(30),
+    );
+
+    /// senders of the sinks of the running jobs, picked up by `handle_child` for the run phase
+    static ref RESULT_SINKS: Mutex<HashMap<Uuid, mpsc::Sender<String>>> = Mutex::new(HashMap::new());
+}
+
+/// Sink resource, the items are posted as NDJSON to `url` with the optional `headers`
+/// (e.g. an `Authorization` header)
+#[derive(Deserialize, Debug)]
+struct ResultSinkTarget {
+    url: String,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+/// Forwarding task of the sink of a running job, see [`start_result_sink`]. Dropping it without
+/// [`ResultSink::finish`], when the job returns early, unregisters the sink and stops the task
+pub struct ResultSink {
+    job_id: Uuid,
+    task: JoinHandle<error::Result<u64>>,
+}
+
+/// Resolve the sink set in the `_RESULT_SINK` arg of the job, if any, and start forwarding the
+/// result items of the job to it
+pub async fn start_result_sink(
+    db: &DB,
+    client: &AuthedClientBackgroundTask,
+    job: &QueuedJob,
+) -> error::Result<Option<ResultSink>> {
+    let Some(target) = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(RESULT_SINK_ARG))
+        .and_then(|x| serde_json::from_str::<Value>(x.get()).ok())
+    else {
+        return Ok(None);
+    };
+
+    let resource = transform_json_value(
+        RESULT_SINK_ARG,
+        &client.get_authed().await,
+        &job.workspace_id,
+        target,
+        job,
+        db,
+    )
+    .await?;
+    let target = serde_json::from_value::<ResultSinkTarget>(resource).map_err(|e| {
+        Error::BadRequest(format!(
+            "`{RESULT_SINK_ARG}` is not a supported result sink: {e}"
+        ))
+    })?;
+
+    let (tx, rx) = mpsc::channel::<String>(*RESULT_SINK_BUFFER_SIZE);
+    RESULT_SINKS.lock().unwrap().insert(job.id, tx);
+    Ok(Some(ResultSink {
+        job_id: job.id,
+        task: tokio::spawn(forward_items(target, rx)),
+    }))
+}
+
+/// Sender of the result sink of the job, if it has one
+pub fn result_sink_sender(job_id: &Uuid) -> Option<mpsc::Sender<String>> {
+    RESULT_SINKS.lock().unwrap().get(job_id).cloned()
+}
+
+impl ResultSink {
+    /// Wait for the buffered items to be forwarded and return how many items were sent in total
+    pub async fn finish(mut self) -> error::Result<u64> {
+        // dropping the last sender ends the forwarding task once the buffer is drained
+        RESULT_SINKS.lock().unwrap().remove(&self.job_id);
+        (&mut self.task)
+            .await
+            .map_err(|e| Error::InternalErr(format!("result sink task: {e}")))?
+    }
+}
+
+impl Drop for ResultSink {
+    fn drop(&mut self) {
+        RESULT_SINKS.lock().unwrap().remove(&self.job_id);
+        self.task.abort();
+    }
+}
+
+async fn forward_items(
+    target: ResultSinkTarget,
+    mut rx: mpsc::Receiver<String>,
+) -> error::Result<u64> {
+    let mut sent = 0;
+    while let Some(item) = rx.recv().await {
+        let mut body = item;
+        let mut batch_len = 1;
+        while batch_len < MAX_ITEMS_PER_REQUEST {
+            match rx.try_recv() {
+                Ok(item) => {
+                    body.push('\n');
+                    body.push_str(&item);
+                    batch_len += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        body.push('\n');
+
+        let mut request = HTTP_CLIENT
+            .post(&target.url)
+            .header("Content-Type", "application/x-ndjson")
+            .timeout(*RESULT_SINK_TIMEOUT)
+            .body(body);
+        for (k, v) in &target.headers {
+            request = request.header(k, v);
+        }
+        request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                Error::ExecutionErr(format!(
+                    "result sink failed after {sent} forwarded result items: {e}"
+                ))
+            })?;
+        sent += batch_len as u64;
+    }
+    Ok(sent)
+}
@@ -1,5 +1,5 @@
 use serde_json::value::RawValue;
-use std::{collections::HashMap, path::Path, process::Stdio};
+use std::{collections::HashMap, path::Path, process::Stdio, sync::Arc};
 use uuid::Uuid;
 use windmill_parser_rust::parse_rust_deps_into_manifest;
 
@@ -11,14 +11,15 @@ use windmill_common::{
     utils::calculate_hash,
     worker::{save_cache, write_file},
 };
-use windmill_queue::{append_logs, CanceledBy};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords};
 
 use crate::{
     common::{
         create_args_and_out_file, get_reserved_variables, read_result, start_child_process,
         OccupancyMetrics,
     },
-    handle_child::handle_child,
+    handle_child::{handle_child, ChildPhase},
+    sandbox_profile::apply_sandbox_profile,
     AuthedClientBackgroundTask, DISABLE_NSJAIL, DISABLE_NUSER, HOME_ENV, NSJAIL_PATH, PATH_ENV,
     PROXY_ENVS, RUST_CACHE_DIR, TZ_ENV,
 };
@@ -126,8 +127,10 @@ pub async fn generate_cargo_lockfile(
     code: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     w_id: &str,
     occupancy_metrics: &mut OccupancyMetrics,
@@ -154,13 +157,16 @@ pub async fn generate_cargo_lockfile(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         gen_lockfile_process,
         false,
         worker_name,
         w_id,
         "cargo generate-lockfile",
+        ChildPhase::DependencyInstall,
         None,
         false,
         &mut Some(occupancy_metrics),
@@ -178,8 +184,10 @@ pub async fn build_rust_crate(
     job_id: &Uuid,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     w_id: &str,
     base_internal_url: &str,
@@ -215,13 +223,16 @@ pub async fn build_rust_crate(
     handle_child(
         job_id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         build_rust_process,
         false,
         worker_name,
         w_id,
         "rust build",
+        ChildPhase::Run,
         None,
         false,
         &mut Some(occupancy_metrics),
@@ -292,8 +303,10 @@ fn check_cargo_exists() -> Result<(), Error> {
 pub async fn handle_rust_job(
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     inner_content: &str,
     job_dir: &str,
@@ -346,8 +359,10 @@ pub async fn handle_rust_job(
             &job.id,
             mem_peak,
             canceled_by,
+            run_records,
             job_dir,
             db,
+            job_queue,
             worker_name,
             &job.workspace_id,
             base_internal_url,
@@ -361,13 +376,14 @@ pub async fn handle_rust_job(
     append_logs(&job.id, &job.workspace_id, logs2, db).await;
 
     let client = &client.get_authed().await;
-    let reserved_variables = get_reserved_variables(job, &client.token, db).await?;
+    let reserved_variables =
+        get_reserved_variables(job, &client.token, db, job_dir, worker_name).await?;
 
     let child = if !*DISABLE_NSJAIL {
         let _ = write_file(
             job_dir,
             "run.config.proto",
-            &NSJAIL_CONFIG_RUN_RUST_CONTENT
+            &apply_sandbox_profile(NSJAIL_CONFIG_RUN_RUST_CONTENT, job)?
                 .replace("{JOB_DIR}", job_dir)
                 .replace("{CACHE_DIR}", RUST_CACHE_DIR)
                 .replace("{CLONE_NEWUSER}", &(!*DISABLE_NUSER).to_string())
@@ -409,13 +425,16 @@ pub async fn handle_rust_job(
     handle_child(
         &job.id,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
+        run_records,
         child,
         !*DISABLE_NSJAIL,
         worker_name,
         &job.workspace_id,
         "rust run",
+        ChildPhase::Run,
         job.timeout,
         false,
         &mut Some(occupancy_metrics),
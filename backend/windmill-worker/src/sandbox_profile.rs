@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, SANDBOX_PROFILE_ARG, SECCOMP_POLICY_ARG},
+};
+
+/// A named set of nsjail settings a job can opt into with the `_SANDBOX_PROFILE` arg.
+/// Profiles only apply when nsjail is enabled.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SandboxProfile {
+    /// whether the job can access the network
+    #[serde(default = "default_network")]
+    pub network: bool,
+    /// raw nsjail config appended to the language config, e.g. additional mounts for /dev access
+    #[serde(default)]
+    pub extra_config: String,
+    /// mount /tmp read-only so that, the root being read-only already, scripts can only write to
+    /// the files of the job dir and to `writable_tmpfs`
+    #[serde(default)]
+    pub read_only_root: bool,
+    /// additional writable tmpfs mounts (e.g. `/tmp/scratch`, `/tmp/.cache`)
+    #[serde(default)]
+    pub writable_tmpfs: Vec<String>,
+    /// seccomp policy of the jobs using the profile, `DEFAULT_SECCOMP_POLICY` if not set
+    #[serde(default)]
+    pub seccomp_policy: Option<String>,
+}
+
+fn default_network() -> bool {
+    true
+}
+
+impl Default for SandboxProfile {
+    fn default() -> Self {
+        Self {
+            network: true,
+            extra_config: String::new(),
+            read_only_root: false,
+            writable_tmpfs: vec![],
+            seccomp_policy: None,
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    /// built-in profiles, extended/overridden by the operator-defined ones in SANDBOX_PROFILES
+    /// (e.g. `{"gpu": {"extra_config": "mount { src: \"/dev/nvidia0\" ... }"}}`)
+    static ref SANDBOX_PROFILES: HashMap<String, SandboxProfile> = {
+        let mut profiles = HashMap::from([
+            ("default".to_string(), SandboxProfile::default()),
+            ("network".to_string(), SandboxProfile::default()),
+            ("strict".to_string(), SandboxProfile { network: false, ..SandboxProfile::default() }),
+            (
+                "read-only".to_string(),
+                SandboxProfile {
+                    read_only_root: true,
+                    writable_tmpfs: vec!["/tmp/scratch".to_string()],
+                    ..SandboxProfile::default()
+                },
+            ),
+        ]);
+        if let Ok(custom) = std::env::var("SANDBOX_PROFILES") {
+            match serde_json::from_str::<HashMap<String, SandboxProfile>>(&custom) {
+                Ok(custom) => profiles.extend(custom),
+                Err(e) => tracing::error!("Invalid SANDBOX_PROFILES, ignoring it: {e}"),
+            }
+        }
+        profiles
+    };
+
+    /// profile used by the jobs that do not pick one
+    static ref DEFAULT_SANDBOX_PROFILE: String = std::env::var("DEFAULT_SANDBOX_PROFILE")
+        .unwrap_or_else(|_| "default".to_string());
+
+    /// profiles jobs can pick with `_SANDBOX_PROFILE` besides the default one, comma-separated
+    /// (e.g. `strict,read-only`). Jobs cannot pick any profile when not set
+    static ref ALLOWED_SANDBOX_PROFILES: Vec<String> = allowlist_from_env("ALLOWED_SANDBOX_PROFILES");
+
+    /// named seccomp policies, in nsjail's kafel syntax, extended/overridden by the
+    /// operator-defined ones in SECCOMP_POLICIES
+    /// (e.g. `{"no-net": "ERRNO(1) { socket, connect } DEFAULT ALLOW"}`). An empty policy
+    /// disables the syscall filter
+    static ref SECCOMP_POLICIES: HashMap<String, String> = {
+        let mut policies = HashMap::from([
+            ("restricted".to_string(), RESTRICTED_SECCOMP_POLICY.to_string()),
+            ("permissive".to_string(), String::new()),
+        ]);
+        if let Ok(custom) = std::env::var("SECCOMP_POLICIES") {
+            match serde_json::from_str::<HashMap<String, String>>(&custom) {
+                Ok(custom) => policies.extend(custom),
+                Err(e) => tracing::error!("Invalid SECCOMP_POLICIES, ignoring it: {e}"),
+            }
+        }
+        policies
+    };
+
+    /// seccomp policy of the jobs whose job and sandbox profile do not pick one. No syscall
+    /// filter by default, set it to `restricted` to opt in
+    static ref DEFAULT_SECCOMP_POLICY: String = std::env::var("DEFAULT_SECCOMP_POLICY")
+        .unwrap_or_else(|_| "permissive".to_string());
+
+    /// policies jobs can pick with `_SECCOMP_POLICY` besides the one their sandbox profile or
+    /// DEFAULT_SECCOMP_POLICY gives them, comma-separated (e.g. `restricted,no-net`). Jobs
+    /// cannot pick any policy, `permissive` included, when not set
+    static ref ALLOWED_SECCOMP_POLICIES: Vec<String> = allowlist_from_env("ALLOWED_SECCOMP_POLICIES");
+}
+
+/// Denies the syscalls that let a job inspect other processes or alter the kernel and the mounts,
+/// everything else is allowed
+const RESTRICTED_SECCOMP_POLICY: &str = "ERRNO(1) { ptrace, process_vm_readv, process_vm_writev, \
+kexec_load, kexec_file_load, init_module, finit_module, delete_module, mount, umount2, pivot_root, \
+swapon, swapoff, reboot, bpf, perf_event_open, userfaultfd, keyctl, add_key, request_key } \
+DEFAULT ALLOW";
+
+fn allowlist_from_env(var: &str) -> Vec<String> {
+    std::env::var(var)
+        .map(|x| {
+            x.split(',')
+                .map(|x| x.trim().to_string())
+                .filter(|x| !x.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn get_sandbox_profile(job: &QueuedJob) -> error::Result<&'static SandboxProfile> {
+    let name = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(SANDBOX_PROFILE_ARG))
+        .map(|x| serde_json::from_str::<String>(x.get()))
+        .transpose()
+        .map_err(|e| Error::BadRequest(format!("`{SANDBOX_PROFILE_ARG}` must be a string: {e}")))?;
+    pick_sandbox_profile(
+        name,
+        &DEFAULT_SANDBOX_PROFILE,
+        &ALLOWED_SANDBOX_PROFILES,
+        &SANDBOX_PROFILES,
+    )
+}
+
+fn pick_sandbox_profile<'a>(
+    name: Option<String>,
+    default: &str,
+    allowed: &[String],
+    profiles: &'a HashMap<String, SandboxProfile>,
+) -> error::Result<&'a SandboxProfile> {
+    let name = match name {
+        Some(name) if name != default && !allowed.contains(&name) => {
+            return Err(Error::BadRequest(format!(
+                "Sandbox profile `{name}` is not allowed, jobs can only pick `{default}` or the profiles in ALLOWED_SANDBOX_PROFILES"
+            )))
+        }
+        Some(name) => name,
+        None => default.to_string(),
+    };
+
+    profiles.get(&name).ok_or_else(|| {
+        Error::BadRequest(format!(
+            "Unknown sandbox profile `{name}`, available profiles are: {}",
+            profiles.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+    })
+}
+
+fn get_seccomp_policy(job: &QueuedJob, profile: &SandboxProfile) -> error::Result<&'static str> {
+    let name = job
+        .args
+        .as_ref()
+        .and_then(|args| args.0.get(SECCOMP_POLICY_ARG))
+        .map(|x| serde_json::from_str::<String>(x.get()))
+        .transpose()
+        .map_err(|e| Error::BadRequest(format!("`{SECCOMP_POLICY_ARG}` must be a string: {e}")))?;
+    let fallback = profile
+        .seccomp_policy
+        .clone()
+        .unwrap_or_else(|| DEFAULT_SECCOMP_POLICY.clone());
+    pick_seccomp_policy(
+        name,
+        &fallback,
+        &ALLOWED_SECCOMP_POLICIES,
+        &SECCOMP_POLICIES,
+    )
+}
+
+fn pick_seccomp_policy<'a>(
+    name: Option<String>,
+    fallback: &str,
+    allowed: &[String],
+    policies: &'a HashMap<String, String>,
+) -> error::Result<&'a str> {
+    let name = match name {
+        Some(name) if name != fallback && !allowed.contains(&name) => {
+            return Err(Error::BadRequest(format!(
+                "Seccomp policy `{name}` is not allowed, jobs can only pick `{fallback}` or the policies in ALLOWED_SECCOMP_POLICIES"
+            )))
+        }
+        Some(name) => name,
+        None => fallback.to_string(),
+    };
+
+    let policy = policies.get(&name).ok_or_else(|| {
+        Error::BadRequest(format!(
+            "Unknown seccomp policy `{name}`, available policies are: {}",
+            policies.keys().cloned().collect::<Vec<_>>().join(", ")
+        ))
+    })?;
+    check_seccomp_policy(policy)
+        .map_err(|e| Error::BadConfig(format!("Invalid seccomp policy `{name}`: {e}")))?;
+    Ok(policy)
+}
+
+/// Catch the obvious mistakes in a policy, nsjail being the one to parse it and failing the job
+/// otherwise with a less explicit error
+fn check_seccomp_policy(policy: &str) -> Result<(), String> {
+    let mut depth = Vec::new();
+    for c in policy.chars() {
+        match c {
+            '{' | '(' => depth.push(c),
+            '}' | ')' => {
+                let expected = if c == '}' { '{' } else { '(' };
+                if depth.pop() != Some(expected) {
+                    return Err(format!("unbalanced `{c}`"));
+                }
+            }
+            _ => (),
+        }
+    }
+    if let Some(c) = depth.pop() {
+        return Err(format!("unclosed `{c}`"));
+    }
+    Ok(())
+}
+
+/// Fill the sandbox profile placeholders of a `run.*.config.proto` template with the profile
+/// selected by the job
+pub fn apply_sandbox_profile(config: &str, job: &QueuedJob) -> error::Result<String> {
+    let profile = get_sandbox_profile(job)?;
+    let seccomp_policy = get_seccomp_policy(job, profile)?;
+    Ok(render_sandbox_profile(config, profile, seccomp_policy))
+}
+
+fn render_sandbox_profile(config: &str, profile: &SandboxProfile, seccomp_policy: &str) -> String {
+    let writable_tmpfs = profile
+        .writable_tmpfs
+        .iter()
+        .map(|dst| {
+            format!(
+                r#"
+mount {{
+    dst: "{dst}"
+    fstype: "tmpfs"
+    rw: true
+    options: "size=500000000"
+}}
+"#
+            )
+        })
+        .collect::<String>();
+    let seccomp = if seccomp_policy.trim().is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\nseccomp_string: \"{}\"\n",
+            seccomp_policy
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        )
+    };
+    config
+        .replace("{CLONE_NEWNET}", &(!profile.network).to_string())
+        .replace("{TMP_RW}", &(!profile.read_only_root).to_string())
+        .replace(
+            "{SANDBOX_PROFILE_EXTRA}",
+            &format!("{writable_tmpfs}{seccomp}{}", profile.extra_config),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn job_with_args(args: &[(&str, &str)]) -> QueuedJob {
+        QueuedJob {
+            args: Some(sqlx::types::Json(
+                args.iter()
+                    .map(|(k, v)| (k.to_string(), serde_json::value::to_raw_value(v).unwrap()))
+                    .collect(),
+            )),
+            ..QueuedJob::default()
+        }
+    }
+
+    fn profiles() -> HashMap<String, SandboxProfile> {
+        HashMap::from([
+            ("default".to_string(), SandboxProfile::default()),
+            (
+                "strict".to_string(),
+                SandboxProfile { network: false, ..SandboxProfile::default() },
+            ),
+        ])
+    }
+
+    fn policies() -> HashMap<String, String> {
+        HashMap::from([
+            (
+                "restricted".to_string(),
+                RESTRICTED_SECCOMP_POLICY.to_string(),
+            ),
+            ("permissive".to_string(), String::new()),
+        ])
+    }
+
+    #[test]
+    fn test_sandbox_profile_allowlist() {
+        let profiles = profiles();
+        let allowed = vec!["strict".to_string()];
+
+        let profile = pick_sandbox_profile(None, "default", &[], &profiles).unwrap();
+        assert!(profile.network);
+        let profile = pick_sandbox_profile(Some("default".to_string()), "default", &[], &profiles);
+        assert!(profile.unwrap().network);
+
+        assert!(
+            pick_sandbox_profile(Some("strict".to_string()), "default", &[], &profiles).is_err()
+        );
+        let profile =
+            pick_sandbox_profile(Some("strict".to_string()), "default", &allowed, &profiles);
+        assert!(!profile.unwrap().network);
+
+        // allowed but not defined
+        let allowed = vec!["gpu".to_string()];
+        assert!(
+            pick_sandbox_profile(Some("gpu".to_string()), "default", &allowed, &profiles).is_err()
+        );
+    }
+
+    #[test]
+    fn test_seccomp_policy_allowlist() {
+        let policies = policies();
+        let allowed = vec!["restricted".to_string()];
+
+        assert_eq!(
+            pick_seccomp_policy(None, "permissive", &[], &policies).unwrap(),
+            ""
+        );
+        assert_eq!(
+            pick_seccomp_policy(None, "restricted", &[], &policies).unwrap(),
+            RESTRICTED_SECCOMP_POLICY
+        );
+
+        // jobs cannot opt out of the filter of their profile unless `permissive` is allowed
+        assert!(pick_seccomp_policy(
+            Some("permissive".to_string()),
+            "restricted",
+            &allowed,
+            &policies
+        )
+        .is_err());
+        assert!(
+            pick_seccomp_policy(Some("restricted".to_string()), "permissive", &[], &policies)
+                .is_err()
+        );
+        assert_eq!(
+            pick_seccomp_policy(
+                Some("restricted".to_string()),
+                "permissive",
+                &allowed,
+                &policies
+            )
+            .unwrap(),
+            RESTRICTED_SECCOMP_POLICY
+        );
+
+        let invalid = HashMap::from([("invalid".to_string(), "ERRNO(1) { ptrace".to_string())]);
+        assert!(pick_seccomp_policy(None, "invalid", &[], &invalid).is_err());
+    }
+
+    #[test]
+    fn test_sandbox_profile_from_job_args() {
+        // no SANDBOX_PROFILES, ALLOWED_SANDBOX_PROFILES nor seccomp env vars in tests
+        let profile = get_sandbox_profile(&QueuedJob::default()).unwrap();
+        assert!(profile.network && !profile.read_only_root);
+        assert_eq!(
+            get_seccomp_policy(&QueuedJob::default(), profile).unwrap(),
+            ""
+        );
+
+        assert!(get_sandbox_profile(&job_with_args(&[(SANDBOX_PROFILE_ARG, "strict")])).is_err());
+        assert!(get_seccomp_policy(
+            &job_with_args(&[(SECCOMP_POLICY_ARG, "restricted")]),
+            profile
+        )
+        .is_err());
+        let not_a_string = QueuedJob {
+            args: Some(sqlx::types::Json(HashMap::from([(
+                SANDBOX_PROFILE_ARG.to_string(),
+                serde_json::value::to_raw_value(&1).unwrap(),
+            )]))),
+            ..QueuedJob::default()
+        };
+        assert!(get_sandbox_profile(&not_a_string).is_err());
+    }
+
+    #[test]
+    fn test_check_seccomp_policy() {
+        assert!(check_seccomp_policy(RESTRICTED_SECCOMP_POLICY).is_ok());
+        assert!(check_seccomp_policy("").is_ok());
+        assert_eq!(
+            check_seccomp_policy("ERRNO(1) { ptrace } }").unwrap_err(),
+            "unbalanced `}`"
+        );
+        assert_eq!(
+            check_seccomp_policy("ERRNO(1 { ptrace )}").unwrap_err(),
+            "unbalanced `)`"
+        );
+        assert_eq!(
+            check_seccomp_policy("ERRNO(1) { ptrace").unwrap_err(),
+            "unclosed `{`"
+        );
+        assert_eq!(check_seccomp_policy("ERRNO(1").unwrap_err(), "unclosed `(`");
+    }
+
+    #[test]
+    fn test_render_sandbox_profile() {
+        let config = "clone_newnet: {CLONE_NEWNET}\nrw: {TMP_RW}\n{SANDBOX_PROFILE_EXTRA}";
+
+        let rendered = render_sandbox_profile(config, &SandboxProfile::default(), "");
+        assert_eq!(rendered, "clone_newnet: false\nrw: true\n");
+
+        let profile = SandboxProfile {
+            network: false,
+            read_only_root: true,
+            writable_tmpfs: vec!["/tmp/scratch".to_string()],
+            extra_config: "mount { src: \"/dev/nvidia0\" }".to_string(),
+            seccomp_policy: None,
+        };
+        let rendered =
+            render_sandbox_profile(config, &profile, "ERRNO(1) { \"ptrace\" }\\\nDEFAULT ALLOW");
+        assert!(rendered.starts_with("clone_newnet: true\nrw: false\n"));
+        assert!(rendered.contains("dst: \"/tmp/scratch\""));
+        assert!(rendered
+            .contains("seccomp_string: \"ERRNO(1) { \\\"ptrace\\\" }\\\\\\nDEFAULT ALLOW\"\n"));
+        assert!(rendered.ends_with("mount { src: \"/dev/nvidia0\" }"));
+    }
+}
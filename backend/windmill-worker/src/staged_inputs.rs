@@ -0,0 +1,359 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+use bytes::Bytes;
+use futures::{stream, Stream, StreamExt, TryStreamExt};
+use reqwest::{redirect::Policy, Response, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::io::AsyncWriteExt;
+use windmill_common::{
+    error::{self, Error},
+    jobs::{QueuedJob, INPUTS_ARG},
+    DB,
+};
+
+use crate::{common::transform_json_value, AuthedClient, AuthedClientBackgroundTask};
+
+/// dir of the job dir the inputs are fetched into
+const INPUTS_DIR: &str = "inputs";
+
+const MAX_CONCURRENT_INPUT_FETCHES: usize = 4;
+
+lazy_static::lazy_static! {
+    /// size above which the fetch of an input fails
+    static ref INPUTS_MAX_SIZE_MB: u64 = std::env::var("INPUTS_MAX_SIZE_MB")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok())
+        .unwrap_or(1024);
+}
+
+/// One entry of the `_INPUTS` arg of a job, the name of the entry being the name of the file in
+/// the `inputs` dir of the job dir. The file is fetched from either:
+///
+/// - `url`: an http(s) url, with optional `headers`. Urls resolving to loopback, private or
+///   link-local addresses are refused and redirects are not followed
+/// - `s3`: a file of the object storage of the workspace, `storage` picking a secondary storage
+/// - `resource`: a `$res:` path, resolved with the permissions of the job and written as JSON
+///
+/// ```json
+/// {"dataset": {"url": "https://example.com/data.csv"}, "model": {"s3": "models/model.bin"}}
+/// ```
+///
+/// The paths of the files, relative to the working dir of the job, are set by name in the
+/// `WM_INPUTS` env variable as JSON (e.g. `{"dataset": "inputs/dataset"}`).
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum InputSource {
+    Url {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    S3 {
+        s3: String,
+        storage: Option<String>,
+    },
+    Resource {
+        resource: String,
+    },
+}
+
+fn get_inputs(job: &QueuedJob) -> error::Result<Option<HashMap<String, InputSource>>> {
+    let Some(inputs) = job.args.as_ref().and_then(|args| args.0.get(INPUTS_ARG)) else {
+        return Ok(None);
+    };
+    let inputs =
+        serde_json::from_str::<HashMap<String, InputSource>>(inputs.get()).map_err(|e| {
+            Error::BadRequest(format!(
+                "`{INPUTS_ARG}` must map the name of each input to a {{\"url\"}}, {{\"s3\"}} or {{\"resource\"}} object: {e}"
+            ))
+        })?;
+    for name in inputs.keys() {
+        let valid = !name.is_empty()
+            && !name.starts_with('.')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+        if !valid {
+            return Err(Error::BadRequest(format!(
+                "`{INPUTS_ARG}`: `{name}` is not a valid input name, only letters, digits, `_`, `-` and `.` are allowed"
+            )));
+        }
+    }
+    Ok(Some(inputs))
+}
+
+/// Fetch the inputs of the `_INPUTS` arg of the job, if any, into the job dir. Fails on the first
+/// input that cannot be fetched
+pub async fn stage_inputs(
+    db: &DB,
+    client: &AuthedClientBackgroundTask,
+    job: &QueuedJob,
+    job_dir: &str,
+) -> error::Result<()> {
+    let Some(inputs) = get_inputs(job)? else {
+        return Ok(());
+    };
+    tokio::fs::create_dir_all(format!("{job_dir}/{INPUTS_DIR}")).await?;
+    let client = &client.get_authed().await;
+    stream::iter(inputs)
+        .map(|(name, source)| async move {
+            let path = format!("{job_dir}/{INPUTS_DIR}/{name}");
+            fetch_input(db, client, job, &path, source)
+                .await
+                .map_err(|e| Error::ExecutionErr(format!("Could not fetch input `{name}`: {e}")))
+        })
+        .buffer_unordered(MAX_CONCURRENT_INPUT_FETCHES)
+        .try_collect::<Vec<()>>()
+        .await?;
+    Ok(())
+}
+
+async fn fetch_input(
+    db: &DB,
+    client: &AuthedClient,
+    job: &QueuedJob,
+    path: &str,
+    source: InputSource,
+) -> error::Result<()> {
+    match source {
+        InputSource::Url { url, headers } => {
+            let (client, url) = public_url_client(&url).await?;
+            let mut request = client.get(url);
+            for (k, v) in headers {
+                request = request.header(k, v);
+            }
+            let response = request
+                .send()
+                .await
+                .map_err(|e| Error::ExecutionErr(e.to_string()))?;
+            write_response(response, path).await
+        }
+        InputSource::S3 { s3, storage } => {
+            let url = format!(
+                "{}/api/w/{}/job_helpers/download_s3_file",
+                client.base_internal_url, job.workspace_id
+            );
+            let mut query = vec![("file_key", s3)];
+            if let Some(storage) = storage {
+                query.push(("storage", storage));
+            }
+            let response = client.get(&url, query).await?;
+            write_response(response, path).await
+        }
+        InputSource::Resource { resource } => {
+            let value = transform_json_value(
+                INPUTS_ARG,
+                client,
+                &job.workspace_id,
+                Value::String(resource),
+                job,
+                db,
+            )
+            .await?;
+            tokio::fs::write(path, value.to_string()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Whether a url input must not reach the address: loopback, private, link-local (where the
+/// cloud metadata endpoints are) and the like
+fn is_denied_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                // shared address space, 100.64.0.0/10
+                || (a == 100 && b & 0xc0 == 64)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_denied_ip(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_multicast()
+                    // unique local, fc00::/7, and link-local, fe80::/10
+                    || first & 0xfe00 == 0xfc00
+                    || first & 0xffc0 == 0xfe80
+            }
+        },
+    }
+}
+
+/// Client for a url input, pinned to the addresses its host resolved to once they are checked
+/// so that the host cannot resolve to another one when connecting
+async fn public_url_client(url: &str) -> error::Result<(reqwest::Client, Url)> {
+    let url = Url::parse(url).map_err(|e| Error::BadRequest(format!("Invalid url: {e}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(Error::BadRequest(format!(
+            "Unsupported url scheme `{}`, only http and https are allowed",
+            url.scheme()
+        )));
+    }
+    let builder = reqwest::ClientBuilder::new()
+        .user_agent("windmill/beta")
+        .timeout(std::time::Duration::from_secs(20))
+        .connect_timeout(std::time::Duration::from_secs(10))
+        .redirect(Policy::none());
+    let host = url
+        .host_str()
+        .ok_or_else(|| Error::BadRequest("Url has no host".to_string()))?;
+    let builder = match host
+        .trim_matches(|c| c == '[' || c == ']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) if is_denied_ip(ip) => {
+            return Err(Error::BadRequest(format!("`{host}` is a private address")))
+        }
+        Ok(_) => builder,
+        Err(_) => {
+            let port = url.port_or_known_default().unwrap_or(443);
+            let addrs = tokio::net::lookup_host((host, port))
+                .await
+                .map_err(|e| Error::ExecutionErr(format!("Could not resolve `{host}`: {e}")))?
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() || addrs.iter().any(|addr| is_denied_ip(addr.ip())) {
+                return Err(Error::BadRequest(format!(
+                    "`{host}` resolves to a private address"
+                )));
+            }
+            builder.resolve_to_addrs(host, &addrs)
+        }
+    };
+    let client = builder
+        .build()
+        .map_err(|e| Error::InternalErr(format!("Could not build http client: {e}")))?;
+    Ok((client, url))
+}
+
+async fn write_response(response: Response, path: &str) -> error::Result<()> {
+    let status = response.status();
+    if !status.is_success() {
+        return Err(Error::ExecutionErr(format!(
+            "{status}: {}",
+            response.text().await.unwrap_or_default()
+        )));
+    }
+    let max_size = *INPUTS_MAX_SIZE_MB * 1024 * 1024;
+    if response.content_length().is_some_and(|x| x > max_size) {
+        return Err(too_large_error());
+    }
+    write_stream(response.bytes_stream(), path, max_size).await
+}
+
+fn too_large_error() -> Error {
+    Error::ExecutionErr(format!(
+        "Input is larger than the {}MB limit (INPUTS_MAX_SIZE_MB)",
+        *INPUTS_MAX_SIZE_MB
+    ))
+}
+
+/// Write the chunks to the file, failing and removing it as soon as they exceed `max_size`
+async fn write_stream<E: std::fmt::Display>(
+    chunks: impl Stream<Item = Result<Bytes, E>>,
+    path: &str,
+    max_size: u64,
+) -> error::Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    let mut chunks = std::pin::pin!(chunks);
+    let mut size = 0u64;
+    while let Some(chunk) = chunks
+        .try_next()
+        .await
+        .map_err(|e| Error::ExecutionErr(e.to_string()))?
+    {
+        size += chunk.len() as u64;
+        if size > max_size {
+            drop(file);
+            let _ = tokio::fs::remove_file(path).await;
+            return Err(too_large_error());
+        }
+        file.write_all(&chunk).await?;
+    }
+    file.flush().await?;
+    Ok(())
+}
+
+/// `WM_INPUTS`, the paths of the staged inputs of the job by name, if it declared any
+pub fn staged_inputs_env(job: &QueuedJob) -> Option<String> {
+    let paths = get_inputs(job)
+        .ok()
+        .flatten()?
+        .into_keys()
+        .map(|name| {
+            let path = format!("{INPUTS_DIR}/{name}");
+            (name, path)
+        })
+        .collect::<HashMap<_, _>>();
+    serde_json::to_string(&paths).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_denied_ip() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "::",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:169.254.169.254",
+        ] {
+            assert!(is_denied_ip(ip.parse().unwrap()), "{ip} should be denied");
+        }
+        for ip in ["1.1.1.1", "100.128.0.1", "2606:4700:4700::1111"] {
+            assert!(!is_denied_ip(ip.parse().unwrap()), "{ip} should be allowed");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_public_url_client_refuses_private_hosts() {
+        for url in [
+            "http://127.0.0.1/data.csv",
+            "http://169.254.169.254/latest/meta-data",
+            "http://[::1]:8000/",
+            "http://localhost:8000/",
+            "file:///etc/passwd",
+        ] {
+            assert!(
+                public_url_client(url).await.is_err(),
+                "{url} should be refused"
+            );
+        }
+        assert!(public_url_client("https://1.1.1.1/data.csv").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_write_stream_max_size() {
+        let path = std::env::temp_dir().join(format!("staged_input_{}", uuid::Uuid::new_v4()));
+        let path = path.to_str().unwrap();
+        let chunks = || {
+            stream::iter(["abcd", "efgh", "ij"].map(|x| Ok::<_, std::io::Error>(Bytes::from(x))))
+        };
+
+        write_stream(chunks(), path, 10).await.unwrap();
+        assert_eq!(tokio::fs::read_to_string(path).await.unwrap(), "abcdefghij");
+
+        assert!(write_stream(chunks(), path, 9).await.is_err());
+        assert!(!std::path::Path::new(path).exists());
+    }
+}
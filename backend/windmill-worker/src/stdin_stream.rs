@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use tokio::{io::AsyncWriteExt, process::ChildStdin};
+use uuid::Uuid;
+use windmill_common::{
+    jobs::{QueuedJob, STDIN_STREAM_ARG},
+    DB,
+};
+
+/// interval at which the input sent to a running job is polled
+const STDIN_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Whether the job keeps the stdin of its process open for the input sent to it while it runs
+pub fn stdin_stream_enabled(job: &QueuedJob) -> bool {
+    job.args
+        .as_ref()
+        .and_then(|args| args.0.get(STDIN_STREAM_ARG))
+        .is_some_and(|x| x.get() == "true")
+}
+
+/// Write the lines sent to the job to the stdin of its process, newline terminated and in the
+/// order they were sent. A job is ready for input as soon as it is queued: the lines sent before
+/// its process started are written once it starts. Stdin is closed, and the script reads EOF, once
+/// the end of the input is sent. Returns then, or when the process closed its stdin
+pub async fn stream_stdin(db: DB, job_id: Uuid, mut stdin: ChildStdin) {
+    let mut last_id = 0;
+    loop {
+        let lines = sqlx::query_as::<_, (i64, String, bool)>(
+            "SELECT id, line, eof FROM job_stdin WHERE job_id = $1 AND id > $2 ORDER BY id",
+        )
+        .bind(job_id)
+        .bind(last_id)
+        .fetch_all(&db)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!(%job_id, "could not fetch the input of the job: {e:#}");
+            vec![]
+        });
+        for (id, line, eof) in lines {
+            last_id = id;
+            if eof {
+                // dropping stdin closes it
+                return;
+            }
+            if let Err(e) = stdin.write_all(format!("{line}\n").as_bytes()).await {
+                tracing::warn!(%job_id, "could not write to the stdin of the job: {e:#}");
+                return;
+            }
+        }
+        if let Err(e) = stdin.flush().await {
+            tracing::warn!(%job_id, "could not write to the stdin of the job: {e:#}");
+            return;
+        }
+        tokio::time::sleep(STDIN_POLL_INTERVAL).await;
+    }
+}
+
+/// Remove the input sent to a job whose process exited
+pub async fn clear_stdin(db: &DB, job_id: &Uuid) {
+    if let Err(e) = sqlx::query("DELETE FROM job_stdin WHERE job_id = $1")
+        .bind(job_id)
+        .execute(db)
+        .await
+    {
+        tracing::error!(%job_id, "could not remove the input of the job: {e:#}");
+    }
+}
@@ -0,0 +1,146 @@
+use std::{
+    sync::{Arc, Once},
+    time::Duration,
+};
+
+use chrono::Utc;
+use tokio::{sync::watch, time::Instant};
+use uuid::Uuid;
+use windmill_common::{error, DB};
+use windmill_queue::{append_logs, get_queued_job, JobQueue, HTTP_CLIENT};
+
+lazy_static::lazy_static! {
+    /// file whose creation signals that the host is about to be terminated, e.g. written by a spot
+    /// interruption handler
+    static ref TERMINATION_NOTICE_FILE: Option<String> = std::env::var("SPOT_TERMINATION_NOTICE_FILE")
+        .ok()
+        .filter(|x| !x.is_empty());
+
+    /// metadata endpoint polled for a termination notice, any successful response being a notice
+    /// (e.g. `http://169.254.169.254/latest/meta-data/spot/instance-action` on AWS)
+    static ref TERMINATION_NOTICE_URL: Option<String> = std::env::var("SPOT_TERMINATION_NOTICE_URL")
+        .ok()
+        .filter(|x| !x.is_empty());
+
+    static ref TERMINATION_NOTICE_POLL_INTERVAL: Duration = Duration::from_secs(
+        std::env::var("SPOT_TERMINATION_NOTICE_POLL_INTERVAL_SECS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .filter(|x| *x > 0)
+            .unwrap_or(5),
+    );
+
+    /// time between the notice and the termination of the host
+    static ref TERMINATION_GRACE: Duration = Duration::from_secs(
+        std::env::var("SPOT_TERMINATION_GRACE_SECS")
+            .ok()
+            .and_then(|x| x.parse::<u64>().ok())
+            .unwrap_or(30),
+    );
+
+    /// expected termination time of the host, set once a notice has been received
+    static ref TERMINATION_DEADLINE: watch::Sender<Option<Instant>> = watch::channel(None).0;
+}
+
+static START_WATCHER: Once = Once::new();
+
+/// Start watching for a termination notice, once per process, if a notice file or url is set
+pub fn start_termination_notice_watcher() {
+    if TERMINATION_NOTICE_FILE.is_none() && TERMINATION_NOTICE_URL.is_none() {
+        return;
+    }
+    START_WATCHER.call_once(|| {
+        tokio::spawn(async {
+            loop {
+                if termination_notice_received().await {
+                    let deadline = Instant::now() + *TERMINATION_GRACE;
+                    tracing::warn!(
+                        "termination notice received, the host is expected to be terminated in {}s",
+                        TERMINATION_GRACE.as_secs()
+                    );
+                    TERMINATION_DEADLINE.send_replace(Some(deadline));
+                    return;
+                }
+                tokio::time::sleep(*TERMINATION_NOTICE_POLL_INTERVAL).await;
+            }
+        });
+    });
+}
+
+async fn termination_notice_received() -> bool {
+    if let Some(path) = TERMINATION_NOTICE_FILE.as_ref() {
+        if tokio::fs::metadata(path).await.is_ok() {
+            return true;
+        }
+    }
+    if let Some(url) = TERMINATION_NOTICE_URL.as_ref() {
+        match HTTP_CLIENT
+            .get(url)
+            .timeout(Duration::from_secs(2))
+            .send()
+            .await
+        {
+            Ok(r) if r.status().is_success() => return true,
+            Ok(_) => (),
+            Err(e) => tracing::debug!("could not poll termination notice url {url}: {e:#}"),
+        }
+    }
+    false
+}
+
+/// Whether a termination notice has been received, in which case no job must be pulled anymore
+pub fn is_terminating() -> bool {
+    TERMINATION_DEADLINE.borrow().is_some()
+}
+
+/// Completes when the job started at `started_at`, that is killed after `timeout`, cannot be
+/// guaranteed to finish before the host is terminated. Never completes if no notice is received,
+/// nor for the `same_worker` jobs, which need the flow dir of this worker and cannot be moved
+pub async fn wait_for_requeue_on_termination(
+    db: &DB,
+    job_id: Uuid,
+    started_at: Instant,
+    timeout: Duration,
+) {
+    let mut rx = TERMINATION_DEADLINE.subscribe();
+    let deadline = match rx.wait_for(|x| x.is_some()).await {
+        Ok(deadline) => deadline.expect("waited for a deadline"),
+        Err(_) => return futures::future::pending().await,
+    };
+    if started_at + timeout <= deadline {
+        tracing::info!("termination notice received, the running job fits in the remaining time");
+        futures::future::pending::<()>().await;
+    }
+    let same_worker = sqlx::query_scalar::<_, bool>("SELECT same_worker FROM queue WHERE id = $1")
+        .bind(job_id)
+        .fetch_optional(db)
+        .await
+        .map_or(true, |x| x.unwrap_or(true));
+    if same_worker {
+        tracing::warn!(
+            "termination notice received, job {job_id} runs on the same worker as its flow and is not re-queued"
+        );
+        futures::future::pending::<()>().await;
+    }
+}
+
+/// Put a job interrupted by the termination of the host back in the queue so that another worker
+/// picks it up right away
+pub async fn requeue_job(
+    db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
+    job_id: &Uuid,
+    w_id: &str,
+) -> error::Result<()> {
+    let Some(job) = get_queued_job(job_id, w_id, db).await? else {
+        return Ok(());
+    };
+    append_logs(
+        job_id,
+        w_id,
+        "\n\nhost is being terminated, job re-queued to be run by another worker\n",
+        db,
+    )
+    .await;
+    job_queue.requeue(&job, Utc::now()).await
+}
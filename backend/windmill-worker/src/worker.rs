@@ -35,7 +35,7 @@ use std::{
     hash::Hash,
     sync::{
         atomic::{AtomicBool, AtomicU16, Ordering},
-        Arc,
+        Arc, Mutex,
     },
     time::Duration,
 };
@@ -45,17 +45,19 @@ use uuid::Uuid;
 use windmill_common::{
     error::{self, to_anyhow, Error},
     get_latest_deployed_hash_for_path,
-    jobs::{JobKind, QueuedJob},
+    jobs::{JobKind, QueuedJob, LOG_LEVEL_ARG},
+    schedule::ScheduleOverlapPolicy,
     scripts::{get_full_hub_script_by_path, ScriptHash, ScriptLang, PREVIEW_IS_CODEBASE_HASH},
     users::SUPERADMIN_SECRET_EMAIL,
     utils::StripPath,
-    worker::{update_ping, CLOUD_HOSTED, NO_LOGS, WORKER_CONFIG, WORKER_GROUP},
+    worker::{update_ping, CLOUD_HOSTED, NO_LOGS, WORKER_CONFIG, WORKER_EXEC_ENVS, WORKER_GROUP},
     DB, IS_READY,
 };
 
 use windmill_queue::{
-    append_logs, canceled_job_to_result, empty_result, pull, push, CanceledBy, PushArgs,
-    PushIsolationLevel, HTTP_CLIENT,
+    add_completed_job, append_logs, cancel_job, canceled_job_to_result, empty_result,
+    finish_inline_job, push, requeue_job, start_inline_job, CanceledBy, JobQueue, JobRunRecords,
+    PushArgs, PushIsolationLevel, HTTP_CLIENT,
 };
 
 #[cfg(feature = "prometheus")]
@@ -84,11 +86,14 @@ use crate::{
     ansible_executor::handle_ansible_job,
     bash_executor::{handle_bash_job, handle_powershell_job},
     bun_executor::handle_bun_job,
+    cache_eviction::release_cache_entries,
     common::{
-        build_args_map, get_cached_resource_value_if_valid, get_reserved_variables, hash_args,
-        update_worker_ping_for_failed_init_script, OccupancyMetrics,
+        build_args_map, check_script_integrity, get_cached_resource_value_if_valid,
+        get_reserved_variables, hash_args, update_worker_ping_for_failed_init_script,
+        OccupancyMetrics, SCRIPT_INTEGRITY_CHECK,
     },
-    deno_executor::handle_deno_job,
+    deno_executor::{handle_deno_job, warmup_deno},
+    env_from_resources::resolve_env_from_resources,
     go_executor::handle_go_job,
     graphql_executor::do_graphql,
     handle_child::SLOW_LOGS,
@@ -98,9 +103,13 @@ use crate::{
     mysql_executor::do_mysql,
     pg_executor::do_postgresql,
     php_executor::handle_php_job,
-    python_executor::handle_python_job,
+    precondition::check_precondition,
+    python_executor::{handle_python_job, warmup_python},
     result_processor::{process_result, start_background_processor},
+    result_sink::start_result_sink,
     rust_executor::handle_rust_job,
+    staged_inputs::stage_inputs,
+    termination_notice::{is_terminating, start_termination_notice_watcher},
     worker_flow::{handle_flow, update_flow_status_in_progress, Step},
     worker_lockfiles::{
         handle_app_dependency_job, handle_dependency_job, handle_flow_dependency_job,
@@ -120,11 +129,14 @@ use windmill_common::bench::{benchmark_init, BenchmarkInfo, BenchmarkIter};
 
 use windmill_common::add_time;
 
+/// Create the ephemeral token of the job in the background. The error, if the token could not be
+/// created, is set in the returned slot before the token lock is released, see [`check_job_token`]
 pub async fn create_token_for_owner_in_bg(
     db: &Pool<Postgres>,
     job: &QueuedJob,
-) -> Arc<RwLock<String>> {
+) -> (Arc<RwLock<String>>, Arc<Mutex<Option<String>>>) {
     let rw_lock = Arc::new(RwLock::new(String::new()));
+    let token_error = Arc::new(Mutex::new(None));
     // skipping test runs
     if job.workspace_id != "" {
         let mut locked = rw_lock.clone().write_owned().await;
@@ -133,6 +145,7 @@ pub async fn create_token_for_owner_in_bg(
         let owner = job.permissioned_as.clone();
         let email = job.email.clone();
         let job_id = job.id.clone();
+        let token_error = token_error.clone();
 
         let label = if job.permissioned_as != format!("u/{}", job.created_by)
             && job.permissioned_as != job.created_by
@@ -142,7 +155,7 @@ pub async fn create_token_for_owner_in_bg(
             "ephemeral-script".to_string()
         };
         tokio::spawn(async move {
-            let token = create_token_for_owner(
+            match create_token_for_owner(
                 &db.clone(),
                 &w_id,
                 &owner,
@@ -152,11 +165,110 @@ pub async fn create_token_for_owner_in_bg(
                 &job_id,
             )
             .await
-            .expect("could not create job token");
-            *locked = token;
+            {
+                Ok(token) => *locked = token,
+                Err(e) => {
+                    tracing::error!(%job_id, "could not create job token: {e:#}");
+                    *token_error.lock().unwrap() = Some(e.to_string());
+                }
+            }
         });
     };
-    return rw_lock;
+    return (rw_lock, token_error);
+}
+
+/// The token of a job is only needed by the scripts calling the api and to resolve the `$res:` and
+/// `$var:` args. A script job whose token could not be created fails with the reason, unless
+/// PROCEED_WITHOUT_JOB_TOKEN is set, in which case it runs without token
+async fn check_job_token(
+    job: &QueuedJob,
+    client: &AuthedClientBackgroundTask,
+    db: &DB,
+) -> error::Result<()> {
+    // waits for the token to be created
+    drop(client.token.read().await);
+    let Some(e) = client.token_error.lock().unwrap().clone() else {
+        return Ok(());
+    };
+    if *PROCEED_WITHOUT_JOB_TOKEN {
+        append_logs(
+            &job.id,
+            &job.workspace_id,
+            format!(
+                "\nWARNING: could not create the token of the job ({e}), running it without token: \
+                 the calls to the windmill api and the `$res:`/`$var:` args will fail\n"
+            ),
+            db,
+        )
+        .await;
+        Ok(())
+    } else {
+        Err(Error::BadConfig(format!(
+            "Could not create the token the job authenticates with to the api: {e}. Check the auth \
+             configuration of the instance (JWT secret, permissions of {}), or set \
+             PROCEED_WITHOUT_JOB_TOKEN=true to run the scripts that do not need it without token",
+            job.permissioned_as
+        )))
+    }
+}
+
+/// File of the job dir holding the current ephemeral token of the job, see [`spawn_token_rotation`]
+pub const TOKEN_FILE: &str = "wm_token";
+
+/// The ephemeral token of a job expires after SCRIPT_TOKEN_EXPIRY. For the jobs running longer than
+/// that, a fresh token is created before expiry and written to the token file, which scripts can
+/// re-read (`WM_TOKEN_FILE`) since the `WM_TOKEN` env variable of a running process cannot be updated.
+/// The worker-side client is updated as well.
+fn spawn_token_rotation(
+    job: &QueuedJob,
+    db: &DB,
+    client: &AuthedClientBackgroundTask,
+    job_dir: &str,
+) -> TokenRotation {
+    let db = db.clone();
+    let token = client.token.clone();
+    let token_file = format!("{job_dir}/{TOKEN_FILE}");
+    let (w_id, owner, email, job_id) = (
+        job.workspace_id.clone(),
+        job.permissioned_as.clone(),
+        job.email.clone(),
+        job.id,
+    );
+    TokenRotation(tokio::spawn(async move {
+        let renew_every = Duration::from_secs((*SCRIPT_TOKEN_EXPIRY * 3 / 4).max(1));
+        loop {
+            if let Err(e) = tokio::fs::write(&token_file, token.read().await.as_bytes()).await {
+                tracing::error!(%job_id, "could not write token file: {e:#}");
+            }
+            tokio::time::sleep(renew_every).await;
+            match create_token_for_owner(
+                &db,
+                &w_id,
+                &owner,
+                "ephemeral-script",
+                *SCRIPT_TOKEN_EXPIRY,
+                &email,
+                &job_id,
+            )
+            .await
+            {
+                Ok(new_token) => {
+                    tracing::info!(%job_id, "rotated the ephemeral token of long running job");
+                    *token.write().await = new_token;
+                }
+                Err(e) => tracing::error!(%job_id, "could not rotate job token: {e:#}"),
+            }
+        }
+    }))
+}
+
+/// Stops the token rotation of the job when dropped, the job returning early on errors included
+struct TokenRotation(tokio::task::JoinHandle<()>);
+
+impl Drop for TokenRotation {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
 }
 
 #[tracing::instrument(level = "trace", skip_all)]
@@ -253,7 +365,8 @@ pub const GO_BIN_CACHE_DIR: &str = concatcp!(ROOT_CACHE_DIR, "gobin");
 pub const POWERSHELL_CACHE_DIR: &str = concatcp!(ROOT_CACHE_DIR, "powershell");
 pub const COMPOSER_CACHE_DIR: &str = concatcp!(ROOT_CACHE_DIR, "composer");
 
-const NUM_SECS_PING: u64 = 5;
+/// interval of the worker pings, also the interval at which the running jobs ping their worker
+pub const NUM_SECS_PING: u64 = 5;
 const NUM_SECS_READINGS: u64 = 60;
 
 const INCLUDE_DEPS_PY_SH_CONTENT: &str = include_str!("../nsjail/download_deps.py.sh");
@@ -296,6 +409,14 @@ lazy_static::lazy_static! {
 
     pub static ref JOB_TOKEN: Option<String> = std::env::var("JOB_TOKEN").ok();
 
+    /// delay before a job of a schedule with the `queue` overlap policy is pulled again while the
+    /// previous run of the schedule is still running
+    static ref SCHEDULE_OVERLAP_REQUEUE_DELAY_SECS: i64 = std::env::var("SCHEDULE_OVERLAP_REQUEUE_DELAY_SECS")
+        .ok()
+        .and_then(|x| x.parse::<i64>().ok())
+        .filter(|x| *x >= 0)
+        .unwrap_or(5);
+
     pub static ref SLEEP_QUEUE: u64 = std::env::var("SLEEP_QUEUE")
     .ok()
     .and_then(|x| x.parse::<u64>().ok())
@@ -316,11 +437,34 @@ lazy_static::lazy_static! {
         .and_then(|x| x.parse::<bool>().ok())
         .unwrap_or(true);
 
+    /// umask of the job processes and of the job dir, e.g. `077` for the files created by the
+    /// jobs to only be accessible to their user. Inherited from the worker if not set
+    pub static ref JOB_UMASK: Option<u32> = std::env::var("JOB_UMASK").ok().and_then(|x| {
+        match u32::from_str_radix(x.trim(), 8) {
+            Ok(umask) if umask <= 0o777 => Some(umask),
+            _ => {
+                tracing::error!("Invalid JOB_UMASK `{x}`, it must be an octal mode such as 077");
+                None
+            }
+        }
+    });
+
     pub static ref KEEP_JOB_DIR: AtomicBool = AtomicBool::new(std::env::var("KEEP_JOB_DIR")
         .ok()
         .and_then(|x| x.parse::<bool>().ok())
         .unwrap_or(false));
 
+    /// default level of the worker's own logs while handling a job, overridable per job with `_LOG_LEVEL`
+    pub static ref JOB_LOG_LEVEL: Option<&'static str> = std::env::var("JOB_LOG_LEVEL")
+        .ok()
+        .and_then(|x| parse_log_level(&x));
+
+    /// append the wrapper generated around the user code to the job logs, to debug arg coercion
+    pub static ref LOG_GENERATED_WRAPPER: bool = std::env::var("LOG_GENERATED_WRAPPER")
+        .ok()
+        .and_then(|x| x.parse::<bool>().ok())
+        .unwrap_or(false);
+
     pub static ref NO_PROXY: Option<String> = std::env::var("no_proxy").ok().or(std::env::var("NO_PROXY").ok());
     pub static ref HTTP_PROXY: Option<String> = std::env::var("http_proxy").ok().or(std::env::var("HTTP_PROXY").ok());
     pub static ref HTTPS_PROXY: Option<String> = std::env::var("https_proxy").ok().or(std::env::var("HTTPS_PROXY").ok());
@@ -339,6 +483,10 @@ lazy_static::lazy_static! {
         proxy_env
     };
     pub static ref DENO_PATH: String = std::env::var("DENO_PATH").unwrap_or_else(|_| "/usr/bin/deno".to_string());
+    /// V8 heap limit of the deno scripts, in MB. Unset, the V8 default applies
+    pub static ref DENO_MAX_HEAP_SIZE_MB: Option<u64> = std::env::var("DENO_MAX_HEAP_SIZE_MB")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok());
     pub static ref BUN_PATH: String = std::env::var("BUN_PATH").unwrap_or_else(|_| "/usr/bin/bun".to_string());
     pub static ref NPM_PATH: String = std::env::var("NPM_PATH").unwrap_or_else(|_| "/usr/bin/npm".to_string());
     pub static ref NODE_BIN_PATH: String = std::env::var("NODE_BIN_PATH").unwrap_or_else(|_| "/usr/bin/node".to_string());
@@ -380,6 +528,41 @@ lazy_static::lazy_static! {
 
     pub static ref MAX_TIMEOUT_DURATION: Duration = Duration::from_secs(*MAX_TIMEOUT);
 
+    /// timeout of the dependency install/compile phases, defaults to
+    /// DEPENDENCY_TIMEOUT_MULTIPLIER times the execution timeout of the job. Capped at TIMEOUT
+    pub static ref DEPENDENCY_TIMEOUT: Option<u64> = std::env::var("DEPENDENCY_TIMEOUT")
+        .ok()
+        .and_then(|x| x.parse::<u64>().ok());
+
+    pub static ref DEPENDENCY_TIMEOUT_MULTIPLIER: u32 = std::env::var("DEPENDENCY_TIMEOUT_MULTIPLIER")
+        .ok()
+        .and_then(|x| x.parse::<u32>().ok())
+        .unwrap_or(3);
+
+    /// pipe the args to the stdin of the python and deno wrappers instead of writing them to the
+    /// args.json file of the job dir
+    pub static ref ARGS_VIA_STDIN: bool = std::env::var("ARGS_VIA_STDIN")
+        .ok()
+        .is_some_and(|x| x == "1" || x == "true");
+
+    /// run the script jobs whose ephemeral token could not be created without token instead of
+    /// failing them, for the scripts not calling the api
+    static ref PROCEED_WITHOUT_JOB_TOKEN: bool = std::env::var("PROCEED_WITHOUT_JOB_TOKEN")
+        .ok()
+        .is_some_and(|x| x == "1" || x == "true");
+
+    /// runtimes warmed up by the worker before it pulls its first job, comma separated among
+    /// `python3` and `deno`. None by default
+    static ref WORKER_WARMUP: Vec<String> = std::env::var("WORKER_WARMUP")
+        .ok()
+        .map(|x| {
+            x.split(',')
+                .map(|x| x.trim().to_lowercase())
+                .filter(|x| !x.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
     pub static ref SCRIPT_TOKEN_EXPIRY: u64 = std::env::var("SCRIPT_TOKEN_EXPIRY")
         .ok()
         .and_then(|x| x.parse::<u64>().ok())
@@ -414,10 +597,15 @@ pub const MAX_RESULT_SIZE: usize = 1024 * 1024 * 2; // 2MB
 
 pub const INIT_SCRIPT_TAG: &str = "init_script";
 
+/// timeout, in seconds, of the warmup of each runtime of WORKER_WARMUP
+pub(crate) const WARMUP_TIMEOUT: i32 = 60;
+
 pub struct AuthedClientBackgroundTask {
     pub base_internal_url: String,
     pub workspace: String,
     pub token: Arc<RwLock<String>>,
+    /// why the token could not be created, if it could not
+    pub token_error: Arc<Mutex<Option<String>>>,
 }
 
 impl AuthedClientBackgroundTask {
@@ -698,6 +886,41 @@ fn add_outstanding_wait_time(
     }.in_current_span());
 }
 
+/// Run a trivial script with each runtime of WORKER_WARMUP, for the first job not to pay for the
+/// startup of the interpreter and the population of its cache. A failed warmup only warns
+async fn warmup_runtimes(
+    db: &Pool<Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
+    worker_name: &str,
+    worker_dir: &str,
+) {
+    let warmup_dir = format!("{worker_dir}/warmup");
+    if let Err(e) = DirBuilder::new().recursive(true).create(&warmup_dir) {
+        tracing::warn!("could not create the warmup dir, skipping the warmup: {e:#}");
+        return;
+    }
+    for runtime in WORKER_WARMUP.iter() {
+        let start = Instant::now();
+        let r = match runtime.as_str() {
+            "python3" => warmup_python(db, job_queue, worker_name, &warmup_dir).await,
+            "deno" => warmup_deno(db, job_queue, worker_name, &warmup_dir).await,
+            _ => {
+                tracing::warn!("unknown runtime `{runtime}` in WORKER_WARMUP, not warming it up");
+                continue;
+            }
+        };
+        match r {
+            Ok(()) => tracing::info!("warmed up {runtime} in {}ms", start.elapsed().as_millis()),
+            Err(e) => tracing::warn!(
+                "warmup of {runtime} failed, the first {runtime} job may be slower: {e:#}"
+            ),
+        }
+    }
+    if let Err(e) = tokio::fs::remove_dir_all(&warmup_dir).await {
+        tracing::warn!("could not remove the warmup dir: {e:#}");
+    }
+}
+
 #[tracing::instrument(name = "worker", level = "info", skip_all, fields(worker = %worker_name, hostname = %hostname))]
 pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 'static>(
     db: &Pool<Postgres>,
@@ -710,6 +933,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
     killpill_tx: tokio::sync::broadcast::Sender<()>,
     base_internal_url: &str,
     rsmq: Option<R>,
+    job_queue: Arc<dyn JobQueue>,
     agent_mode: bool,
 ) {
     #[cfg(not(feature = "enterprise"))]
@@ -721,6 +945,13 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
 
     let start_time = Instant::now();
 
+    if !WORKER_EXEC_ENVS.is_empty() {
+        tracing::info!(
+            "worker provides the execution environments: {}",
+            WORKER_EXEC_ENVS.join(", ")
+        );
+    }
+
     let worker_dir = format!("{TMP_DIR}/{worker_name}");
     tracing::debug!(worker_dir = %worker_dir, "Creating worker dir");
 
@@ -742,6 +973,10 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
         );
     }
 
+    if !WORKER_WARMUP.is_empty() {
+        warmup_runtimes(db, &job_queue, &worker_name, &worker_dir).await;
+    }
+
     let mut last_ping = Instant::now() - Duration::from_secs(NUM_SECS_PING + 1);
 
     update_ping(hostname, &worker_name, ip, db).await;
@@ -1007,6 +1242,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
         &killpill_tx,
         &killpill_rx,
         db,
+        &job_queue,
         &worker_dir,
         base_internal_url,
         &worker_name,
@@ -1054,6 +1290,9 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
     let mut last_30jobs_suspended: Vec<bool> = vec![false; 30];
     let mut last_suspend_first = Instant::now();
     let mut killed_but_draining_same_worker_jobs = false;
+    let mut paused = false;
+    let mut terminating = false;
+    start_termination_notice_watcher();
 
     loop {
         #[cfg(feature = "benchmark")]
@@ -1114,6 +1353,27 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                 tracing::error!("failed to update worker ping, exiting: {}", e);
                 killpill_tx.send(()).unwrap_or_default();
             }
+            match is_worker_paused(db, &worker_name).await {
+                Ok(is_paused) if is_paused != paused => {
+                    paused = is_paused;
+                    if paused {
+                        tracing::info!("worker {worker_name} paused, jobs are not pulled anymore until it is resumed");
+                    } else {
+                        tracing::info!("worker {worker_name} resumed");
+                    }
+                    if let Err(e) =
+                        sqlx::query("UPDATE worker_ping SET paused = $1 WHERE worker = $2")
+                            .bind(paused)
+                            .bind(&worker_name)
+                            .execute(db)
+                            .await
+                    {
+                        tracing::error!("failed to update paused state of worker ping: {e:#}");
+                    }
+                }
+                Ok(_) => (),
+                Err(e) => tracing::error!("failed to check if worker is paused: {e:#}"),
+            }
             tracing::info!(
                 "ping update, memory: container={}MB, windmill={}MB",
                 memory_usage.unwrap_or_default() / (1024 * 1024),
@@ -1214,6 +1474,16 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                     tokio::time::sleep(Duration::from_millis(200)).await;
                     continue;
                 }
+            } else if paused {
+                // same_worker jobs and killpills are still processed so that running flows
+                // complete and the worker can be stopped while paused
+                Ok(None)
+            } else if terminating || is_terminating() {
+                if !terminating {
+                    tracing::warn!("worker {worker_name} received a termination notice, jobs are not pulled anymore");
+                    terminating = true;
+                }
+                Ok(None)
             } else {
                 let pull_time = Instant::now();
                 let likelihood_of_suspend =
@@ -1226,7 +1496,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                     last_suspend_first = Instant::now();
                 }
 
-                let job = pull(&db, rsmq.clone(), suspend_first).await;
+                let job = job_queue.claim(suspend_first).await;
 
                 add_time!(bench, "job pulled from DB");
                 let duration_pull_s = pull_time.elapsed().as_secs_f64();
@@ -1337,12 +1607,13 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                             cached_res_path: None,
                             token: "".to_string(),
                             canceled_by: None,
+                            records: JobRunRecords::default(),
                         })
                         .await
                         .expect("send job completed END");
                     add_time!(bench, "sent job completed");
                 } else {
-                    let token = create_token_for_owner_in_bg(&db, &job).await;
+                    let (token, token_error) = create_token_for_owner_in_bg(&db, &job).await;
                     add_outstanding_wait_time(&job, db, OUTSTANDING_WAIT_TIME_THRESHOLD_MS);
 
                     #[cfg(feature = "prometheus")]
@@ -1399,10 +1670,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
 
                     let job_dir = format!("{worker_dir}/{}", job.id);
 
-                    DirBuilder::new()
-                        .recursive(true)
-                        .create(&job_dir)
-                        .expect("could not create job dir");
+                    create_job_dir(&job_dir).expect("could not create job dir");
 
                     let same_worker = job.same_worker;
 
@@ -1441,6 +1709,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                     let authed_client = AuthedClientBackgroundTask {
                         base_internal_url: base_internal_url.to_string(),
                         token,
+                        token_error,
                         workspace: job.workspace_id.to_string(),
                     };
 
@@ -1453,6 +1722,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                     match handle_queued_job(
                         arc_job.clone(),
                         db,
+                        &job_queue,
                         &authed_client,
                         &hostname,
                         &worker_name,
@@ -1505,6 +1775,7 @@ pub async fn run_worker<R: rsmq_async::RsmqConnection + Send + Sync + Clone + 's
                         }
                         _ => {}
                     }
+                    release_cache_entries(&arc_job.id);
 
                     #[cfg(feature = "prometheus")]
                     if let Some(duration) = _timer.map(|x| x.stop_and_record()) {
@@ -1691,6 +1962,7 @@ pub struct JobCompleted {
     pub cached_res_path: Option<String>,
     pub token: String,
     pub canceled_by: Option<CanceledBy>,
+    pub records: JobRunRecords,
 }
 
 async fn do_nativets(
@@ -1699,6 +1971,7 @@ async fn do_nativets(
     env_code: String,
     code: String,
     db: &Pool<Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
     worker_name: &str,
@@ -1719,6 +1992,7 @@ async fn do_nativets(
         job.id,
         job.timeout,
         db,
+        job_queue,
         mem_peak,
         canceled_by,
         worker_name,
@@ -1735,10 +2009,153 @@ pub struct PreviousResult<'a> {
     pub previous_result: Option<&'a RawValue>,
 }
 
-#[tracing::instrument(name = "job", level = "info", skip_all, fields(job_id = %job.id))]
+/// Runs of the schedule that are running and were claimed before the job. Two runs claimed at the
+/// same instant each see the other as running, only the one claimed last (the greatest id on a
+/// tie) must apply the overlap policy, otherwise both would be skipped or re-queued again and again
+async fn previous_schedule_runs(
+    job: &QueuedJob,
+    schedule_path: &str,
+    db: &DB,
+) -> windmill_common::error::Result<Vec<Uuid>> {
+    Ok(sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM queue WHERE schedule_path = $1 AND workspace_id = $2 AND id != $3 AND running = true AND parent_job IS NULL
+            AND (started_at, id) < ($4, $3)",
+    )
+    .bind(schedule_path)
+    .bind(&job.workspace_id)
+    .bind(job.id)
+    .bind(job.started_at.unwrap_or_else(chrono::Utc::now))
+    .fetch_all(db)
+    .await?)
+}
+
+/// Enforce the overlap policy of the schedule that triggered the job, if any.
+/// Returns true if the job must not be run now (skipped or put back in the queue)
+async fn apply_schedule_overlap_policy<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
+    job: &QueuedJob,
+    db: &DB,
+    rsmq: Option<R>,
+    #[cfg(feature = "benchmark")] bench: &mut BenchmarkIter,
+) -> windmill_common::error::Result<bool> {
+    let schedule_path = match job.schedule_path.as_ref() {
+        Some(schedule_path) if !job.is_flow_step => schedule_path,
+        _ => return Ok(false),
+    };
+    let policy = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT overlap_policy FROM schedule WHERE path = $1 AND workspace_id = $2",
+    )
+    .bind(schedule_path)
+    .bind(&job.workspace_id)
+    .fetch_optional(db)
+    .await?
+    .flatten()
+    .and_then(|x| x.parse::<ScheduleOverlapPolicy>().ok());
+    let Some(policy) = policy else {
+        return Ok(false);
+    };
+
+    let overlapping = previous_schedule_runs(job, schedule_path, db).await?;
+    if overlapping.is_empty() {
+        return Ok(false);
+    }
+    let overlapping_str = overlapping
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    match policy {
+        ScheduleOverlapPolicy::Skip => {
+            let msg = format!("skipped: not allowed to overlap with {overlapping_str}");
+            append_logs(&job.id, &job.workspace_id, format!("{msg}\n"), db).await;
+            add_completed_job(
+                db,
+                job,
+                true,
+                true,
+                Json(&serde_json::json!(msg)),
+                0,
+                None,
+                JobRunRecords::default(),
+                rsmq,
+                false,
+                #[cfg(feature = "benchmark")]
+                bench,
+            )
+            .await?;
+            Ok(true)
+        }
+        ScheduleOverlapPolicy::Queue => {
+            tracing::info!(job_id = %job.id, "schedule {schedule_path} is still running {overlapping_str}, re-queuing job");
+            let scheduled_for = chrono::Utc::now()
+                + chrono::Duration::seconds(*SCHEDULE_OVERLAP_REQUEUE_DELAY_SECS);
+            requeue_job(db, rsmq, job, scheduled_for).await?;
+            Ok(true)
+        }
+        ScheduleOverlapPolicy::CancelPrevious => {
+            for id in overlapping {
+                let tx = db.begin().await?;
+                let (tx, _) = cancel_job(
+                    "schedule",
+                    Some(format!(
+                        "superseded by {} of schedule {schedule_path}",
+                        job.id
+                    )),
+                    id,
+                    &job.workspace_id,
+                    tx,
+                    db,
+                    None,
+                    false,
+                    false,
+                )
+                .await?;
+                tx.commit().await?;
+            }
+            append_logs(
+                &job.id,
+                &job.workspace_id,
+                format!("cancelled previous run(s) of the schedule: {overlapping_str}\n"),
+                db,
+            )
+            .await;
+            Ok(false)
+        }
+    }
+}
+
+fn parse_log_level(level: &str) -> Option<&'static str> {
+    ["error", "warn", "info", "debug", "trace"]
+        .into_iter()
+        .find(|x| x.eq_ignore_ascii_case(level.trim()))
+}
+
+/// Level of the worker's own logs while handling the job, recorded on the job span
+fn job_log_level(job: &QueuedJob) -> Option<&'static str> {
+    job.args
+        .as_ref()
+        .and_then(|args| args.0.get(LOG_LEVEL_ARG))
+        .and_then(|x| serde_json::from_str::<String>(x.get()).ok())
+        .and_then(|x| parse_log_level(&x))
+        .or(*JOB_LOG_LEVEL)
+}
+
+/// Whether the worker has been paused through the `worker_pause` control table
+async fn is_worker_paused(db: &DB, worker_name: &str) -> error::Result<bool> {
+    let paused = sqlx::query_scalar::<_, bool>(
+        "SELECT EXISTS(SELECT 1 FROM worker_pause WHERE worker = $1)",
+    )
+    .bind(worker_name)
+    .fetch_one(db)
+    .await?;
+    Ok(paused)
+}
+
+#[tracing::instrument(name = "job", level = "info", skip_all, fields(job_id = %job.id, workspace_id = %job.workspace_id, log_level = job_log_level(&job)))]
 async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
     job: Arc<QueuedJob>,
     db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     hostname: &str,
     worker_name: &str,
@@ -1758,6 +2175,40 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
         return Err(Error::ExecutionErr(e.to_string()));
     }
 
+    if apply_schedule_overlap_policy(
+        &job,
+        db,
+        rsmq.clone(),
+        #[cfg(feature = "benchmark")]
+        bench,
+    )
+    .await?
+    {
+        return Ok(true);
+    }
+
+    // flow steps have `skip_if` instead
+    if !job.is_flow_step && !check_precondition(&job, client, db).await? {
+        let msg = "skipped: precondition not met";
+        append_logs(&job.id, &job.workspace_id, format!("{msg}\n"), db).await;
+        add_completed_job(
+            db,
+            &job,
+            true,
+            true,
+            Json(&serde_json::json!(msg)),
+            0,
+            None,
+            JobRunRecords::default(),
+            rsmq.clone(),
+            false,
+            #[cfg(feature = "benchmark")]
+            bench,
+        )
+        .await?;
+        return Ok(true);
+    }
+
     #[cfg(any(not(feature = "enterprise"), feature = "sqlx"))]
     if job.created_by.starts_with("email-") {
         let daily_count = sqlx::query!(
@@ -1895,6 +2346,7 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
                     success: true,
                     cached_res_path: None,
                     token: authed_client.token,
+                    records: JobRunRecords::default(),
                 })
                 .await
                 .expect("send job completed");
@@ -1919,6 +2371,7 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
         let mut logs = "".to_string();
         let mut mem_peak: i32 = 0;
         let mut canceled_by: Option<CanceledBy> = None;
+        let mut run_records = JobRunRecords::default();
         // println!("handle queue {:?}",  SystemTime::now());
 
         logs.push_str(&format!(
@@ -1960,8 +2413,10 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
                     &job,
                     &mut mem_peak,
                     &mut canceled_by,
+                    &mut run_records,
                     job_dir,
                     db,
+                    job_queue,
                     worker_name,
                     worker_dir,
                     base_internal_url,
@@ -1976,8 +2431,10 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
                     &job,
                     &mut mem_peak,
                     &mut canceled_by,
+                    &mut run_records,
                     job_dir,
                     db,
+                    job_queue,
                     worker_name,
                     worker_dir,
                     base_internal_url,
@@ -1991,8 +2448,10 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
                 &job,
                 &mut mem_peak,
                 &mut canceled_by,
+                &mut run_records,
                 job_dir,
                 db,
+                job_queue,
                 worker_name,
                 worker_dir,
                 base_internal_url,
@@ -2014,11 +2473,13 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
                 let r = handle_code_execution_job(
                     job.as_ref(),
                     db,
+                    job_queue,
                     client,
                     job_dir,
                     worker_dir,
                     &mut mem_peak,
                     &mut canceled_by,
+                    &mut run_records,
                     base_internal_url,
                     worker_name,
                     &mut column_order,
@@ -2051,6 +2512,7 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
             job_completed_tx,
             mem_peak,
             canceled_by,
+            run_records,
             cached_res_path,
             client.get_token().await,
             column_order,
@@ -2061,6 +2523,104 @@ async fn handle_queued_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
     }
 }
 
+/// Create the dir of a job, with the permissions allowed by JOB_UMASK
+fn create_job_dir(job_dir: &str) -> std::io::Result<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    if let Some(umask) = *JOB_UMASK {
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o777 & !umask);
+    }
+    builder.create(job_dir)
+}
+
+/// Settings of the worker a job run with [`execute_job_inline`] pretends to run on
+pub struct InlineJobConfig {
+    pub worker_name: String,
+    /// the job dir is created in it and removed once the job is done
+    pub worker_dir: String,
+    pub base_internal_url: String,
+    /// token the job authenticates with to the api, e.g. to fetch variables and resources
+    pub token: String,
+}
+
+/// Outcome of a job run with [`execute_job_inline`]
+pub struct InlineJobResult {
+    pub result: error::Result<Box<RawValue>>,
+    pub logs: String,
+    pub mem_peak: i32,
+}
+
+/// Run a script job on the current process, without it being pulled from or completed in the
+/// queue, and return its result and logs. Nothing about the job is written to the database, so
+/// the job must not be in the queue: it cannot be canceled and its result is not cached.
+/// Meant for the tests of the executors and for embedding them
+pub async fn execute_job_inline(
+    job: &QueuedJob,
+    db: &DB,
+    job_queue: &Arc<dyn JobQueue>,
+    config: &InlineJobConfig,
+) -> error::Result<InlineJobResult> {
+    if !matches!(
+        job.job_kind,
+        JobKind::Preview
+            | JobKind::Script
+            | JobKind::Script_Hub
+            | JobKind::DeploymentCallback
+            | JobKind::Identity
+    ) {
+        return Err(Error::BadRequest(format!(
+            "only script jobs can be run inline, not {:?} jobs",
+            job.job_kind
+        )));
+    }
+
+    let job_dir = format!("{}/{}", config.worker_dir, job.id);
+    create_job_dir(&job_dir)
+        .map_err(|e| Error::InternalErr(format!("could not create job dir {job_dir}: {e:#}")))?;
+    let client = AuthedClientBackgroundTask {
+        base_internal_url: config.base_internal_url.clone(),
+        workspace: job.workspace_id.clone(),
+        token: Arc::new(RwLock::new(config.token.clone())),
+        token_error: Arc::new(Mutex::new(None)),
+    };
+
+    start_inline_job(job.id);
+    let mut mem_peak: i32 = 0;
+    let mut canceled_by: Option<CanceledBy> = None;
+    let result = match job.job_kind {
+        JobKind::Identity => Ok(job
+            .args
+            .as_ref()
+            .and_then(|x| x.get("previous_result"))
+            .map(|x| x.to_owned())
+            .unwrap_or_else(|| serde_json::from_str("{}").unwrap())),
+        _ => {
+            handle_code_execution_job(
+                job,
+                db,
+                job_queue,
+                &client,
+                &job_dir,
+                &config.worker_dir,
+                &mut mem_peak,
+                &mut canceled_by,
+                &mut JobRunRecords::default(),
+                &config.base_internal_url,
+                &config.worker_name,
+                &mut None,
+                &mut None,
+                &mut OccupancyMetrics::new(Instant::now()),
+            )
+            .await
+        }
+    };
+    let logs = finish_inline_job(&job.id);
+    let _ = tokio::fs::remove_dir_all(&job_dir).await;
+
+    Ok(InlineJobResult { result, logs, mem_peak })
+}
+
 pub fn build_envs(
     envs: Option<Vec<String>>,
 ) -> windmill_common::error::Result<HashMap<String, String>> {
@@ -2143,16 +2703,18 @@ pub async fn get_script_content_by_hash(
             Option<String>,
             Option<ScriptLang>,
             Option<Vec<String>>,
-            Option<bool>
+            Option<bool>,
+            Option<String>,
         ),
     >(
-        "SELECT content, lock, language, envs, codebase LIKE '%.tar' as codebase FROM script WHERE hash = $1 AND workspace_id = $2",
+        "SELECT content, lock, language, envs, codebase LIKE '%.tar' as codebase, content_hash FROM script WHERE hash = $1 AND workspace_id = $2",
     )
     .bind(script_hash.0)
     .bind(w_id)
     .fetch_optional(db)
     .await?
     .ok_or_else(|| Error::InternalErr(format!("expected content and lock")))?;
+    check_script_integrity(*SCRIPT_INTEGRITY_CHECK, script_hash, &r.0, r.5.as_deref())?;
     Ok(ContentReqLangEnvs {
         content: r.0,
         lockfile: r.1,
@@ -2176,11 +2738,13 @@ pub async fn get_script_content_by_hash(
 async fn handle_code_execution_job(
     job: &QueuedJob,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     client: &AuthedClientBackgroundTask,
     job_dir: &str,
     worker_dir: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     base_internal_url: &str,
     worker_name: &str,
     column_order: &mut Option<Vec<String>>,
@@ -2231,12 +2795,19 @@ async fn handle_code_execution_job(
         ),
     };
 
+    check_job_token(job, client, db).await?;
+
+    // kept until the job completes, `get_reserved_variables` and `handle_child` pick it up
+    let _env_from_resources = resolve_env_from_resources(db, client, job).await?;
+    stage_inputs(db, client, job, job_dir).await?;
+
     if language == Some(ScriptLang::Postgresql) {
         return do_postgresql(
             job,
             &client,
             &inner_content,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
             worker_name,
@@ -2250,6 +2821,7 @@ async fn handle_code_execution_job(
             &client,
             &inner_content,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
             worker_name,
@@ -2272,6 +2844,7 @@ async fn handle_code_execution_job(
                 &client,
                 &inner_content,
                 db,
+                job_queue,
                 mem_peak,
                 canceled_by,
                 worker_name,
@@ -2295,6 +2868,7 @@ async fn handle_code_execution_job(
                 &client,
                 &inner_content,
                 db,
+                job_queue,
                 mem_peak,
                 canceled_by,
                 worker_name,
@@ -2318,6 +2892,7 @@ async fn handle_code_execution_job(
                 &client,
                 &inner_content,
                 db,
+                job_queue,
                 mem_peak,
                 canceled_by,
                 worker_name,
@@ -2331,6 +2906,7 @@ async fn handle_code_execution_job(
             &client,
             &inner_content,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
             worker_name,
@@ -2346,7 +2922,9 @@ async fn handle_code_execution_job(
         )
         .await;
 
-        let reserved_variables = get_reserved_variables(job, &client.get_token().await, db).await?;
+        let reserved_variables =
+            get_reserved_variables(job, &client.get_token().await, db, job_dir, worker_name)
+                .await?;
 
         let env_code = format!(
             "const process = {{ env: {{}} }};\nconst BASE_URL = '{base_internal_url}';\nconst BASE_INTERNAL_URL = '{base_internal_url}';\nprocess.env['BASE_URL'] = BASE_URL;process.env['BASE_INTERNAL_URL'] = BASE_INTERNAL_URL;\n{}",
@@ -2362,6 +2940,7 @@ async fn handle_code_execution_job(
             env_code,
             inner_content,
             db,
+            job_queue,
             mem_peak,
             canceled_by,
             worker_name,
@@ -2408,6 +2987,8 @@ mount {{
 
     let envs = build_envs(envs)?;
 
+    let result_sink = start_result_sink(db, client, job).await?;
+    let token_rotation = spawn_token_rotation(job, db, client, job_dir);
     let result: error::Result<Box<RawValue>> = match language {
         None => {
             return Err(Error::ExecutionErr(
@@ -2423,7 +3004,9 @@ mount {{
                 job,
                 mem_peak,
                 canceled_by,
+                run_records,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 &shared_mount,
@@ -2439,8 +3022,10 @@ mount {{
                 requirements_o,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 job_dir,
                 &inner_content,
@@ -2458,8 +3043,10 @@ mount {{
                 codebase,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 job_dir,
                 &inner_content,
@@ -2476,8 +3063,10 @@ mount {{
             handle_go_job(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 job_dir,
@@ -2494,8 +3083,10 @@ mount {{
             handle_bash_job(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 job_dir,
@@ -2511,8 +3102,10 @@ mount {{
             handle_powershell_job(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 job_dir,
@@ -2529,8 +3122,10 @@ mount {{
                 requirements_o,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 job_dir,
                 &inner_content,
@@ -2546,8 +3141,10 @@ mount {{
             handle_rust_job(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 job_dir,
@@ -2569,7 +3166,9 @@ mount {{
                 job,
                 mem_peak,
                 canceled_by,
+                run_records,
                 db,
+                job_queue,
                 client,
                 &inner_content,
                 &shared_mount,
@@ -2581,6 +3180,22 @@ mount {{
         }
         _ => panic!("unreachable, language is not supported: {language:#?}"),
     };
+    drop(token_rotation);
+    if let Some(result_sink) = result_sink {
+        // the result of the job is kept either way, a job killed because of its sink having
+        // failed with its own error
+        let msg = match result_sink.finish().await {
+            Ok(sent) => format!("\n{sent} result item(s) forwarded to the result sink\n"),
+            Err(e) => {
+                tracing::error!(job_id = %job.id, "result sink failed: {e:#}");
+                format!("\nresult sink failed: {e}\n")
+            }
+        };
+        append_logs(&job.id, &job.workspace_id, msg, db).await;
+    }
+    if *LOG_GENERATED_WRAPPER {
+        log_generated_wrapper(job, db, job_dir).await;
+    }
     tracing::info!(
         workspace_id = %job.workspace_id,
         is_ok = result.is_ok(),
@@ -2592,3 +3207,98 @@ mount {{
 
     result
 }
+
+const GENERATED_WRAPPER_FILES: &[&str] = &[
+    "wrapper.py",
+    "wrapper.ts",
+    "wrapper.mjs",
+    "wrapper.php",
+    "wrapper.sh",
+    "wrapper.ps1",
+];
+
+async fn log_generated_wrapper(job: &QueuedJob, db: &DB, job_dir: &str) {
+    for file in GENERATED_WRAPPER_FILES {
+        if let Ok(content) = tokio::fs::read_to_string(format!("{job_dir}/{file}")).await {
+            tracing::debug!(job_id = %job.id, "generated {file}:\n{content}");
+            append_logs(
+                &job.id,
+                &job.workspace_id,
+                format!("\n\n--- GENERATED {file} ---\n{content}\n--- END OF {file} ---\n"),
+                db,
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn insert_running_schedule_run(db: &DB, started_secs_ago: i64) -> QueuedJob {
+        sqlx::query_as::<_, QueuedJob>(
+            "INSERT INTO queue (id, workspace_id, created_by, scheduled_for, running, started_at, schedule_path)
+             VALUES ($1, 'test-workspace', 'test-user', now(), true, now() - ($2 || ' seconds')::interval, 'f/system/schedule')
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(started_secs_ago.to_string())
+        .fetch_one(db)
+        .await
+        .unwrap()
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../tests/fixtures", scripts("base"))
+    )]
+    async fn test_previous_schedule_runs(db: DB) {
+        let first = insert_running_schedule_run(&db, 10).await;
+        let second = insert_running_schedule_run(&db, 0).await;
+
+        assert!(previous_schedule_runs(&first, "f/system/schedule", &db)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            previous_schedule_runs(&second, "f/system/schedule", &db)
+                .await
+                .unwrap(),
+            vec![first.id]
+        );
+    }
+
+    #[sqlx::test(
+        migrations = "../migrations",
+        fixtures(path = "../../tests/fixtures", scripts("base"))
+    )]
+    async fn test_previous_schedule_runs_claimed_at_the_same_instant(db: DB) {
+        let a = insert_running_schedule_run(&db, 0).await;
+        let b = sqlx::query_as::<_, QueuedJob>(
+            "INSERT INTO queue (id, workspace_id, created_by, scheduled_for, running, started_at, schedule_path)
+             VALUES ($1, 'test-workspace', 'test-user', now(), true, $2, 'f/system/schedule')
+             RETURNING *",
+        )
+        .bind(Uuid::new_v4())
+        .bind(a.started_at)
+        .fetch_one(&db)
+        .await
+        .unwrap();
+
+        // exactly one of the two runs applies the overlap policy
+        let a_previous = previous_schedule_runs(&a, "f/system/schedule", &db)
+            .await
+            .unwrap();
+        let b_previous = previous_schedule_runs(&b, "f/system/schedule", &db)
+            .await
+            .unwrap();
+        assert_eq!(a_previous.len() + b_previous.len(), 1);
+        let last = if a.id > b.id {
+            &a_previous
+        } else {
+            &b_previous
+        };
+        assert_eq!(last.len(), 1);
+    }
+}
@@ -42,6 +42,7 @@ use windmill_common::jobs::{
     script_hash_to_tag_and_limits, script_path_to_payload, BranchResults, JobPayload, QueuedJob,
     RawCode, ENTRYPOINT_OVERRIDE,
 };
+use windmill_common::result_compression::completed_job_result;
 use windmill_common::worker::to_raw_value;
 use windmill_common::{
     error::{self, to_anyhow, Error},
@@ -54,7 +55,7 @@ use windmill_common::{
 use windmill_queue::schedule::get_schedule_opt;
 use windmill_queue::{
     add_completed_job, add_completed_job_error, append_logs, handle_maybe_scheduled_job,
-    CanceledBy, PushArgs, PushIsolationLevel, WrappedError,
+    CanceledBy, JobRunRecords, PushArgs, PushIsolationLevel, WrappedError,
 };
 
 type DB = sqlx::Pool<sqlx::Postgres>;
@@ -247,6 +248,15 @@ pub async fn update_flow_status_after_job_completion_internal<
 
         let module_step = Step::from_i32_and_len(old_status.step, old_status.modules.len());
 
+        // error of the failed step, to check whether its retry policy applies to it
+        let step_error = if success {
+            None
+        } else {
+            serde_json::from_str::<Value>(result.get())
+                .ok()
+                .and_then(|x| x.get("error").cloned())
+        };
+
         let module_status = match module_step {
             Step::PreprocessorStep => old_status
                 .preprocessor_module
@@ -400,13 +410,28 @@ pub async fn update_flow_status_after_job_completion_internal<
         };
 
         if matches!(module_step, Step::PreprocessorStep) {
-            sqlx::query!(
-                "UPDATE queue SET args = (select result FROM completed_job WHERE id = $1) WHERE id = $2",
-                job_id_for_status,
-                flow
-            ).execute(db).await.map_err(|e| {
-                Error::InternalErr(format!("error while updating args in preprocessing step: {e:#}"))
-            })?;
+            let (result, result_compressed) =
+                sqlx::query_as::<_, (Option<Json<Box<RawValue>>>, Option<Vec<u8>>)>(
+                    "SELECT result, result_compressed FROM completed_job WHERE id = $1",
+                )
+                .bind(job_id_for_status)
+                .fetch_one(db)
+                .await
+                .map_err(|e| {
+                    Error::InternalErr(format!(
+                        "error while fetching result of preprocessing step: {e:#}"
+                    ))
+                })?;
+            sqlx::query("UPDATE queue SET args = $1 WHERE id = $2")
+                .bind(completed_job_result(result, result_compressed).map(Json))
+                .bind(flow)
+                .execute(db)
+                .await
+                .map_err(|e| {
+                    Error::InternalErr(format!(
+                        "error while updating args in preprocessing step: {e:#}"
+                    ))
+                })?;
 
             sqlx::query!(
                 r#"UPDATE completed_job SET args = '{"reason":"PREPROCESSOR_ARGS_ARE_DISCARDED"}'::jsonb WHERE id = $1"#,
@@ -735,7 +760,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                             .unwrap_or_default();
 
                         tracing::info!("update flow status on rety: {retry:#?} ");
-                        next_retry(&retry, &old_status.retry).is_none()
+                        next_retry(&retry, &old_status.retry, step_error.as_ref()).is_none()
                     } else {
                         false
                     };
@@ -955,6 +980,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                 if next_retry(
                     &module.and_then(|m| m.retry.clone()).unwrap_or_default(),
                     &old_status.retry,
+                    step_error.as_ref(),
                 )
                 .is_some() =>
             {
@@ -1008,7 +1034,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                 );
                 sqlx::query!(
                     "UPDATE completed_job
-                    SET logs = '##DELETED##', args = '{}'::jsonb, result = '{}'::jsonb
+                    SET logs = '##DELETED##', args = '{}'::jsonb, result = '{}'::jsonb, result_compressed = null
                     WHERE id = ANY($1)",
                     &_cleanup_module.flow_jobs_to_clean,
                 )
@@ -1028,6 +1054,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                     username: flow_job.canceled_by.clone(),
                     reason: flow_job.canceled_reason.clone(),
                 }),
+                JobRunRecords::default(),
                 canceled_job_to_result(&flow_job),
                 rsmq.clone(),
                 worker_name,
@@ -1075,6 +1102,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                     Json(&nresult),
                     0,
                     None,
+                    JobRunRecords::default(),
                     rsmq.clone(),
                     true,
                     #[cfg(feature = "benchmark")]
@@ -1094,6 +1122,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                     ),
                     0,
                     None,
+                    JobRunRecords::default(),
                     rsmq.clone(),
                     true,
                     #[cfg(feature = "benchmark")]
@@ -1131,6 +1160,7 @@ pub async fn update_flow_status_after_job_completion_internal<
                     &flow_job,
                     0,
                     None,
+                    JobRunRecords::default(),
                     e,
                     rsmq.clone(),
                     worker_name,
@@ -1214,7 +1244,7 @@ async fn retrieve_flow_jobs_results(
     job_uuids: &Vec<Uuid>,
 ) -> error::Result<Box<RawValue>> {
     let results = sqlx::query_as::<_, BranchResults>(
-        "SELECT result, id
+        "SELECT result, result_compressed, id
         FROM completed_job
         WHERE id = ANY($1) AND workspace_id = $2",
     )
@@ -1223,7 +1253,7 @@ async fn retrieve_flow_jobs_results(
     .fetch_all(db)
     .await?
     .into_iter()
-    .map(|br| (br.id, br.result))
+    .map(|br| (br.id, completed_job_result(br.result, br.result_compressed)))
     .collect::<HashMap<_, _>>();
 
     let results = job_uuids
@@ -1328,8 +1358,12 @@ async fn has_failure_module<'c>(flow: Uuid, db: &DB) -> Result<bool, Error> {
 //         )))
 // }
 
-fn next_retry(retry: &Retry, status: &RetryStatus) -> Option<(u16, Duration)> {
-    (status.fail_count <= MAX_RETRY_ATTEMPTS)
+fn next_retry(
+    retry: &Retry,
+    status: &RetryStatus,
+    error: Option<&Value>,
+) -> Option<(u16, Duration)> {
+    (status.fail_count <= MAX_RETRY_ATTEMPTS && retry.is_retryable(error))
         .then(|| &retry)
         .and_then(|retry| retry.interval(status.fail_count, false))
         .map(|d| (status.fail_count + 1, std::cmp::min(d, MAX_RETRY_INTERVAL)))
@@ -1520,6 +1554,169 @@ async fn transform_input(
     Ok(mapped)
 }
 
+/// How the args of a flow step computed from the result of the previous step are checked against
+/// the schema of the step, see [`check_step_input_schema`]
+#[derive(PartialEq)]
+enum StepSchemaValidation {
+    Off,
+    Warn,
+    Fail,
+}
+
+lazy_static::lazy_static! {
+    /// `off` (default), `warn` in the logs of the flow, or `fail` the step whose args computed from
+    /// the result of the previous step do not match its schema
+    static ref FLOW_STEP_SCHEMA_VALIDATION: StepSchemaValidation =
+        match std::env::var("FLOW_STEP_SCHEMA_VALIDATION").ok().as_deref() {
+            Some("warn") => StepSchemaValidation::Warn,
+            Some("fail") => StepSchemaValidation::Fail,
+            _ => StepSchemaValidation::Off,
+        };
+}
+
+/// Check the args of a script step computed from the result of the previous step against the
+/// schema of the script, so that an incompatible output is reported at the boundary between the
+/// steps instead of making the step fail cryptically. Only deployed scripts have a schema: inline
+/// scripts, and the args without a type, are not checked
+async fn check_step_input_schema(
+    db: &DB,
+    flow_job: &QueuedJob,
+    flow: &FlowValue,
+    step: &Step,
+    value: &anyhow::Result<FlowModuleValue>,
+    args: &HashMap<String, Box<RawValue>>,
+) -> error::Result<()> {
+    if *FLOW_STEP_SCHEMA_VALIDATION == StepSchemaValidation::Off {
+        return Ok(());
+    }
+    let Step::Step(i) = *step else {
+        return Ok(());
+    };
+    let (Some(previous), Some(current)) = (
+        i.checked_sub(1).and_then(|i| flow.modules.get(i)),
+        flow.modules.get(i),
+    ) else {
+        return Ok(());
+    };
+    let Ok(FlowModuleValue::Script { input_transforms, path, hash, .. }) = value else {
+        return Ok(());
+    };
+    if path.starts_with("hub/") {
+        return Ok(());
+    }
+
+    let fields_from_previous = input_transforms
+        .iter()
+        .filter_map(|(field, transform)| match transform {
+            InputTransform::Javascript { expr }
+                if expr.contains("previous_result")
+                    || expr.contains(&format!("results.{}", previous.id))
+                    || expr.contains(&format!("results[\"{}\"]", previous.id)) =>
+            {
+                Some(field)
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if fields_from_previous.is_empty() {
+        return Ok(());
+    }
+
+    let schema = if let Some(hash) = hash {
+        sqlx::query_scalar::<_, Option<Json<Value>>>(
+            "SELECT schema FROM script WHERE hash = $1 AND workspace_id = $2",
+        )
+        .bind(hash.0)
+        .bind(&flow_job.workspace_id)
+        .fetch_optional(db)
+        .await?
+    } else {
+        sqlx::query_scalar::<_, Option<Json<Value>>>(
+            "SELECT schema FROM script WHERE path = $1 AND workspace_id = $2 AND archived = false
+            ORDER BY created_at DESC LIMIT 1",
+        )
+        .bind(path)
+        .bind(&flow_job.workspace_id)
+        .fetch_optional(db)
+        .await?
+    };
+    let Some(Json(schema)) = schema.flatten() else {
+        return Ok(());
+    };
+
+    let required = schema
+        .get("required")
+        .and_then(|x| x.as_array())
+        .map(|x| x.iter().filter_map(|x| x.as_str()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let mut errors = fields_from_previous
+        .into_iter()
+        .filter_map(|field| {
+            let property = schema.get("properties").and_then(|x| x.get(field))?;
+            let value = args
+                .get(field)
+                .and_then(|x| serde_json::from_str::<Value>(x.get()).ok())
+                .unwrap_or(Value::Null);
+            schema_type_mismatch(property, &value, required.contains(&field.as_str()))
+                .map(|e| format!("field `{field}` {e}"))
+        })
+        .collect::<Vec<_>>();
+    if errors.is_empty() {
+        return Ok(());
+    }
+    errors.sort();
+
+    let msg = format!(
+        "step {} ({}) output incompatible with step {i} ({}) input: {}",
+        i - 1,
+        previous.id,
+        current.id,
+        errors.join(", ")
+    );
+    if *FLOW_STEP_SCHEMA_VALIDATION == StepSchemaValidation::Fail {
+        Err(Error::ExecutionErr(msg))
+    } else {
+        append_logs(
+            &flow_job.id,
+            &flow_job.workspace_id,
+            format!("WARNING: {msg}\n"),
+            db,
+        )
+        .await;
+        Ok(())
+    }
+}
+
+/// Why the value does not match the type of the schema property, if it does not
+fn schema_type_mismatch(property: &Value, value: &Value, required: bool) -> Option<String> {
+    if value.is_null() {
+        return required.then(|| "is required but missing".to_string());
+    }
+    let expected = property.get("type").and_then(|x| x.as_str())?;
+    let matches = match expected {
+        "string" => value.is_string(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "boolean" => value.is_boolean(),
+        "array" => value.is_array(),
+        // resources can be passed by path
+        "object" => value.is_object() || value.as_str().is_some_and(|x| x.starts_with("$res:")),
+        _ => true,
+    };
+    if matches {
+        return None;
+    }
+    let actual = match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    };
+    Some(format!("expected {expected}, got {actual}"))
+}
+
 #[instrument(level = "trace", skip_all)]
 pub async fn handle_flow<R: rsmq_async::RsmqConnection + Send + Sync + Clone>(
     flow_job: Arc<QueuedJob>,
@@ -2146,9 +2343,21 @@ async fn push_next_flow_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
         }
     };
 
-    let retry = if matches!(&status_module, FlowStatusModule::Failure { .. },) {
+    let retry = if let FlowStatusModule::Failure { job, .. } = &status_module {
         let retry = &module.retry.clone().unwrap_or_default();
-        next_retry(retry, &status.retry)
+        let error = if retry.has_attempts() {
+            sqlx::query_scalar::<_, Option<Value>>(
+                "SELECT result->'error' FROM completed_job WHERE id = $1 AND workspace_id = $2",
+            )
+            .bind(job)
+            .bind(&flow_job.workspace_id)
+            .fetch_optional(db)
+            .await?
+            .flatten()
+        } else {
+            None
+        };
+        next_retry(retry, &status.retry, error.as_ref())
     } else {
         None
     };
@@ -2300,7 +2509,7 @@ async fn push_next_flow_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
                     let ctx = get_transform_context(&flow_job, &previous_id, &status).await?;
                     transform_context = Some(ctx);
                     let by_id = transform_context.as_ref().unwrap();
-                    transform_input(
+                    match transform_input(
                         arc_flow_job_args.clone(),
                         arc_last_job_result.clone(),
                         input_transforms,
@@ -2311,7 +2520,14 @@ async fn push_next_flow_job<R: rsmq_async::RsmqConnection + Send + Sync + Clone>
                         client,
                     )
                     .await
-                    .map(Marc::new)
+                    {
+                        Ok(args) => {
+                            check_step_input_schema(db, &flow_job, &flow, &step, &value, &args)
+                                .await
+                                .map(|()| Marc::new(args))
+                        }
+                        Err(e) => Err(e),
+                    }
                 },
                 Ok(_) => Ok(arc_flow_job_args.clone()),
                 Err(e) => {
@@ -3901,16 +4117,17 @@ async fn get_previous_job_result(
         Some(FlowStatusModule::Success { flow_jobs: Some(flow_jobs), .. }) => {
             Ok(Some(retrieve_flow_jobs_results(db, w_id, flow_jobs).await?))
         }
-        Some(FlowStatusModule::Success { job, .. }) => Ok(Some(
-            sqlx::query_scalar::<_, Json<Box<RawValue>>>(
-                "SELECT result FROM completed_job WHERE id = $1 AND workspace_id = $2",
-            )
-            .bind(job)
-            .bind(w_id)
-            .fetch_one(db)
-            .await?
-            .0,
-        )),
+        Some(FlowStatusModule::Success { job, .. }) => {
+            let (result, result_compressed) =
+                sqlx::query_as::<_, (Option<Json<Box<RawValue>>>, Option<Vec<u8>>)>(
+                    "SELECT result, result_compressed FROM completed_job WHERE id = $1 AND workspace_id = $2",
+                )
+                .bind(job)
+                .bind(w_id)
+                .fetch_one(db)
+                .await?;
+            Ok(completed_job_result(result, result_compressed))
+        }
         _ => Ok(None),
     }
 }
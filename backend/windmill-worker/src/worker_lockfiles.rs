@@ -1,5 +1,7 @@
 use std::collections::HashMap;
 use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use async_recursion::async_recursion;
 use serde_json::value::RawValue;
@@ -17,13 +19,14 @@ use windmill_common::{
     error::{self, to_anyhow},
     flows::FlowValue,
     jobs::QueuedJob,
+    log_compression::decompress_log_chunks,
     scripts::ScriptLang,
     DB,
 };
 use windmill_git_sync::{handle_deployment_metadata, DeployedObject};
 use windmill_parser_py_imports::parse_relative_imports;
 use windmill_parser_ts::parse_expr_for_imports;
-use windmill_queue::{append_logs, CanceledBy, PushIsolationLevel};
+use windmill_queue::{append_logs, CanceledBy, JobQueue, JobRunRecords, PushIsolationLevel};
 
 use crate::common::OccupancyMetrics;
 use crate::python_executor::{create_dependencies_dir, handle_python_reqs, uv_pip_compile};
@@ -206,8 +209,10 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     base_internal_url: &str,
@@ -255,6 +260,7 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
         None
     };
 
+    let start = Instant::now();
     let content = capture_dependency_job(
         &job.id,
         job.language.as_ref().map(|v| Ok(v)).unwrap_or_else(|| {
@@ -265,8 +271,10 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
         &raw_code,
         mem_peak,
         canceled_by,
+        run_records,
         job_dir,
         db,
+        job_queue,
         worker_name,
         &job.workspace_id,
         worker_dir,
@@ -298,6 +306,8 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
             )
             .execute(db)
             .await?;
+            let outcome = LockOutcome::success(&content, job.language.as_ref(), start.elapsed());
+            store_lock_outcome(db, w_id, hash, &outcome).await;
 
             let (deployment_message, parent_path) =
                 get_deployment_msg_and_parent_path_from_args(job.args.clone());
@@ -373,6 +383,7 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
             .fetch_optional(db)
             .await?
             .flatten()
+            .map(|logs| decompress_log_chunks(&logs).into_owned())
             .unwrap_or_else(|| "no logs".to_string());
             sqlx::query!(
                 "UPDATE script SET lock_error_logs = $1 WHERE hash = $2 AND workspace_id = $3",
@@ -382,11 +393,112 @@ pub async fn handle_dependency_job<R: rsmq_async::RsmqConnection + Send + Sync +
             )
             .execute(db)
             .await?;
+            let outcome = LockOutcome::failure(&error, &logs2, start.elapsed());
+            store_lock_outcome(
+                db,
+                &job.workspace_id,
+                job.script_hash.unwrap_or(ScriptHash(0)),
+                &outcome,
+            )
+            .await;
             Err(Error::ExecutionErr(format!("Error locking file: {error}")))?
         }
     }
 }
 
+/// Structured outcome of a dependency job, stored with the script alongside the lock or the
+/// `lock_error_logs`, to show it in the UI and to query the failed resolutions by cause
+struct LockOutcome {
+    success: bool,
+    exit_code: Option<i32>,
+    duration_ms: i64,
+    /// number of pinned packages, python only
+    pinned_packages: Option<i32>,
+    /// `no_matching_distribution`, `conflicting_pins`, `network` or `other` on failure
+    error_kind: Option<&'static str>,
+}
+
+impl LockOutcome {
+    fn success(lock: &str, language: Option<&ScriptLang>, duration: Duration) -> Self {
+        Self {
+            success: true,
+            exit_code: Some(0),
+            duration_ms: duration.as_millis() as i64,
+            pinned_packages: (language == Some(&ScriptLang::Python3))
+                .then(|| count_pinned_python_packages(lock)),
+            error_kind: None,
+        }
+    }
+
+    fn failure(error: &Error, logs: &str, duration: Duration) -> Self {
+        Self {
+            success: false,
+            exit_code: match error {
+                Error::ExitStatus(code) => Some(*code),
+                _ => None,
+            },
+            duration_ms: duration.as_millis() as i64,
+            pinned_packages: None,
+            error_kind: Some(classify_lock_error(&format!("{logs}\n{error}"))),
+        }
+    }
+}
+
+/// Number of `name==version` lines of a python lock
+fn count_pinned_python_packages(lock: &str) -> i32 {
+    lock.lines()
+        .map(str::trim)
+        .filter(|l| !l.starts_with('#') && !l.starts_with('-') && l.contains("=="))
+        .count() as i32
+}
+
+/// Cause of a failed resolution, from the logs of the pip/uv compile
+fn classify_lock_error(logs: &str) -> &'static str {
+    let logs = logs.to_lowercase();
+    let contains_any = |patterns: &[&str]| patterns.iter().any(|p| logs.contains(p));
+    if contains_any(&[
+        "failed to fetch",
+        "failed to download",
+        "connection refused",
+        "connection reset",
+        "temporary failure in name resolution",
+        "network is unreachable",
+        "error sending request",
+    ]) {
+        "network"
+    } else if contains_any(&[
+        "no matching distribution",
+        "there is no version of",
+        "not found in the package registry",
+        "could not find a version",
+    ]) {
+        "no_matching_distribution"
+    } else if contains_any(&["unsatisfiable", "resolutionimpossible", "conflicting"]) {
+        "conflicting_pins"
+    } else {
+        "other"
+    }
+}
+
+async fn store_lock_outcome(db: &DB, w_id: &str, hash: ScriptHash, outcome: &LockOutcome) {
+    if let Err(e) = sqlx::query(
+        "UPDATE script SET lock_success = $1, lock_exit_code = $2, lock_duration_ms = $3, \
+         lock_pinned_packages = $4, lock_error_kind = $5 WHERE hash = $6 AND workspace_id = $7",
+    )
+    .bind(outcome.success)
+    .bind(outcome.exit_code)
+    .bind(outcome.duration_ms)
+    .bind(outcome.pinned_packages)
+    .bind(outcome.error_kind)
+    .bind(hash.0)
+    .bind(w_id)
+    .execute(db)
+    .await
+    {
+        tracing::error!(%w_id, "could not store the outcome of the lock of script {hash}: {e:#}");
+    }
+}
+
 async fn trigger_dependents_to_recompute_dependencies<
     R: rsmq_async::RsmqConnection + Send + Sync + Clone,
 >(
@@ -529,8 +641,10 @@ pub async fn handle_flow_dependency_job<R: rsmq_async::RsmqConnection + Send + S
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     base_internal_url: &str,
@@ -600,8 +714,10 @@ pub async fn handle_flow_dependency_job<R: rsmq_async::RsmqConnection + Send + S
         job,
         mem_peak,
         canceled_by,
+        run_records,
         job_dir,
         db,
+        job_queue,
         tx,
         worker_name,
         worker_dir,
@@ -705,8 +821,10 @@ async fn lock_modules<'c>(
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     mut tx: sqlx::Transaction<'c, sqlx::Postgres>,
     worker_name: &str,
     worker_dir: &str,
@@ -752,8 +870,10 @@ async fn lock_modules<'c>(
                         job,
                         mem_peak,
                         canceled_by,
+                        run_records,
                         job_dir,
                         db,
+                        job_queue,
                         tx,
                         worker_name,
                         worker_dir,
@@ -784,8 +904,10 @@ async fn lock_modules<'c>(
                             job,
                             mem_peak,
                             canceled_by,
+                            run_records,
                             job_dir,
                             db,
+                            job_queue,
                             tx,
                             worker_name,
                             worker_dir,
@@ -809,8 +931,10 @@ async fn lock_modules<'c>(
                         job,
                         mem_peak,
                         canceled_by,
+                        run_records,
                         job_dir,
                         db,
+                        job_queue,
                         tx,
                         worker_name,
                         worker_dir,
@@ -836,8 +960,10 @@ async fn lock_modules<'c>(
                             job,
                             mem_peak,
                             canceled_by,
+                            run_records,
                             job_dir,
                             db,
+                            job_queue,
                             tx,
                             worker_name,
                             worker_dir,
@@ -858,8 +984,10 @@ async fn lock_modules<'c>(
                         job,
                         mem_peak,
                         canceled_by,
+                        run_records,
                         job_dir,
                         db,
+                        job_queue,
                         tx,
                         worker_name,
                         worker_dir,
@@ -903,8 +1031,10 @@ async fn lock_modules<'c>(
             &content,
             mem_peak,
             canceled_by,
+            run_records,
             job_dir,
             db,
+            job_queue,
             worker_name,
             &job.workspace_id,
             worker_dir,
@@ -1022,8 +1152,10 @@ async fn lock_modules_app(
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     job_path: &str,
@@ -1064,8 +1196,10 @@ async fn lock_modules_app(
                                 &content,
                                 mem_peak,
                                 canceled_by,
+                                run_records,
                                 job_dir,
                                 db,
+                                job_queue,
                                 worker_name,
                                 &job.workspace_id,
                                 worker_dir,
@@ -1125,8 +1259,10 @@ async fn lock_modules_app(
                         job,
                         mem_peak,
                         canceled_by,
+                        run_records,
                         job_dir,
                         db,
+                        job_queue,
                         worker_name,
                         worker_dir,
                         job_path,
@@ -1148,8 +1284,10 @@ async fn lock_modules_app(
                         job,
                         mem_peak,
                         canceled_by,
+                        run_records,
                         job_dir,
                         db,
+                        job_queue,
                         worker_name,
                         worker_dir,
                         job_path,
@@ -1170,8 +1308,10 @@ pub async fn handle_app_dependency_job<R: rsmq_async::RsmqConnection + Send + Sy
     job: &QueuedJob,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     worker_dir: &str,
     base_internal_url: &str,
@@ -1200,8 +1340,10 @@ pub async fn handle_app_dependency_job<R: rsmq_async::RsmqConnection + Send + Sy
             job,
             mem_peak,
             canceled_by,
+            run_records,
             job_dir,
             db,
+            job_queue,
             worker_name,
             worker_dir,
             &job_path,
@@ -1277,8 +1419,10 @@ async fn python_dep(
     job_id: &Uuid,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     w_id: &str,
     worker_dir: &str,
@@ -1290,8 +1434,10 @@ async fn python_dep(
         &reqs,
         mem_peak,
         canceled_by,
+        run_records,
         job_dir,
         db,
+        job_queue,
         worker_name,
         w_id,
         occupancy_metrics,
@@ -1307,7 +1453,9 @@ async fn python_dep(
             w_id,
             mem_peak,
             canceled_by,
+            run_records,
             db,
+            job_queue,
             worker_name,
             job_dir,
             worker_dir,
@@ -1331,8 +1479,10 @@ async fn capture_dependency_job(
     job_raw_code: &str,
     mem_peak: &mut i32,
     canceled_by: &mut Option<CanceledBy>,
+    run_records: &mut JobRunRecords,
     job_dir: &str,
     db: &sqlx::Pool<sqlx::Postgres>,
+    job_queue: &Arc<dyn JobQueue>,
     worker_name: &str,
     w_id: &str,
     worker_dir: &str,
@@ -1366,8 +1516,10 @@ async fn capture_dependency_job(
                 job_id,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 db,
+                job_queue,
                 worker_name,
                 w_id,
                 worker_dir,
@@ -1389,8 +1541,10 @@ async fn capture_dependency_job(
                 job_id,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 db,
+                job_queue,
                 worker_name,
                 w_id,
                 worker_dir,
@@ -1409,8 +1563,10 @@ async fn capture_dependency_job(
                 job_raw_code,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 db,
+                job_queue,
                 false,
                 false,
                 false,
@@ -1431,8 +1587,10 @@ async fn capture_dependency_job(
                 job_raw_code,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 Some(db),
+                Some(job_queue),
                 w_id,
                 worker_name,
                 base_internal_url,
@@ -1450,9 +1608,11 @@ async fn capture_dependency_job(
             let req = gen_bun_lockfile(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_id,
                 w_id,
                 Some(db),
+                Some(job_queue),
                 token,
                 script_path,
                 job_dir,
@@ -1476,6 +1636,7 @@ async fn capture_dependency_job(
                     job_id,
                     w_id,
                     Some(db.clone()),
+                    Some(job_queue),
                     &job_dir,
                     base_internal_url,
                     worker_name,
@@ -1504,9 +1665,11 @@ async fn capture_dependency_job(
             composer_install(
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_id,
                 w_id,
                 db,
+                job_queue,
                 job_dir,
                 worker_name,
                 reqs,
@@ -1527,8 +1690,10 @@ async fn capture_dependency_job(
                 job_raw_code,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 db,
+                job_queue,
                 worker_name,
                 w_id,
                 occupancy_metrics,
@@ -1539,8 +1704,10 @@ async fn capture_dependency_job(
                 job_id,
                 mem_peak,
                 canceled_by,
+                run_records,
                 job_dir,
                 db,
+                job_queue,
                 worker_name,
                 w_id,
                 base_internal_url,